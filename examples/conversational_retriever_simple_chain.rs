@@ -1,5 +1,3 @@
-use std::error::Error;
-
 use async_trait::async_trait;
 use futures_util::StreamExt;
 use langchain_rust::{
@@ -10,7 +8,7 @@ use langchain_rust::{
     message_formatter,
     prompt::HumanMessagePromptTemplate,
     prompt_args,
-    schemas::{Document, Message, Retriever},
+    schemas::{Document, Message, Retriever, RetrieverError},
     template_jinja2,
 };
 
@@ -20,7 +18,7 @@ impl Retriever for RetrieverMock {
     async fn get_relevant_documents(
         &self,
         _question: &str,
-    ) -> Result<Vec<Document>, Box<dyn Error>> {
+    ) -> Result<Vec<Document>, RetrieverError> {
         Ok(vec![
             Document::new(format!(
                 "\nQuestion: {}\nAnswer: {}\n",
@@ -116,10 +116,8 @@ async fn add_documents_to_index(store: &Store) -> Result<Vec<String>, Box<dyn Er
     let doc4 = Document::new("Capital of France is Paris.");
 
     let opts = VecStoreOptions {
-        name_space: None,
-        score_threshold: None,
-        filters: None,
         embedder: Some(store.embedder.clone()),
+        ..Default::default()
     };
 
     let result = store
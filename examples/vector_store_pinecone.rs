@@ -0,0 +1,70 @@
+// To run this example execute: cargo run --example vector_store_pinecone --features pinecone
+
+#[cfg(feature = "pinecone")]
+use langchain_rust::{
+    embedding::openai::openai_embedder::OpenAiEmbedder,
+    schemas::Document,
+    vectorstore::pinecone::StoreBuilder,
+    vectorstore::{VecStoreOptions, VectorStore},
+};
+#[cfg(feature = "pinecone")]
+use serde_json::json;
+#[cfg(feature = "pinecone")]
+use std::io::Write;
+
+#[cfg(feature = "pinecone")]
+#[tokio::main]
+async fn main() {
+    // Requires OpenAI API key to be set in the environment variable OPENAI_API_KEY
+    let embedder = OpenAiEmbedder::default();
+
+    // Requires a Pinecone index of 1536 dimensions with the cosine metric, its host is
+    // shown in the Pinecone console
+    let store = StoreBuilder::new()
+        .embedder(embedder)
+        .host(&std::env::var("PINECONE_INDEX_HOST").unwrap())
+        .api_key(&std::env::var("PINECONE_API_KEY").unwrap())
+        .namespace("langchain-rs")
+        .build()
+        .unwrap();
+
+    // Add documents to the index
+    let doc1 = Document::new(
+        "langchain-rust is a port of the langchain python library to rust and was written in 2024.",
+    )
+    .with_metadata([("language".to_string(), json!("rust"))].into());
+    let doc2 = Document::new(
+        "langchaingo is a port of the langchain python library to go language and was written in 2023."
+    )
+    .with_metadata([("language".to_string(), json!("go"))].into());
+
+    store
+        .add_documents(&[doc1, doc2], &VecStoreOptions::default())
+        .await
+        .unwrap();
+
+    // Ask for user input
+    print!("Query> ");
+    std::io::stdout().flush().unwrap();
+    let mut query = String::new();
+    std::io::stdin().read_line(&mut query).unwrap();
+
+    // Only search the rust documents
+    let options = VecStoreOptions::default().with_filters(json!({"language": {"$eq": "rust"}}));
+    let results = store.similarity_search(&query, 2, &options).await.unwrap();
+
+    if results.is_empty() {
+        println!("No results found.");
+    } else {
+        results.iter().for_each(|r| {
+            println!("Document: {} ({})", r.page_content, r.score);
+        });
+    }
+}
+
+#[cfg(not(feature = "pinecone"))]
+fn main() {
+    println!("This example requires the 'pinecone' feature to be enabled.");
+    println!("Please run the command as follows:");
+    println!("cargo run --example vector_store_pinecone --features pinecone");
+}
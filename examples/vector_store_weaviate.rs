@@ -0,0 +1,75 @@
+// To run this example execute: cargo run --example vector_store_weaviate --features weaviate
+
+#[cfg(feature = "weaviate")]
+use langchain_rust::{
+    embedding::openai::openai_embedder::OpenAiEmbedder,
+    schemas::Document,
+    vectorstore::weaviate::StoreBuilder,
+    vectorstore::{VecStoreOptions, VectorStore},
+};
+#[cfg(feature = "weaviate")]
+use serde_json::json;
+#[cfg(feature = "weaviate")]
+use std::io::Write;
+
+#[cfg(feature = "weaviate")]
+#[tokio::main]
+async fn main() {
+    // Requires OpenAI API key to be set in the environment variable OPENAI_API_KEY
+    let embedder = OpenAiEmbedder::default();
+
+    // Ensure Weaviate is running at localhost
+    // docker run -p 8080:8080 cr.weaviate.io/semitechnologies/weaviate
+    let store = StoreBuilder::new()
+        .embedder(embedder)
+        .url("http://localhost:8080")
+        .class_name("LangchainRs")
+        .vector_dimensions(1536)
+        .build()
+        .await
+        .unwrap();
+
+    // Add documents to the database
+    let doc1 = Document::new(
+        "langchain-rust is a port of the langchain python library to rust and was written in 2024.",
+    )
+    .with_metadata([("language".to_string(), json!("rust"))].into());
+    let doc2 = Document::new(
+        "langchaingo is a port of the langchain python library to go language and was written in 2023."
+    )
+    .with_metadata([("language".to_string(), json!("go"))].into());
+
+    store
+        .add_documents(&[doc1, doc2], &VecStoreOptions::default())
+        .await
+        .unwrap();
+
+    // Ask for user input
+    print!("Query> ");
+    std::io::stdout().flush().unwrap();
+    let mut query = String::new();
+    std::io::stdin().read_line(&mut query).unwrap();
+
+    // Only search the rust documents
+    let options = VecStoreOptions::default().with_filters(json!({
+        "path": ["language"],
+        "operator": "Equal",
+        "valueText": "rust",
+    }));
+    let results = store.similarity_search(&query, 2, &options).await.unwrap();
+
+    if results.is_empty() {
+        println!("No results found.");
+    } else {
+        results.iter().for_each(|r| {
+            println!("Document: {} ({})", r.page_content, r.score);
+        });
+    }
+}
+
+#[cfg(not(feature = "weaviate"))]
+fn main() {
+    println!("This example requires the 'weaviate' feature to be enabled.");
+    println!("Please run the command as follows:");
+    println!("cargo run --example vector_store_weaviate --features weaviate");
+}
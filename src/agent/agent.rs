@@ -3,20 +3,76 @@ use std::sync::Arc;
 use async_trait::async_trait;
 
 use crate::{
+    language_models::TokenUsage,
     prompt::PromptArgs,
-    schemas::agent::{AgentAction, AgentEvent},
+    schemas::{
+        agent::{AgentAction, AgentEvent, AgentPlan},
+        Message,
+    },
     tools::Tool,
 };
 
 use super::AgentError;
 
+/// Input variable set by the executor when the maximum number of iterations is reached,
+/// with the message asking the agent for a final answer.
+pub(crate) const ULTIMATUM_INPUT_KEY: &str = "ultimatum";
+
+/// The message asking for a final answer, to add at the end of the scratchpad.
+pub(crate) fn ultimatum_message(inputs: &PromptArgs) -> Option<Message> {
+    inputs
+        .get(ULTIMATUM_INPUT_KEY)
+        .and_then(|ultimatum| ultimatum.as_str())
+        .map(Message::new_human_message)
+}
+
 #[async_trait]
 pub trait Agent: Send + Sync {
+    /// Plans the next step from the steps already taken, with the token usage of the LLM
+    /// calls of the step, used by the executor to report the usage of the whole run.
     async fn plan(
         &self,
         intermediate_steps: &[(AgentAction, String)],
         inputs: PromptArgs,
-    ) -> Result<AgentEvent, AgentError>;
+    ) -> Result<(AgentEvent, Option<TokenUsage>), AgentError>;
+
+    /// Same as `plan`, but the final answer may be returned as a stream. When it is, its
+    /// usage is in the chunks of the stream.
+    ///
+    /// The default implementation does not stream, it returns the result of `plan`.
+    async fn stream_plan(
+        &self,
+        intermediate_steps: &[(AgentAction, String)],
+        inputs: PromptArgs,
+    ) -> Result<(AgentPlan, Option<TokenUsage>), AgentError> {
+        self.plan(intermediate_steps, inputs)
+            .await
+            .map(|(event, usage)| (AgentPlan::Text(event), usage))
+    }
+
+    #[deprecated(note = "`plan` returns the usage, call it instead")]
+    async fn plan_with_usage(
+        &self,
+        intermediate_steps: &[(AgentAction, String)],
+        inputs: PromptArgs,
+    ) -> Result<(AgentEvent, Option<TokenUsage>), AgentError> {
+        self.plan(intermediate_steps, inputs).await
+    }
+
+    #[deprecated(note = "`stream_plan` returns the usage, call it instead")]
+    async fn stream_plan_with_usage(
+        &self,
+        intermediate_steps: &[(AgentAction, String)],
+        inputs: PromptArgs,
+    ) -> Result<(AgentPlan, Option<TokenUsage>), AgentError> {
+        self.stream_plan(intermediate_steps, inputs).await
+    }
 
     fn get_tools(&self) -> Vec<Arc<dyn Tool>>;
+
+    /// Input keys required by the agent prompt, including the ones that are
+    /// filled by the executor like `chat_history` and `agent_scratchpad`.
+    fn get_input_keys(&self) -> Vec<String> {
+        vec!["input".to_string()]
+    }
 }
@@ -0,0 +1,32 @@
+use async_trait::async_trait;
+
+use crate::schemas::agent::AgentAction;
+
+/// Decision of a `ToolApproval` about an action planned by the agent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ApprovalDecision {
+    Approve,
+    Deny,
+    /// Denies the action, the reason is sent to the agent with the denial.
+    DenyWithReason(String),
+}
+
+/// Consulted by the `AgentExecutor` before each tool is executed, to let a human or a
+/// policy approve sensitive actions like running commands.
+///
+/// Closures `Fn(&AgentAction) -> ApprovalDecision` implement it, implement the trait to
+/// ask for the approval asynchronously.
+#[async_trait]
+pub trait ToolApproval: Send + Sync {
+    async fn approve(&self, action: &AgentAction) -> ApprovalDecision;
+}
+
+#[async_trait]
+impl<F> ToolApproval for F
+where
+    F: Fn(&AgentAction) -> ApprovalDecision + Send + Sync,
+{
+    async fn approve(&self, action: &AgentAction) -> ApprovalDecision {
+        self(action)
+    }
+}
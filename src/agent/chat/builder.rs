@@ -9,15 +9,17 @@ use crate::{
 
 use super::{
     output_parser::ChatOutputParser,
-    prompt::{PREFIX, SUFFIX},
-    ConversationalAgent,
+    prompt::{FORMAT_INSTRUCTIONS, PREFIX, SUFFIX},
+    ConversationalAgent, ScratchpadFormat,
 };
 
 pub struct ConversationalAgentBuilder {
     tools: Option<Vec<Arc<dyn Tool>>>,
     prefix: Option<String>,
     suffix: Option<String>,
+    format_instructions: Option<String>,
     options: Option<ChainCallOptions>,
+    scratchpad_format: ScratchpadFormat,
 }
 
 impl ConversationalAgentBuilder {
@@ -26,7 +28,9 @@ impl ConversationalAgentBuilder {
             tools: None,
             prefix: None,
             suffix: None,
+            format_instructions: None,
             options: None,
+            scratchpad_format: ScratchpadFormat::default(),
         }
     }
 
@@ -45,17 +49,40 @@ impl ConversationalAgentBuilder {
         self
     }
 
+    /// Instructions on the format of the responses, in place of `{{format_instructions}}` in
+    /// the suffix. `{{tool_names}}` in them is replaced by the names of the tools. The
+    /// responses must still be parsable by the agent.
+    pub fn format_instructions<S: Into<String>>(mut self, format_instructions: S) -> Self {
+        self.format_instructions = Some(format_instructions.into());
+        self
+    }
+
     pub fn options(mut self, options: ChainCallOptions) -> Self {
         self.options = Some(options);
         self
     }
 
+    /// How the steps already taken are given to the model, as text by default. See
+    /// `ScratchpadFormat`.
+    pub fn scratchpad_format(mut self, scratchpad_format: ScratchpadFormat) -> Self {
+        self.scratchpad_format = scratchpad_format;
+        self
+    }
+
     pub fn build<L: Into<Box<dyn LLM>>>(self, llm: L) -> Result<ConversationalAgent, AgentError> {
         let tools = self.tools.unwrap_or_default();
         let prefix = self.prefix.unwrap_or_else(|| PREFIX.to_string());
         let suffix = self.suffix.unwrap_or_else(|| SUFFIX.to_string());
+        let format_instructions = self
+            .format_instructions
+            .unwrap_or_else(|| FORMAT_INSTRUCTIONS.to_string());
 
-        let prompt = ConversationalAgent::create_prompt(&tools, &suffix, &prefix)?;
+        let prompt = ConversationalAgent::create_prompt_with_format_instructions(
+            &tools,
+            &suffix,
+            &prefix,
+            &format_instructions,
+        )?;
         let default_options = ChainCallOptions::default().with_max_tokens(1000);
         let chain = Box::new(
             LLMChainBuilder::new()
@@ -69,6 +96,7 @@ impl ConversationalAgentBuilder {
             chain,
             tools,
             output_parser: ChatOutputParser::new(),
+            scratchpad_format: self.scratchpad_format,
         })
     }
 }
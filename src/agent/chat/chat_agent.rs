@@ -1,11 +1,16 @@
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use serde_json::json;
+use serde_json::{json, Value};
 
 use crate::{
-    agent::{agent::Agent, chat::prompt::FORMAT_INSTRUCTIONS, AgentError},
+    agent::{
+        agent::{ultimatum_message, Agent},
+        chat::prompt::FORMAT_INSTRUCTIONS,
+        AgentError,
+    },
     chain::chain_trait::Chain,
+    language_models::TokenUsage,
     message_formatter,
     prompt::{
         HumanMessagePromptTemplate, MessageFormatterStruct, MessageOrTemplate, PromptArgs,
@@ -15,6 +20,7 @@ use crate::{
     schemas::{
         agent::{AgentAction, AgentEvent},
         messages::Message,
+        FunctionCallResponse, FunctionDetail,
     },
     template_jinja2,
     tools::Tool,
@@ -22,10 +28,30 @@ use crate::{
 
 use super::{output_parser::ChatOutputParser, prompt::TEMPLATE_TOOL_RESPONSE};
 
+/// How the steps already taken are given to the model in the `agent_scratchpad`
+/// placeholder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScratchpadFormat {
+    /// The output of the model as an AI message, then the observation of the tool as a
+    /// human message rendered with the tool response template.
+    #[default]
+    Text,
+    /// An AI message with the tool call, then a tool message with the observation, answering
+    /// the call by its id. For the models expecting the tool results as tool messages, like
+    /// the ones of the `OpenAiToolAgent`.
+    ToolCalls,
+}
+
 pub struct ConversationalAgent {
     pub(crate) chain: Box<dyn Chain>,
     pub(crate) tools: Vec<Arc<dyn Tool>>,
     pub(crate) output_parser: ChatOutputParser,
+    pub(crate) scratchpad_format: ScratchpadFormat,
+}
+
+/// The text wrapped in a raw block, to appear as is in a Jinja2 template.
+fn raw_jinja2(text: &str) -> String {
+    format!("{{% raw %}}{}{{% endraw %}}", text)
 }
 
 impl ConversationalAgent {
@@ -33,6 +59,18 @@ impl ConversationalAgent {
         tools: &[Arc<dyn Tool>],
         suffix: &str,
         prefix: &str,
+    ) -> Result<MessageFormatterStruct, AgentError> {
+        Self::create_prompt_with_format_instructions(tools, suffix, prefix, FORMAT_INSTRUCTIONS)
+    }
+
+    /// Like `create_prompt`, with the instructions on the format of the responses in place
+    /// of the default ones. `{{tool_names}}` in them is replaced by the names of the tools,
+    /// then they replace `{{format_instructions}}` in the suffix.
+    pub fn create_prompt_with_format_instructions(
+        tools: &[Arc<dyn Tool>],
+        suffix: &str,
+        prefix: &str,
+        format_instructions: &str,
     ) -> Result<MessageFormatterStruct, AgentError> {
         let tool_string = tools
             .iter()
@@ -45,15 +83,18 @@ impl ConversationalAgent {
             .collect::<Vec<_>>()
             .join(", ");
 
-        let sufix_prompt = template_jinja2!(suffix, "tools", "format_instructions");
+        let format_instructions = template_jinja2!(format_instructions, "tool_names")
+            .format(prompt_args! {"tool_names" => tool_names})?;
 
-        let input_variables_fstring = prompt_args! {
-            "tools" => tool_string,
-            "format_instructions" => FORMAT_INSTRUCTIONS,
-            "tool_names"=>tool_names
+        // The suffix is rendered again with the input, so `{{input}}` is kept and the tools and
+        // instructions are inserted as raw text for their braces not to be rendered.
+        let sufix_prompt = template_jinja2!(suffix, "tools", "format_instructions");
+        let input_variables = prompt_args! {
+            "tools" => raw_jinja2(&tool_string),
+            "format_instructions" => raw_jinja2(&format_instructions),
+            "input" => "{{input}}",
         };
-
-        let sufix_prompt = sufix_prompt.format(input_variables_fstring)?;
+        let sufix_prompt = sufix_prompt.format(input_variables)?;
         let formatter = message_formatter![
             MessageOrTemplate::Message(Message::new_system_message(prefix)),
             MessageOrTemplate::MessagesPlaceholder("chat_history".to_string()),
@@ -74,11 +115,35 @@ impl ConversationalAgent {
         intermediate_steps: &[(AgentAction, String)],
     ) -> Result<Vec<Message>, AgentError> {
         let mut thoughts: Vec<Message> = Vec::new();
-        for (action, observation) in intermediate_steps.iter() {
-            thoughts.push(Message::new_ai_message(&action.log));
-            let tool_response = template_jinja2!(TEMPLATE_TOOL_RESPONSE, "observation")
-                .format(prompt_args!("observation"=>observation))?;
-            thoughts.push(Message::new_human_message(&tool_response));
+        for (i, (action, observation)) in intermediate_steps.iter().enumerate() {
+            match self.scratchpad_format {
+                ScratchpadFormat::Text => {
+                    thoughts.push(Message::new_ai_message(&action.log));
+                    let tool_response = template_jinja2!(TEMPLATE_TOOL_RESPONSE, "observation")
+                        .format(prompt_args!("observation"=>observation))?;
+                    thoughts.push(Message::new_human_message(&tool_response));
+                }
+                ScratchpadFormat::ToolCalls => {
+                    // Tool call arguments must be a JSON object, a plain input is wrapped
+                    // as `{"input": ...}`.
+                    let arguments = match serde_json::from_str::<Value>(&action.tool_input) {
+                        Ok(Value::Object(_)) => action.tool_input.clone(),
+                        _ => json!({ "input": action.tool_input }).to_string(),
+                    };
+                    let tool_call = FunctionCallResponse {
+                        id: format!("call_{}", i),
+                        type_field: "function".into(),
+                        function: FunctionDetail {
+                            name: action.tool.clone(),
+                            arguments,
+                        },
+                    };
+                    thoughts.push(Message::new_ai_tool_calls_message(std::slice::from_ref(
+                        &tool_call,
+                    )));
+                    thoughts.push(Message::new_tool_result(&tool_call, observation));
+                }
+            }
         }
         Ok(thoughts)
     }
@@ -90,18 +155,24 @@ impl Agent for ConversationalAgent {
         &self,
         intermediate_steps: &[(AgentAction, String)],
         inputs: PromptArgs,
-    ) -> Result<AgentEvent, AgentError> {
-        let scratchpad = self.construct_scratchpad(intermediate_steps)?;
+    ) -> Result<(AgentEvent, Option<TokenUsage>), AgentError> {
+        let mut scratchpad = self.construct_scratchpad(intermediate_steps)?;
+        scratchpad.extend(ultimatum_message(&inputs));
         let mut inputs = inputs.clone();
         inputs.insert("agent_scratchpad".to_string(), json!(scratchpad));
-        let output = self.chain.call(inputs.clone()).await?.generation;
-        let parsed_output = self.output_parser.parse(&output)?;
-        Ok(parsed_output)
+        let output = self.chain.call(inputs.clone()).await?;
+        log::debug!("Agent output: {}", output.generation);
+        let parsed_output = self.output_parser.parse(&output.generation)?;
+        Ok((parsed_output, output.tokens))
     }
 
     fn get_tools(&self) -> Vec<Arc<dyn Tool>> {
         self.tools.clone()
     }
+
+    fn get_input_keys(&self) -> Vec<String> {
+        self.chain.get_required_input_keys()
+    }
 }
 
 #[cfg(test)]
@@ -109,17 +180,27 @@ mod tests {
     use std::{error::Error, sync::Arc};
 
     use async_trait::async_trait;
-    use serde_json::Value;
+    use serde_json::{json, Value};
 
     use crate::{
-        agent::{chat::builder::ConversationalAgentBuilder, executor::AgentExecutor},
+        agent::{
+            chat::{
+                builder::ConversationalAgentBuilder,
+                prompt::{PREFIX, SUFFIX},
+            },
+            executor::AgentExecutor,
+        },
         chain::chain_trait::Chain,
         llm::openai::{OpenAI, OpenAIModel},
         memory::SimpleMemory,
+        prompt::MessageFormatter,
         prompt_args,
+        schemas::{agent::AgentAction, MessageType},
         tools::Tool,
     };
 
+    use super::{ConversationalAgent, ScratchpadFormat};
+
     struct Calc {}
 
     #[async_trait]
@@ -135,6 +216,120 @@ mod tests {
         }
     }
 
+    struct Template {}
+
+    #[async_trait]
+    impl Tool for Template {
+        fn name(&self) -> String {
+            "Template".to_string()
+        }
+        fn description(&self) -> String {
+            "Renders templates like {{ name }} or {% if x %}".to_string()
+        }
+        async fn run(&self, _input: Value) -> Result<String, Box<dyn Error>> {
+            Ok(String::new())
+        }
+    }
+
+    #[test]
+    fn test_create_prompt() {
+        let tools: [Arc<dyn Tool>; 2] = [Arc::new(Calc {}), Arc::new(Template {})];
+        let prompt = ConversationalAgent::create_prompt(&tools, SUFFIX, PREFIX).unwrap();
+        let messages = prompt
+            .format_messages(prompt_args! {
+                "input" => "What is {{ 2 + 2 }}?",
+                "chat_history" => json!([]),
+                "agent_scratchpad" => json!([]),
+            })
+            .unwrap();
+        let content = &messages[1].content;
+        assert!(content.contains("> Template: Renders templates like {{ name }} or {% if x %}"));
+        assert!(content.contains("Must be one of Calculator, Template"));
+        assert!(content.ends_with("What is {{ 2 + 2 }}?"));
+    }
+
+    #[test]
+    fn test_create_prompt_with_format_instructions() {
+        let tools: [Arc<dyn Tool>; 1] = [Arc::new(Calc {})];
+        let prompt = ConversationalAgent::create_prompt_with_format_instructions(
+            &tools,
+            "Herramientas:\n{{tools}}\n{{format_instructions}}\n{{input}}",
+            "Eres un asistente",
+            "Responde con una de {{tool_names}}",
+        )
+        .unwrap();
+        let messages = prompt
+            .format_messages(prompt_args! {
+                "input" => "Hola",
+                "chat_history" => json!([]),
+                "agent_scratchpad" => json!([]),
+            })
+            .unwrap();
+
+        assert_eq!(messages[0].content, "Eres un asistente");
+        assert_eq!(
+            messages[1].content,
+            "Herramientas:\n> Calculator: Usefull to make calculations\n\
+             Responde con una de Calculator\nHola"
+        );
+    }
+
+    #[test]
+    fn test_construct_scratchpad() {
+        let steps = vec![(
+            AgentAction {
+                tool: "Calculator".into(),
+                tool_input: "2 + 2".into(),
+                log: "{\"action\": \"Calculator\", \"action_input\": \"2 + 2\"}".into(),
+            },
+            "4".to_string(),
+        )];
+        let build = |format| {
+            ConversationalAgentBuilder::new()
+                .tools(&[Arc::new(Calc {})])
+                .scratchpad_format(format)
+                .build(OpenAI::default())
+                .unwrap()
+        };
+
+        let text = build(ScratchpadFormat::Text)
+            .construct_scratchpad(&steps)
+            .unwrap();
+        assert_eq!(text[0].content, steps[0].0.log);
+        assert_eq!(text[1].message_type, MessageType::HumanMessage);
+        assert!(text[1].content.contains('4'));
+
+        let tool_calls = build(ScratchpadFormat::ToolCalls)
+            .construct_scratchpad(&steps)
+            .unwrap();
+        let calls = tool_calls[0].tool_calls.as_ref().unwrap();
+        assert_eq!(calls[0]["function"]["name"], "Calculator");
+        assert_eq!(
+            calls[0]["function"]["arguments"],
+            json!({ "input": "2 + 2" }).to_string()
+        );
+        assert_eq!(tool_calls[1].message_type, MessageType::ToolMessage);
+        assert_eq!(tool_calls[1].tool_call_id(), calls[0]["id"].as_str());
+        assert_eq!(tool_calls[1].content, "4");
+
+        let object_input = vec![(
+            AgentAction {
+                tool: "Calculator".into(),
+                tool_input: "{\"expression\":\"2 + 2\"}".into(),
+                log: "".into(),
+            },
+            "4".to_string(),
+        )];
+        let tool_calls = build(ScratchpadFormat::ToolCalls)
+            .construct_scratchpad(&object_input)
+            .unwrap();
+        let calls = tool_calls[0].tool_calls.as_ref().unwrap();
+        assert_eq!(
+            calls[0]["function"]["arguments"],
+            object_input[0].0.tool_input
+        );
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_invoke_agent() {
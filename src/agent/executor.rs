@@ -1,31 +1,53 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, pin::Pin, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
-use serde_json::json;
+use futures::{stream, Stream, StreamExt};
+use serde_json::{json, Value};
 use tokio::sync::Mutex;
 
-use super::{agent::Agent, AgentError};
-use crate::schemas::{LogTools, Message};
+use super::{
+    agent::{Agent, ULTIMATUM_INPUT_KEY},
+    AgentError, ApprovalDecision, ToolApproval, TruncationStrategy,
+};
+use crate::schemas::{FunctionCallResponse, ImageContent, LogTools, Message};
 use crate::{
+    callbacks::{CallbackHandler, RunInfo},
     chain::{chain_trait::Chain, ChainError},
-    language_models::GenerateResult,
+    language_models::{GenerateResult, TokenUsage},
     memory::SimpleMemory,
-    prompt::PromptArgs,
+    prompt::{images_from_args, PromptArgs},
     schemas::{
-        agent::{AgentAction, AgentEvent},
+        agent::{AgentAction, AgentEvent, AgentPlan},
         memory::BaseMemory,
+        StreamData,
     },
     tools::Tool,
 };
 
+//Keys the executor fills on its own before planning
+const AGENT_INTERNAL_INPUT_KEYS: [&str; 2] = ["chat_history", "agent_scratchpad"];
+const MAX_ITERATIONS_MESSAGE: &str = "Max iterations reached";
+const DENIED_ACTION_MESSAGE: &str = "The user denied this action";
+const DEFAULT_FORCE_FINAL_ANSWER_MESSAGE: &str = "You have run out of steps, don't use any more \
+tools. Give your best final answer with the information you already have.";
+const DEFAULT_MAX_OBSERVATION_LENGTH: usize = 10_000;
+
 pub struct AgentExecutor<A>
 where
     A: Agent,
 {
-    agent: A,
+    agent: Arc<A>,
     max_iterations: Option<i32>,
     break_if_error: bool,
+    stream_status: bool,
+    tool_timeout: Option<Duration>,
+    max_tool_concurrency: Option<usize>,
     pub memory: Option<Arc<Mutex<dyn BaseMemory>>>,
+    callbacks: Vec<Arc<dyn CallbackHandler>>,
+    approval: Option<Arc<dyn ToolApproval>>,
+    force_final_answer_message: String,
+    max_observation_length: Option<usize>,
+    truncation_strategy: TruncationStrategy,
 }
 
 impl<A> AgentExecutor<A>
@@ -34,13 +56,23 @@ where
 {
     pub fn from_agent(agent: A) -> Self {
         Self {
-            agent,
+            agent: Arc::new(agent),
             max_iterations: Some(10),
             break_if_error: false,
+            stream_status: false,
+            tool_timeout: None,
+            max_tool_concurrency: None,
             memory: None,
+            callbacks: Vec::new(),
+            approval: None,
+            force_final_answer_message: DEFAULT_FORCE_FINAL_ANSWER_MESSAGE.to_string(),
+            max_observation_length: Some(DEFAULT_MAX_OBSERVATION_LENGTH),
+            truncation_strategy: TruncationStrategy::default(),
         }
     }
 
+    /// Maximum number of tool executions. Once it's reached the agent is asked one last
+    /// time for an answer, see `with_force_final_answer_message`.
     pub fn with_max_iterations(mut self, max_iterations: i32) -> Self {
         self.max_iterations = Some(max_iterations);
         self
@@ -56,6 +88,107 @@ where
         self
     }
 
+    /// Maximum time a tool can take. A tool that times out is handled like a tool
+    /// that failed: the error is sent back to the agent as the observation, or the
+    /// execution stops if `break_if_error` is set.
+    pub fn with_tool_timeout(mut self, tool_timeout: Duration) -> Self {
+        self.tool_timeout = Some(tool_timeout);
+        self
+    }
+
+    /// Maximum number of tools executed at the same time when the agent plans several
+    /// actions in one step. By default all the actions of a step run concurrently, use
+    /// `1` to execute them one after the other.
+    pub fn with_max_tool_concurrency(mut self, max_tool_concurrency: usize) -> Self {
+        self.max_tool_concurrency = Some(max_tool_concurrency.max(1));
+        self
+    }
+
+    /// When streaming, send a status `StreamData` for each tool executed before the
+    /// final answer. The status has an empty `content`, the tool, its input and the
+    /// observation are in `value`.
+    pub fn with_stream_status(mut self, stream_status: bool) -> Self {
+        self.stream_status = stream_status;
+        self
+    }
+
+    /// Registers the handlers notified of the chain, agent and tool events of the executor.
+    /// To observe the LLM calls of the agent, register the handlers on the LLM options too.
+    pub fn with_callbacks(mut self, callbacks: Vec<Arc<dyn CallbackHandler>>) -> Self {
+        self.callbacks = callbacks;
+        self
+    }
+
+    /// Asks for the approval of every action before its tool is executed. A denied action
+    /// is not executed, the agent gets a denial as the observation and can plan something
+    /// else. Denied actions count as steps for the maximum number of iterations.
+    ///
+    /// The approvals of the actions of a step are asked one after the other, before any
+    /// of them runs.
+    pub fn with_approval<T: ToolApproval + 'static>(mut self, approval: T) -> Self {
+        self.approval = Some(Arc::new(approval));
+        self
+    }
+
+    /// Message sent to the agent when the maximum number of iterations is reached, asking
+    /// for a final answer without using more tools. If the agent still plans actions the
+    /// result is "Max iterations reached".
+    ///
+    /// The message is in the `ultimatum` input variable, the agents of the crate add it at
+    /// the end of their scratchpad.
+    pub fn with_force_final_answer_message<S: Into<String>>(mut self, message: S) -> Self {
+        self.force_final_answer_message = message.into();
+        self
+    }
+
+    /// Maximum number of characters of a tool result sent to the agent and saved in the
+    /// memory, 10000 by default. Longer results are cut with `with_truncation_strategy`,
+    /// the steps returned by `call_with_steps` keep the full results. `None` disables
+    /// the truncation.
+    pub fn with_max_observation_length(mut self, max_observation_length: Option<usize>) -> Self {
+        self.max_observation_length = max_observation_length;
+        self
+    }
+
+    /// Part of the long tool results kept, the start and the end by default. The removed
+    /// part is replaced by a marker telling the agent how many characters are missing.
+    pub fn with_truncation_strategy(mut self, truncation_strategy: TruncationStrategy) -> Self {
+        self.truncation_strategy = truncation_strategy;
+        self
+    }
+
+    /// The steps as seen by the agent, with the long observations truncated.
+    fn agent_steps(&self, steps: &[(AgentAction, String)]) -> Vec<(AgentAction, String)> {
+        steps
+            .iter()
+            .map(|(action, observation)| {
+                let observation = match self.max_observation_length {
+                    Some(max_length) => self.truncation_strategy.truncate(observation, max_length),
+                    None => observation.clone(),
+                };
+                (action.clone(), observation)
+            })
+            .collect()
+    }
+
+    /// A copy of the executor sharing its agent, owned by the stream of `stream`.
+    fn shared(&self) -> Self {
+        Self {
+            agent: self.agent.clone(),
+            max_iterations: self.max_iterations,
+            break_if_error: self.break_if_error,
+            stream_status: self.stream_status,
+            tool_timeout: self.tool_timeout,
+            max_tool_concurrency: self.max_tool_concurrency,
+            memory: self.memory.clone(),
+            callbacks: self.callbacks.clone(),
+            approval: self.approval.clone(),
+            force_final_answer_message: self.force_final_answer_message.clone(),
+            max_observation_length: self.max_observation_length,
+            truncation_strategy: self.truncation_strategy,
+        }
+    }
+
     fn get_name_to_tools(&self) -> HashMap<String, Arc<dyn Tool>> {
         let mut name_to_tool = HashMap::new();
         for tool in self.agent.get_tools().iter() {
@@ -69,13 +202,191 @@ where
 #[async_trait]
 impl<A> Chain for AgentExecutor<A>
 where
-    A: Agent + Send + Sync,
+    A: Agent + Send + Sync + 'static,
 {
     async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
-        let mut input_variables = input_variables.clone();
-        let name_to_tools = self.get_name_to_tools();
-        let mut steps: Vec<(AgentAction, String)> = Vec::new();
-        log::debug!("steps: {:?}", steps);
+        self.call_with_steps(input_variables)
+            .await
+            .map(|(result, _)| result)
+    }
+
+    async fn invoke(&self, input_variables: PromptArgs) -> Result<String, ChainError> {
+        let result = self.call(input_variables).await?;
+        Ok(result.generation)
+    }
+
+    /// Runs the tools like `call` does, but the final answer of the agent is streamed.
+    /// If the agent does not support streaming the final answer is sent as a single chunk.
+    /// The steps run as the stream is consumed, with `with_stream_status` the status of
+    /// the tools of a step is sent as soon as the step is done.
+    ///
+    /// The memory is updated once the stream has been consumed. If the stream is dropped
+    /// before its end, the memory is not updated and the run is reported to the callbacks
+    /// as an error.
+    async fn stream(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
+    {
+        let run = RunInfo::new();
+        for handler in self.callbacks.iter() {
+            handler
+                .on_chain_start(&run, "AgentExecutor", &input_variables)
+                .await;
+        }
+
+        let prepared = async {
+            let input_variables = self.prepare_input_variables(input_variables).await?;
+            let images = images_from_args(&input_variables)?;
+            Ok::<_, ChainError>((input_variables, images))
+        }
+        .await;
+        let (input_variables, images) = match prepared {
+            Ok(prepared) => prepared,
+            Err(err) => {
+                for handler in self.callbacks.iter() {
+                    handler.on_chain_error(&run, &err).await;
+                }
+                return Err(err);
+            }
+        };
+
+        let executor = self.shared();
+        let mut run = StreamRun::new(run, self.callbacks.clone());
+        let output_stream = async_stream::stream! {
+            let name_to_tools = executor.get_name_to_tools();
+            let mut steps: Vec<(AgentAction, String)> = Vec::new();
+            let mut tokens = None;
+            let plan = loop {
+                let done = steps.len();
+                let step = executor.agent_step(&input_variables, &name_to_tools, &mut steps, &mut tokens, true);
+                match run.info.scope(step).await {
+                    Ok(AgentLoopStep::Actions) => {
+                        if executor.stream_status {
+                            for (action, observation) in &steps[done..] {
+                                yield Ok(status_stream_data(action, observation));
+                            }
+                        }
+                    }
+                    Ok(AgentLoopStep::Answer(plan)) => break plan,
+                    Err(err) => {
+                        run.error(&err).await;
+                        yield Err(err);
+                        return;
+                    }
+                }
+            };
+
+            // The memory is not updated when the maximum number of iterations is reached
+            let memory = match plan {
+                Some(_) => executor.memory.clone(),
+                None => None,
+            };
+            let mut final_stream: Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>> =
+                match plan {
+                    Some(AgentPlan::Stream(final_stream)) => final_stream,
+                    Some(AgentPlan::Text(AgentEvent::Finish(finish))) => {
+                        Box::pin(stream::once(async move {
+                            Ok(StreamData::new(json!(finish), None, finish.output.clone()))
+                        }))
+                    }
+                    Some(AgentPlan::Text(AgentEvent::Action(_))) => {
+                        let err = ChainError::AgentError(
+                            "The agent returned an unexpected plan".to_string(),
+                        );
+                        run.error(&err).await;
+                        yield Err(err);
+                        return;
+                    }
+                    None => Box::pin(stream::once(async {
+                        Ok(StreamData::new(Value::Null, None, MAX_ITERATIONS_MESSAGE))
+                    })),
+                };
+
+            let mut complete_output = String::new();
+            let mut failed = false;
+            // The usage of the final answer is the one of the last chunk that has one
+            let mut answer_tokens = None;
+            while let Some(result) = final_stream.next().await {
+                match &result {
+                    Ok(data) => {
+                        complete_output.push_str(&data.content);
+                        if data.tokens.is_some() {
+                            answer_tokens = data.tokens.clone();
+                        }
+                    }
+                    Err(_) => failed = true,
+                }
+                yield result;
+            }
+            add_usage(&mut tokens, answer_tokens.as_ref());
+
+            if let (Some(memory), false) = (memory, failed) {
+                let input = input_variables.get("input").cloned().unwrap_or_default();
+                let memory_steps = executor.agent_steps(&steps);
+                if let Err(e) = save_agent_memory(&memory, &input, images, &memory_steps, &complete_output).await {
+                    yield Err(e);
+                }
+            }
+
+            let result = GenerateResult {
+                generation: complete_output,
+                tokens,
+                ..Default::default()
+            };
+            run.end(&result).await;
+        };
+
+        Ok(Box::pin(output_stream))
+    }
+
+    fn get_input_keys(&self) -> Vec<String> {
+        self.get_required_input_keys()
+    }
+
+    fn get_required_input_keys(&self) -> Vec<String> {
+        self.agent
+            .get_input_keys()
+            .into_iter()
+            .filter(|key| !AGENT_INTERNAL_INPUT_KEYS.contains(&key.as_str()))
+            .collect()
+    }
+}
+
+impl<A> AgentExecutor<A>
+where
+    A: Agent + Send + Sync + 'static,
+{
+    /// Same as `call`, also returning the actions the agent took with their observations,
+    /// in the order they were planned.
+    pub async fn call_with_steps(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<(GenerateResult, Vec<(AgentAction, String)>), ChainError> {
+        let run = RunInfo::new();
+        for handler in self.callbacks.iter() {
+            handler
+                .on_chain_start(&run, "AgentExecutor", &input_variables)
+                .await;
+        }
+
+        let result = run.scope(self.execute_agent(input_variables)).await;
+
+        for handler in self.callbacks.iter() {
+            match &result {
+                Ok((generate_result, _)) => handler.on_chain_end(&run, generate_result).await,
+                Err(err) => handler.on_chain_error(&run, err).await,
+            }
+        }
+        result
+    }
+
+    async fn prepare_input_variables(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<PromptArgs, ChainError> {
+        self.validate_input_variables(&input_variables)?;
+        let mut input_variables = input_variables;
         if let Some(memory) = &self.memory {
             let memory = memory.lock().await;
             input_variables.insert("chat_history".to_string(), json!(memory.messages()));
@@ -85,90 +396,811 @@ where
                 json!(SimpleMemory::new().messages()),
             );
         }
+        Ok(input_variables)
+    }
 
+    /// Plans and executes the tools until the agent gives an answer, which is returned
+    /// as an `AgentPlan`. Returns `None` if the maximum number of iterations is reached.
+    /// The token usage of the planning steps is added to `usage`.
+    async fn run_agent_loop(
+        &self,
+        input_variables: &PromptArgs,
+        steps: &mut Vec<(AgentAction, String)>,
+        usage: &mut Option<TokenUsage>,
+        stream: bool,
+    ) -> Result<Option<AgentPlan>, ChainError> {
+        let name_to_tools = self.get_name_to_tools();
         loop {
-            let agent_event = self
-                .agent
+            if let AgentLoopStep::Answer(plan) = self
+                .agent_step(input_variables, &name_to_tools, steps, usage, stream)
+                .await?
+            {
+                return Ok(plan);
+            }
+        }
+    }
+
+    /// A single step of the agent loop: asks the agent for a plan and executes the tools
+    /// of its actions, adding them to `steps`. Once the maximum number of iterations is
+    /// reached, the agent is asked for its final answer instead.
+    async fn agent_step(
+        &self,
+        input_variables: &PromptArgs,
+        name_to_tools: &HashMap<String, Arc<dyn Tool>>,
+        steps: &mut Vec<(AgentAction, String)>,
+        usage: &mut Option<TokenUsage>,
+        stream: bool,
+    ) -> Result<AgentLoopStep, ChainError> {
+        if let Some(max_iterations) = self.max_iterations {
+            if !steps.is_empty() && steps.len() >= max_iterations as usize {
+                return self
+                    .force_final_answer(input_variables, steps, usage, stream)
+                    .await
+                    .map(AgentLoopStep::Answer);
+            }
+        }
+
+        let plan = self
+            .plan_step(input_variables, steps, usage, stream)
+            .await?;
+        let actions = match plan {
+            AgentPlan::Text(AgentEvent::Action(actions)) => actions,
+            plan => return Ok(AgentLoopStep::Answer(Some(plan))),
+        };
+
+        let mut planned = Vec::with_capacity(actions.len());
+        for action in actions {
+            log::debug!("Action: {:?}", action.tool_input);
+            let tool = name_to_tools
+                .get(&action.tool)
+                .ok_or_else(|| AgentError::ToolError(format!("Tool {} not found", action.tool)))
+                .map_err(|e| ChainError::AgentError(e.to_string()))?;
+            planned.push((tool.clone(), action));
+        }
+
+        let mut denials = Vec::with_capacity(planned.len());
+        for (_, action) in planned.iter() {
+            denials.push(self.denial(action).await);
+        }
+
+        // Observations are collected in the order the agent requested the actions,
+        // so the scratchpad is the same whatever tool finishes first.
+        // At most `max_tool_concurrency` tools run at a time, the next one starts as
+        // soon as one finishes. The runs are collected first, futures do nothing until
+        // polled, so no closure is kept in the stream.
+        let max_concurrency = self.max_tool_concurrency.unwrap_or(planned.len()).max(1);
+        let runs: Vec<_> = planned
+            .iter()
+            .zip(&denials)
+            .map(|((tool, action), denial)| self.run_tool(tool, action, denial))
+            .collect();
+        let observations: Vec<Result<String, ChainError>> =
+            stream::iter(runs).buffered(max_concurrency).collect().await;
+        for ((_, action), observation) in planned.iter().zip(observations) {
+            steps.push((action.clone(), observation?));
+        }
+        Ok(AgentLoopStep::Actions)
+    }
+
+    async fn plan_step(
+        &self,
+        input_variables: &PromptArgs,
+        steps: &[(AgentAction, String)],
+        usage: &mut Option<TokenUsage>,
+        stream: bool,
+    ) -> Result<AgentPlan, ChainError> {
+        let steps = self.agent_steps(steps);
+        let (plan, plan_usage) = if stream {
+            self.agent
+                .stream_plan(&steps, input_variables.clone())
+                .await
+        } else {
+            self.agent
                 .plan(&steps, input_variables.clone())
                 .await
-                .map_err(|e| ChainError::AgentError(format!("Error in agent planning: {}", e)))?;
-            match agent_event {
-                AgentEvent::Action(actions) => {
-                    for action in actions {
-                        log::debug!("Action: {:?}", action.tool_input);
-                        let tool = name_to_tools
-                            .get(&action.tool)
-                            .ok_or_else(|| {
-                                AgentError::ToolError(format!("Tool {} not found", action.tool))
-                            })
-                            .map_err(|e| ChainError::AgentError(e.to_string()))?;
-
-                        let observation_result = tool.call(&action.tool_input).await;
-
-                        let observation = match observation_result {
-                            Ok(result) => result,
-                            Err(err) => {
-                                log::info!(
-                                    "The tool return the following error: {}",
-                                    err.to_string()
-                                );
-                                if self.break_if_error {
-                                    return Err(ChainError::AgentError(
-                                        AgentError::ToolError(err.to_string()).to_string(),
-                                    ));
-                                } else {
-                                    format!("The tool return the following error: {}", err)
-                                }
-                            }
-                        };
+                .map(|(event, usage)| (AgentPlan::Text(event), usage))
+        }
+        .map_err(|e| ChainError::AgentError(format!("Error in agent planning: {}", e)))?;
+        add_usage(usage, plan_usage.as_ref());
+        Ok(plan)
+    }
 
-                        steps.push((action, observation));
-                    }
-                }
-                AgentEvent::Finish(finish) => {
-                    if let Some(memory) = &self.memory {
-                        let mut memory = memory.lock().await;
-
-                        memory.add_user_message(match &input_variables["input"] {
-                            // This avoids adding extra quotes to the user input in the history.
-                            serde_json::Value::String(s) => s,
-                            x => x, // this the json encoded value.
-                        });
-
-                        let mut tools_ai_message_seen: HashMap<String, ()> = HashMap::default();
-                        for (action, observation) in steps {
-                            let LogTools { tool_id, tools } = serde_json::from_str(&action.log)?;
-                            let tools_value: serde_json::Value = serde_json::from_str(&tools)?;
-                            if tools_ai_message_seen.insert(tools, ()).is_none() {
-                                memory.add_message(
-                                    Message::new_ai_message("").with_tool_calls(tools_value),
-                                );
-                            }
-                            memory.add_message(Message::new_tool_message(observation, tool_id));
-                        }
+    /// Asks the agent for an answer with the force final answer message, once the maximum
+    /// number of iterations is reached. Returns `None` if the agent still plans actions.
+    async fn force_final_answer(
+        &self,
+        input_variables: &PromptArgs,
+        steps: &[(AgentAction, String)],
+        usage: &mut Option<TokenUsage>,
+        stream: bool,
+    ) -> Result<Option<AgentPlan>, ChainError> {
+        let mut input_variables = input_variables.clone();
+        input_variables.insert(
+            ULTIMATUM_INPUT_KEY.to_string(),
+            json!(self.force_final_answer_message),
+        );
+        match self
+            .plan_step(&input_variables, steps, usage, stream)
+            .await?
+        {
+            AgentPlan::Text(AgentEvent::Action(_)) => {
+                log::warn!("The agent planned more actions after the maximum number of iterations");
+                Ok(None)
+            }
+            plan => Ok(Some(plan)),
+        }
+    }
 
-                        memory.add_ai_message(&finish.output);
-                    }
-                    return Ok(GenerateResult {
-                        generation: finish.output,
-                        ..Default::default()
-                    });
+    /// The observation sent to the agent if the action is denied, `None` if it's approved
+    /// or there is no approval hook.
+    async fn denial(&self, action: &AgentAction) -> Option<String> {
+        let approval = self.approval.as_ref()?;
+        match approval.approve(action).await {
+            ApprovalDecision::Approve => None,
+            ApprovalDecision::Deny => Some(DENIED_ACTION_MESSAGE.to_string()),
+            ApprovalDecision::DenyWithReason(reason) => {
+                Some(format!("{}: {}", DENIED_ACTION_MESSAGE, reason))
+            }
+        }
+    }
+
+    /// Runs a single tool of the plan, returning the observation for the agent. Tool
+    /// errors become the observation unless `break_if_error` is set. A denied action is
+    /// not run, the denial is the observation.
+    async fn run_tool(
+        &self,
+        tool: &Arc<dyn Tool>,
+        action: &AgentAction,
+        denial: &Option<String>,
+    ) -> Result<String, ChainError> {
+        let tool_run = RunInfo::new();
+        for handler in self.callbacks.iter() {
+            handler.on_agent_action(&tool_run, action).await;
+        }
+        if let Some(denial) = denial {
+            log::info!("The action of the tool {} was denied", action.tool);
+            return Ok(denial.clone());
+        }
+        for handler in self.callbacks.iter() {
+            handler
+                .on_tool_start(&tool_run, &action.tool, &action.tool_input)
+                .await;
+        }
+
+        // The error is turned into a String right away, `Box<dyn Error>` is not
+        // `Send` and can't be held while the callbacks are awaited.
+        let tool_call = tool_run.scope(tool.call(&action.tool_input));
+        let observation_result = match self.tool_timeout {
+            Some(tool_timeout) => match tokio::time::timeout(tool_timeout, tool_call).await {
+                Ok(result) => result.map_err(|err| err.to_string()),
+                Err(_) => Err(format!(
+                    "Tool {} timed out after {:?}",
+                    action.tool, tool_timeout
+                )),
+            },
+            None => tool_call.await.map_err(|err| err.to_string()),
+        };
+
+        match observation_result {
+            Ok(result) => {
+                for handler in self.callbacks.iter() {
+                    handler.on_tool_end(&tool_run, &result).await;
                 }
+                Ok(result)
             }
+            Err(err) => {
+                log::info!("The tool return the following error: {}", err);
+                for handler in self.callbacks.iter() {
+                    handler.on_tool_error(&tool_run, &err).await;
+                }
+                if self.break_if_error {
+                    Err(ChainError::AgentError(
+                        AgentError::ToolError(err).to_string(),
+                    ))
+                } else {
+                    Ok(format!("The tool return the following error: {}", err))
+                }
+            }
+        }
+    }
+
+    async fn execute_agent(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<(GenerateResult, Vec<(AgentAction, String)>), ChainError> {
+        let input_variables = self.prepare_input_variables(input_variables).await?;
+        let mut steps: Vec<(AgentAction, String)> = Vec::new();
+        let mut tokens = None;
 
-            if let Some(max_iterations) = self.max_iterations {
-                if steps.len() >= max_iterations as usize {
-                    return Ok(GenerateResult {
-                        generation: "Max iterations reached".to_string(),
-                        ..Default::default()
-                    });
+        match self
+            .run_agent_loop(&input_variables, &mut steps, &mut tokens, false)
+            .await?
+        {
+            Some(AgentPlan::Text(AgentEvent::Finish(finish))) => {
+                if let Some(memory) = &self.memory {
+                    save_agent_memory(
+                        memory,
+                        &input_variables["input"],
+                        images_from_args(&input_variables)?,
+                        &self.agent_steps(&steps),
+                        &finish.output,
+                    )
+                    .await?;
                 }
+                let result = GenerateResult {
+                    generation: finish.output,
+                    tokens,
+                    ..Default::default()
+                };
+                Ok((result, steps))
+            }
+            Some(_) => Err(ChainError::AgentError(
+                "The agent returned an unexpected plan".to_string(),
+            )),
+            None => {
+                let result = GenerateResult {
+                    generation: MAX_ITERATIONS_MESSAGE.to_string(),
+                    tokens,
+                    ..Default::default()
+                };
+                Ok((result, steps))
             }
         }
     }
+}
 
-    async fn invoke(&self, input_variables: PromptArgs) -> Result<String, ChainError> {
-        let result = self.call(input_variables).await?;
-        Ok(result.generation)
+/// How a step of the agent loop ended.
+enum AgentLoopStep {
+    /// The tools of the planned actions were executed.
+    Actions,
+    /// The answer of the agent, `None` if the maximum number of iterations is reached.
+    Answer(Option<AgentPlan>),
+}
+
+/// The run of a stream, reported to the callbacks when the stream ends. If the stream is
+/// dropped before, the run is reported as an error by a spawned task.
+struct StreamRun {
+    info: RunInfo,
+    callbacks: Vec<Arc<dyn CallbackHandler>>,
+    finished: bool,
+}
+
+impl StreamRun {
+    fn new(info: RunInfo, callbacks: Vec<Arc<dyn CallbackHandler>>) -> Self {
+        Self {
+            info,
+            callbacks,
+            finished: false,
+        }
+    }
+
+    async fn end(&mut self, result: &GenerateResult) {
+        self.finished = true;
+        for handler in self.callbacks.iter() {
+            handler.on_chain_end(&self.info, result).await;
+        }
+    }
+
+    async fn error(&mut self, err: &ChainError) {
+        self.finished = true;
+        for handler in self.callbacks.iter() {
+            handler.on_chain_error(&self.info, err).await;
+        }
+    }
+}
+
+impl Drop for StreamRun {
+    fn drop(&mut self) {
+        if self.finished || self.callbacks.is_empty() {
+            return;
+        }
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                let info = self.info.clone();
+                let callbacks = std::mem::take(&mut self.callbacks);
+                handle.spawn(async move {
+                    let err = ChainError::OtherError(
+                        "The stream was dropped before the end of the run".to_string(),
+                    );
+                    for handler in callbacks.iter() {
+                        handler.on_chain_error(&info, &err).await;
+                    }
+                });
+            }
+            Err(_) => log::warn!("The end of the run of a dropped stream could not be reported"),
+        }
+    }
+}
+
+/// Adds the usage of a LLM call to the usage of the run, calls without usage are skipped.
+fn add_usage(total: &mut Option<TokenUsage>, usage: Option<&TokenUsage>) {
+    match (total.as_mut(), usage) {
+        (Some(total), Some(usage)) => total.add(usage),
+        (None, Some(usage)) => *total = Some(usage.clone()),
+        (_, None) => {}
+    }
+}
+
+/// Builds the status `StreamData` sent for each tool execution when streaming.
+fn status_stream_data(action: &AgentAction, observation: &str) -> StreamData {
+    StreamData::new(
+        json!({
+            "type": "status",
+            "tool": action.tool,
+            "tool_input": action.tool_input,
+            "observation": observation,
+        }),
+        None,
+        "",
+    )
+}
+
+async fn save_agent_memory(
+    memory: &Arc<Mutex<dyn BaseMemory>>,
+    input: &Value,
+    images: Vec<ImageContent>,
+    steps: &[(AgentAction, String)],
+    output: &str,
+) -> Result<(), ChainError> {
+    let mut memory = memory.lock().await;
+
+    let input = match input {
+        // This avoids adding extra quotes to the user input in the history.
+        Value::String(s) => s.clone(),
+        x => x.to_string(), // this the json encoded value.
+    };
+    memory.add_message(Message::new_human_message(input).with_images(images));
+
+    let mut tools_ai_message_seen: HashMap<String, ()> = HashMap::default();
+    for (action, observation) in steps {
+        let LogTools { tool_id, tools } = serde_json::from_str(&action.log)?;
+        let tool_calls: Vec<FunctionCallResponse> = serde_json::from_str(&tools)?;
+        if tools_ai_message_seen.insert(tools, ()).is_none() {
+            memory.add_message(Message::new_ai_tool_calls_message(&tool_calls));
+        }
+        memory.add_message(Message::new_tool_message(observation, tool_id));
+    }
+
+    memory.add_ai_message(&output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::{memory::SimpleMemory, prompt_args};
+
+    struct SlowTool {}
+
+    /// Sleeps for the milliseconds given as input and returns them.
+    struct SleepTool {}
+
+    #[async_trait]
+    impl Tool for SleepTool {
+        fn name(&self) -> String {
+            "Sleep".to_string()
+        }
+        fn description(&self) -> String {
+            "Sleeps for the given milliseconds".to_string()
+        }
+        async fn run(&self, input: Value) -> Result<String, Box<dyn std::error::Error>> {
+            let millis = input.as_str().unwrap_or_default().parse::<u64>()?;
+            tokio::time::sleep(Duration::from_millis(millis)).await;
+            Ok(millis.to_string())
+        }
+    }
+
+    /// Plans several sleeps in a single step, then answers with the observations.
+    struct ParallelAgent {}
+
+    #[async_trait]
+    impl Agent for ParallelAgent {
+        async fn plan(
+            &self,
+            intermediate_steps: &[(AgentAction, String)],
+            _inputs: PromptArgs,
+        ) -> Result<(AgentEvent, Option<TokenUsage>), AgentError> {
+            if !intermediate_steps.is_empty() {
+                let observations = intermediate_steps
+                    .iter()
+                    .map(|(_, observation)| observation.clone())
+                    .collect::<Vec<_>>();
+                let finish = AgentEvent::Finish(crate::schemas::AgentFinish {
+                    output: observations.join(","),
+                });
+                return Ok((finish, None));
+            }
+            let sleeps = ["60", "1", "30"]
+                .iter()
+                .map(|millis| AgentAction {
+                    tool: "Sleep".to_string(),
+                    tool_input: millis.to_string(),
+                    log: "".to_string(),
+                })
+                .collect();
+            Ok((AgentEvent::Action(sleeps), None))
+        }
+
+        fn get_tools(&self) -> Vec<Arc<dyn Tool>> {
+            vec![Arc::new(SleepTool {})]
+        }
+    }
+
+    #[async_trait]
+    impl Tool for SlowTool {
+        fn name(&self) -> String {
+            "Slow".to_string()
+        }
+        fn description(&self) -> String {
+            "A tool that never answers in time".to_string()
+        }
+        async fn run(&self, _input: Value) -> Result<String, Box<dyn std::error::Error>> {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok("too late".to_string())
+        }
+    }
+
+    /// Calls the slow tool once, then answers with the observation it got.
+    struct SlowToolAgent {}
+
+    #[async_trait]
+    impl Agent for SlowToolAgent {
+        async fn plan(
+            &self,
+            intermediate_steps: &[(AgentAction, String)],
+            _inputs: PromptArgs,
+        ) -> Result<(AgentEvent, Option<TokenUsage>), AgentError> {
+            let event = match intermediate_steps.last() {
+                Some((_, observation)) => AgentEvent::Finish(crate::schemas::AgentFinish {
+                    output: observation.clone(),
+                }),
+                None => AgentEvent::Action(vec![AgentAction {
+                    tool: "Slow".to_string(),
+                    tool_input: "".to_string(),
+                    log: "".to_string(),
+                }]),
+            };
+            Ok((event, None))
+        }
+
+        fn get_tools(&self) -> Vec<Arc<dyn Tool>> {
+            vec![Arc::new(SlowTool {})]
+        }
+    }
+
+    struct FinishAgent {}
+
+    #[async_trait]
+    impl Agent for FinishAgent {
+        async fn plan(
+            &self,
+            _intermediate_steps: &[(AgentAction, String)],
+            _inputs: PromptArgs,
+        ) -> Result<(AgentEvent, Option<TokenUsage>), AgentError> {
+            let finish = AgentEvent::Finish(crate::schemas::AgentFinish {
+                output: "Hello Luis".to_string(),
+            });
+            Ok((finish, None))
+        }
+
+        fn get_tools(&self) -> Vec<Arc<dyn Tool>> {
+            vec![]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_writes_memory_after_completion() {
+        let memory: Arc<Mutex<dyn BaseMemory>> = SimpleMemory::new().into();
+        let executor = AgentExecutor::from_agent(FinishAgent {}).with_memory(memory.clone());
+
+        let mut stream = executor
+            .stream(prompt_args! {"input" => "Hi, I'm Luis"})
+            .await
+            .unwrap();
+        let mut output = String::new();
+        while let Some(data) = stream.next().await {
+            output.push_str(&data.unwrap().content);
+        }
+
+        assert_eq!(output, "Hello Luis");
+        let messages = memory.lock().await.messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].content, "Hi, I'm Luis");
+        assert_eq!(messages[1].content, "Hello Luis");
+    }
+
+    /// Sleeps twice then answers, counting its plans.
+    struct CountingAgent {
+        plans: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Agent for CountingAgent {
+        async fn plan(
+            &self,
+            intermediate_steps: &[(AgentAction, String)],
+            _inputs: PromptArgs,
+        ) -> Result<(AgentEvent, Option<TokenUsage>), AgentError> {
+            self.plans.fetch_add(1, Ordering::SeqCst);
+            let event = match intermediate_steps.len() {
+                2 => AgentEvent::Finish(crate::schemas::AgentFinish {
+                    output: "done".to_string(),
+                }),
+                _ => AgentEvent::Action(vec![AgentAction {
+                    tool: "Sleep".to_string(),
+                    tool_input: "1".to_string(),
+                    log: "".to_string(),
+                }]),
+            };
+            Ok((event, None))
+        }
+
+        fn get_tools(&self) -> Vec<Arc<dyn Tool>> {
+            vec![Arc::new(SleepTool {})]
+        }
+    }
+
+    /// Records the end of the chain runs.
+    #[derive(Default)]
+    struct ChainEndRecorder {
+        events: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl CallbackHandler for ChainEndRecorder {
+        async fn on_chain_end(&self, _run: &RunInfo, result: &GenerateResult) {
+            let event = format!("end: {}", result.generation);
+            self.events.lock().unwrap().push(event);
+        }
+
+        async fn on_chain_error(&self, _run: &RunInfo, error: &ChainError) {
+            let event = format!("error: {}", error);
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_status_is_sent_as_each_step_finishes() {
+        let plans = Arc::new(AtomicUsize::new(0));
+        let recorder = Arc::new(ChainEndRecorder::default());
+        let executor = AgentExecutor::from_agent(CountingAgent {
+            plans: plans.clone(),
+        })
+        .with_stream_status(true)
+        .with_callbacks(vec![recorder.clone()]);
+
+        let mut stream = executor
+            .stream(prompt_args! {"input" => "sleep"})
+            .await
+            .unwrap();
+        assert_eq!(plans.load(Ordering::SeqCst), 0);
+
+        let status = stream.next().await.unwrap().unwrap();
+        assert_eq!(status.value["type"], "status");
+        assert_eq!(plans.load(Ordering::SeqCst), 1);
+
+        let rest: Vec<StreamData> = stream.map(Result::unwrap).collect().await;
+        assert_eq!(rest.len(), 2);
+        assert_eq!(rest[1].content, "done");
+        assert_eq!(*recorder.events.lock().unwrap(), vec!["end: done"]);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_stream_ends_the_run_with_an_error() {
+        let memory: Arc<Mutex<dyn BaseMemory>> = SimpleMemory::new().into();
+        let recorder = Arc::new(ChainEndRecorder::default());
+        let executor = AgentExecutor::from_agent(CountingAgent {
+            plans: Arc::new(AtomicUsize::new(0)),
+        })
+        .with_stream_status(true)
+        .with_memory(memory.clone())
+        .with_callbacks(vec![recorder.clone()]);
+
+        let mut stream = executor
+            .stream(prompt_args! {"input" => "sleep"})
+            .await
+            .unwrap();
+        stream.next().await.unwrap().unwrap();
+        drop(stream);
+        // The error is reported by a spawned task
+        tokio::task::yield_now().await;
+
+        let events = recorder.events.lock().unwrap().clone();
+        assert_eq!(events.len(), 1);
+        assert!(events[0].starts_with("error:"), "{}", events[0]);
+        assert!(memory.lock().await.messages().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tool_timeout_is_a_tool_error() {
+        let executor = AgentExecutor::from_agent(SlowToolAgent {})
+            .with_tool_timeout(Duration::from_millis(10));
+        let output = executor
+            .invoke(prompt_args! {"input" => "run the slow tool"})
+            .await
+            .unwrap();
+        assert!(output.contains("timed out"), "{}", output);
+
+        let executor = AgentExecutor::from_agent(SlowToolAgent {})
+            .with_tool_timeout(Duration::from_millis(10))
+            .with_break_if_error(true);
+        let result = executor
+            .invoke(prompt_args! {"input" => "run the slow tool"})
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_parallel_tools_keep_the_planned_order() {
+        let executor = AgentExecutor::from_agent(ParallelAgent {});
+        let output = executor
+            .invoke(prompt_args! {"input" => "sleep"})
+            .await
+            .unwrap();
+        assert_eq!(output, "60,1,30");
+
+        let executor = AgentExecutor::from_agent(ParallelAgent {}).with_max_tool_concurrency(2);
+        let output = executor
+            .invoke(prompt_args! {"input" => "sleep"})
+            .await
+            .unwrap();
+        assert_eq!(output, "60,1,30");
+    }
+
+    #[tokio::test]
+    async fn test_call_with_steps() {
+        let executor = AgentExecutor::from_agent(ParallelAgent {});
+        let (result, steps) = executor
+            .call_with_steps(prompt_args! {"input" => "sleep"})
+            .await
+            .unwrap();
+        assert_eq!(result.generation, "60,1,30");
+        let steps = steps
+            .iter()
+            .map(|(action, observation)| (action.tool_input.as_str(), observation.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(steps, vec![("60", "60"), ("1", "1"), ("30", "30")]);
+    }
+
+    #[tokio::test]
+    async fn test_denied_action_is_an_observation() {
+        let executor =
+            AgentExecutor::from_agent(ParallelAgent {}).with_approval(|action: &AgentAction| {
+                match action.tool_input.as_str() {
+                    "60" => ApprovalDecision::DenyWithReason("too slow".to_string()),
+                    "30" => ApprovalDecision::Deny,
+                    _ => ApprovalDecision::Approve,
+                }
+            });
+        let output = executor
+            .invoke(prompt_args! {"input" => "sleep"})
+            .await
+            .unwrap();
+        assert_eq!(
+            output,
+            format!(
+                "{}: too slow,1,{}",
+                DENIED_ACTION_MESSAGE, DENIED_ACTION_MESSAGE
+            )
+        );
+    }
+
+    /// Sleeps twice and answers, reporting usage on the first and last steps only.
+    struct UsageAgent {}
+
+    #[async_trait]
+    impl Agent for UsageAgent {
+        async fn plan(
+            &self,
+            intermediate_steps: &[(AgentAction, String)],
+            _inputs: PromptArgs,
+        ) -> Result<(AgentEvent, Option<TokenUsage>), AgentError> {
+            let sleep = AgentEvent::Action(vec![AgentAction {
+                tool: "Sleep".to_string(),
+                tool_input: "1".to_string(),
+                log: "".to_string(),
+            }]);
+            Ok(match intermediate_steps.len() {
+                0 => (sleep, Some(TokenUsage::new(10, 5))),
+                1 => (sleep, None),
+                _ => (
+                    AgentEvent::Finish(crate::schemas::AgentFinish {
+                        output: "done".to_string(),
+                    }),
+                    Some(TokenUsage::new(20, 2)),
+                ),
+            })
+        }
+
+        fn get_tools(&self) -> Vec<Arc<dyn Tool>> {
+            vec![Arc::new(SleepTool {})]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_token_usage_is_summed_over_the_run() {
+        let executor = AgentExecutor::from_agent(UsageAgent {});
+        let result = executor
+            .call(prompt_args! {"input" => "sleep"})
+            .await
+            .unwrap();
+        let tokens = result.tokens.unwrap();
+        assert_eq!(tokens.prompt_tokens, 30);
+        assert_eq!(tokens.completion_tokens, 7);
+        assert_eq!(tokens.total_tokens, 37);
+    }
+
+    /// Keeps sleeping until it's given an ultimatum, then answers with it.
+    struct StubbornAgent {
+        listens: bool,
+    }
+
+    #[async_trait]
+    impl Agent for StubbornAgent {
+        async fn plan(
+            &self,
+            _intermediate_steps: &[(AgentAction, String)],
+            inputs: PromptArgs,
+        ) -> Result<(AgentEvent, Option<TokenUsage>), AgentError> {
+            let event = match inputs.get(ULTIMATUM_INPUT_KEY) {
+                Some(ultimatum) if self.listens => {
+                    AgentEvent::Finish(crate::schemas::AgentFinish {
+                        output: ultimatum.as_str().unwrap().to_string(),
+                    })
+                }
+                _ => AgentEvent::Action(vec![AgentAction {
+                    tool: "Sleep".to_string(),
+                    tool_input: "1".to_string(),
+                    log: "".to_string(),
+                }]),
+            };
+            Ok((event, None))
+        }
+
+        fn get_tools(&self) -> Vec<Arc<dyn Tool>> {
+            vec![Arc::new(SleepTool {})]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_force_final_answer() {
+        let executor = AgentExecutor::from_agent(StubbornAgent { listens: true })
+            .with_max_iterations(2)
+            .with_force_final_answer_message("Answer now");
+        let (result, steps) = executor
+            .call_with_steps(prompt_args! {"input" => "sleep"})
+            .await
+            .unwrap();
+        assert_eq!(result.generation, "Answer now");
+        assert_eq!(steps.len(), 2);
+
+        let executor =
+            AgentExecutor::from_agent(StubbornAgent { listens: false }).with_max_iterations(2);
+        let output = executor
+            .invoke(prompt_args! {"input" => "sleep"})
+            .await
+            .unwrap();
+        assert_eq!(output, MAX_ITERATIONS_MESSAGE);
+    }
+
+    #[tokio::test]
+    async fn test_observations_are_truncated_for_the_agent() {
+        let executor = AgentExecutor::from_agent(ParallelAgent {})
+            .with_max_observation_length(Some(1))
+            .with_truncation_strategy(TruncationStrategy::Head);
+        let (result, steps) = executor
+            .call_with_steps(prompt_args! {"input" => "sleep"})
+            .await
+            .unwrap();
+        assert_eq!(
+            result.generation,
+            "6\n[... 1 characters of the tool output were truncated ...],1,\
+            3\n[... 1 characters of the tool output were truncated ...]"
+        );
+        assert_eq!(steps[0].1, "60");
     }
 }
@@ -1,9 +1,15 @@
 mod agent;
 pub use agent::*;
 
+mod approval;
+pub use approval::*;
+
 mod executor;
 pub use executor::*;
 
+mod truncation;
+pub use truncation::*;
+
 mod chat;
 pub use chat::*;
 
@@ -2,17 +2,20 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use futures::{stream, StreamExt};
 use serde_json::json;
 
 use crate::{
-    agent::{Agent, AgentError},
+    agent::{agent::ultimatum_message, Agent, AgentError},
     chain::Chain,
-    fmt_message, fmt_placeholder, fmt_template, message_formatter,
+    fmt_message, fmt_placeholder, fmt_template,
+    language_models::TokenUsage,
+    message_formatter,
     prompt::{HumanMessagePromptTemplate, MessageFormatterStruct, PromptArgs},
     schemas::{
-        agent::{AgentAction, AgentEvent, AgentFinish, LogTools},
+        agent::{AgentAction, AgentEvent, AgentFinish, AgentPlan, LogTools},
         messages::Message,
-        FunctionCallResponse,
+        FunctionCallResponse, ToolCallAccumulator,
     },
     template_jinja2,
     tools::Tool,
@@ -55,7 +58,7 @@ impl OpenAiToolAgent {
             // the scratchpad before the related observations.  There can also be multiple
             // different actions in the same thought chain.
             if tools_ai_message_seen.insert(tools, ()).is_none() {
-                thoughts.push(Message::new_ai_message("").with_tool_calls(json!(tools_vec)));
+                thoughts.push(Message::new_ai_tool_calls_message(&tools_vec));
             }
 
             // Add a tool message for each observation. Observation is the ouput of the tool call.
@@ -73,11 +76,70 @@ impl Agent for OpenAiToolAgent {
         &self,
         intermediate_steps: &[(AgentAction, String)],
         inputs: PromptArgs,
-    ) -> Result<AgentEvent, AgentError> {
+    ) -> Result<(AgentEvent, Option<TokenUsage>), AgentError> {
         let mut inputs = inputs.clone();
-        let scratchpad = self.construct_scratchpad(intermediate_steps)?;
+        let mut scratchpad = self.construct_scratchpad(intermediate_steps)?;
+        scratchpad.extend(ultimatum_message(&inputs));
         inputs.insert("agent_scratchpad".to_string(), json!(scratchpad));
-        let output = self.chain.call(inputs).await?.generation;
+        let output = self.chain.call(inputs).await?;
+        Ok((self.parse_output(output.generation)?, output.tokens))
+    }
+
+    async fn stream_plan(
+        &self,
+        intermediate_steps: &[(AgentAction, String)],
+        inputs: PromptArgs,
+    ) -> Result<(AgentPlan, Option<TokenUsage>), AgentError> {
+        let mut inputs = inputs.clone();
+        let mut scratchpad = self.construct_scratchpad(intermediate_steps)?;
+        scratchpad.extend(ultimatum_message(&inputs));
+        inputs.insert("agent_scratchpad".to_string(), json!(scratchpad));
+        let mut output_stream = self.chain.stream(inputs).await?;
+
+        let mut tool_calls = ToolCallAccumulator::new();
+        // Providers send the usage of the call in the last chunks
+        let mut usage = None;
+        while let Some(result) = output_stream.next().await {
+            let data = result?;
+            if data.tokens.is_some() {
+                usage = data.tokens.clone();
+            }
+            if tool_calls.push_stream_data(&data) {
+                continue;
+            }
+            if tool_calls.is_empty() && !data.content.is_empty() {
+                // The model is answering, the rest of the stream is the final answer
+                let first_chunk = stream::once(async { Ok(data) });
+                return Ok((
+                    AgentPlan::Stream(Box::pin(first_chunk.chain(output_stream))),
+                    None,
+                ));
+            }
+        }
+
+        if tool_calls.is_empty() {
+            return Ok((
+                AgentPlan::Text(AgentEvent::Finish(AgentFinish {
+                    output: String::new(),
+                })),
+                usage,
+            ));
+        }
+        let output = serde_json::to_string(&tool_calls.finish())?;
+        Ok((AgentPlan::Text(self.parse_output(output)?), usage))
+    }
+
+    fn get_tools(&self) -> Vec<Arc<dyn Tool>> {
+        self.tools.clone()
+    }
+
+    fn get_input_keys(&self) -> Vec<String> {
+        self.chain.get_required_input_keys()
+    }
+}
+
+impl OpenAiToolAgent {
+    fn parse_output(&self, output: String) -> Result<AgentEvent, AgentError> {
         match serde_json::from_str::<Vec<FunctionCallResponse>>(&output) {
             Ok(tools) => {
                 let mut actions: Vec<AgentAction> = Vec::new();
@@ -94,13 +156,9 @@ impl Agent for OpenAiToolAgent {
                         log: serde_json::to_string(&log)?, //We send this as string to minimise changes
                     });
                 }
-                return Ok(AgentEvent::Action(actions));
+                Ok(AgentEvent::Action(actions))
             }
-            Err(_) => return Ok(AgentEvent::Finish(AgentFinish { output })),
+            Err(_) => Ok(AgentEvent::Finish(AgentFinish { output })),
         }
     }
-
-    fn get_tools(&self) -> Vec<Arc<dyn Tool>> {
-        self.tools.clone()
-    }
 }
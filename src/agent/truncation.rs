@@ -0,0 +1,95 @@
+/// Part of a long tool output kept in the observation sent to the agent.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TruncationStrategy {
+    /// Keeps the start of the output.
+    Head,
+    /// Keeps the end of the output, useful for logs where errors come last.
+    Tail,
+    /// Keeps the start and the end of the output.
+    #[default]
+    HeadAndTail,
+}
+
+impl TruncationStrategy {
+    /// Truncates the text to about `max_chars` characters, with a marker telling how many
+    /// characters were removed.
+    pub fn truncate(&self, text: &str, max_chars: usize) -> String {
+        let total = text.chars().count();
+        if total <= max_chars {
+            return text.to_string();
+        }
+        let marker = format!(
+            "[... {} characters of the tool output were truncated ...]",
+            total - max_chars
+        );
+        match self {
+            TruncationStrategy::Head => format!("{}\n{}", head(text, max_chars), marker),
+            TruncationStrategy::Tail => format!("{}\n{}", marker, tail(text, total, max_chars)),
+            TruncationStrategy::HeadAndTail => {
+                let head_chars = max_chars.div_ceil(2);
+                format!(
+                    "{}\n{}\n{}",
+                    head(text, head_chars),
+                    marker,
+                    tail(text, total, max_chars - head_chars)
+                )
+            }
+        }
+    }
+}
+
+fn head(text: &str, chars: usize) -> &str {
+    match text.char_indices().nth(chars) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+fn tail(text: &str, total: usize, chars: usize) -> &str {
+    match text.char_indices().nth(total - chars) {
+        Some((start, _)) => &text[start..],
+        None => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate() {
+        let text = "abcdefghij";
+        assert_eq!(TruncationStrategy::Head.truncate(text, 20), text);
+        assert_eq!(
+            TruncationStrategy::Head.truncate(text, 4),
+            "abcd\n[... 6 characters of the tool output were truncated ...]"
+        );
+        assert_eq!(
+            TruncationStrategy::Tail.truncate(text, 4),
+            "[... 6 characters of the tool output were truncated ...]\nghij"
+        );
+        assert_eq!(
+            TruncationStrategy::HeadAndTail.truncate(text, 5),
+            "abc\n[... 5 characters of the tool output were truncated ...]\nij"
+        );
+    }
+
+    #[test]
+    fn test_truncate_cuts_on_char_boundaries() {
+        let text = "ñandú ñandú";
+        // The head ends right after the two-byte ñ
+        assert_eq!(
+            TruncationStrategy::Head.truncate(text, 2),
+            "ña\n[... 9 characters of the tool output were truncated ...]"
+        );
+        // The tail starts right before the two-byte ú
+        assert_eq!(
+            TruncationStrategy::Tail.truncate(text, 1),
+            "[... 10 characters of the tool output were truncated ...]\nú"
+        );
+        assert_eq!(
+            TruncationStrategy::HeadAndTail.truncate(text, 4),
+            "ña\n[... 7 characters of the tool output were truncated ...]\ndú"
+        );
+    }
+}
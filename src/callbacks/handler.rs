@@ -0,0 +1,56 @@
+use async_trait::async_trait;
+
+use crate::{
+    chain::ChainError,
+    language_models::{GenerateResult, LLMError},
+    prompt::PromptArgs,
+    schemas::{AgentAction, Message},
+};
+
+use super::RunInfo;
+
+/// `CallbackHandler` lets you observe what happens inside of chains, LLMs and tools,
+/// it is the base for logging, metrics and tracing integrations.
+///
+/// Every method has an empty default implementation, so you only need to implement
+/// the events you care about.
+///
+/// # Usage
+/// ```rust,ignore
+/// struct TokenPrinter;
+///
+/// #[async_trait]
+/// impl CallbackHandler for TokenPrinter {
+///     async fn on_llm_new_token(&self, _run: &RunInfo, token: &str) {
+///         print!("{}", token);
+///     }
+/// }
+///
+/// let llm = OpenAI::default()
+///     .with_options(CallOptions::new().with_callbacks(vec![Arc::new(TokenPrinter)]));
+/// ```
+#[async_trait]
+pub trait CallbackHandler: Send + Sync {
+    async fn on_llm_start(&self, _run: &RunInfo, _model: &str, _messages: &[Message]) {}
+
+    /// Called for every token (or chunk of text) received while streaming.
+    async fn on_llm_new_token(&self, _run: &RunInfo, _token: &str) {}
+
+    async fn on_llm_end(&self, _run: &RunInfo, _result: &GenerateResult) {}
+
+    async fn on_llm_error(&self, _run: &RunInfo, _error: &LLMError) {}
+
+    async fn on_chain_start(&self, _run: &RunInfo, _chain: &str, _inputs: &PromptArgs) {}
+
+    async fn on_chain_end(&self, _run: &RunInfo, _result: &GenerateResult) {}
+
+    async fn on_chain_error(&self, _run: &RunInfo, _error: &ChainError) {}
+
+    async fn on_agent_action(&self, _run: &RunInfo, _action: &AgentAction) {}
+
+    async fn on_tool_start(&self, _run: &RunInfo, _tool: &str, _input: &str) {}
+
+    async fn on_tool_end(&self, _run: &RunInfo, _output: &str) {}
+
+    async fn on_tool_error(&self, _run: &RunInfo, _error: &str) {}
+}
@@ -0,0 +1,10 @@
+mod handler;
+pub use handler::*;
+
+mod run;
+pub use run::*;
+
+#[cfg(feature = "otel")]
+mod otel;
+#[cfg(feature = "otel")]
+pub use otel::*;
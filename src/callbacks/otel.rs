@@ -0,0 +1,475 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use opentelemetry::{
+    global::{self, BoxedTracer},
+    trace::{Status, TraceContextExt, Tracer},
+    Context, KeyValue,
+};
+
+use crate::{
+    chain::ChainError,
+    language_models::{GenerateResult, LLMError},
+    prompt::PromptArgs,
+    schemas::{AgentAction, Message},
+};
+
+use super::{CallbackHandler, RunInfo};
+
+const DEFAULT_TRACER_NAME: &str = "langchain-rust";
+const DEFAULT_MAX_SPAN_AGE: Duration = Duration::from_secs(60 * 60);
+
+struct ActiveSpan {
+    context: Context,
+    started_at: Instant,
+}
+
+/// `OpenTelemetryHandler` is a `CallbackHandler` that reports every chain, LLM and tool
+/// run as an OpenTelemetry span. Runs started inside another run are exported as child
+/// spans, so an agent execution is reported as `AgentExecutor -> llm -> tool`.
+///
+/// The spans include the model, the token usage and the latency of each run. The input and
+/// output of the tools can hold secrets and large payloads, they are only recorded with
+/// `with_tool_io`.
+///
+/// A span is kept until its run ends. Runs that never end, like the ones of a dropped
+/// stream, are ended with an error once they are older than `with_max_span_age`, one hour
+/// by default.
+///
+/// # Usage
+/// ```rust,ignore
+/// let handler: Arc<dyn CallbackHandler> = Arc::new(OpenTelemetryHandler::new());
+///
+/// let llm = OpenAI::default()
+///     .with_options(CallOptions::new().with_callbacks(vec![handler.clone()]));
+/// let executor = AgentExecutor::from_agent(agent).with_callbacks(vec![handler]);
+/// ```
+pub struct OpenTelemetryHandler {
+    tracer: BoxedTracer,
+    spans: Mutex<HashMap<String, ActiveSpan>>,
+    tool_io_max_length: Option<usize>,
+    max_span_age: Duration,
+}
+
+impl Default for OpenTelemetryHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OpenTelemetryHandler {
+    /// Creates a handler using the tracer of the global tracer provider.
+    pub fn new() -> Self {
+        Self::with_tracer(global::tracer(DEFAULT_TRACER_NAME))
+    }
+
+    pub fn with_tracer(tracer: BoxedTracer) -> Self {
+        Self {
+            tracer,
+            spans: Mutex::new(HashMap::new()),
+            tool_io_max_length: None,
+            max_span_age: DEFAULT_MAX_SPAN_AGE,
+        }
+    }
+
+    /// Records the input and the output of the tools in their spans, cut to `max_length`
+    /// characters.
+    pub fn with_tool_io(mut self, max_length: usize) -> Self {
+        self.tool_io_max_length = Some(max_length);
+        self
+    }
+
+    /// Age after which the span of a run that didn't end is ended with an error.
+    pub fn with_max_span_age(mut self, max_span_age: Duration) -> Self {
+        self.max_span_age = max_span_age;
+        self
+    }
+
+    /// The tool input or output as an attribute, if they are recorded.
+    fn tool_io_attribute(&self, key: &'static str, value: &str) -> Option<KeyValue> {
+        let max_length = self.tool_io_max_length?;
+        let value = match value.char_indices().nth(max_length) {
+            Some((end, _)) => format!("{}...", &value[..end]),
+            None => value.to_string(),
+        };
+        Some(KeyValue::new(key, value))
+    }
+
+    fn start_span(&self, run: &RunInfo, name: String, attributes: Vec<KeyValue>) {
+        let mut spans = self.spans.lock().unwrap_or_else(|e| e.into_inner());
+        let parent_context = run
+            .parent_run_id
+            .as_ref()
+            .and_then(|parent_run_id| spans.get(parent_run_id))
+            .map(|parent| parent.context.clone())
+            .unwrap_or_else(Context::current);
+
+        let expired: Vec<String> = spans
+            .iter()
+            .filter(|(_, active)| active.started_at.elapsed() > self.max_span_age)
+            .map(|(run_id, _)| run_id.clone())
+            .collect();
+        for run_id in expired {
+            if let Some(active) = spans.remove(&run_id) {
+                log::warn!("The run {} never ended, its span is closed", run_id);
+                let span = active.context.span();
+                span.set_status(Status::error("The run never ended"));
+                span.end();
+            }
+        }
+
+        let span = self.tracer.start_with_context(name, &parent_context);
+        let context = parent_context.with_span(span);
+        context.span().set_attributes(attributes);
+        context
+            .span()
+            .set_attribute(KeyValue::new("langchain.run_id", run.run_id.clone()));
+
+        spans.insert(
+            run.run_id.clone(),
+            ActiveSpan {
+                context,
+                started_at: Instant::now(),
+            },
+        );
+    }
+
+    fn add_event(&self, run: &RunInfo, name: &'static str, attributes: Vec<KeyValue>) {
+        let spans = self.spans.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(active) = spans.get(&run.run_id) {
+            active.context.span().add_event(name, attributes);
+        }
+    }
+
+    fn end_span(&self, run: &RunInfo, attributes: Vec<KeyValue>, error: Option<String>) {
+        let active = {
+            let mut spans = self.spans.lock().unwrap_or_else(|e| e.into_inner());
+            spans.remove(&run.run_id)
+        };
+        let Some(active) = active else {
+            log::warn!("No span found for run {}", run.run_id);
+            return;
+        };
+
+        let span = active.context.span();
+        span.set_attributes(attributes);
+        span.set_attribute(KeyValue::new(
+            "langchain.latency_ms",
+            active.started_at.elapsed().as_millis() as i64,
+        ));
+        match error {
+            Some(error) => span.set_status(Status::error(error)),
+            None => span.set_status(Status::Ok),
+        }
+        span.end();
+    }
+
+    fn usage_attributes(result: &GenerateResult) -> Vec<KeyValue> {
+        match &result.tokens {
+            Some(tokens) => vec![
+                KeyValue::new("gen_ai.usage.input_tokens", tokens.prompt_tokens as i64),
+                KeyValue::new(
+                    "gen_ai.usage.output_tokens",
+                    tokens.completion_tokens as i64,
+                ),
+                KeyValue::new("gen_ai.usage.total_tokens", tokens.total_tokens as i64),
+            ],
+            None => vec![],
+        }
+    }
+
+    fn active_spans(&self) -> usize {
+        self.spans.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+}
+
+#[async_trait]
+impl CallbackHandler for OpenTelemetryHandler {
+    async fn on_llm_start(&self, run: &RunInfo, model: &str, messages: &[Message]) {
+        self.start_span(
+            run,
+            "llm".to_string(),
+            vec![
+                KeyValue::new("gen_ai.request.model", model.to_string()),
+                KeyValue::new("gen_ai.request.messages", messages.len() as i64),
+            ],
+        );
+    }
+
+    async fn on_llm_new_token(&self, run: &RunInfo, _token: &str) {
+        self.add_event(run, "new_token", vec![]);
+    }
+
+    async fn on_llm_end(&self, run: &RunInfo, result: &GenerateResult) {
+        self.end_span(run, Self::usage_attributes(result), None);
+    }
+
+    async fn on_llm_error(&self, run: &RunInfo, error: &LLMError) {
+        self.end_span(run, vec![], Some(error.to_string()));
+    }
+
+    async fn on_chain_start(&self, run: &RunInfo, chain: &str, inputs: &PromptArgs) {
+        let mut input_keys = inputs.keys().cloned().collect::<Vec<_>>();
+        input_keys.sort();
+        self.start_span(
+            run,
+            chain.to_string(),
+            vec![KeyValue::new("langchain.input_keys", input_keys.join(","))],
+        );
+    }
+
+    async fn on_chain_end(&self, run: &RunInfo, result: &GenerateResult) {
+        self.end_span(run, Self::usage_attributes(result), None);
+    }
+
+    async fn on_chain_error(&self, run: &RunInfo, error: &ChainError) {
+        self.end_span(run, vec![], Some(error.to_string()));
+    }
+
+    async fn on_agent_action(&self, run: &RunInfo, action: &AgentAction) {
+        if let Some(parent_run_id) = &run.parent_run_id {
+            let parent = RunInfo {
+                run_id: parent_run_id.clone(),
+                parent_run_id: None,
+            };
+            self.add_event(
+                &parent,
+                "agent_action",
+                vec![KeyValue::new("langchain.tool", action.tool.clone())],
+            );
+        }
+    }
+
+    async fn on_tool_start(&self, run: &RunInfo, tool: &str, input: &str) {
+        let mut attributes = vec![KeyValue::new("langchain.tool", tool.to_string())];
+        attributes.extend(self.tool_io_attribute("langchain.tool.input", input));
+        self.start_span(run, format!("tool {}", tool), attributes);
+    }
+
+    async fn on_tool_end(&self, run: &RunInfo, output: &str) {
+        let attributes = self
+            .tool_io_attribute("langchain.tool.output", output)
+            .into_iter()
+            .collect();
+        self.end_span(run, attributes, None);
+    }
+
+    async fn on_tool_error(&self, run: &RunInfo, error: &str) {
+        self.end_span(run, vec![], Some(error.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        borrow::Cow,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        time::SystemTime,
+    };
+
+    use opentelemetry::trace::{
+        Span, SpanBuilder, SpanContext, SpanId, TraceFlags, TraceId, TraceState,
+    };
+
+    use super::*;
+
+    /// A span as seen by the `RecordingTracer`.
+    #[derive(Debug, Clone)]
+    struct RecordedSpan {
+        name: String,
+        id: SpanId,
+        parent: Option<SpanId>,
+        attributes: Vec<KeyValue>,
+    }
+
+    /// Records the spans it starts, with their parent and their attributes.
+    #[derive(Clone, Default)]
+    struct RecordingTracer {
+        spans: Arc<Mutex<Vec<RecordedSpan>>>,
+        next_id: Arc<AtomicU64>,
+    }
+
+    impl RecordingTracer {
+        fn span(&self, name: &str) -> RecordedSpan {
+            let spans = self.spans.lock().unwrap();
+            spans.iter().find(|span| span.name == name).unwrap().clone()
+        }
+    }
+
+    struct RecordingSpan {
+        context: SpanContext,
+        index: usize,
+        spans: Arc<Mutex<Vec<RecordedSpan>>>,
+    }
+
+    impl Span for RecordingSpan {
+        fn add_event_with_timestamp<T>(&mut self, _: T, _: SystemTime, _: Vec<KeyValue>)
+        where
+            T: Into<Cow<'static, str>>,
+        {
+        }
+
+        fn span_context(&self) -> &SpanContext {
+            &self.context
+        }
+
+        fn is_recording(&self) -> bool {
+            true
+        }
+
+        fn set_attribute(&mut self, attribute: KeyValue) {
+            self.spans.lock().unwrap()[self.index]
+                .attributes
+                .push(attribute);
+        }
+
+        fn set_status(&mut self, _: Status) {}
+
+        fn update_name<T>(&mut self, _: T)
+        where
+            T: Into<Cow<'static, str>>,
+        {
+        }
+
+        fn add_link(&mut self, _: SpanContext, _: Vec<KeyValue>) {}
+
+        fn end_with_timestamp(&mut self, _: SystemTime) {}
+    }
+
+    impl Tracer for RecordingTracer {
+        type Span = RecordingSpan;
+
+        fn build_with_context(&self, builder: SpanBuilder, parent_cx: &Context) -> RecordingSpan {
+            let id = SpanId::from(self.next_id.fetch_add(1, Ordering::SeqCst) + 1);
+            let parent = parent_cx
+                .has_active_span()
+                .then(|| parent_cx.span().span_context().span_id());
+            let mut spans = self.spans.lock().unwrap();
+            spans.push(RecordedSpan {
+                name: builder.name.to_string(),
+                id,
+                parent,
+                attributes: builder.attributes.unwrap_or_default(),
+            });
+            RecordingSpan {
+                context: SpanContext::new(
+                    TraceId::from_bytes([1; 16]),
+                    id,
+                    TraceFlags::SAMPLED,
+                    false,
+                    TraceState::default(),
+                ),
+                index: spans.len() - 1,
+                spans: self.spans.clone(),
+            }
+        }
+    }
+
+    fn recording_handler() -> (OpenTelemetryHandler, RecordingTracer) {
+        let tracer = RecordingTracer::default();
+        let handler = OpenTelemetryHandler::with_tracer(BoxedTracer::new(Box::new(tracer.clone())));
+        (handler, tracer)
+    }
+
+    fn attribute(span: &RecordedSpan, key: &str) -> Option<String> {
+        span.attributes
+            .iter()
+            .find(|attribute| attribute.key.as_str() == key)
+            .map(|attribute| attribute.value.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_spans_are_closed() {
+        let handler = OpenTelemetryHandler::new();
+        let chain_run = RunInfo::new();
+        handler
+            .on_chain_start(&chain_run, "AgentExecutor", &PromptArgs::new())
+            .await;
+
+        let tool_run = chain_run.scope(async { RunInfo::new() }).await;
+        handler.on_tool_start(&tool_run, "Calculator", "1+1").await;
+        assert_eq!(handler.active_spans(), 2);
+
+        handler.on_tool_end(&tool_run, "2").await;
+        handler
+            .on_chain_end(&chain_run, &GenerateResult::default())
+            .await;
+        assert_eq!(handler.active_spans(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_nested_runs_are_child_spans() {
+        let (handler, tracer) = recording_handler();
+        let chain_run = RunInfo::new();
+        handler
+            .on_chain_start(&chain_run, "AgentExecutor", &PromptArgs::new())
+            .await;
+        let llm_run = chain_run.scope(async { RunInfo::new() }).await;
+        handler.on_llm_start(&llm_run, "gpt", &[]).await;
+        let tool_run = llm_run.scope(async { RunInfo::new() }).await;
+        handler.on_tool_start(&tool_run, "Calculator", "1+1").await;
+        handler.on_tool_end(&tool_run, "2").await;
+        handler
+            .on_llm_end(&llm_run, &GenerateResult::default())
+            .await;
+        handler
+            .on_chain_end(&chain_run, &GenerateResult::default())
+            .await;
+
+        let chain = tracer.span("AgentExecutor");
+        let llm = tracer.span("llm");
+        let tool = tracer.span("tool Calculator");
+        assert_eq!(chain.parent, None);
+        assert_eq!(llm.parent, Some(chain.id));
+        assert_eq!(tool.parent, Some(llm.id));
+    }
+
+    #[tokio::test]
+    async fn test_tool_io_is_opt_in() {
+        let (handler, tracer) = recording_handler();
+        let run = RunInfo::new();
+        handler.on_tool_start(&run, "Secret", "password").await;
+        handler.on_tool_end(&run, "token").await;
+        let span = tracer.span("tool Secret");
+        assert_eq!(attribute(&span, "langchain.tool.input"), None);
+        assert_eq!(attribute(&span, "langchain.tool.output"), None);
+
+        let (handler, tracer) = recording_handler();
+        let handler = handler.with_tool_io(4);
+        let run = RunInfo::new();
+        handler.on_tool_start(&run, "Echo", "abcdef").await;
+        handler.on_tool_end(&run, "abc").await;
+        let span = tracer.span("tool Echo");
+        assert_eq!(
+            attribute(&span, "langchain.tool.input").as_deref(),
+            Some("abcd...")
+        );
+        assert_eq!(
+            attribute(&span, "langchain.tool.output").as_deref(),
+            Some("abc")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_old_spans_are_ended() {
+        let handler = OpenTelemetryHandler::new().with_max_span_age(Duration::ZERO);
+        let dropped_run = RunInfo::new();
+        handler
+            .on_chain_start(&dropped_run, "AgentExecutor", &PromptArgs::new())
+            .await;
+        std::thread::sleep(Duration::from_millis(1));
+
+        let run = RunInfo::new();
+        handler
+            .on_chain_start(&run, "AgentExecutor", &PromptArgs::new())
+            .await;
+        assert_eq!(handler.active_spans(), 1);
+    }
+}
@@ -0,0 +1,70 @@
+use std::{
+    future::Future,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+static RUN_COUNTER: AtomicU64 = AtomicU64::new(1);
+
+tokio::task_local! {
+    static CURRENT_RUN_ID: String;
+}
+
+/// `RunInfo` identifies a single execution (a chain call, an LLM call or a tool call)
+/// reported to a `CallbackHandler`.
+///
+/// Runs created while another run is in scope (see `RunInfo::scope`) get it as parent,
+/// this way handlers can rebuild the tree of an execution, for example
+/// `AgentExecutor -> LLM -> Tool`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunInfo {
+    pub run_id: String,
+    pub parent_run_id: Option<String>,
+}
+
+impl Default for RunInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RunInfo {
+    /// Creates a new run, child of the run currently in scope if there is one.
+    pub fn new() -> Self {
+        Self {
+            run_id: format!("run-{}", RUN_COUNTER.fetch_add(1, Ordering::Relaxed)),
+            parent_run_id: Self::current_run_id(),
+        }
+    }
+
+    /// Returns the id of the run currently in scope.
+    pub fn current_run_id() -> Option<String> {
+        CURRENT_RUN_ID.try_with(|run_id| run_id.clone()).ok()
+    }
+
+    /// Executes the future with this run in scope, every run created inside of it
+    /// will be a child of this one.
+    pub async fn scope<F: Future>(&self, f: F) -> F::Output {
+        CURRENT_RUN_ID.scope(self.run_id.clone(), f).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_nested_runs() {
+        let parent = RunInfo::new();
+        assert_eq!(parent.parent_run_id, None);
+
+        let child = parent.scope(async { RunInfo::new() }).await;
+        assert_eq!(child.parent_run_id, Some(parent.run_id.clone()));
+
+        let grandchild = parent
+            .scope(async { child.scope(async { RunInfo::new() }).await })
+            .await;
+        assert_eq!(grandchild.parent_run_id, Some(child.run_id.clone()));
+
+        assert_eq!(RunInfo::current_run_id(), None);
+    }
+}
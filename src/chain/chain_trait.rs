@@ -4,13 +4,45 @@ use async_trait::async_trait;
 use futures::Stream;
 use serde_json::{json, Value};
 
-use crate::{language_models::GenerateResult, prompt::PromptArgs, schemas::StreamData};
+use crate::{
+    language_models::GenerateResult,
+    prompt::{FormatPrompter, PromptArgs},
+    schemas::StreamData,
+};
 
 use super::ChainError;
 
 pub(crate) const DEFAULT_OUTPUT_KEY: &str = "output";
 pub(crate) const DEFAULT_RESULT_KEY: &str = "generate_result";
 
+/// Checks on build that the prompt of a chain uses the `required` variables, and when the
+/// chain only passes a fixed set of variables to the prompt, that all of them are `provided`.
+pub(crate) fn validate_prompt_variables(
+    prompt: &dyn FormatPrompter,
+    required: &[&str],
+    provided: Option<&[&str]>,
+) -> Result<(), ChainError> {
+    let variables = prompt.get_input_variables();
+    if let Some(missing) = required
+        .iter()
+        .find(|&&r| !variables.iter().any(|v| v == r))
+    {
+        return Err(ChainError::InvalidPrompt(format!(
+            "the prompt doesn't declare the `{}` variable, its variables are {:?}",
+            missing, variables
+        )));
+    }
+    if let Some(provided) = provided {
+        if let Some(unknown) = variables.iter().find(|v| !provided.contains(&v.as_str())) {
+            return Err(ChainError::InvalidPrompt(format!(
+                "the `{}` variable of the prompt is not provided by the chain, which provides {:?}",
+                unknown, provided
+            )));
+        }
+    }
+    Ok(())
+}
+
 #[async_trait]
 pub trait Chain: Sync + Send {
     /// Call the `Chain` and receive as output the result of the generation process along with
@@ -180,6 +212,12 @@ pub trait Chain: Sync + Send {
         unimplemented!()
     }
 
+    /// Name of the chain, its type name by default. The chains holding other chains show
+    /// them by name in their `Debug` output.
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
     // Get the input keys of the prompt
     fn get_input_keys(&self) -> Vec<String> {
         log::info!("Using default implementation");
@@ -193,6 +231,39 @@ pub trait Chain: Sync + Send {
             String::from(DEFAULT_RESULT_KEY),
         ]
     }
+
+    /// Get every input key the caller has to provide to run the `Chain`, including the
+    /// keys required by any inner chains that are not produced internally.
+    ///
+    /// Composed chains (`SequentialChain`, `AgentExecutor`) override this to aggregate the
+    /// keys of their children, the default is the same as `get_input_keys`.
+    fn get_required_input_keys(&self) -> Vec<String> {
+        self.get_input_keys()
+    }
+
+    /// Check that every key returned by `get_required_input_keys` is present in the input
+    /// variables. All the missing keys are reported at once in a single
+    /// `ChainError::MissingInputVariable`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let chain = sequential_chain!(chain1, chain2);
+    /// chain.validate_input_variables(&prompt_args! {"input" => "socks"})?;
+    /// ```
+    fn validate_input_variables(&self, input_variables: &PromptArgs) -> Result<(), ChainError> {
+        let mut missing = self
+            .get_required_input_keys()
+            .into_iter()
+            .filter(|key| !input_variables.contains_key(key))
+            .collect::<Vec<String>>();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        missing.sort();
+        missing.dedup();
+        Err(ChainError::MissingInputVariable(missing.join(", ")))
+    }
 }
 
 impl<C> From<C> for Box<dyn Chain>
@@ -4,17 +4,23 @@ use tokio::sync::Mutex;
 
 use crate::{
     chain::{
-        llm_chain::LLMChainBuilder, options::ChainCallOptions, ChainError, DEFAULT_OUTPUT_KEY,
+        llm_chain::LLMChainBuilder, options::ChainCallOptions, validate_prompt_variables,
+        ChainError, DEFAULT_OUTPUT_KEY,
     },
+    fmt_message, fmt_template,
     language_models::llm::LLM,
     memory::SimpleMemory,
+    message_formatter,
     output_parsers::OutputParser,
     prompt::{FormatPrompter, HumanMessagePromptTemplate},
-    schemas::memory::BaseMemory,
+    schemas::{memory::BaseMemory, Message},
     template_fstring,
 };
 
-use super::{prompt::DEFAULT_TEMPLATE, ConversationalChain, DEFAULT_INPUT_VARIABLE};
+use super::{
+    prompt::{DEFAULT_TEMPLATE, HISTORY_TEMPLATE},
+    ConversationalChain, StreamMemoryMode, DEFAULT_INPUT_VARIABLE,
+};
 
 pub struct ConversationalChainBuilder {
     llm: Option<Box<dyn LLM>>,
@@ -24,6 +30,8 @@ pub struct ConversationalChainBuilder {
     output_parser: Option<Box<dyn OutputParser>>,
     input_key: Option<String>,
     prompt: Option<Box<dyn FormatPrompter>>,
+    system_prompt: Option<String>,
+    stream_memory_mode: StreamMemoryMode,
 }
 
 impl ConversationalChainBuilder {
@@ -36,6 +44,8 @@ impl ConversationalChainBuilder {
             output_parser: None,
             input_key: None,
             prompt: None,
+            system_prompt: None,
+            stream_memory_mode: StreamMemoryMode::default(),
         }
     }
 
@@ -70,23 +80,58 @@ impl ConversationalChainBuilder {
     }
 
     ///If you want to add a custom prompt,keep in mind which variables are obligatory.
+    ///The prompt must declare the `history` variable and the input key, `input` by default,
+    ///otherwise the chain fails to build.
     pub fn prompt<P: Into<Box<dyn FormatPrompter>>>(mut self, prompt: P) -> Self {
         self.prompt = Some(prompt.into());
         self
     }
 
+    /// System message sent ahead of the conversation history, to give the AI a persona or
+    /// instructions while keeping the default `history` and `input` template.
+    /// It can't be combined with a custom prompt, add the system message to the prompt instead.
+    pub fn system_prompt<S: Into<String>>(mut self, system_prompt: S) -> Self {
+        self.system_prompt = Some(system_prompt.into());
+        self
+    }
+
+    /// When `stream` saves the turn in memory, once the stream is consumed by default. See
+    /// `StreamMemoryMode`.
+    pub fn stream_memory_mode(mut self, stream_memory_mode: StreamMemoryMode) -> Self {
+        self.stream_memory_mode = stream_memory_mode;
+        self
+    }
+
     pub fn build(self) -> Result<ConversationalChain, ChainError> {
         let llm = self
             .llm
             .ok_or_else(|| ChainError::MissingObject("LLM must be set".into()))?;
-        let prompt = match self.prompt {
-            Some(prompt) => prompt,
-            None => Box::new(HumanMessagePromptTemplate::new(template_fstring!(
+        let prompt: Box<dyn FormatPrompter> = match (self.prompt, self.system_prompt) {
+            (Some(_), Some(_)) => {
+                return Err(ChainError::InvalidPrompt(
+                    "a system prompt can't be combined with a custom prompt".into(),
+                ))
+            }
+            (Some(prompt), None) => prompt,
+            (None, Some(system_prompt)) => Box::new(message_formatter![
+                fmt_message!(Message::new_system_message(system_prompt)),
+                fmt_template!(HumanMessagePromptTemplate::new(template_fstring!(
+                    HISTORY_TEMPLATE,
+                    "history",
+                    "input"
+                )))
+            ]),
+            (None, None) => Box::new(HumanMessagePromptTemplate::new(template_fstring!(
                 DEFAULT_TEMPLATE,
                 "history",
                 "input"
             ))),
         };
+        let input_key = self
+            .input_key
+            .unwrap_or_else(|| DEFAULT_INPUT_VARIABLE.to_string());
+        validate_prompt_variables(prompt.as_ref(), &[input_key.as_str(), "history"], None)?;
+
         let llm_chain = {
             let mut builder = LLMChainBuilder::new()
                 .prompt(prompt)
@@ -111,9 +156,93 @@ impl ConversationalChainBuilder {
         Ok(ConversationalChain {
             llm: llm_chain,
             memory,
-            input_key: self
-                .input_key
-                .unwrap_or_else(|| DEFAULT_INPUT_VARIABLE.to_string()),
+            input_key,
+            stream_memory_mode: self.stream_memory_mode,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        chain::{Chain, ChainError},
+        llm::{fake::FakeLLM, openai::OpenAI},
+        prompt::HumanMessagePromptTemplate,
+        prompt_args,
+        schemas::MessageType,
+        template_fstring,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_build_validates_prompt_variables() {
+        let result = ConversationalChainBuilder::new()
+            .llm(OpenAI::default())
+            .input_key("question")
+            .build();
+        assert!(matches!(result, Err(ChainError::InvalidPrompt(_))));
+
+        let result = ConversationalChainBuilder::new()
+            .llm(OpenAI::default())
+            .prompt(HumanMessagePromptTemplate::new(template_fstring!(
+                "{history}\nHuman: {inptu}",
+                "history",
+                "inptu"
+            )))
+            .build();
+        assert!(matches!(result, Err(ChainError::InvalidPrompt(_))));
+
+        let result = ConversationalChainBuilder::new()
+            .llm(OpenAI::default())
+            .input_key("question")
+            .prompt(HumanMessagePromptTemplate::new(template_fstring!(
+                "{history}\nHuman: {question}",
+                "history",
+                "question"
+            )))
+            .build();
+        assert!(result.is_ok());
+    }
+
+    /// Answers with the messages it got, as JSON.
+    fn echo_llm() -> FakeLLM {
+        FakeLLM::answering(|messages| serde_json::to_string(messages).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_build_with_system_prompt() {
+        let chain = ConversationalChainBuilder::new()
+            .llm(echo_llm())
+            .system_prompt("You are a pirate")
+            .build()
+            .unwrap();
+        chain
+            .memory
+            .lock()
+            .await
+            .add_message(Message::new_ai_message("ahoy"));
+
+        let generation = chain
+            .invoke(prompt_args! { "input" => "who are you?" })
+            .await
+            .unwrap();
+        let messages: Vec<Message> = serde_json::from_str(&generation).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].message_type, MessageType::SystemMessage);
+        assert_eq!(messages[0].content, "You are a pirate");
+        assert!(messages[1].content.contains("ai: ahoy"));
+        assert!(messages[1].content.contains("Human: who are you?"));
+
+        let result = ConversationalChainBuilder::new()
+            .llm(echo_llm())
+            .system_prompt("You are a pirate")
+            .prompt(HumanMessagePromptTemplate::new(template_fstring!(
+                "{history}\nHuman: {input}",
+                "history",
+                "input"
+            )))
+            .build();
+        assert!(matches!(result, Err(ChainError::InvalidPrompt(_))));
+    }
+}
@@ -1,16 +1,21 @@
-use std::{pin::Pin, sync::Arc};
+use std::{fmt, pin::Pin, sync::Arc};
 
 use async_stream::stream;
 use async_trait::async_trait;
 use futures::Stream;
 use futures_util::{pin_mut, StreamExt};
+use serde_json::json;
 use tokio::sync::Mutex;
 
 use crate::{
     language_models::GenerateResult,
-    prompt::PromptArgs,
+    prompt::{images_from_args, PromptArgs, IMAGES_INPUT_VARIABLE},
     prompt_args,
-    schemas::{memory::BaseMemory, messages::Message, StreamData},
+    schemas::{
+        memory::BaseMemory,
+        messages::{ImageContent, Message},
+        StreamData,
+    },
 };
 
 const DEFAULT_INPUT_VARIABLE: &str = "input";
@@ -21,14 +26,17 @@ pub mod builder;
 mod prompt;
 
 ///This is only usefull when you dont modify the original prompt
+#[derive(Debug, Clone)]
 pub struct ConversationalChainPromptBuilder {
     input: String,
+    images: Vec<ImageContent>,
 }
 
 impl ConversationalChainPromptBuilder {
     pub fn new() -> Self {
         Self {
             input: "".to_string(),
+            images: Vec::new(),
         }
     }
 
@@ -37,17 +45,52 @@ impl ConversationalChainPromptBuilder {
         self
     }
 
+    /// Adds an image to the human message, an url or a `data:` uri.
+    pub fn image<I: Into<ImageContent>>(mut self, image: I) -> Self {
+        self.images.push(image.into());
+        self
+    }
+
     pub fn build(self) -> PromptArgs {
-        prompt_args! {
+        let mut args = prompt_args! {
             DEFAULT_INPUT_VARIABLE => self.input,
+        };
+        if !self.images.is_empty() {
+            args.insert(IMAGES_INPUT_VARIABLE.to_string(), json!(self.images));
         }
+        args
     }
 }
 
+/// When `ConversationalChain::stream` saves the turn in memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StreamMemoryMode {
+    /// The human and AI messages are saved once the stream is consumed to the end. A
+    /// stream dropped before is not saved.
+    #[default]
+    OnCompletion,
+    /// The human message is saved when the stream starts, and the AI message with what was
+    /// generated when the stream ends or is dropped, unless nothing was generated.
+    Eager,
+}
+
+/// Clones share the memory.
+#[derive(Clone)]
 pub struct ConversationalChain {
     llm: LLMChain,
     input_key: String,
     pub memory: Arc<Mutex<dyn BaseMemory>>,
+    stream_memory_mode: StreamMemoryMode,
+}
+
+impl fmt::Debug for ConversationalChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConversationalChain")
+            .field("llm", &self.llm)
+            .field("input_key", &self.input_key)
+            .field("stream_memory_mode", &self.stream_memory_mode)
+            .finish_non_exhaustive()
+    }
 }
 
 //Conversational Chain is a simple chain to interact with ai as a string of messages
@@ -55,15 +98,84 @@ impl ConversationalChain {
     pub fn prompt_builder(&self) -> ConversationalChainPromptBuilder {
         ConversationalChainPromptBuilder::new()
     }
+
+    /// The human message saved in memory, with the images of the input variables.
+    fn human_message(&self, input_variables: &PromptArgs) -> Result<Message, ChainError> {
+        let input_variable = input_variables
+            .get(&self.input_key)
+            .ok_or(ChainError::MissingInputVariable(self.input_key.clone()))?;
+        Ok(Message::new_human_message(input_variable)
+            .with_images(images_from_args(input_variables)?))
+    }
+}
+
+/// The AI message of an eager stream, saved once: when the stream ends, or when it is
+/// dropped before.
+///
+/// If the memory is locked when the stream is dropped, the message is saved by a spawned
+/// task, which can run after the next turn has read or written the memory. Consume the
+/// stream to the end to keep the messages in order.
+struct PendingAiMessage {
+    memory: Arc<Mutex<dyn BaseMemory>>,
+    content: Option<String>,
+}
+
+impl PendingAiMessage {
+    fn new(memory: Arc<Mutex<dyn BaseMemory>>) -> Self {
+        Self {
+            memory,
+            content: Some(String::new()),
+        }
+    }
+
+    fn push_str(&mut self, content: &str) {
+        if let Some(message) = &mut self.content {
+            message.push_str(content);
+        }
+    }
+
+    async fn save(&mut self) {
+        if self.content.as_deref().unwrap_or_default().is_empty() {
+            return;
+        }
+        // The content is only taken once the lock is held: if the stream is dropped while
+        // waiting, the message is still saved on drop.
+        let mut memory = self.memory.lock().await;
+        if let Some(content) = self.content.take() {
+            memory.add_message(Message::new_ai_message(content));
+        }
+    }
+}
+
+impl Drop for PendingAiMessage {
+    fn drop(&mut self) {
+        let Some(content) = self.content.take().filter(|c| !c.is_empty()) else {
+            return;
+        };
+        if let Ok(mut memory) = self.memory.try_lock() {
+            memory.add_message(Message::new_ai_message(content));
+            return;
+        }
+        // The memory is in use, the message is saved once it is released
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                let memory = self.memory.clone();
+                handle.spawn(async move {
+                    memory
+                        .lock()
+                        .await
+                        .add_message(Message::new_ai_message(content));
+                });
+            }
+            Err(_) => log::warn!("The AI message of a dropped stream could not be saved"),
+        }
+    }
 }
 
 #[async_trait]
 impl Chain for ConversationalChain {
     async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
-        let input_variable = &input_variables
-            .get(&self.input_key)
-            .ok_or(ChainError::MissingInputVariable(self.input_key.clone()))?;
-        let human_message = Message::new_human_message(input_variable);
+        let human_message = self.human_message(&input_variables)?;
 
         let history = {
             let memory = self.memory.lock().await;
@@ -84,10 +196,7 @@ impl Chain for ConversationalChain {
         input_variables: PromptArgs,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
     {
-        let input_variable = &input_variables
-            .get(&self.input_key)
-            .ok_or(ChainError::MissingInputVariable(self.input_key.clone()))?;
-        let human_message = Message::new_human_message(input_variable);
+        let human_message = self.human_message(&input_variables)?;
 
         let history = {
             let memory = self.memory.lock().await;
@@ -97,12 +206,28 @@ impl Chain for ConversationalChain {
         let mut input_variables = input_variables;
         input_variables.insert("history".to_string(), history.into());
 
+        let memory = self.memory.clone();
+        let stream = self.llm.stream(input_variables).await?;
+
+        if self.stream_memory_mode == StreamMemoryMode::Eager {
+            memory.lock().await.add_message(human_message);
+            let mut ai_message = PendingAiMessage::new(memory);
+            let output_stream = stream! {
+                pin_mut!(stream);
+                while let Some(result) = stream.next().await {
+                    if let Ok(data) = &result {
+                        ai_message.push_str(&data.content);
+                    }
+                    yield result;
+                }
+                ai_message.save().await;
+            };
+            return Ok(Box::pin(output_stream));
+        }
+
         let complete_ai_message = Arc::new(Mutex::new(String::new()));
         let complete_ai_message_clone = complete_ai_message.clone();
 
-        let memory = self.memory.clone();
-
-        let stream = self.llm.stream(input_variables).await?;
         let output_stream = stream! {
             pin_mut!(stream);
             while let Some(result) = stream.next().await {
@@ -135,14 +260,88 @@ impl Chain for ConversationalChain {
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
     use crate::{
         chain::conversational::builder::ConversationalChainBuilder,
-        llm::openai::{OpenAI, OpenAIModel},
+        llm::{
+            fake::FakeLLM,
+            openai::{OpenAI, OpenAIModel},
+        },
+        memory::SimpleMemory,
         prompt_args,
+        schemas::MessageType,
     };
 
     use super::*;
 
+    /// Streams "Hel" then "lo".
+    fn hello_llm() -> FakeLLM {
+        FakeLLM::fixed("Hello")
+            .with_chunks(["Hel", "lo"].map(|chunk| StreamData::new(json!({}), None, chunk)))
+    }
+
+    fn hello_chain(mode: StreamMemoryMode) -> ConversationalChain {
+        ConversationalChainBuilder::new()
+            .llm(hello_llm())
+            .memory(Arc::new(Mutex::new(SimpleMemory::new())))
+            .stream_memory_mode(mode)
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_eager_stream_saves_a_dropped_turn_once() {
+        let chain = hello_chain(StreamMemoryMode::Eager);
+        let mut stream = chain.stream(prompt_args! {"input" => "Hi"}).await.unwrap();
+        assert_eq!(chain.memory.lock().await.messages().len(), 1);
+
+        stream.next().await.unwrap().unwrap();
+        drop(stream);
+
+        let messages = chain.memory.lock().await.messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].message_type, MessageType::HumanMessage);
+        assert_eq!(messages[1].content, "Hel");
+
+        let stream = chain.stream(prompt_args! {"input" => "Hi"}).await.unwrap();
+        stream.collect::<Vec<_>>().await;
+        let messages = chain.memory.lock().await.messages();
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[3].content, "Hello");
+    }
+
+    #[tokio::test]
+    async fn test_eager_stream_dropped_while_saving_keeps_the_message() {
+        let chain = hello_chain(StreamMemoryMode::Eager);
+        let mut stream = chain.stream(prompt_args! {"input" => "Hi"}).await.unwrap();
+        stream.next().await.unwrap().unwrap();
+        stream.next().await.unwrap().unwrap();
+
+        // The stream waits for the memory to save the message, and is dropped meanwhile.
+        let memory = chain.memory.lock().await;
+        let waiting = tokio::time::timeout(Duration::from_millis(10), stream.next()).await;
+        assert!(waiting.is_err());
+        drop(stream);
+        drop(memory);
+
+        // The message is saved by a spawned task, once the memory is released.
+        tokio::task::yield_now().await;
+        let messages = chain.memory.lock().await.messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].content, "Hello");
+    }
+
+    #[tokio::test]
+    async fn test_stream_on_completion_skips_a_dropped_turn() {
+        let chain = hello_chain(StreamMemoryMode::OnCompletion);
+        let mut stream = chain.stream(prompt_args! {"input" => "Hi"}).await.unwrap();
+        stream.next().await.unwrap().unwrap();
+        drop(stream);
+
+        assert!(chain.memory.lock().await.messages().is_empty());
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_invoke_conversational() {
@@ -5,3 +5,11 @@ Current conversation:
 Human: {input}
 AI:
 "#;
+
+/// Human message used after a custom system prompt, which replaces the introduction of the
+/// default template.
+pub const HISTORY_TEMPLATE: &str = r#"Current conversation:
+{history}
+Human: {input}
+AI:
+"#;
@@ -3,12 +3,14 @@ use tokio::sync::Mutex;
 
 use crate::{
     chain::{
-        Chain, ChainError, CondenseQuestionGeneratorChain, StuffDocumentBuilder, DEFAULT_OUTPUT_KEY,
+        validate_prompt_variables, Chain, ChainError, CondenseQuestionGeneratorChain,
+        StuffDocumentBuilder, DEFAULT_OUTPUT_KEY,
     },
     language_models::llm::LLM,
     memory::SimpleMemory,
     prompt::FormatPrompter,
-    schemas::{BaseMemory, Retriever},
+    schemas::{BaseMemory, RetrievalOptions, Retriever},
+    vectorstore::SearchType,
 };
 
 use super::ConversationalRetrieverChain;
@@ -28,6 +30,17 @@ const CONVERSATIONAL_RETRIEVAL_QA_DEFAULT_INPUT_KEY: &str = "question";
 ///     .expect("Error building ConversationalChain");
 ///
 /// ```
+/// ## Retrieval parameters
+/// ```rust,ignore
+/// let chain = ConversationalRetrieverChainBuilder::new()
+///     .llm(llm)
+///     .retriever(Retriever::new(store, 10))
+///     .top_k(4)
+///     .score_threshold(0.7)
+///     .search_type(SearchType::Mmr { fetch_k: 20, lambda: 0.5 })
+///     .build()
+///     .expect("Error building ConversationalChain");
+/// ```
 /// ## Custom way
 /// ```rust,ignore
 ///
@@ -47,6 +60,7 @@ const CONVERSATIONAL_RETRIEVAL_QA_DEFAULT_INPUT_KEY: &str = "question";
 pub struct ConversationalRetrieverChainBuilder {
     llm: Option<Box<dyn LLM>>,
     retriever: Option<Box<dyn Retriever>>,
+    retrieval_options: RetrievalOptions,
     memory: Option<Arc<Mutex<dyn BaseMemory>>>,
     combine_documents_chain: Option<Box<dyn Chain>>,
     condense_question_chain: Option<Box<dyn Chain>>,
@@ -61,6 +75,7 @@ impl ConversationalRetrieverChainBuilder {
         ConversationalRetrieverChainBuilder {
             llm: None,
             retriever: None,
+            retrieval_options: RetrievalOptions::default(),
             memory: None,
             combine_documents_chain: None,
             condense_question_chain: None,
@@ -77,7 +92,34 @@ impl ConversationalRetrieverChainBuilder {
         self
     }
 
+    /// Maximum number of documents retrieved for each question, the number of documents
+    /// of the retriever by default.
+    pub fn top_k(mut self, top_k: usize) -> Self {
+        self.retrieval_options.top_k = Some(top_k);
+        self
+    }
+
+    /// Minimum score of the documents retrieved, none by default.
+    pub fn score_threshold(mut self, score_threshold: f32) -> Self {
+        self.retrieval_options.score_threshold = Some(score_threshold);
+        self
+    }
+
+    /// How the documents are picked, the search type of the retriever by default. Only
+    /// supported by the vector store retriever.
+    pub fn search_type(mut self, search_type: SearchType) -> Self {
+        self.retrieval_options.search_type = Some(search_type);
+        self
+    }
+
+    /// Sets the top k, score threshold and search type at once.
+    pub fn retrieval_options(mut self, retrieval_options: RetrievalOptions) -> Self {
+        self.retrieval_options = retrieval_options;
+        self
+    }
+
     ///If you want to add a custom prompt,keep in mind which variables are obligatory.
+    ///The prompt must declare the `context` variable, and may declare the `question` one.
     pub fn prompt<P: Into<Box<dyn FormatPrompter>>>(mut self, prompt: P) -> Self {
         self.prompt = Some(prompt.into());
         self
@@ -127,6 +169,17 @@ impl ConversationalRetrieverChainBuilder {
     }
 
     pub fn build(mut self) -> Result<ConversationalRetrieverChain, ChainError> {
+        self.retrieval_options
+            .validate()
+            .map_err(ChainError::InvalidParameter)?;
+        if let Some(prompt) = &self.prompt {
+            validate_prompt_variables(
+                prompt.as_ref(),
+                &["context"],
+                Some(&["context", "question"]),
+            )?;
+        }
+
         if let Some(llm) = self.llm {
             let combine_documents_chain = {
                 let mut builder = StuffDocumentBuilder::new().llm(llm.clone_box());
@@ -160,6 +213,7 @@ impl ConversationalRetrieverChainBuilder {
         })?;
         Ok(ConversationalRetrieverChain {
             retriever,
+            retrieval_options: self.retrieval_options,
             memory,
             combine_documents_chain,
             condense_question_chain,
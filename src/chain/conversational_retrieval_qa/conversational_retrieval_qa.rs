@@ -1,6 +1,6 @@
 use futures::Stream;
 use futures_util::{pin_mut, StreamExt};
-use std::{collections::HashMap, pin::Pin, sync::Arc};
+use std::{collections::HashMap, fmt, pin::Pin, sync::Arc};
 
 use async_stream::stream;
 use async_trait::async_trait;
@@ -10,10 +10,11 @@ use tokio::sync::Mutex;
 use crate::{
     chain::{
         Chain, ChainError, CondenseQuestionPromptBuilder, StuffQAPromptBuilder, DEFAULT_RESULT_KEY,
+        STUFF_DOCUMENTS_CITATIONS_KEY,
     },
     language_models::{GenerateResult, TokenUsage},
     prompt::PromptArgs,
-    schemas::{BaseMemory, Message, Retriever, StreamData},
+    schemas::{BaseMemory, Message, RetrievalOptions, Retriever, StreamData},
 };
 // _conversationalRetrievalQADefaultInputKey             = "question"
 // _conversationalRetrievalQADefaultSourceDocumentKey    = "source_documents"
@@ -25,6 +26,7 @@ const CONVERSATIONAL_RETRIEVAL_QA_DEFAULT_GENERATED_QUESTION_KEY: &str = "genera
 
 pub struct ConversationalRetrieverChain {
     pub(crate) retriever: Box<dyn Retriever>,
+    pub(crate) retrieval_options: RetrievalOptions,
     pub memory: Arc<Mutex<dyn BaseMemory>>,
     pub(crate) combine_documents_chain: Box<dyn Chain>,
     pub(crate) condense_question_chain: Box<dyn Chain>,
@@ -33,6 +35,26 @@ pub struct ConversationalRetrieverChain {
     pub(crate) input_key: String,  //Default is `question`
     pub(crate) output_key: String, //default is output
 }
+
+impl fmt::Debug for ConversationalRetrieverChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConversationalRetrieverChain")
+            .field(
+                "combine_documents_chain",
+                &self.combine_documents_chain.name(),
+            )
+            .field(
+                "condense_question_chain",
+                &self.condense_question_chain.name(),
+            )
+            .field("retrieval_options", &self.retrieval_options)
+            .field("rephrase_question", &self.rephrase_question)
+            .field("return_source_documents", &self.return_source_documents)
+            .field("input_key", &self.input_key)
+            .field("output_key", &self.output_key)
+            .finish_non_exhaustive()
+    }
+}
 impl ConversationalRetrieverChain {
     async fn get_question(
         &self,
@@ -96,19 +118,21 @@ impl Chain for ConversationalRetrieverChain {
 
         let documents = self
             .retriever
-            .get_relevant_documents(&question)
+            .get_relevant_documents_with_options(&question, &self.retrieval_options)
             .await
-            .map_err(|e| ChainError::RetrieverError(e.to_string()))?;
+            .map_err(ChainError::RetrieverError)?;
 
-        let mut output = self
+        let mut combined = self
             .combine_documents_chain
-            .call(
+            .execute(
                 StuffQAPromptBuilder::new()
                     .documents(&documents)
                     .question(question.clone())
                     .build(),
             )
             .await?;
+        let mut output: GenerateResult =
+            serde_json::from_value(combined.remove(DEFAULT_RESULT_KEY).unwrap_or_default())?;
 
         match &output.tokens {
             Some(tokens) => {
@@ -131,6 +155,10 @@ impl Chain for ConversationalRetrieverChain {
 
         result.insert(DEFAULT_RESULT_KEY.to_string(), json!(output));
 
+        if let Some(citations) = combined.remove(STUFF_DOCUMENTS_CITATIONS_KEY) {
+            result.insert(STUFF_DOCUMENTS_CITATIONS_KEY.to_string(), citations);
+        }
+
         if self.return_source_documents {
             result.insert(
                 CONVERSATIONAL_RETRIEVAL_QA_DEFAULT_SOURCE_DOCUMENT_KEY.to_string(),
@@ -167,9 +195,9 @@ impl Chain for ConversationalRetrieverChain {
 
         let documents = self
             .retriever
-            .get_relevant_documents(&question)
+            .get_relevant_documents_with_options(&question, &self.retrieval_options)
             .await
-            .map_err(|e| ChainError::RetrieverError(e.to_string()))?;
+            .map_err(ChainError::RetrieverError)?;
 
         let stream = self
             .combine_documents_chain
@@ -232,14 +260,14 @@ impl Chain for ConversationalRetrieverChain {
 
 #[cfg(test)]
 mod tests {
-    use std::error::Error;
-
     use crate::{
         chain::ConversationalRetrieverChainBuilder,
         llm::openai::{OpenAI, OpenAIModel},
         memory::SimpleMemory,
         prompt_args,
-        schemas::Document,
+        schemas::{Document, RetrieverError},
+        template_jinja2,
+        vectorstore::SearchType,
     };
 
     use super::*;
@@ -250,7 +278,7 @@ mod tests {
         async fn get_relevant_documents(
             &self,
             _question: &str,
-        ) -> Result<Vec<Document>, Box<dyn Error>> {
+        ) -> Result<Vec<Document>, RetrieverError> {
             Ok(vec![
                 Document::new(format!(
                     "\nQuestion: {}\nAnswer: {}\n",
@@ -314,4 +342,58 @@ mod tests {
             println!("Result: {:?}", result);
         }
     }
+
+    #[test]
+    fn test_build_validates_prompt_variables() {
+        let result = ConversationalRetrieverChainBuilder::new()
+            .llm(OpenAI::default())
+            .retriever(RetrieverTest {})
+            .prompt(template_jinja2!(
+                "{{documents}}\nQuestion: {{question}}",
+                "documents",
+                "question"
+            ))
+            .build();
+        assert!(matches!(result, Err(ChainError::InvalidPrompt(_))));
+
+        let result = ConversationalRetrieverChainBuilder::new()
+            .llm(OpenAI::default())
+            .retriever(RetrieverTest {})
+            .prompt(template_jinja2!("{{context}}\nHelpful Answer:", "context"))
+            .build();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_build_validates_retrieval_options() {
+        let build = |builder: ConversationalRetrieverChainBuilder| {
+            builder
+                .llm(OpenAI::default())
+                .retriever(RetrieverTest {})
+                .build()
+        };
+
+        let result = build(ConversationalRetrieverChainBuilder::new().top_k(0));
+        assert!(matches!(result, Err(ChainError::InvalidParameter(_))));
+        let result = build(ConversationalRetrieverChainBuilder::new().search_type(
+            SearchType::Mmr {
+                fetch_k: 10,
+                lambda: 2.0,
+            },
+        ));
+        assert!(matches!(result, Err(ChainError::InvalidParameter(_))));
+
+        let chain = build(
+            ConversationalRetrieverChainBuilder::new()
+                .top_k(2)
+                .score_threshold(0.5),
+        )
+        .unwrap();
+        assert_eq!(
+            chain.retrieval_options,
+            RetrievalOptions::new()
+                .with_top_k(2)
+                .with_score_threshold(0.5)
+        );
+    }
 }
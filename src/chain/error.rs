@@ -1,6 +1,9 @@
 use thiserror::Error;
 
-use crate::{language_models::LLMError, output_parsers::OutputParserError, prompt::PromptError};
+use crate::{
+    language_models::LLMError, output_parsers::OutputParserError, prompt::PromptError,
+    schemas::RetrieverError,
+};
 
 #[derive(Error, Debug)]
 pub enum ChainError {
@@ -8,7 +11,7 @@ pub enum ChainError {
     LLMError(#[from] LLMError),
 
     #[error("Retriever error: {0}")]
-    RetrieverError(String),
+    RetrieverError(#[from] RetrieverError),
 
     #[error("OutputParser error: {0}")]
     OutputParser(#[from] OutputParserError),
@@ -19,6 +22,12 @@ pub enum ChainError {
     #[error("Missing Object On Builder: {0}")]
     MissingObject(String),
 
+    #[error("Invalid parameter: {0}")]
+    InvalidParameter(String),
+
+    #[error("Invalid prompt: {0}")]
+    InvalidPrompt(String),
+
     #[error("Missing input variable: {0}")]
     MissingInputVariable(String),
 
@@ -0,0 +1,124 @@
+use serde_json::{json, Value};
+
+use crate::{
+    chain::{options::ChainCallOptions, ChainError, LLMChainBuilder},
+    fmt_message, fmt_template,
+    language_models::llm::LLM,
+    message_formatter,
+    output_parsers::JsonOutputParser,
+    prompt::HumanMessagePromptTemplate,
+    schemas::Message,
+    template_fstring,
+};
+
+use super::ExtractionChain;
+
+pub(crate) const ITEMS_KEY: &str = "items";
+
+pub struct ExtractionChainBuilder {
+    llm: Option<Box<dyn LLM>>,
+    schema: Option<Value>,
+    many: bool,
+    options: Option<ChainCallOptions>,
+    output_key: Option<String>,
+}
+
+impl ExtractionChainBuilder {
+    pub fn new() -> Self {
+        Self {
+            llm: None,
+            schema: None,
+            many: false,
+            options: None,
+            output_key: None,
+        }
+    }
+
+    pub fn llm<L: Into<Box<dyn LLM>>>(mut self, llm: L) -> Self {
+        self.llm = Some(llm.into());
+        self
+    }
+
+    /// JSON schema of the data to extract, for example one generated with `schemars`.
+    pub fn schema(mut self, schema: Value) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// When set the chain extracts every item matching the schema found in the text, and
+    /// returns a JSON array.
+    pub fn many(mut self, many: bool) -> Self {
+        self.many = many;
+        self
+    }
+
+    /// Options of the LLM, the JSON mode is enabled unless it's disabled in the options.
+    pub fn options(mut self, options: ChainCallOptions) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    pub fn output_key<S: Into<String>>(mut self, output_key: S) -> Self {
+        self.output_key = Some(output_key.into());
+        self
+    }
+
+    pub fn build(self) -> Result<ExtractionChain, ChainError> {
+        let llm = self
+            .llm
+            .ok_or_else(|| ChainError::MissingObject("LLM must be set".into()))?;
+        let schema = self
+            .schema
+            .ok_or_else(|| ChainError::MissingObject("Schema must be set".into()))?;
+
+        // JSON mode requires an object, so the items are wrapped in one
+        let prompt_schema = if self.many {
+            json!({
+                "type": "object",
+                "properties": { ITEMS_KEY: { "type": "array", "items": schema } },
+                "required": [ITEMS_KEY],
+            })
+        } else {
+            schema.clone()
+        };
+        let instructions = if self.many {
+            "Extract every item described by the JSON schema below from the text of the user."
+        } else {
+            "Extract the information described by the JSON schema below from the text of the user."
+        };
+        let system_message = format!(
+            "{} Reply only with JSON that matches the schema, without any explanation. \
+            Leave out the properties that are not in the text, unless they are required.\n\n\
+            JSON schema:\n{}",
+            instructions,
+            serde_json::to_string_pretty(&prompt_schema)?
+        );
+
+        let prompt = message_formatter![
+            fmt_message!(Message::new_system_message(system_message)),
+            fmt_template!(HumanMessagePromptTemplate::new(template_fstring!(
+                "{input}", "input"
+            )))
+        ];
+
+        let mut options = self.options.unwrap_or_default();
+        options.json_mode = options.json_mode.or(Some(true));
+
+        let mut builder = LLMChainBuilder::new()
+            .prompt(prompt)
+            .llm(llm)
+            .options(options)
+            .output_parser(JsonOutputParser::new());
+        if let Some(output_key) = self.output_key {
+            builder = builder.output_key(output_key);
+        }
+
+        Ok(ExtractionChain::new(builder.build()?, schema, self.many))
+    }
+}
+
+impl Default for ExtractionChainBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
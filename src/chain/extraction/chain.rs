@@ -0,0 +1,226 @@
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::{
+    chain::{Chain, ChainError, LLMChain},
+    language_models::GenerateResult,
+    output_parsers::{JsonOutputParser, OutputParserError},
+    prompt::PromptArgs,
+    prompt_args,
+};
+
+use super::builder::ITEMS_KEY;
+
+/// Extracts structured data from text, as JSON matching a JSON schema. The text is the
+/// `input` variable, and the generation of the chain is the extracted JSON.
+///
+/// # Example
+/// ```rust,ignore
+/// let chain = ExtractionChainBuilder::new()
+///     .llm(OpenAI::default())
+///     .schema(json!({
+///         "type": "object",
+///         "properties": { "name": { "type": "string" }, "age": { "type": "integer" } },
+///         "required": ["name"],
+///     }))
+///     .many(true)
+///     .build()?;
+///
+/// let people = chain.extract("Ana is 31, her brother Luis is 28").await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct ExtractionChain {
+    llm_chain: LLMChain,
+    schema: Value,
+    many: bool,
+}
+
+impl ExtractionChain {
+    pub fn new(llm_chain: LLMChain, schema: Value, many: bool) -> Self {
+        Self {
+            llm_chain,
+            schema,
+            many,
+        }
+    }
+
+    /// Extracts the data from the text, validated against the schema. In `many` mode the
+    /// result is an array of the items found.
+    pub async fn extract(&self, text: &str) -> Result<Value, ChainError> {
+        let result = self.call(prompt_args! { "input" => text }).await?;
+        Ok(serde_json::from_str(&result.generation)?)
+    }
+
+    fn parse_output(&self, output: &str) -> Result<Value, ChainError> {
+        let mut value = JsonOutputParser::new().parse_value(output)?;
+        if self.many {
+            value = match value {
+                Value::Object(mut object) => object.remove(ITEMS_KEY).unwrap_or(Value::Null),
+                // Some models reply with the array even when asked for an object
+                value => value,
+            };
+            let items = value.as_array().ok_or_else(|| {
+                OutputParserError::ParsingError(format!("Expected an array of items: {}", value))
+            })?;
+            for (i, item) in items.iter().enumerate() {
+                validate(&self.schema, item, &format!("$[{}]", i))?;
+            }
+        } else {
+            validate(&self.schema, &value, "$")?;
+        }
+        Ok(value)
+    }
+}
+
+#[async_trait]
+impl Chain for ExtractionChain {
+    async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
+        let mut result = self.llm_chain.call(input_variables).await?;
+        result.generation = self.parse_output(&result.generation)?.to_string();
+        Ok(result)
+    }
+
+    fn get_input_keys(&self) -> Vec<String> {
+        self.llm_chain.get_input_keys()
+    }
+
+    fn get_output_keys(&self) -> Vec<String> {
+        self.llm_chain.get_output_keys()
+    }
+}
+
+/// Checks the value against the `type`, `enum`, `required`, `properties` and `items` of the
+/// schema, other keywords are not checked. The path of the value is used in the errors.
+fn validate(schema: &Value, value: &Value, path: &str) -> Result<(), OutputParserError> {
+    let error = |message: String| OutputParserError::ParsingError(format!("{} {}", path, message));
+
+    if let Some(types) = schema.get("type") {
+        let types = match types {
+            Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+            types => types.as_str().into_iter().collect::<Vec<_>>(),
+        };
+        if !types.iter().any(|t| is_type(value, t)) {
+            return Err(error(format!(
+                "should be {}: {}",
+                types.join(" or "),
+                value
+            )));
+        }
+    }
+
+    if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+        if !values.contains(value) {
+            return Err(error(format!("should be one of {:?}: {}", values, value)));
+        }
+    }
+
+    if let Value::Object(object) = value {
+        for key in schema
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+        {
+            if !object.contains_key(key) {
+                return Err(error(format!("is missing the required property {}", key)));
+            }
+        }
+        if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+            for (key, property_schema) in properties {
+                if let Some(property) = object.get(key) {
+                    validate(property_schema, property, &format!("{}.{}", path, key))?;
+                }
+            }
+        }
+    }
+
+    if let (Value::Array(items), Some(items_schema)) = (value, schema.get("items")) {
+        for (i, item) in items.iter().enumerate() {
+            validate(items_schema, item, &format!("{}[{}]", path, i))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn is_type(value: &Value, schema_type: &str) -> bool {
+    match schema_type {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{chain::ExtractionChainBuilder, llm::fake::FakeLLM};
+
+    fn person_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "age": { "type": "integer" },
+            },
+            "required": ["name"],
+        })
+    }
+
+    fn chain(answer: &str, many: bool) -> ExtractionChain {
+        ExtractionChainBuilder::new()
+            .llm(FakeLLM::fixed(answer))
+            .schema(person_schema())
+            .many(many)
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_extract() {
+        let person = chain(r#"{"name": "Ana", "age": 31}"#, false)
+            .extract("Ana is 31")
+            .await
+            .unwrap();
+        assert_eq!(person, json!({"name": "Ana", "age": 31}));
+
+        let error = chain(r#"{"name": "Ana", "age": "31"}"#, false)
+            .extract("Ana is 31")
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("$.age should be integer"));
+    }
+
+    #[tokio::test]
+    async fn test_extract_many() {
+        let people = chain(
+            r#"```json
+{"items": [{"name": "Ana", "age": 31}, {"name": "Luis"}]}
+```"#,
+            true,
+        )
+        .extract("Ana is 31, her brother is Luis")
+        .await
+        .unwrap();
+        assert_eq!(
+            people,
+            json!([{"name": "Ana", "age": 31}, {"name": "Luis"}])
+        );
+
+        let error = chain(r#"{"items": [{"age": 3}]}"#, true)
+            .extract("someone is 3")
+            .await
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("[0] is missing the required property name"));
+    }
+}
@@ -1,14 +1,16 @@
-use std::pin::Pin;
+use std::{fmt, pin::Pin, sync::Arc};
 
+use async_stream::stream;
 use async_trait::async_trait;
-use futures::Stream;
-use futures_util::TryStreamExt;
+use futures::{pin_mut, Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
 
 use crate::{
     language_models::{llm::LLM, GenerateResult},
-    output_parsers::{OutputParser, SimpleParser},
+    output_parsers::{OutputParser, OutputParserError, SimpleParser, FORMAT_INSTRUCTIONS_KEY},
     prompt::{FormatPrompter, PromptArgs},
-    schemas::StreamData,
+    schemas::{Message, StreamData},
 };
 
 use super::{chain_trait::Chain, options::ChainCallOptions, ChainError};
@@ -19,6 +21,7 @@ pub struct LLMChainBuilder {
     output_key: Option<String>,
     options: Option<ChainCallOptions>,
     output_parser: Option<Box<dyn OutputParser>>,
+    format_instructions: bool,
 }
 
 impl LLMChainBuilder {
@@ -29,6 +32,7 @@ impl LLMChainBuilder {
             options: None,
             output_key: None,
             output_parser: None,
+            format_instructions: false,
         }
     }
     pub fn options(mut self, options: ChainCallOptions) -> Self {
@@ -56,6 +60,14 @@ impl LLMChainBuilder {
         self
     }
 
+    /// Tells the model how to format its answer with the format instructions of the output
+    /// parser. They fill the `{format_instructions}` variable of the prompt, or are appended
+    /// to its last message when the prompt has no such variable. Disabled by default.
+    pub fn format_instructions(mut self, format_instructions: bool) -> Self {
+        self.format_instructions = format_instructions;
+        self
+    }
+
     pub fn build(self) -> Result<LLMChain, ChainError> {
         let prompt = self
             .prompt
@@ -71,29 +83,116 @@ impl LLMChainBuilder {
         }
 
         let chain = LLMChain {
-            prompt,
+            prompt: prompt.into(),
             llm,
             output_key: self.output_key.unwrap_or("output".to_string()),
             output_parser: self
                 .output_parser
-                .unwrap_or_else(|| Box::new(SimpleParser::default())),
+                .map(Arc::<dyn OutputParser>::from)
+                .unwrap_or_else(|| Arc::new(SimpleParser::default())),
+            format_instructions: self.format_instructions,
         };
 
         Ok(chain)
     }
 }
 
+/// A prompt formatted and sent to an LLM. Clones share the prompt and the output parser,
+/// and have a clone of the LLM.
 pub struct LLMChain {
-    prompt: Box<dyn FormatPrompter>,
+    prompt: Arc<dyn FormatPrompter>,
     llm: Box<dyn LLM>,
     output_key: String,
-    output_parser: Box<dyn OutputParser>,
+    output_parser: Arc<dyn OutputParser>,
+    format_instructions: bool,
+}
+
+impl Clone for LLMChain {
+    fn clone(&self) -> Self {
+        Self {
+            prompt: self.prompt.clone(),
+            llm: self.llm.clone_box(),
+            output_key: self.output_key.clone(),
+            output_parser: self.output_parser.clone(),
+            format_instructions: self.format_instructions,
+        }
+    }
+}
+
+impl fmt::Debug for LLMChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LLMChain")
+            .field("input_variables", &self.prompt.get_input_variables())
+            .field("output_key", &self.output_key)
+            .field("format_instructions", &self.format_instructions)
+            .finish_non_exhaustive()
+    }
+}
+
+impl LLMChain {
+    fn prompt_messages(&self, mut input_variables: PromptArgs) -> Result<Vec<Message>, ChainError> {
+        if !self.format_instructions {
+            let prompt = self.prompt.format_prompt(input_variables)?;
+            log::debug!("Prompt: {:?}", prompt);
+            return Ok(prompt.to_chat_messages());
+        }
+
+        let instructions = self.output_parser.format_instructions();
+        let in_template = self
+            .prompt
+            .get_input_variables()
+            .iter()
+            .any(|variable| variable == FORMAT_INSTRUCTIONS_KEY);
+        if in_template {
+            input_variables
+                .entry(FORMAT_INSTRUCTIONS_KEY.to_string())
+                .or_insert(instructions.clone().into());
+        }
+
+        let prompt = self.prompt.format_prompt(input_variables)?;
+        log::debug!("Prompt: {:?}", prompt);
+        let mut messages = prompt.to_chat_messages();
+        if !in_template && !instructions.is_empty() {
+            if let Some(message) = messages.last_mut() {
+                message.content = format!("{}\n\n{}", message.content, instructions);
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Parses the generation with the output parser and deserializes it into `T`, like a
+    /// `Vec<String>` with a `ListOutputParser`. The parsed output is read as JSON, and as a
+    /// JSON string when it isn't JSON, so `T` can be a `String` with the default parser.
+    ///
+    /// Both a failure of the parser and an output that doesn't match `T` are returned as
+    /// `ChainError::OutputParser`. Enable `format_instructions` on the builder to tell the
+    /// model the format the parser expects.
+    pub async fn invoke_parsed<T: DeserializeOwned>(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<T, ChainError> {
+        let messages = self.prompt_messages(input_variables)?;
+        let output = self.llm.generate(&messages).await?.generation;
+        let parsed = self.output_parser.parse(&output).await?;
+        serde_json::from_str(&parsed)
+            .or_else(|_| serde_json::from_value(Value::String(parsed.clone())))
+            .map_err(|e| {
+                ChainError::OutputParser(OutputParserError::ParsingError(format!(
+                    "{}, parsed output: {}",
+                    e, parsed
+                )))
+            })
+    }
 }
 
 #[async_trait]
 impl Chain for LLMChain {
     fn get_input_keys(&self) -> Vec<String> {
-        self.prompt.get_input_variables()
+        let mut keys = self.prompt.get_input_variables();
+        if self.format_instructions {
+            keys.retain(|key| key != FORMAT_INSTRUCTIONS_KEY);
+        }
+        keys
     }
 
     fn get_output_keys(&self) -> Vec<String> {
@@ -101,53 +200,212 @@ impl Chain for LLMChain {
     }
 
     async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
-        let prompt = self.prompt.format_prompt(input_variables.clone())?;
-        log::debug!("Prompt: {:?}", prompt);
-        let mut output = self.llm.generate(&prompt.to_chat_messages()).await?;
+        let messages = self.prompt_messages(input_variables)?;
+        let mut output = self.llm.generate(&messages).await?;
         output.generation = self.output_parser.parse(&output.generation).await?;
 
         Ok(output)
     }
 
     async fn invoke(&self, input_variables: PromptArgs) -> Result<String, ChainError> {
-        let prompt = self.prompt.format_prompt(input_variables.clone())?;
-        log::debug!("Prompt: {:?}", prompt);
-        let output = self
-            .llm
-            .generate(&prompt.to_chat_messages())
-            .await?
-            .generation;
+        let messages = self.prompt_messages(input_variables)?;
+        let output = self.llm.generate(&messages).await?.generation;
         Ok(output)
     }
 
+    /// Streams the generation of the LLM.
+    ///
+    /// The providers report the token usage differently: some on a chunk of its own at the
+    /// end, some on several chunks. The chain hides it: the content chunks never carry
+    /// `tokens`, and when the provider reported any usage, the stream ends with exactly
+    /// one `StreamData` with empty content and the last usage reported. At most one item
+    /// of the stream has `tokens` set, and it is the last one.
+    ///
+    /// OpenAI and the OpenAI compatible APIs only report the usage of a stream when asked
+    /// to, see `ChainCallOptions::with_stream_usage`.
     async fn stream(
         &self,
         input_variables: PromptArgs,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
     {
-        let prompt = self.prompt.format_prompt(input_variables.clone())?;
-        log::debug!("Prompt: {:?}", prompt);
-        let llm_stream = self.llm.stream(&prompt.to_chat_messages()).await?;
+        let messages = self.prompt_messages(input_variables)?;
+        let llm_stream = self.llm.stream(&messages).await?;
 
-        // Map the errors from LLMError to ChainError
-        let mapped_stream = llm_stream.map_err(ChainError::from);
+        let output_stream = stream! {
+            pin_mut!(llm_stream);
+            let mut usage = None;
+            while let Some(result) = llm_stream.next().await {
+                match result {
+                    Ok(mut data) => {
+                        if let Some(tokens) = data.tokens.take() {
+                            usage = Some((data.value.clone(), tokens));
+                            if data.content.is_empty() {
+                                continue;
+                            }
+                        }
+                        yield Ok(data);
+                    }
+                    Err(e) => yield Err(ChainError::from(e)),
+                }
+            }
+            if let Some((value, tokens)) = usage {
+                yield Ok(StreamData::new(value, Some(tokens), ""));
+            }
+        };
 
-        Ok(Box::pin(mapped_stream))
+        Ok(Box::pin(output_stream))
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use serde_json::json;
+
     use crate::{
         chain::options::ChainCallOptions,
-        llm::openai::{OpenAI, OpenAIModel},
+        language_models::TokenUsage,
+        llm::{
+            fake::FakeLLM,
+            openai::{OpenAI, OpenAIModel},
+        },
         message_formatter,
+        output_parsers::ListOutputParser,
         prompt::{HumanMessagePromptTemplate, MessageOrTemplate},
         prompt_args, template_fstring,
     };
 
     use super::*;
 
+    /// Streams "Hello world", reporting a growing usage on the content chunks and the
+    /// final usage on a chunk of its own.
+    fn usage_llm() -> FakeLLM {
+        FakeLLM::fixed("Hello world").with_chunks([
+            StreamData::new(json!(1), Some(TokenUsage::new(5, 1)), "Hello"),
+            StreamData::new(json!(2), Some(TokenUsage::new(5, 2)), " world"),
+            StreamData::new(json!(3), None, ""),
+            StreamData::new(json!(4), Some(TokenUsage::new(5, 3)), ""),
+        ])
+    }
+
+    /// Answers with the content of the last message.
+    fn echo_llm() -> FakeLLM {
+        FakeLLM::answering(|messages| messages.last().unwrap().content.clone())
+    }
+
+    #[tokio::test]
+    async fn test_format_instructions() {
+        let instructions = ListOutputParser::new().format_instructions();
+
+        let chain = LLMChainBuilder::new()
+            .prompt(message_formatter![MessageOrTemplate::Template(
+                HumanMessagePromptTemplate::new(template_fstring!(
+                    "List {topic}. {format_instructions}",
+                    "topic",
+                    "format_instructions"
+                ))
+                .into()
+            )])
+            .llm(echo_llm())
+            .output_parser(ListOutputParser::new())
+            .format_instructions(true)
+            .build()
+            .unwrap();
+        assert_eq!(chain.get_input_keys(), vec!["topic"]);
+        let output = chain
+            .invoke(prompt_args! {"topic" => "colors"})
+            .await
+            .unwrap();
+        assert_eq!(output, format!("List colors. {}", instructions));
+
+        let chain = LLMChainBuilder::new()
+            .prompt(message_formatter![MessageOrTemplate::Template(
+                HumanMessagePromptTemplate::new(template_fstring!("List {topic}.", "topic")).into()
+            )])
+            .llm(echo_llm())
+            .output_parser(ListOutputParser::new())
+            .format_instructions(true)
+            .build()
+            .unwrap();
+        let output = chain
+            .invoke(prompt_args! {"topic" => "colors"})
+            .await
+            .unwrap();
+        assert_eq!(output, format!("List colors.\n\n{}", instructions));
+    }
+
+    #[tokio::test]
+    async fn test_invoke_parsed() {
+        let prompt = || {
+            message_formatter![MessageOrTemplate::Template(
+                HumanMessagePromptTemplate::new(template_fstring!("{answer}", "answer")).into()
+            )]
+        };
+        let chain = LLMChainBuilder::new()
+            .prompt(prompt())
+            .llm(echo_llm())
+            .output_parser(ListOutputParser::new())
+            .build()
+            .unwrap();
+        let colors: Vec<String> = chain
+            .invoke_parsed(prompt_args! {"answer" => "Colors:\n- red\n- green"})
+            .await
+            .unwrap();
+        assert_eq!(colors, vec!["red", "green"]);
+
+        let error = chain
+            .invoke_parsed::<Vec<String>>(prompt_args! {"answer" => " "})
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ChainError::OutputParser(_)));
+        let error = chain
+            .invoke_parsed::<Vec<u32>>(prompt_args! {"answer" => "- red"})
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ChainError::OutputParser(_)));
+
+        let chain = LLMChainBuilder::new()
+            .prompt(prompt())
+            .llm(echo_llm())
+            .build()
+            .unwrap();
+        let answer: String = chain
+            .invoke_parsed(prompt_args! {"answer" => "Hello"})
+            .await
+            .unwrap();
+        assert_eq!(answer, "Hello");
+        let number: u32 = chain
+            .invoke_parsed(prompt_args! {"answer" => "42"})
+            .await
+            .unwrap();
+        assert_eq!(number, 42);
+    }
+
+    #[tokio::test]
+    async fn test_stream_ends_with_a_single_usage() {
+        let chain = LLMChainBuilder::new()
+            .prompt(message_formatter![MessageOrTemplate::Template(
+                HumanMessagePromptTemplate::new(template_fstring!("Hi {name}", "name")).into()
+            )])
+            .llm(usage_llm())
+            .build()
+            .unwrap();
+
+        let items: Vec<StreamData> = chain
+            .stream(prompt_args! {"name" => "luis"})
+            .await
+            .unwrap()
+            .map(|data| data.unwrap())
+            .collect()
+            .await;
+
+        let content: String = items.iter().map(|data| data.content.as_str()).collect();
+        assert_eq!(content, "Hello world");
+        let (last, rest) = items.split_last().unwrap();
+        assert!(rest.iter().all(|data| data.tokens.is_none()));
+        assert_eq!(last.content, "");
+        assert_eq!(last.tokens.as_ref().unwrap().completion_tokens, 3);
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_invoke_chain() {
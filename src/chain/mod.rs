@@ -22,6 +22,12 @@ pub use question_answering::*;
 mod conversational_retrieval_qa;
 pub use conversational_retrieval_qa::*;
 
+mod retrieval_qa;
+pub use retrieval_qa::*;
+
+mod extraction;
+pub use extraction::*;
+
 mod error;
 pub use error::*;
 
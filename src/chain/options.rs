@@ -1,7 +1,7 @@
 use futures::Future;
-use std::pin::Pin;
+use std::{fmt, pin::Pin, sync::Arc};
 
-use crate::language_models::options::CallOptions;
+use crate::{callbacks::CallbackHandler, language_models::options::CallOptions};
 
 pub struct ChainCallOptions {
     pub max_tokens: Option<u32>,
@@ -16,6 +16,32 @@ pub struct ChainCallOptions {
     pub min_length: Option<usize>,
     pub max_length: Option<usize>,
     pub repetition_penalty: Option<f32>,
+    pub json_mode: Option<bool>,
+    pub stream_usage: Option<bool>,
+    pub callbacks: Option<Vec<Arc<dyn CallbackHandler>>>,
+}
+
+impl fmt::Debug for ChainCallOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChainCallOptions")
+            .field("max_tokens", &self.max_tokens)
+            .field("temperature", &self.temperature)
+            .field("stop_words", &self.stop_words)
+            .field("streaming_func", &self.streaming_func.is_some())
+            .field("top_k", &self.top_k)
+            .field("top_p", &self.top_p)
+            .field("seed", &self.seed)
+            .field("min_length", &self.min_length)
+            .field("max_length", &self.max_length)
+            .field("repetition_penalty", &self.repetition_penalty)
+            .field("json_mode", &self.json_mode)
+            .field("stream_usage", &self.stream_usage)
+            .field(
+                "callbacks",
+                &self.callbacks.as_ref().map(|callbacks| callbacks.len()),
+            )
+            .finish()
+    }
 }
 
 impl Default for ChainCallOptions {
@@ -37,6 +63,9 @@ impl ChainCallOptions {
             min_length: None,
             max_length: None,
             repetition_penalty: None,
+            json_mode: None,
+            stream_usage: None,
+            callbacks: None,
         }
     }
 
@@ -69,6 +98,15 @@ impl ChainCallOptions {
         if let Some(repetition_penalty) = options.repetition_penalty {
             llm_option = llm_option.with_repetition_penalty(repetition_penalty);
         }
+        if let Some(json_mode) = options.json_mode {
+            llm_option = llm_option.with_json_mode(json_mode);
+        }
+        if let Some(stream_usage) = options.stream_usage {
+            llm_option = llm_option.with_stream_usage(stream_usage);
+        }
+        if let Some(callbacks) = options.callbacks {
+            llm_option = llm_option.with_callbacks(callbacks);
+        }
 
         if let Some(streaming_func) = options.streaming_func {
             llm_option = llm_option.with_streaming_func(streaming_func)
@@ -130,4 +168,21 @@ impl ChainCallOptions {
         self.repetition_penalty = Some(repetition_penalty);
         self
     }
+
+    pub fn with_json_mode(mut self, json_mode: bool) -> Self {
+        self.json_mode = Some(json_mode);
+        self
+    }
+
+    /// Asks the providers that only report the token usage of a stream on request, like
+    /// OpenAI and the OpenAI compatible APIs, to send it.
+    pub fn with_stream_usage(mut self, stream_usage: bool) -> Self {
+        self.stream_usage = Some(stream_usage);
+        self
+    }
+
+    pub fn with_callbacks(mut self, callbacks: Vec<Arc<dyn CallbackHandler>>) -> Self {
+        self.callbacks = Some(callbacks);
+        self
+    }
 }
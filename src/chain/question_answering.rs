@@ -22,6 +22,7 @@ Chat History:
 Follow Up Input: {{question}}
 Standalone question:"#;
 
+#[derive(Debug, Clone)]
 pub struct CondenseQuestionPromptBuilder {
     chat_history: String,
     question: String,
@@ -52,6 +53,7 @@ impl CondenseQuestionPromptBuilder {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct CondenseQuestionGeneratorChain {
     chain: LLMChain,
 }
@@ -98,6 +100,15 @@ Question:{{question}}
 Helpful Answer:
 "#;
 
+pub(crate) const DEFAULT_STUFF_QA_WITH_CITATIONS_TEMPLATE: &str = r#"Use the following numbered sources to answer the question at the end. Cite the sources each statement comes from with their number in square brackets, like [1] or [1, 3]. If you don't know the answer, just say that you don't know, don't try to make up an answer.
+
+{{context}}
+
+Question:{{question}}
+Helpful Answer:
+"#;
+
+#[derive(Debug, Clone)]
 pub struct StuffQAPromptBuilder<'a> {
     input_documents: Vec<&'a Document>,
     question: String,
@@ -148,6 +159,26 @@ pub(crate) fn load_stuff_qa<L: Into<Box<dyn LLM>>>(
     StuffDocument::new(llm_chain)
 }
 
+pub(crate) fn load_stuff_qa_with_citations<L: Into<Box<dyn LLM>>>(
+    llm: L,
+    options: Option<ChainCallOptions>,
+) -> StuffDocument {
+    let qa_prompt_template = template_jinja2!(
+        DEFAULT_STUFF_QA_WITH_CITATIONS_TEMPLATE,
+        "context",
+        "question"
+    );
+
+    let llm_chain = LLMChainBuilder::new()
+        .prompt(qa_prompt_template)
+        .options(options.unwrap_or_default())
+        .llm(llm)
+        .build()
+        .unwrap();
+
+    StuffDocument::new(llm_chain).with_citations(true)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
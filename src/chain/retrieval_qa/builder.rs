@@ -0,0 +1,151 @@
+use crate::{
+    chain::{
+        validate_prompt_variables, Chain, ChainError, StuffDocumentBuilder, DEFAULT_OUTPUT_KEY,
+    },
+    language_models::llm::LLM,
+    prompt::FormatPrompter,
+    schemas::Retriever,
+};
+
+use super::RetrievalQaChain;
+
+const RETRIEVAL_QA_DEFAULT_INPUT_KEY: &str = "question";
+const DEFAULT_NO_DOCUMENTS_ANSWER: &str =
+    "I don't know, I couldn't find any information to answer the question.";
+
+/// Retrieval QA Chain Builder
+/// # Usage
+/// ```rust,ignore
+/// let chain = RetrievalQaChainBuilder::new()
+///     .llm(llm)
+///     .retriever(Retriever::new(store, 10))
+///     .top_k(4)
+///     .return_source_documents(true)
+///     .build()
+///     .expect("Error building RetrievalQaChain");
+/// ```
+pub struct RetrievalQaChainBuilder {
+    llm: Option<Box<dyn LLM>>,
+    retriever: Option<Box<dyn Retriever>>,
+    combine_documents_chain: Option<Box<dyn Chain>>,
+    prompt: Option<Box<dyn FormatPrompter>>,
+    return_source_documents: bool,
+    top_k: Option<usize>,
+    no_documents_answer: String,
+    input_key: String,
+    output_key: String,
+}
+
+impl RetrievalQaChainBuilder {
+    pub fn new() -> Self {
+        Self {
+            llm: None,
+            retriever: None,
+            combine_documents_chain: None,
+            prompt: None,
+            return_source_documents: false,
+            top_k: None,
+            no_documents_answer: DEFAULT_NO_DOCUMENTS_ANSWER.to_string(),
+            input_key: RETRIEVAL_QA_DEFAULT_INPUT_KEY.to_string(),
+            output_key: DEFAULT_OUTPUT_KEY.to_string(),
+        }
+    }
+
+    pub fn llm<L: Into<Box<dyn LLM>>>(mut self, llm: L) -> Self {
+        self.llm = Some(llm.into());
+        self
+    }
+
+    pub fn retriever<R: Into<Box<dyn Retriever>>>(mut self, retriever: R) -> Self {
+        self.retriever = Some(retriever.into());
+        self
+    }
+
+    ///Chain designed to take the documents and the question and generate an output
+    pub fn combine_documents_chain<C: Into<Box<dyn Chain>>>(
+        mut self,
+        combine_documents_chain: C,
+    ) -> Self {
+        self.combine_documents_chain = Some(combine_documents_chain.into());
+        self
+    }
+
+    ///If you want to add a custom prompt,keep in mind which variables are obligatory.
+    ///The prompt must declare the `context` variable, and may declare the `question` one.
+    pub fn prompt<P: Into<Box<dyn FormatPrompter>>>(mut self, prompt: P) -> Self {
+        self.prompt = Some(prompt.into());
+        self
+    }
+
+    /// Adds the retrieved documents to the output, under `source_documents`.
+    pub fn return_source_documents(mut self, return_source_documents: bool) -> Self {
+        self.return_source_documents = return_source_documents;
+        self
+    }
+
+    /// Number of retrieved documents given to the LLM, the first ones returned by the
+    /// retriever. By default all of them are used.
+    pub fn top_k(mut self, top_k: usize) -> Self {
+        self.top_k = Some(top_k);
+        self
+    }
+
+    /// Answer returned without calling the LLM when no document is retrieved.
+    pub fn no_documents_answer<S: Into<String>>(mut self, no_documents_answer: S) -> Self {
+        self.no_documents_answer = no_documents_answer.into();
+        self
+    }
+
+    pub fn input_key<S: Into<String>>(mut self, input_key: S) -> Self {
+        self.input_key = input_key.into();
+        self
+    }
+
+    pub fn output_key<S: Into<String>>(mut self, output_key: S) -> Self {
+        self.output_key = output_key.into();
+        self
+    }
+
+    pub fn build(mut self) -> Result<RetrievalQaChain, ChainError> {
+        if let Some(prompt) = &self.prompt {
+            validate_prompt_variables(
+                prompt.as_ref(),
+                &["context"],
+                Some(&["context", "question"]),
+            )?;
+        }
+
+        if let Some(llm) = self.llm {
+            let mut builder = StuffDocumentBuilder::new().llm(llm);
+            if let Some(prompt) = self.prompt {
+                builder = builder.prompt(prompt);
+            }
+            self.combine_documents_chain = Some(Box::new(builder.build()?));
+        }
+
+        let retriever = self
+            .retriever
+            .ok_or_else(|| ChainError::MissingObject("Retriever must be set".into()))?;
+        let combine_documents_chain = self.combine_documents_chain.ok_or_else(|| {
+            ChainError::MissingObject(
+                "Combine documents chain must be set or llm must be set".into(),
+            )
+        })?;
+
+        Ok(RetrievalQaChain {
+            retriever,
+            combine_documents_chain,
+            return_source_documents: self.return_source_documents,
+            top_k: self.top_k,
+            no_documents_answer: self.no_documents_answer,
+            input_key: self.input_key,
+            output_key: self.output_key,
+        })
+    }
+}
+
+impl Default for RetrievalQaChainBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,266 @@
+use std::{collections::HashMap, fmt, pin::Pin};
+
+use async_trait::async_trait;
+use futures::{stream, Stream};
+use serde_json::{json, Value};
+
+use crate::{
+    chain::{
+        Chain, ChainError, StuffQAPromptBuilder, DEFAULT_RESULT_KEY, STUFF_DOCUMENTS_CITATIONS_KEY,
+    },
+    language_models::GenerateResult,
+    prompt::PromptArgs,
+    schemas::{Document, Retriever, StreamData},
+};
+
+const RETRIEVAL_QA_DEFAULT_SOURCE_DOCUMENT_KEY: &str = "source_documents";
+
+/// Answers a question with the documents of a retriever, stuffed in the prompt of the
+/// combine documents chain. Unlike the `ConversationalRetrieverChain` it has no memory,
+/// each question is answered on its own.
+///
+/// When no document is retrieved the LLM is not called, the chain answers with the
+/// `no_documents_answer` of the builder.
+pub struct RetrievalQaChain {
+    pub(crate) retriever: Box<dyn Retriever>,
+    pub(crate) combine_documents_chain: Box<dyn Chain>,
+    pub(crate) return_source_documents: bool,
+    pub(crate) top_k: Option<usize>,
+    pub(crate) no_documents_answer: String,
+    pub(crate) input_key: String,  //Default is `question`
+    pub(crate) output_key: String, //default is output
+}
+
+impl fmt::Debug for RetrievalQaChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetrievalQaChain")
+            .field(
+                "combine_documents_chain",
+                &self.combine_documents_chain.name(),
+            )
+            .field("return_source_documents", &self.return_source_documents)
+            .field("top_k", &self.top_k)
+            .field("no_documents_answer", &self.no_documents_answer)
+            .field("input_key", &self.input_key)
+            .field("output_key", &self.output_key)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RetrievalQaChain {
+    async fn retrieve(
+        &self,
+        input_variables: &PromptArgs,
+    ) -> Result<(String, Vec<Document>), ChainError> {
+        let question = match input_variables
+            .get(&self.input_key)
+            .ok_or(ChainError::MissingInputVariable(self.input_key.clone()))?
+        {
+            Value::String(question) => question.clone(),
+            question => question.to_string(),
+        };
+
+        let mut documents = self
+            .retriever
+            .get_relevant_documents(&question)
+            .await
+            .map_err(ChainError::RetrieverError)?;
+        if let Some(top_k) = self.top_k {
+            documents.truncate(top_k);
+        }
+        Ok((question, documents))
+    }
+}
+
+#[async_trait]
+impl Chain for RetrievalQaChain {
+    async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
+        let output = self.execute(input_variables).await?;
+        let result: GenerateResult = serde_json::from_value(output[DEFAULT_RESULT_KEY].clone())?;
+        Ok(result)
+    }
+
+    async fn execute(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<HashMap<String, Value>, ChainError> {
+        let (question, documents) = self.retrieve(&input_variables).await?;
+
+        let mut result = HashMap::new();
+        let output = if documents.is_empty() {
+            log::info!("No documents retrieved for the question, the LLM is not called");
+            GenerateResult {
+                generation: self.no_documents_answer.clone(),
+                tokens: None,
+                ..Default::default()
+            }
+        } else {
+            let mut combined = self
+                .combine_documents_chain
+                .execute(
+                    StuffQAPromptBuilder::new()
+                        .documents(&documents)
+                        .question(question)
+                        .build(),
+                )
+                .await?;
+            if let Some(citations) = combined.remove(STUFF_DOCUMENTS_CITATIONS_KEY) {
+                result.insert(STUFF_DOCUMENTS_CITATIONS_KEY.to_string(), citations);
+            }
+            serde_json::from_value(combined.remove(DEFAULT_RESULT_KEY).unwrap_or_default())?
+        };
+
+        result.insert(self.output_key.clone(), json!(output.generation));
+        result.insert(DEFAULT_RESULT_KEY.to_string(), json!(output));
+        if self.return_source_documents {
+            result.insert(
+                RETRIEVAL_QA_DEFAULT_SOURCE_DOCUMENT_KEY.to_string(),
+                json!(documents),
+            );
+        }
+
+        Ok(result)
+    }
+
+    async fn stream(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
+    {
+        let (question, documents) = self.retrieve(&input_variables).await?;
+
+        if documents.is_empty() {
+            let answer = self.no_documents_answer.clone();
+            return Ok(Box::pin(stream::once(async move {
+                Ok(StreamData::new(json!(answer), None, answer))
+            })));
+        }
+
+        self.combine_documents_chain
+            .stream(
+                StuffQAPromptBuilder::new()
+                    .documents(&documents)
+                    .question(question)
+                    .build(),
+            )
+            .await
+    }
+
+    fn get_input_keys(&self) -> Vec<String> {
+        vec![self.input_key.clone()]
+    }
+
+    fn get_output_keys(&self) -> Vec<String> {
+        let mut keys = Vec::new();
+        if self.return_source_documents {
+            keys.push(RETRIEVAL_QA_DEFAULT_SOURCE_DOCUMENT_KEY.to_string());
+        }
+        keys.push(self.output_key.clone());
+        keys.push(DEFAULT_RESULT_KEY.to_string());
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::StreamExt;
+
+    use super::*;
+    use crate::{
+        chain::{Citation, RetrievalQaChainBuilder, StuffDocument},
+        llm::fake::FakeLLM,
+        prompt_args,
+        schemas::RetrieverError,
+    };
+
+    /// Answers with the prompt it got.
+    fn echo_llm() -> FakeLLM {
+        FakeLLM::answering(|messages| messages[0].content.clone())
+    }
+
+    struct RetrieverTest {
+        documents: Vec<&'static str>,
+    }
+
+    #[async_trait]
+    impl Retriever for RetrieverTest {
+        async fn get_relevant_documents(
+            &self,
+            _question: &str,
+        ) -> Result<Vec<Document>, RetrieverError> {
+            Ok(self.documents.iter().map(|d| Document::new(*d)).collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retrieval_qa() {
+        let chain = RetrievalQaChainBuilder::new()
+            .llm(echo_llm())
+            .retriever(RetrieverTest {
+                documents: vec!["Luis lives in Peru", "Luis is 24", "Luis likes Nvim"],
+            })
+            .top_k(2)
+            .return_source_documents(true)
+            .build()
+            .unwrap();
+
+        let output = chain
+            .execute(prompt_args! {"question" => "Where does Luis live?"})
+            .await
+            .unwrap();
+        let answer = output["output"].as_str().unwrap();
+        assert!(answer.contains("Luis lives in Peru"));
+        assert!(answer.contains("Where does Luis live?"));
+        assert!(!answer.contains("Nvim"));
+        assert_eq!(output["source_documents"].as_array().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_retrieval_qa_with_citations() {
+        let chain = RetrievalQaChainBuilder::new()
+            .retriever(RetrieverTest {
+                documents: vec!["Luis lives in Peru", "Luis is 24"],
+            })
+            .combine_documents_chain(StuffDocument::load_stuff_qa_with_citations(FakeLLM::fixed(
+                "Luis lives in Peru [1]",
+            )))
+            .build()
+            .unwrap();
+
+        let output = chain
+            .execute(prompt_args! {"question" => "Where does Luis live?"})
+            .await
+            .unwrap();
+        assert_eq!(output["output"], "Luis lives in Peru [1]");
+        let citations: Vec<Citation> = serde_json::from_value(output["citations"].clone()).unwrap();
+        assert_eq!(citations.len(), 1);
+        assert_eq!(citations[0].index, 1);
+        assert_eq!(citations[0].document.page_content, "Luis lives in Peru");
+    }
+
+    #[tokio::test]
+    async fn test_retrieval_qa_without_documents() {
+        let chain = RetrievalQaChainBuilder::new()
+            .llm(echo_llm())
+            .retriever(RetrieverTest { documents: vec![] })
+            .no_documents_answer("I don't know")
+            .build()
+            .unwrap();
+
+        let answer = chain
+            .invoke(prompt_args! {"question" => "Where does Luis live?"})
+            .await
+            .unwrap();
+        assert_eq!(answer, "I don't know");
+
+        let mut stream = chain
+            .stream(prompt_args! {"question" => "Where does Luis live?"})
+            .await
+            .unwrap();
+        assert_eq!(
+            stream.next().await.unwrap().unwrap().content,
+            "I don't know"
+        );
+        assert!(stream.next().await.is_none());
+    }
+}
@@ -1,4 +1,7 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
 
 use async_trait::async_trait;
 use serde_json::{json, Value};
@@ -16,6 +19,17 @@ pub struct SequentialChain {
     pub(crate) outputs: HashSet<String>,
 }
 
+impl fmt::Debug for SequentialChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let chains: Vec<&str> = self.chains.iter().map(|chain| chain.name()).collect();
+        f.debug_struct("SequentialChain")
+            .field("chains", &chains)
+            .field("input_keys", &self.input_keys)
+            .field("outputs", &self.outputs)
+            .finish()
+    }
+}
+
 #[async_trait]
 impl Chain for SequentialChain {
     async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
@@ -36,10 +50,26 @@ impl Chain for SequentialChain {
         self.outputs.iter().cloned().collect()
     }
 
+    fn get_required_input_keys(&self) -> Vec<String> {
+        //Keys produced by a previous chain are passed internally to the next ones
+        let mut produced: HashSet<String> = HashSet::new();
+        let mut required: Vec<String> = Vec::new();
+        for chain in self.chains.iter() {
+            for key in chain.get_required_input_keys() {
+                if !produced.contains(&key) && !required.contains(&key) {
+                    required.push(key);
+                }
+            }
+            produced.extend(chain.get_output_keys());
+        }
+        required
+    }
+
     async fn execute(
         &self,
         input_variables: PromptArgs,
     ) -> Result<HashMap<String, Value>, ChainError> {
+        self.validate_input_variables(&input_variables)?;
         let mut input_variables = input_variables;
         let mut final_token_usage: Option<TokenUsage> = None;
         let mut output_result = HashMap::new();
@@ -88,11 +118,78 @@ impl Chain for SequentialChain {
 #[cfg(test)]
 mod tests {
     use crate::{
-        chain::{Chain, LLMChainBuilder},
+        chain::{Chain, ChainError, LLMChainBuilder},
+        language_models::GenerateResult,
         llm::openai::OpenAI,
+        prompt::PromptArgs,
         prompt_args, sequential_chain, template_fstring,
     };
 
+    use async_trait::async_trait;
+
+    struct KeysChain {
+        input_keys: Vec<String>,
+        output_key: String,
+    }
+
+    impl KeysChain {
+        fn new(input_keys: &[&str], output_key: &str) -> Self {
+            Self {
+                input_keys: input_keys.iter().map(|k| k.to_string()).collect(),
+                output_key: output_key.to_string(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Chain for KeysChain {
+        async fn call(&self, _input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
+            Ok(GenerateResult {
+                generation: self.output_key.clone(),
+                ..Default::default()
+            })
+        }
+
+        fn get_input_keys(&self) -> Vec<String> {
+            self.input_keys.clone()
+        }
+
+        fn get_output_keys(&self) -> Vec<String> {
+            vec![self.output_key.clone()]
+        }
+    }
+
+    #[test]
+    fn test_sequential_required_input_keys() {
+        let chain = sequential_chain!(
+            KeysChain::new(&["input"], "nombre"),
+            KeysChain::new(&["nombre", "palabra"], "slogan")
+        );
+
+        assert_eq!(
+            chain.get_required_input_keys(),
+            vec!["input".to_string(), "palabra".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sequential_reports_all_missing_keys() {
+        let chain = sequential_chain!(
+            KeysChain::new(&["input"], "nombre"),
+            KeysChain::new(&["nombre", "palabra"], "slogan")
+        );
+
+        match chain.execute(prompt_args! {}).await {
+            Err(ChainError::MissingInputVariable(keys)) => assert_eq!(keys, "input, palabra"),
+            other => panic!("Expected MissingInputVariable, got {:?}", other),
+        }
+
+        let result = chain
+            .execute(prompt_args! {"input"=>"medias","palabra"=>"arroz"})
+            .await;
+        assert!(result.is_ok());
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_sequential() {
@@ -1,10 +1,11 @@
 use crate::{
     chain::{
-        llm_chain::LLMChainBuilder, options::ChainCallOptions, ChainError, DEFAULT_OUTPUT_KEY,
+        llm_chain::LLMChainBuilder, options::ChainCallOptions, validate_prompt_variables,
+        ChainError, DEFAULT_OUTPUT_KEY,
     },
     language_models::llm::LLM,
     output_parsers::OutputParser,
-    prompt::HumanMessagePromptTemplate,
+    prompt::{FormatPrompter, HumanMessagePromptTemplate},
     template_jinja2,
     tools::SQLDatabase,
 };
@@ -22,6 +23,7 @@ pub struct SQLDatabaseChainBuilder {
     database: Option<SQLDatabase>,
     output_key: Option<String>,
     output_parser: Option<Box<dyn OutputParser>>,
+    prompt: Option<Box<dyn FormatPrompter>>,
 }
 
 impl SQLDatabaseChainBuilder {
@@ -33,6 +35,7 @@ impl SQLDatabaseChainBuilder {
             database: None,
             output_key: None,
             output_parser: None,
+            prompt: None,
         }
     }
 
@@ -66,6 +69,15 @@ impl SQLDatabaseChainBuilder {
         self
     }
 
+    ///If you want to add a custom prompt,keep in mind which variables are obligatory.
+    ///The prompt must declare the `input` and `table_info` variables, and may declare `dialect`
+    ///and `top_k`. The model is stopped at `SQLResult:`, so the prompt should ask for the query
+    ///followed by it, like the default one.
+    pub fn prompt<P: Into<Box<dyn FormatPrompter>>>(mut self, prompt: P) -> Self {
+        self.prompt = Some(prompt.into());
+        self
+    }
+
     pub fn build(self) -> Result<SQLDatabaseChain, ChainError> {
         let llm = self
             .llm
@@ -77,13 +89,21 @@ impl SQLDatabaseChainBuilder {
             .database
             .ok_or_else(|| ChainError::MissingObject("Database must be set".into()))?;
 
-        let prompt = HumanMessagePromptTemplate::new(template_jinja2!(
-            format!("{}{}", DEFAULT_SQLTEMPLATE, DEFAULT_SQLSUFFIX),
-            "dialect",
-            "table_info",
-            "top_k",
-            "input"
-        ));
+        let prompt = match self.prompt {
+            Some(prompt) => prompt,
+            None => Box::new(HumanMessagePromptTemplate::new(template_jinja2!(
+                format!("{}{}", DEFAULT_SQLTEMPLATE, DEFAULT_SQLSUFFIX),
+                "dialect",
+                "table_info",
+                "top_k",
+                "input"
+            ))),
+        };
+        validate_prompt_variables(
+            prompt.as_ref(),
+            &["input", "table_info"],
+            Some(&["input", "table_info", "dialect", "top_k"]),
+        )?;
 
         let llm_chain = {
             let mut builder = LLMChainBuilder::new()
@@ -18,6 +18,7 @@ use super::{
     STOP_WORD,
 };
 
+#[derive(Debug, Clone)]
 pub struct SqlChainPromptBuilder {
     query: String,
 }
@@ -40,6 +41,7 @@ impl SqlChainPromptBuilder {
     }
 }
 
+#[derive(Debug)]
 pub struct SQLDatabaseChain {
     pub(crate) llmchain: LLMChain,
     pub(crate) top_k: usize,
@@ -180,6 +182,7 @@ impl Chain for SQLDatabaseChain {
         Ok(GenerateResult {
             generation: output.to_string(),
             tokens: token_usage,
+            ..Default::default()
         })
     }
 
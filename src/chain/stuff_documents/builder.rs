@@ -1,6 +1,9 @@
 use crate::{
-    chain::{options::ChainCallOptions, ChainError, LLMChainBuilder},
-    language_models::llm::LLM,
+    chain::{
+        options::ChainCallOptions, ChainError, LLMChainBuilder,
+        DEFAULT_STUFF_QA_WITH_CITATIONS_TEMPLATE,
+    },
+    language_models::{llm::LLM, TokenCounter},
     output_parsers::OutputParser,
     prompt::FormatPrompter,
     template_jinja2,
@@ -14,6 +17,9 @@ pub struct StuffDocumentBuilder {
     output_key: Option<String>,
     output_parser: Option<Box<dyn OutputParser>>,
     prompt: Option<Box<dyn FormatPrompter>>,
+    max_tokens: Option<usize>,
+    token_counter: Option<TokenCounter>,
+    citations: bool,
 }
 impl StuffDocumentBuilder {
     pub fn new() -> Self {
@@ -23,6 +29,9 @@ impl StuffDocumentBuilder {
             output_key: None,
             output_parser: None,
             prompt: None,
+            max_tokens: None,
+            token_counter: None,
+            citations: false,
         }
     }
 
@@ -47,12 +56,37 @@ impl StuffDocumentBuilder {
         self
     }
 
+    /// Maximum number of tokens of the documents and the question, see
+    /// `StuffDocument::with_max_tokens`.
+    pub fn max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    pub fn token_counter(mut self, token_counter: TokenCounter) -> Self {
+        self.token_counter = Some(token_counter);
+        self
+    }
+
+    /// Numbers the documents and returns the ones the answer cites, see
+    /// `StuffDocument::with_citations`. Without a custom prompt, the default one asks the
+    /// model to cite its sources.
+    pub fn citations(mut self, citations: bool) -> Self {
+        self.citations = citations;
+        self
+    }
+
     pub fn build(self) -> Result<StuffDocument, ChainError> {
         let llm = self
             .llm
             .ok_or_else(|| ChainError::MissingObject("LLM must be set".into()))?;
         let prompt = match self.prompt {
             Some(prompt) => prompt,
+            None if self.citations => Box::new(template_jinja2!(
+                DEFAULT_STUFF_QA_WITH_CITATIONS_TEMPLATE,
+                "context",
+                "question"
+            )),
             None => Box::new(template_jinja2!(
                 DEFAULT_STUFF_QA_TEMPLATE,
                 "context",
@@ -72,7 +106,14 @@ impl StuffDocumentBuilder {
             builder.build()?
         };
 
-        Ok(StuffDocument::new(llm_chain))
+        let mut stuff_document = StuffDocument::new(llm_chain).with_citations(self.citations);
+        if let Some(max_tokens) = self.max_tokens {
+            stuff_document = stuff_document.with_max_tokens(max_tokens);
+        }
+        if let Some(token_counter) = self.token_counter {
+            stuff_document = stuff_document.with_token_counter(token_counter);
+        }
+        Ok(stuff_document)
     }
 }
 
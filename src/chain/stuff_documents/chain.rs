@@ -1,14 +1,15 @@
-use std::pin::Pin;
+use std::{collections::HashMap, pin::Pin};
 
 use async_trait::async_trait;
 use futures::Stream;
-use serde_json::Value;
+use serde_json::{json, Value};
 
 use crate::{
     chain::{
-        load_stuff_qa, options::ChainCallOptions, Chain, ChainError, LLMChain, StuffQAPromptBuilder,
+        extract_citations, load_stuff_qa, load_stuff_qa_with_citations, options::ChainCallOptions,
+        Chain, ChainError, LLMChain, StuffQAPromptBuilder, DEFAULT_OUTPUT_KEY, DEFAULT_RESULT_KEY,
     },
-    language_models::{llm::LLM, GenerateResult},
+    language_models::{llm::LLM, GenerateResult, TokenCounter},
     prompt::PromptArgs,
     schemas::{Document, StreamData},
 };
@@ -17,12 +18,17 @@ const COMBINE_DOCUMENTS_DEFAULT_INPUT_KEY: &str = "input_documents";
 const COMBINE_DOCUMENTS_DEFAULT_OUTPUT_KEY: &str = "text";
 const COMBINE_DOCUMENTS_DEFAULT_DOCUMENT_VARIABLE_NAME: &str = "context";
 const STUFF_DOCUMENTS_DEFAULT_SEPARATOR: &str = "\n\n";
+pub(crate) const STUFF_DOCUMENTS_CITATIONS_KEY: &str = "citations";
 
+#[derive(Debug, Clone)]
 pub struct StuffDocument {
     llm_chain: LLMChain,
     input_key: String,
     document_variable_name: String,
     separator: String,
+    max_tokens: Option<usize>,
+    token_counter: TokenCounter,
+    citations: bool,
 }
 
 impl StuffDocument {
@@ -32,16 +38,134 @@ impl StuffDocument {
             input_key: COMBINE_DOCUMENTS_DEFAULT_INPUT_KEY.to_string(),
             document_variable_name: COMBINE_DOCUMENTS_DEFAULT_DOCUMENT_VARIABLE_NAME.to_string(),
             separator: STUFF_DOCUMENTS_DEFAULT_SEPARATOR.to_string(),
+            max_tokens: None,
+            token_counter: TokenCounter::default(),
+            citations: false,
         }
     }
 
-    fn join_documents(&self, docs: Vec<Document>) -> String {
+    /// Numbers the documents of the context, `[1]` for the first one, so the prompt can ask
+    /// the model to cite them. `execute` then adds the cited documents to its output under
+    /// `citations`, see `extract_citations`.
+    pub fn with_citations(mut self, citations: bool) -> Self {
+        self.citations = citations;
+        self
+    }
+
+    /// Maximum number of tokens of the documents and the other input variables, like the
+    /// question. Leave room for the template of the prompt and the answer.
+    ///
+    /// The documents are expected from the most to the least relevant, the last ones are
+    /// dropped until the rest fits. The first document is always kept, truncated if needed.
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    /// Counter used for the maximum number of tokens, the `cl100k_base` encoding by default.
+    pub fn with_token_counter(mut self, token_counter: TokenCounter) -> Self {
+        self.token_counter = token_counter;
+        self
+    }
+
+    /// Drops the documents that don't fit in the maximum number of tokens.
+    fn fit_documents(&self, docs: Vec<Document>, input_variables: &PromptArgs) -> Vec<Document> {
+        let max_tokens = match self.max_tokens {
+            Some(max_tokens) => max_tokens,
+            None => return docs,
+        };
+
+        let input_tokens: usize = input_variables
+            .iter()
+            .filter(|(key, _)| **key != self.input_key && **key != self.document_variable_name)
+            .filter_map(|(_, value)| value.as_str())
+            .map(|value| self.token_counter.count(value))
+            .sum();
+        let separator_tokens = self.token_counter.count(&self.separator);
+        let budget = max_tokens.saturating_sub(input_tokens);
+
+        let total = docs.len();
+        let mut used = 0;
+        let mut fitted = Vec::with_capacity(total);
+        for mut doc in docs {
+            let separator = if fitted.is_empty() {
+                0
+            } else {
+                separator_tokens
+            };
+            let tokens = self.token_counter.count(&doc.page_content);
+            if used + separator + tokens <= budget {
+                used += separator + tokens;
+                fitted.push(doc);
+                continue;
+            }
+            if fitted.is_empty() {
+                if budget > 0 {
+                    log::warn!(
+                        "Truncating the first document from {} to {} tokens",
+                        tokens,
+                        budget
+                    );
+                    doc.page_content = self.token_counter.truncate(&doc.page_content, budget);
+                } else {
+                    log::warn!(
+                        "The input variables use {} of the {} tokens, the first document is kept anyway",
+                        input_tokens,
+                        max_tokens
+                    );
+                }
+                fitted.push(doc);
+            }
+            break;
+        }
+
+        if fitted.len() < total {
+            log::warn!(
+                "Dropped {} of {} documents to fit in {} tokens",
+                total - fitted.len(),
+                total,
+                max_tokens
+            );
+        }
+        fitted
+    }
+
+    fn join_documents(&self, docs: &[Document]) -> String {
         docs.iter()
-            .map(|doc| doc.page_content.clone())
+            .enumerate()
+            .map(|(i, doc)| match self.citations {
+                true => format!("[{}] {}", i + 1, doc.page_content),
+                false => doc.page_content.clone(),
+            })
             .collect::<Vec<_>>()
             .join(&self.separator)
     }
 
+    /// The input of the LLM chain, with the documents that fit joined in the context.
+    fn prepare_input(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<(PromptArgs, Vec<Document>), ChainError> {
+        let docs = input_variables
+            .get(&self.input_key)
+            .ok_or_else(|| ChainError::MissingInputVariable(self.input_key.clone()))?;
+
+        let documents: Vec<Document> = serde_json::from_value(docs.clone()).map_err(|e| {
+            ChainError::IncorrectInputVariable {
+                source: e,
+                expected_type: "Vec<Document>".to_string(),
+            }
+        })?;
+
+        let documents = self.fit_documents(documents, &input_variables);
+        let mut input_values = input_variables;
+        input_values.insert(
+            self.document_variable_name.clone(),
+            Value::String(self.join_documents(&documents)),
+        );
+        Ok((input_values, documents))
+    }
+
     ///Inly use thi if you use the deafult prompt
     pub fn qa_prompt_builder<'a>(&self) -> StuffQAPromptBuilder<'a> {
         StuffQAPromptBuilder::new()
@@ -112,29 +236,38 @@ impl StuffDocument {
     pub fn load_stuff_qa_with_options<L: LLM + 'static>(llm: L, opt: ChainCallOptions) -> Self {
         load_stuff_qa(llm, Some(opt))
     }
+
+    /// Like `load_stuff_qa`, with a prompt asking to cite the numbered documents. The output
+    /// of `execute` has the cited documents under `citations`.
+    pub fn load_stuff_qa_with_citations<L: Into<Box<dyn LLM>>>(llm: L) -> Self {
+        load_stuff_qa_with_citations(llm, None)
+    }
 }
 
 #[async_trait]
 impl Chain for StuffDocument {
     async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
-        let docs = input_variables
-            .get(&self.input_key)
-            .ok_or_else(|| ChainError::MissingInputVariable(self.input_key.clone()))?;
-
-        let documents: Vec<Document> = serde_json::from_value(docs.clone()).map_err(|e| {
-            ChainError::IncorrectInputVariable {
-                source: e,
-                expected_type: "Vec<Document>".to_string(),
-            }
-        })?;
+        let (input_values, _) = self.prepare_input(input_variables)?;
+        self.llm_chain.call(input_values).await
+    }
 
-        let mut input_values = input_variables.clone();
-        input_values.insert(
-            self.document_variable_name.clone(),
-            Value::String(self.join_documents(documents)),
-        );
+    async fn execute(
+        &self,
+        input_variables: PromptArgs,
+    ) -> Result<HashMap<String, Value>, ChainError> {
+        let (input_values, documents) = self.prepare_input(input_variables)?;
+        let result = self.llm_chain.call(input_values).await?;
 
-        self.llm_chain.call(input_values).await
+        let mut output = HashMap::new();
+        if self.citations {
+            output.insert(
+                STUFF_DOCUMENTS_CITATIONS_KEY.to_string(),
+                json!(extract_citations(&result.generation, &documents)),
+            );
+        }
+        output.insert(DEFAULT_OUTPUT_KEY.to_string(), json!(result.generation));
+        output.insert(DEFAULT_RESULT_KEY.to_string(), json!(result));
+        Ok(output)
     }
 
     async fn stream(
@@ -142,22 +275,7 @@ impl Chain for StuffDocument {
         input_variables: PromptArgs,
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
     {
-        let docs = input_variables
-            .get(&self.input_key)
-            .ok_or_else(|| ChainError::MissingInputVariable(self.input_key.clone()))?;
-
-        let documents: Vec<Document> = serde_json::from_value(docs.clone()).map_err(|e| {
-            ChainError::IncorrectInputVariable {
-                source: e,
-                expected_type: "Vec<Document>".to_string(),
-            }
-        })?;
-
-        let mut input_values = input_variables.clone();
-        input_values.insert(
-            self.document_variable_name.clone(),
-            Value::String(self.join_documents(documents)),
-        );
+        let (input_values, _) = self.prepare_input(input_variables)?;
         self.llm_chain.stream(input_values).await
     }
 
@@ -165,3 +283,46 @@ impl Chain for StuffDocument {
         vec![self.input_key.clone()]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{llm::openai::OpenAI, prompt_args};
+
+    use super::*;
+
+    #[test]
+    fn test_fit_documents() {
+        let chain = StuffDocument::load_stuff_qa(OpenAI::default()).with_max_tokens(8);
+        let docs = vec![
+            Document::new("one two three"),
+            Document::new("four five"),
+            Document::new("six"),
+        ];
+        let input = prompt_args! { "question" => "how many" };
+
+        // 2 tokens of the question, 3 + 1 separator + 2 of the first two documents
+        let fitted = chain.fit_documents(docs.clone(), &input);
+        assert_eq!(fitted.len(), 2);
+
+        let chain = chain.with_max_tokens(4);
+        let fitted = chain.fit_documents(docs.clone(), &input);
+        assert_eq!(fitted.len(), 1);
+        assert_eq!(fitted[0].page_content, "one two");
+
+        let chain = chain.with_max_tokens(1);
+        let fitted = chain.fit_documents(docs, &input);
+        assert_eq!(fitted.len(), 1);
+        assert_eq!(fitted[0].page_content, "one two three");
+    }
+
+    #[test]
+    fn test_join_documents_with_citations() {
+        let docs = vec![Document::new("one"), Document::new("two")];
+
+        let chain = StuffDocument::load_stuff_qa(OpenAI::default());
+        assert_eq!(chain.join_documents(&docs), "one\n\ntwo");
+
+        let chain = StuffDocument::load_stuff_qa_with_citations(OpenAI::default());
+        assert_eq!(chain.join_documents(&docs), "[1] one\n\n[2] two");
+    }
+}
@@ -0,0 +1,61 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::schemas::Document;
+
+/// A source cited by an answer: the number it has in the context, starting at 1, and the
+/// document.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Citation {
+    pub index: usize,
+    pub document: Document,
+}
+
+/// The documents cited by an answer with markers like `[2]` or `[1, 3]`, in the order they
+/// are first cited. The numbers are the positions of the documents, starting at 1; numbers
+/// without a document are ignored, so an answer without valid citations has none.
+pub fn extract_citations(answer: &str, documents: &[Document]) -> Vec<Citation> {
+    let marker = Regex::new(r"\[(\d+(?:\s*,\s*\d+)*)\]").expect("valid citation regex");
+    let mut indexes: Vec<usize> = Vec::new();
+    for captures in marker.captures_iter(answer) {
+        for number in captures[1].split(',') {
+            match number.trim().parse::<usize>() {
+                Ok(index) if (1..=documents.len()).contains(&index) => {
+                    if !indexes.contains(&index) {
+                        indexes.push(index);
+                    }
+                }
+                _ => log::debug!("Ignoring the citation of an unknown source: {}", number),
+            }
+        }
+    }
+
+    indexes
+        .into_iter()
+        .map(|index| Citation {
+            index,
+            document: documents[index - 1].clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_citations() {
+        let documents = vec![Document::new("Luis is 24"), Document::new("Luis uses Nvim")];
+
+        let citations = extract_citations(
+            "Luis is 24 [1] and uses Nvim [2, 1], born in Peru [7].",
+            &documents,
+        );
+
+        let indexes: Vec<usize> = citations.iter().map(|c| c.index).collect();
+        assert_eq!(indexes, vec![1, 2]);
+        assert_eq!(citations[1].document.page_content, "Luis uses Nvim");
+        assert!(extract_citations("I don't know.", &documents).is_empty());
+        assert!(extract_citations("See [0] and [3]", &documents).is_empty());
+    }
+}
@@ -3,3 +3,6 @@ pub use chain::*;
 
 mod builder;
 pub use builder::*;
+
+mod citations;
+pub use citations::*;
@@ -1,5 +1,5 @@
-use crate::document_loaders::{process_doc_stream, LoaderError};
-use crate::{document_loaders::Loader, schemas::Document, text_splitter::TextSplitter};
+use crate::document_loaders::LoaderError;
+use crate::{document_loaders::Loader, schemas::Document};
 use async_stream::stream;
 use async_trait::async_trait;
 use csv;
@@ -87,18 +87,6 @@ impl<R: Read + Send + Sync + 'static> Loader for CsvLoader<R> {
 
         Ok(Box::pin(stream))
     }
-
-    async fn load_and_split<TS: TextSplitter + 'static>(
-        mut self,
-        splitter: TS,
-    ) -> Result<
-        Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
-        LoaderError,
-    > {
-        let doc_stream = self.load().await?;
-        let stream = process_doc_stream(doc_stream, splitter).await;
-        Ok(Box::pin(stream))
-    }
 }
 
 #[cfg(test)]
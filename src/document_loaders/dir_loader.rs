@@ -1,9 +1,22 @@
-use async_recursion::async_recursion;
-use std::sync::Arc;
-use std::{fmt, path::Path, pin::Pin};
+use async_stream::stream;
+use async_trait::async_trait;
+use futures::{future::BoxFuture, Stream};
+use futures_util::StreamExt;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    pin::Pin,
+};
 use tokio::fs;
 
-use super::LoaderError;
+use crate::schemas::Document;
+
+use super::{Loader, LoaderError};
+
+type DocumentStream = Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>;
 
 pub struct PathFilter(Arc<dyn Fn(&Path) -> bool + Send + Sync>);
 
@@ -33,92 +46,401 @@ pub struct DirLoaderOptions {
     pub glob: Option<String>,
     pub suffixes: Option<Vec<String>>,
     pub path_filter: Option<PathFilter>,
+    /// Glob patterns a file has to match, any of them is enough.
+    pub include: Option<Vec<String>>,
+    /// Glob patterns of the files and directories to skip.
+    pub exclude: Option<Vec<String>>,
+    /// How deep to descend into subdirectories, `Some(0)` only lists the top directory.
+    pub max_depth: Option<usize>,
 }
 
-/// Recursively list all files in a directory
-#[async_recursion]
-pub async fn list_files_in_path(
-    dir_path: &Path,
-    files: &mut Vec<String>,
+/// Glob patterns are matched against the path relative to the loaded directory and
+/// against the full path.
+struct PathMatcher {
+    root: PathBuf,
+    glob: Option<glob::Pattern>,
+    include: Vec<glob::Pattern>,
+    exclude: Vec<glob::Pattern>,
+}
+
+impl PathMatcher {
+    fn new(root: &Path, opts: &DirLoaderOptions) -> Result<Self, LoaderError> {
+        let compile = |patterns: &Option<Vec<String>>| {
+            patterns
+                .iter()
+                .flatten()
+                .map(|p| glob::Pattern::new(p))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| LoaderError::OtherError(format!("Invalid glob pattern: {}", e)))
+        };
+        Ok(Self {
+            root: root.to_path_buf(),
+            glob: opts
+                .glob
+                .as_deref()
+                .map(glob::Pattern::new)
+                .transpose()
+                .map_err(|e| LoaderError::OtherError(format!("Invalid glob pattern: {}", e)))?,
+            include: compile(&opts.include)?,
+            exclude: compile(&opts.exclude)?,
+        })
+    }
+
+    fn matches_any(&self, patterns: &[glob::Pattern], path: &Path) -> bool {
+        let relative = path.strip_prefix(&self.root).unwrap_or(path);
+        patterns
+            .iter()
+            .any(|p| p.matches_path(relative) || p.matches_path(path))
+    }
+
+    fn is_excluded(&self, path: &Path) -> bool {
+        self.matches_any(&self.exclude, path)
+    }
+
+    fn matches_file(&self, path: &Path, opts: &DirLoaderOptions) -> bool {
+        let path_str = path.to_string_lossy();
+        if let Some(suffixes) = &opts.suffixes {
+            if !suffixes.iter().any(|suffix| path_str.ends_with(suffix)) {
+                return false;
+            }
+        }
+        // Skip this path if the filter returns true
+        if opts.path_filter.as_ref().is_some_and(|f| f.0(path)) {
+            return false;
+        }
+        if let Some(glob) = &self.glob {
+            if !glob.matches(&path_str) {
+                return false;
+            }
+        }
+        if !self.include.is_empty() && !self.matches_any(&self.include, path) {
+            return false;
+        }
+        !self.is_excluded(path)
+    }
+}
+
+/// Walks the directory tree and returns the files that match the options. Directories
+/// that can't be read are added to `errors`, directories that were already visited, such
+/// as the target of a symlink loop, are skipped.
+async fn walk_dir(
+    root: &Path,
     opts: &DirLoaderOptions,
-) -> Result<Pin<Box<()>>, LoaderError> {
-    if dir_path.is_file() {
-        files.push(dir_path.to_string_lossy().to_string());
-        return Ok(Box::pin(()));
+    errors: &mut Vec<(PathBuf, LoaderError)>,
+) -> Result<Vec<PathBuf>, LoaderError> {
+    let matcher = PathMatcher::new(root, opts)?;
+    if root.is_file() {
+        return Ok(if matcher.matches_file(root, opts) {
+            vec![root.to_path_buf()]
+        } else {
+            Vec::new()
+        });
     }
-    if !dir_path.is_dir() {
+    if !root.is_dir() {
         return Err(LoaderError::OtherError(format!(
             "Path is not a directory: {:?}",
-            dir_path
+            root
         )));
     }
-    let mut reader = fs::read_dir(dir_path).await.unwrap();
-    while let Some(entry) = reader.next_entry().await.unwrap() {
-        let path = entry.path();
-        if path.is_file() {
-            files.push(path.to_string_lossy().to_string());
-        } else if path.is_dir() {
-            if opts
-                .path_filter
-                .as_ref()
-                .map_or(false, |f| f.0(path.as_path()))
-            {
+
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+    let mut pending = vec![(root.to_path_buf(), 0)];
+    while let Some((dir, depth)) = pending.pop() {
+        match fs::canonicalize(&dir).await {
+            Ok(canonical) => {
+                if !visited.insert(canonical) {
+                    log::warn!("Skipping already visited directory {:?}", dir);
+                    continue;
+                }
+            }
+            Err(e) => {
+                errors.push((dir, e.into()));
                 continue;
             }
+        }
 
-            list_files_in_path(&path, files, opts).await.unwrap();
+        let mut entries = match fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                errors.push((dir, e.into()));
+                continue;
+            }
+        };
+        let mut dir_files = Vec::new();
+        let mut subdirs = Vec::new();
+        loop {
+            let path = match entries.next_entry().await {
+                Ok(Some(entry)) => entry.path(),
+                Ok(None) => break,
+                Err(e) => {
+                    errors.push((dir.clone(), e.into()));
+                    break;
+                }
+            };
+            if path.is_file() {
+                if matcher.matches_file(&path, opts) {
+                    dir_files.push(path);
+                }
+            } else if path.is_dir() {
+                if opts.max_depth.is_some_and(|max| depth >= max)
+                    || opts.path_filter.as_ref().is_some_and(|f| f.0(&path))
+                    || matcher.is_excluded(&path)
+                {
+                    continue;
+                }
+                subdirs.push(path);
+            }
         }
+        dir_files.sort();
+        files.extend(dir_files);
+        subdirs.sort();
+        pending.extend(subdirs.into_iter().rev().map(|d| (d, depth + 1)));
     }
+    Ok(files)
+}
+
+/// Recursively list all files in a directory that match the given options
+pub async fn list_files_in_path(
+    dir_path: &Path,
+    files: &mut Vec<String>,
+    opts: &DirLoaderOptions,
+) -> Result<Pin<Box<()>>, LoaderError> {
+    let mut errors = Vec::new();
+    let found = walk_dir(dir_path, opts, &mut errors).await?;
+    if let Some((_, e)) = errors.into_iter().next() {
+        return Err(e);
+    }
+    files.extend(found.iter().map(|p| p.to_string_lossy().to_string()));
     Ok(Box::pin(()))
 }
 
 /// Find files in a directory that match the given options
 pub async fn find_files_with_extension(folder_path: &str, opts: &DirLoaderOptions) -> Vec<String> {
     let mut matching_files = Vec::new();
-    let folder_path = Path::new(folder_path);
-    let mut all_files: Vec<String> = Vec::new();
-
-    list_files_in_path(folder_path, &mut all_files, &opts.clone())
+    list_files_in_path(Path::new(folder_path), &mut matching_files, opts)
         .await
         .unwrap();
+    matching_files
+}
 
-    for file_name in all_files {
-        let path_str = file_name.clone();
+type LoaderFactory =
+    Arc<dyn Fn(&Path) -> BoxFuture<'static, Result<DocumentStream, LoaderError>> + Send + Sync>;
 
-        // check if the file has the required extension
-        if let Some(suffixes) = &opts.suffixes {
-            let mut has_suffix = false;
-            for suffix in suffixes {
-                if path_str.ends_with(suffix) {
-                    has_suffix = true;
-                    break;
-                }
-            }
-            if !has_suffix {
-                continue;
-            }
+/// A file that couldn't be loaded by the `DirLoader`.
+#[derive(Debug)]
+pub struct FileLoadError {
+    pub path: PathBuf,
+    pub error: LoaderError,
+}
+
+/// Errors collected by a `DirLoader` while its stream is consumed.
+#[derive(Debug, Clone, Default)]
+pub struct FileLoadErrors(Arc<Mutex<Vec<FileLoadError>>>);
+
+impl FileLoadErrors {
+    fn push(&self, path: PathBuf, error: LoaderError) {
+        log::warn!("Failed to load {:?}: {}", path, error);
+        self.0.lock().unwrap().push(FileLoadError { path, error });
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes and returns the errors collected so far.
+    pub fn take(&self) -> Vec<FileLoadError> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+/// Loads every file of a directory tree that matches the options.
+///
+/// Files are read as text unless a loader is registered for their extension. The file
+/// path is added to the `source` metadata of each `Document`. By default the stream stops
+/// at the first file that fails to load, with `with_collect_errors(true)` the file is
+/// skipped and the error is added to `errors()`.
+///
+/// # Example
+/// ```rust,ignore
+/// let loader = DirLoader::new("./docs")
+///     .with_include(vec!["**/*.md".to_string(), "**/*.pdf".to_string()])
+///     .with_exclude(vec!["**/drafts/**".to_string()])
+///     .with_max_depth(3)
+///     .with_loader("pdf", |path| LoPdfLoader::from_path(path))
+///     .with_collect_errors(true);
+/// let errors = loader.errors();
+///
+/// let docs = loader.load().await?.collect::<Vec<_>>().await;
+/// for error in errors.take() {
+///     println!("{:?}: {}", error.path, error.error);
+/// }
+/// ```
+#[derive(Clone)]
+pub struct DirLoader {
+    path: PathBuf,
+    options: DirLoaderOptions,
+    loaders: HashMap<String, LoaderFactory>,
+    collect_errors: bool,
+    errors: FileLoadErrors,
+}
+
+impl fmt::Debug for DirLoader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DirLoader")
+            .field("path", &self.path)
+            .field("options", &self.options)
+            .field("loaders", &self.loaders.keys().collect::<Vec<_>>())
+            .field("collect_errors", &self.collect_errors)
+            .finish()
+    }
+}
+
+impl DirLoader {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            options: DirLoaderOptions::default(),
+            loaders: HashMap::new(),
+            collect_errors: false,
+            errors: FileLoadErrors::default(),
         }
+    }
+
+    pub fn with_options(mut self, options: DirLoaderOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn with_include(mut self, patterns: Vec<String>) -> Self {
+        self.options.include = Some(patterns);
+        self
+    }
+
+    pub fn with_exclude(mut self, patterns: Vec<String>) -> Self {
+        self.options.exclude = Some(patterns);
+        self
+    }
+
+    pub fn with_suffixes(mut self, suffixes: Vec<String>) -> Self {
+        self.options.suffixes = Some(suffixes);
+        self
+    }
+
+    pub fn with_path_filter(mut self, path_filter: PathFilter) -> Self {
+        self.options.path_filter = Some(path_filter);
+        self
+    }
+
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.options.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Uses the loader built by `f` for the files with the given extension.
+    pub fn with_loader<L, F>(mut self, extension: &str, f: F) -> Self
+    where
+        L: Loader + 'static,
+        F: Fn(&Path) -> Result<L, LoaderError> + Send + Sync + 'static,
+    {
+        let factory: LoaderFactory = Arc::new(
+            move |path: &Path| -> BoxFuture<'static, Result<DocumentStream, LoaderError>> {
+                let loader = f(path);
+                Box::pin(async move { loader?.load().await })
+            },
+        );
+        self.loaders
+            .insert(extension.trim_start_matches('.').to_lowercase(), factory);
+        self
+    }
+
+    pub fn with_collect_errors(mut self, collect_errors: bool) -> Self {
+        self.collect_errors = collect_errors;
+        self
+    }
 
-        if opts
-            .path_filter
-            .as_ref()
-            .map_or(false, |f| f.0(&Path::new(&file_name)))
-        {
-            continue; // Skip this path if the filter returns true
+    /// Handle to the errors collected while loading, only used with
+    /// `with_collect_errors(true)`.
+    pub fn errors(&self) -> FileLoadErrors {
+        self.errors.clone()
+    }
+
+    async fn load_file(
+        loaders: &HashMap<String, LoaderFactory>,
+        path: &Path,
+    ) -> Result<DocumentStream, LoaderError> {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match loaders.get(&extension) {
+            Some(factory) => factory(path).await,
+            None => {
+                let content = fs::read_to_string(path).await?;
+                Ok(Box::pin(futures::stream::iter(vec![Ok(Document::new(
+                    content,
+                ))])))
+            }
         }
+    }
+}
 
-        // check if the file matches the glob pattern
-        if let Some(glob_pattern) = &opts.glob {
-            let glob = glob::Pattern::new(glob_pattern).unwrap();
-            if !glob.matches(&path_str) {
-                continue;
+#[async_trait]
+impl Loader for DirLoader {
+    async fn load(
+        self,
+    ) -> Result<
+        Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
+        LoaderError,
+    > {
+        let mut walk_errors = Vec::new();
+        let files = walk_dir(&self.path, &self.options, &mut walk_errors).await?;
+        for (path, error) in walk_errors {
+            if !self.collect_errors {
+                return Err(error);
             }
+            self.errors.push(path, error);
         }
 
-        matching_files.push(path_str);
-    }
+        let stream = stream! {
+            'files: for path in files {
+                let source = Value::from(path.to_string_lossy().to_string());
+                let mut docs = match Self::load_file(&self.loaders, &path).await {
+                    Ok(docs) => docs,
+                    Err(e) if self.collect_errors => {
+                        self.errors.push(path, e);
+                        continue;
+                    }
+                    Err(e) => {
+                        yield Err(e);
+                        break;
+                    }
+                };
+                while let Some(doc) = docs.next().await {
+                    match doc {
+                        Ok(mut doc) => {
+                            doc.metadata.insert("source".to_string(), source.clone());
+                            yield Ok(doc);
+                        }
+                        Err(e) if self.collect_errors => {
+                            self.errors.push(path, e);
+                            continue 'files;
+                        }
+                        Err(e) => {
+                            yield Err(e);
+                            break 'files;
+                        }
+                    }
+                }
+            }
+        };
 
-    matching_files
+        Ok(Box::pin(stream))
+    }
 }
 
 #[cfg(test)]
@@ -158,9 +480,8 @@ mod tests {
         let found_files = find_files_with_extension(
             temp_dir.as_path().to_str().unwrap(),
             &DirLoaderOptions {
-                glob: None,
                 suffixes: Some(vec![".txt".to_string()]),
-                path_filter: None,
+                ..Default::default()
             },
         )
         .await
@@ -182,4 +503,63 @@ mod tests {
             .await
             .expect("Failed to remove temporary directory");
     }
+
+    #[tokio::test]
+    async fn test_dir_loader() {
+        let temp_dir = env::temp_dir().join("dir_loader_builder_test_dir");
+        if temp_dir.exists() {
+            fs::remove_dir_all(&temp_dir).await.unwrap();
+        }
+        std::fs::create_dir_all(temp_dir.join("a/b/c")).unwrap();
+        std::fs::create_dir_all(temp_dir.join("skip")).unwrap();
+        std::fs::write(temp_dir.join("root.md"), "root").unwrap();
+        std::fs::write(temp_dir.join("a/one.md"), "one").unwrap();
+        std::fs::write(temp_dir.join("a/b/two.md"), "two").unwrap();
+        std::fs::write(temp_dir.join("a/b/c/three.md"), "three").unwrap();
+        std::fs::write(temp_dir.join("a/binary.md"), [0xff, 0xfe, 0x00]).unwrap();
+        std::fs::write(temp_dir.join("a/notes.txt"), "notes").unwrap();
+        std::fs::write(temp_dir.join("skip/skipped.md"), "skipped").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&temp_dir, temp_dir.join("a/loop")).unwrap();
+
+        let loader = DirLoader::new(&temp_dir)
+            .with_include(vec!["**/*.md".to_string()])
+            .with_exclude(vec!["skip".to_string()])
+            .with_max_depth(2)
+            .with_collect_errors(true);
+        let errors = loader.errors();
+
+        let docs = loader
+            .load()
+            .await
+            .unwrap()
+            .map(|d| d.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+
+        let contents = docs
+            .iter()
+            .map(|d| d.page_content.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(contents, vec!["root", "one", "two"]);
+        assert_eq!(
+            docs[1].metadata["source"],
+            Value::from(temp_dir.join("a/one.md").to_string_lossy().to_string())
+        );
+
+        let errors = errors.take();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, temp_dir.join("a/binary.md"));
+
+        let result = DirLoader::new(&temp_dir)
+            .with_suffixes(vec![".md".to_string()])
+            .load()
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert!(result.last().unwrap().is_err());
+
+        fs::remove_dir_all(&temp_dir).await.unwrap();
+    }
 }
@@ -4,11 +4,15 @@ use async_stream::stream;
 use async_trait::async_trait;
 use futures::Stream;
 use futures_util::{pin_mut, StreamExt};
+use serde_json::json;
 
 use crate::{schemas::Document, text_splitter::TextSplitter};
 
 use super::LoaderError;
 
+/// Metadata key of the position of a chunk in its document, set by `load_and_split`.
+pub const CHUNK_INDEX_KEY: &str = "chunk_index";
+
 #[async_trait]
 pub trait Loader: Send + Sync {
     async fn load(
@@ -17,13 +21,23 @@ pub trait Loader: Send + Sync {
         Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
         LoaderError,
     >;
+
+    /// Loads the documents and splits them in chunks. Each chunk has the metadata of its
+    /// document, like the source path, and its position in the document as `chunk_index`.
     async fn load_and_split<TS: TextSplitter + 'static>(
         self,
         splitter: TS,
     ) -> Result<
         Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
         LoaderError,
-    >;
+    >
+    where
+        Self: Sized,
+    {
+        let doc_stream = self.load().await?;
+        let stream = process_doc_stream(doc_stream, splitter).await;
+        Ok(Box::pin(stream))
+    }
 }
 
 pub(crate) async fn process_doc_stream<TS: TextSplitter + 'static>(
@@ -37,7 +51,8 @@ pub(crate) async fn process_doc_stream<TS: TextSplitter + 'static>(
                 Ok(doc) => {
                     match splitter.split_documents(&[doc]).await {
                         Ok(docs) => {
-                            for doc in docs {
+                            for (chunk_index, mut doc) in docs.into_iter().enumerate() {
+                                doc.metadata.insert(CHUNK_INDEX_KEY.to_string(), json!(chunk_index));
                                 yield Ok(doc);
                             }
                         },
@@ -49,3 +64,64 @@ pub(crate) async fn process_doc_stream<TS: TextSplitter + 'static>(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{
+        document_loaders::TextLoader,
+        text_splitter::{SplitterOptions, TokenSplitter},
+    };
+
+    struct SourceLoader {}
+
+    #[async_trait]
+    impl Loader for SourceLoader {
+        async fn load(
+            self,
+        ) -> Result<
+            Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
+            LoaderError,
+        > {
+            let docs = ["hello world hello world", "hello world"].map(|text| {
+                Ok(Document::new(text).with_metadata(
+                    [("source".to_string(), json!(format!("{}.txt", text.len())))].into(),
+                ))
+            });
+            Ok(Box::pin(futures::stream::iter(docs)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_load_and_split_keeps_metadata() {
+        let splitter = TokenSplitter::new(SplitterOptions::new().with_chunk_size(2));
+        let docs: Vec<Document> = SourceLoader {}
+            .load_and_split(splitter)
+            .await
+            .unwrap()
+            .map(|doc| doc.unwrap())
+            .collect()
+            .await;
+
+        assert!(docs.len() > 2);
+        let chunks = |source: &str| {
+            docs.iter()
+                .filter(|doc| doc.metadata["source"] == json!(source))
+                .map(|doc| doc.metadata[CHUNK_INDEX_KEY].clone())
+                .collect::<Vec<Value>>()
+        };
+        assert_eq!(chunks("23.txt"), vec![json!(0), json!(1)]);
+        assert_eq!(chunks("11.txt"), vec![json!(0)]);
+
+        let docs: Vec<Document> = TextLoader::new("hello")
+            .load_and_split(TokenSplitter::default())
+            .await
+            .unwrap()
+            .map(|doc| doc.unwrap())
+            .collect()
+            .await;
+        assert_eq!(docs[0].metadata[CHUNK_INDEX_KEY], json!(0));
+    }
+}
@@ -0,0 +1,236 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, Read, Seek},
+    path::Path,
+    pin::Pin,
+};
+
+use async_trait::async_trait;
+use futures::{stream, Stream};
+use quick_xml::{events::Event, Reader};
+use serde_json::Value;
+use zip::ZipArchive;
+
+use crate::{
+    document_loaders::{Loader, LoaderError},
+    schemas::Document,
+};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct DocxSection {
+    heading: Option<String>,
+    paragraphs: Vec<String>,
+}
+
+/// Loads the text of a DOCX file from `word/document.xml`, creating a `Document` for
+/// each section that starts with a heading. Paragraphs are separated by a blank line,
+/// images and other embedded objects are skipped.
+#[derive(Debug, Clone)]
+pub struct DocxLoader {
+    title: Option<String>,
+    sections: Vec<DocxSection>,
+    source: Option<String>,
+}
+
+impl DocxLoader {
+    /// Creates a new DocxLoader from anything that implements `Read` and `Seek`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let data = std::io::Cursor::new(bytes);
+    /// let loader = DocxLoader::new(data)?;
+    /// ```
+    pub fn new<R: Read + Seek>(reader: R) -> Result<Self, LoaderError> {
+        let mut archive = ZipArchive::new(reader)?;
+        let mut xml = String::new();
+        archive
+            .by_name("word/document.xml")?
+            .read_to_string(&mut xml)?;
+        let (title, sections) = parse_document_xml(&xml)?;
+        Ok(Self {
+            title,
+            sections,
+            source: None,
+        })
+    }
+
+    /// Creates a new DocxLoader from a path to a DOCX file, the file name is used as the
+    /// `source` metadata.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let loader = DocxLoader::from_path("/path/to/my.docx")?;
+    /// ```
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, LoaderError> {
+        let file = File::open(&path)?;
+        let loader = Self::new(BufReader::new(file))?;
+        Ok(loader.with_source(path.as_ref().to_string_lossy()))
+    }
+
+    pub fn with_source<S: Into<String>>(mut self, source: S) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+}
+
+fn is_heading_style(style: &str) -> bool {
+    let style = style.to_lowercase();
+    style == "title" || style.starts_with("heading")
+}
+
+fn parse_document_xml(xml: &str) -> Result<(Option<String>, Vec<DocxSection>), LoaderError> {
+    let mut reader = Reader::from_str(xml);
+
+    let mut title = None;
+    let mut sections = vec![DocxSection::default()];
+    let mut paragraph = String::new();
+    let mut paragraph_style = String::new();
+    let mut in_text = false;
+    // Depth inside drawings and embedded objects, their content is not text of the document
+    let mut skip_depth = 0;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => match e.name().as_ref() {
+                b"w:drawing" | b"w:pict" | b"w:object" | b"mc:AlternateContent" => skip_depth += 1,
+                b"w:p" if skip_depth == 0 => {
+                    paragraph.clear();
+                    paragraph_style.clear();
+                }
+                b"w:t" => in_text = skip_depth == 0,
+                _ => {}
+            },
+            Event::Empty(e) if skip_depth == 0 => match e.name().as_ref() {
+                b"w:pStyle" => {
+                    if let Some(style) = e
+                        .try_get_attribute("w:val")
+                        .map_err(quick_xml::Error::from)?
+                    {
+                        paragraph_style = style.unescape_value()?.to_string();
+                    }
+                }
+                b"w:tab" => paragraph.push('\t'),
+                b"w:br" | b"w:cr" => paragraph.push('\n'),
+                _ => {}
+            },
+            Event::Text(t) if in_text => paragraph.push_str(&t.unescape()?),
+            Event::End(e) => match e.name().as_ref() {
+                b"w:drawing" | b"w:pict" | b"w:object" | b"mc:AlternateContent" => skip_depth -= 1,
+                b"w:t" => in_text = false,
+                b"w:p" if skip_depth == 0 => {
+                    let text = paragraph.trim();
+                    if text.is_empty() {
+                        continue;
+                    }
+                    if paragraph_style.eq_ignore_ascii_case("title") && title.is_none() {
+                        title = Some(text.to_string());
+                    } else if is_heading_style(&paragraph_style) {
+                        sections.push(DocxSection {
+                            heading: Some(text.to_string()),
+                            paragraphs: Vec::new(),
+                        });
+                    } else if let Some(section) = sections.last_mut() {
+                        section.paragraphs.push(text.to_string());
+                    }
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    sections.retain(|section| section.heading.is_some() || !section.paragraphs.is_empty());
+    Ok((title, sections))
+}
+
+#[async_trait]
+impl Loader for DocxLoader {
+    async fn load(
+        self,
+    ) -> Result<
+        Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
+        LoaderError,
+    > {
+        let docs = self
+            .sections
+            .iter()
+            .map(|section| {
+                let mut metadata = HashMap::new();
+                if let Some(source) = &self.source {
+                    metadata.insert("source".to_string(), Value::from(source.as_str()));
+                }
+                if let Some(title) = &self.title {
+                    metadata.insert("title".to_string(), Value::from(title.as_str()));
+                }
+                if let Some(heading) = &section.heading {
+                    metadata.insert("section".to_string(), Value::from(heading.as_str()));
+                }
+
+                let mut content = section.paragraphs.join("\n\n");
+                if let Some(heading) = &section.heading {
+                    content = format!("{}\n\n{}", heading, content);
+                }
+                Ok(Document::new(content.trim_end()).with_metadata(metadata))
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Box::pin(stream::iter(docs)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::StreamExt;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_document_xml() {
+        let xml = r#"<w:document><w:body>
+<w:p><w:pPr><w:pStyle w:val="Title"/></w:pPr><w:r><w:t>My title</w:t></w:r></w:p>
+<w:p><w:r><w:t xml:space="preserve">Intro </w:t></w:r><w:r><w:t>text &amp; more</w:t></w:r></w:p>
+<w:p><w:pPr><w:pStyle w:val="Heading1"/></w:pPr><w:r><w:t>Chapter</w:t></w:r></w:p>
+<w:p><w:r><w:drawing><wp:docPr name="image"/><w:t>not text</w:t></w:drawing></w:r><w:r><w:t>Body</w:t></w:r></w:p>
+</w:body></w:document>"#;
+
+        let (title, sections) = parse_document_xml(xml).unwrap();
+        assert_eq!(title, Some("My title".to_string()));
+        assert_eq!(
+            sections,
+            vec![
+                DocxSection {
+                    heading: None,
+                    paragraphs: vec!["Intro text & more".to_string()],
+                },
+                DocxSection {
+                    heading: Some("Chapter".to_string()),
+                    paragraphs: vec!["Body".to_string()],
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_docx_loader() {
+        let path = "./src/document_loaders/test_data/sample.docx";
+        let docs = DocxLoader::from_path(path)
+            .unwrap()
+            .load()
+            .await
+            .unwrap()
+            .map(|doc| doc.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+
+        assert!(!docs.is_empty());
+        assert_eq!(docs[0].metadata["source"], Value::from(path));
+        assert_eq!(docs[0].metadata["title"], Value::from("Lorem ipsum"));
+        assert!(docs[0]
+            .page_content
+            .starts_with("Lorem ipsum dolor sit amet"));
+    }
+}
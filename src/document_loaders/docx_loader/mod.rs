@@ -0,0 +1,2 @@
+mod docx_loader;
+pub use docx_loader::*;
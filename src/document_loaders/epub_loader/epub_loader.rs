@@ -0,0 +1,347 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, Read, Seek},
+    path::Path,
+    pin::Pin,
+};
+
+use async_trait::async_trait;
+use futures::{stream, Stream};
+use quick_xml::{events::Event, Reader};
+use scraper::{ElementRef, Html, Selector};
+use serde_json::Value;
+use zip::ZipArchive;
+
+use crate::{
+    document_loaders::{Loader, LoaderError},
+    schemas::Document,
+};
+
+const BLOCK_ELEMENTS: [&str; 10] = [
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "p",
+    "li",
+    "pre",
+    "blockquote",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+struct EpubChapter {
+    title: Option<String>,
+    paragraphs: Vec<String>,
+}
+
+/// Loads the text of an EPUB file, creating a `Document` for each chapter following the
+/// spine, which is the reading order of the book. Paragraphs are separated by a blank
+/// line, images and other non XHTML resources are skipped.
+#[derive(Debug, Clone)]
+pub struct EpubLoader {
+    title: Option<String>,
+    chapters: Vec<EpubChapter>,
+    source: Option<String>,
+}
+
+impl EpubLoader {
+    /// Creates a new EpubLoader from anything that implements `Read` and `Seek`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let data = std::io::Cursor::new(bytes);
+    /// let loader = EpubLoader::new(data)?;
+    /// ```
+    pub fn new<R: Read + Seek>(reader: R) -> Result<Self, LoaderError> {
+        let mut archive = ZipArchive::new(reader)?;
+
+        let container = read_entry(&mut archive, "META-INF/container.xml")?;
+        let opf_path = find_attribute(&container, b"rootfile", b"full-path")?.ok_or_else(|| {
+            LoaderError::LoadDocumentError("EPUB container without rootfile".to_string())
+        })?;
+        let opf_dir = match opf_path.rfind('/') {
+            Some(index) => &opf_path[..=index],
+            None => "",
+        };
+
+        let package = parse_package(&read_entry(&mut archive, &opf_path)?)?;
+
+        let mut chapters = Vec::new();
+        for idref in &package.spine {
+            let Some((href, media_type)) = package.manifest.get(idref) else {
+                continue;
+            };
+            if media_type != "application/xhtml+xml" && media_type != "text/html" {
+                continue;
+            }
+            // The href can have a fragment and is percent encoded
+            let href = href.split('#').next().unwrap_or_default();
+            let href = urlencoding::decode(href)
+                .map(|href| href.into_owned())
+                .unwrap_or_else(|_| href.to_string());
+            let xhtml = read_entry(&mut archive, &format!("{}{}", opf_dir, href))?;
+            let chapter = parse_chapter(&xhtml);
+            if !chapter.paragraphs.is_empty() {
+                chapters.push(chapter);
+            }
+        }
+
+        Ok(Self {
+            title: package.title,
+            chapters,
+            source: None,
+        })
+    }
+
+    /// Creates a new EpubLoader from a path to an EPUB file, the file name is used as the
+    /// `source` metadata.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let loader = EpubLoader::from_path("/path/to/my.epub")?;
+    /// ```
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, LoaderError> {
+        let file = File::open(&path)?;
+        let loader = Self::new(BufReader::new(file))?;
+        Ok(loader.with_source(path.as_ref().to_string_lossy()))
+    }
+
+    pub fn with_source<S: Into<String>>(mut self, source: S) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+}
+
+fn read_entry<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+) -> Result<String, LoaderError> {
+    let mut content = String::new();
+    archive.by_name(name)?.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+// Returns the first value of the attribute in an element with the given name
+fn find_attribute(
+    xml: &str,
+    element: &[u8],
+    attribute: &[u8],
+) -> Result<Option<String>, LoaderError> {
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) if e.local_name().as_ref() == element => {
+                if let Some(value) = e
+                    .try_get_attribute(attribute)
+                    .map_err(quick_xml::Error::from)?
+                {
+                    return Ok(Some(value.unescape_value()?.to_string()));
+                }
+            }
+            Event::Eof => return Ok(None),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct EpubPackage {
+    title: Option<String>,
+    // id -> (href, media type)
+    manifest: HashMap<String, (String, String)>,
+    spine: Vec<String>,
+}
+
+fn parse_package(opf: &str) -> Result<EpubPackage, LoaderError> {
+    let mut reader = Reader::from_str(opf);
+    let mut package = EpubPackage::default();
+    let mut in_title = false;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                b"title" => in_title = package.title.is_none(),
+                b"item" => {
+                    let mut id = String::new();
+                    let mut href = String::new();
+                    let mut media_type = String::new();
+                    for attr in e.attributes() {
+                        let attr = attr.map_err(quick_xml::Error::from)?;
+                        let value = attr.unescape_value()?.to_string();
+                        match attr.key.local_name().as_ref() {
+                            b"id" => id = value,
+                            b"href" => href = value,
+                            b"media-type" => media_type = value,
+                            _ => {}
+                        }
+                    }
+                    package.manifest.insert(id, (href, media_type));
+                }
+                b"itemref" => {
+                    if let Some(idref) = e
+                        .try_get_attribute("idref")
+                        .map_err(quick_xml::Error::from)?
+                    {
+                        package.spine.push(idref.unescape_value()?.to_string());
+                    }
+                }
+                _ => {}
+            },
+            Event::Text(t) if in_title => {
+                package.title = Some(t.unescape()?.trim().to_string());
+                in_title = false;
+            }
+            Event::End(e) if e.local_name().as_ref() == b"title" => in_title = false,
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(package)
+}
+
+fn parse_chapter(xhtml: &str) -> EpubChapter {
+    let document = Html::parse_document(xhtml);
+    let blocks = Selector::parse(&BLOCK_ELEMENTS.join(", ")).unwrap();
+    let headings = Selector::parse("h1, h2, h3, h4, h5, h6").unwrap();
+    let head_title = Selector::parse("title").unwrap();
+
+    let is_block = |element: ElementRef| BLOCK_ELEMENTS.contains(&element.value().name());
+    let paragraphs = document
+        .select(&blocks)
+        // Only the outermost blocks, a paragraph in a list item is already in its text
+        .filter(|element| {
+            !element
+                .ancestors()
+                .filter_map(ElementRef::wrap)
+                .any(is_block)
+        })
+        .map(|element| {
+            element
+                .text()
+                .collect::<String>()
+                .split_whitespace()
+                .collect::<Vec<&str>>()
+                .join(" ")
+        })
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<String>>();
+
+    let title = document
+        .select(&headings)
+        .chain(document.select(&head_title))
+        .map(|element| {
+            element
+                .text()
+                .collect::<String>()
+                .split_whitespace()
+                .collect::<Vec<&str>>()
+                .join(" ")
+        })
+        .find(|text| !text.is_empty());
+
+    EpubChapter { title, paragraphs }
+}
+
+#[async_trait]
+impl Loader for EpubLoader {
+    async fn load(
+        self,
+    ) -> Result<
+        Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
+        LoaderError,
+    > {
+        let docs = self
+            .chapters
+            .iter()
+            .enumerate()
+            .map(|(index, chapter)| {
+                let mut metadata = HashMap::new();
+                if let Some(source) = &self.source {
+                    metadata.insert("source".to_string(), Value::from(source.as_str()));
+                }
+                if let Some(title) = &self.title {
+                    metadata.insert("title".to_string(), Value::from(title.as_str()));
+                }
+                if let Some(chapter_title) = &chapter.title {
+                    metadata.insert("chapter".to_string(), Value::from(chapter_title.as_str()));
+                }
+                metadata.insert("chapter_index".to_string(), Value::from(index));
+
+                Ok(Document::new(chapter.paragraphs.join("\n\n")).with_metadata(metadata))
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Box::pin(stream::iter(docs)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use futures_util::StreamExt;
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use super::*;
+
+    fn build_epub() -> Vec<u8> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let files = [
+            (
+                "META-INF/container.xml",
+                r#"<container><rootfiles><rootfile full-path="OEBPS/content.opf"/></rootfiles></container>"#,
+            ),
+            (
+                "OEBPS/content.opf",
+                r#"<package><metadata><dc:title>My book</dc:title></metadata>
+<manifest>
+<item id="c2" href="chapter%202.xhtml" media-type="application/xhtml+xml"/>
+<item id="c1" href="text/chapter1.xhtml" media-type="application/xhtml+xml"/>
+<item id="cover" href="cover.jpg" media-type="image/jpeg"/>
+</manifest>
+<spine><itemref idref="cover"/><itemref idref="c1"/><itemref idref="c2"/></spine></package>"#,
+            ),
+            (
+                "OEBPS/text/chapter1.xhtml",
+                r#"<html><body><h1>First</h1><p>Hello <b>world</b></p><img src="a.png"/><ul><li><p>item</p></li></ul></body></html>"#,
+            ),
+            (
+                "OEBPS/chapter 2.xhtml",
+                r#"<html><head><title>Second</title></head><body><p>Bye</p></body></html>"#,
+            ),
+        ];
+        for (name, content) in files {
+            writer
+                .start_file(name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[tokio::test]
+    async fn test_epub_loader_follows_the_spine() {
+        let docs = EpubLoader::new(Cursor::new(build_epub()))
+            .unwrap()
+            .with_source("book.epub")
+            .load()
+            .await
+            .unwrap()
+            .map(|doc| doc.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(docs.len(), 2);
+        assert_eq!(docs[0].page_content, "First\n\nHello world\n\nitem");
+        assert_eq!(docs[0].metadata["chapter"], Value::from("First"));
+        assert_eq!(docs[0].metadata["title"], Value::from("My book"));
+        assert_eq!(docs[0].metadata["source"], Value::from("book.epub"));
+        assert_eq!(docs[1].page_content, "Bye");
+        assert_eq!(docs[1].metadata["chapter"], Value::from("Second"));
+    }
+}
@@ -0,0 +1,2 @@
+mod epub_loader;
+pub use epub_loader::*;
@@ -36,6 +36,17 @@ pub enum LoaderError {
     #[error(transparent)]
     ReadabilityError(#[from] readability::error::Error),
 
+    #[cfg(any(feature = "docx", feature = "epub"))]
+    #[error(transparent)]
+    ZipError(#[from] zip::result::ZipError),
+
+    #[cfg(any(feature = "docx", feature = "epub"))]
+    #[error(transparent)]
+    XmlError(#[from] quick_xml::Error),
+
+    #[error(transparent)]
+    RequestError(#[from] reqwest::Error),
+
     #[error(transparent)]
     JoinError(#[from] tokio::task::JoinError),
 
@@ -1,8 +1,8 @@
 use std::collections::HashMap;
 use std::pin::Pin;
 
-use crate::document_loaders::{process_doc_stream, LoaderError};
-use crate::{document_loaders::Loader, schemas::Document, text_splitter::TextSplitter};
+use crate::document_loaders::LoaderError;
+use crate::{document_loaders::Loader, schemas::Document};
 use async_trait::async_trait;
 use futures::Stream;
 use gix::ThreadSafeRepository;
@@ -71,18 +71,6 @@ impl Loader for GitCommitLoader {
 
         Ok(Box::pin(rx.into_stream()))
     }
-
-    async fn load_and_split<TS: TextSplitter + 'static>(
-        mut self,
-        splitter: TS,
-    ) -> Result<
-        Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
-        LoaderError,
-    > {
-        let doc_stream = self.load().await?;
-        let stream = process_doc_stream(doc_stream, splitter).await;
-        Ok(Box::pin(stream))
-    }
 }
 
 #[cfg(test)]
@@ -12,9 +12,8 @@ use serde_json::Value;
 use url::Url;
 
 use crate::{
-    document_loaders::{process_doc_stream, Loader, LoaderError},
+    document_loaders::{Loader, LoaderError},
     schemas::Document,
-    text_splitter::TextSplitter,
 };
 
 #[derive(Debug, Clone)]
@@ -62,18 +61,6 @@ impl<R: Read + Send + Sync + 'static> Loader for HtmlLoader<R> {
         let stream = stream::iter(vec![Ok(doc)]);
         Ok(Box::pin(stream))
     }
-
-    async fn load_and_split<TS: TextSplitter + 'static>(
-        mut self,
-        splitter: TS,
-    ) -> Result<
-        Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
-        LoaderError,
-    > {
-        let doc_stream = self.load().await?;
-        let stream = process_doc_stream(doc_stream, splitter).await;
-        Ok(Box::pin(stream))
-    }
 }
 
 #[cfg(test)]
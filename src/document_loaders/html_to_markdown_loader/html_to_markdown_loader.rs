@@ -14,9 +14,8 @@ use url::Url;
 pub use htmd::{HtmlToMarkdown, HtmlToMarkdownBuilder};
 
 use crate::{
-    document_loaders::{process_doc_stream, Loader, LoaderError},
+    document_loaders::{Loader, LoaderError},
     schemas::Document,
-    text_splitter::TextSplitter,
 };
 
 #[derive(Debug, Clone)]
@@ -105,18 +104,6 @@ impl<R: Read + Send + Sync + 'static> Loader for HtmlToMarkdownLoader<R> {
         let stream = stream::iter(vec![Ok(doc)]);
         Ok(Box::pin(stream))
     }
-
-    async fn load_and_split<TS: TextSplitter + 'static>(
-        mut self,
-        splitter: TS,
-    ) -> Result<
-        Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
-        LoaderError,
-    > {
-        let doc_stream = self.load().await?;
-        let stream = process_doc_stream(doc_stream, splitter).await;
-        Ok(Box::pin(stream))
-    }
 }
 
 #[cfg(test)]
@@ -38,3 +38,19 @@ pub use dir_loader::*;
 mod source_code_loader;
 #[cfg(feature = "tree-sitter")]
 pub use source_code_loader::*;
+
+mod youtube_loader;
+pub use youtube_loader::*;
+
+mod notion_loader;
+pub use notion_loader::*;
+
+#[cfg(feature = "docx")]
+mod docx_loader;
+#[cfg(feature = "docx")]
+pub use docx_loader::*;
+
+#[cfg(feature = "epub")]
+mod epub_loader;
+#[cfg(feature = "epub")]
+pub use epub_loader::*;
@@ -0,0 +1,654 @@
+use std::{collections::HashMap, pin::Pin, time::Duration};
+
+use async_recursion::async_recursion;
+use async_stream::stream;
+use async_trait::async_trait;
+use futures::Stream;
+use reqwest::{RequestBuilder, StatusCode};
+use serde_json::{json, Value};
+
+use crate::{
+    document_loaders::{Loader, LoaderError},
+    schemas::Document,
+};
+
+const NOTION_API_URL: &str = "https://api.notion.com/v1";
+const NOTION_VERSION: &str = "2022-06-28";
+const PAGE_SIZE: usize = 100;
+
+/// What the `NotionLoader` reads: every page of a database or a single page.
+#[derive(Debug, Clone)]
+pub enum NotionSource {
+    Database(String),
+    Page(String),
+}
+
+#[derive(Debug, Clone)]
+struct NotionBlock {
+    block: Value,
+    children: Vec<NotionBlock>,
+}
+
+/// Loads Notion pages through the Notion API as markdown `Document`s.
+///
+/// A `Document` is created for each page, the blocks are converted to markdown and nested
+/// child blocks are flattened in reading order. The page properties are added to the
+/// metadata together with `source`, `page_id`, `title`, `created_time` and
+/// `last_edited_time`. Databases are paginated lazily while the stream is consumed.
+///
+/// Requests rejected with `429 Too Many Requests` are retried after the `Retry-After`
+/// delay, or with an exponential backoff when the header is missing.
+///
+/// # Example
+/// ```rust,ignore
+/// let loader = NotionLoader::from_database(std::env::var("NOTION_TOKEN")?, "database-id")
+///     .with_filter(json!({ "property": "Published", "checkbox": { "equals": true } }));
+///
+/// let docs = loader.load().await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct NotionLoader {
+    token: String,
+    source: NotionSource,
+    filter: Option<Value>,
+    base_url: String,
+    max_retries: u32,
+    initial_backoff: Duration,
+    client: reqwest::Client,
+}
+
+impl NotionLoader {
+    pub fn new<S: Into<String>>(token: S, source: NotionSource) -> Self {
+        Self {
+            token: token.into(),
+            source,
+            filter: None,
+            base_url: NOTION_API_URL.to_string(),
+            max_retries: 5,
+            initial_backoff: Duration::from_secs(1),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn from_database<S: Into<String>, I: Into<String>>(token: S, database_id: I) -> Self {
+        Self::new(token, NotionSource::Database(database_id.into()))
+    }
+
+    pub fn from_page<S: Into<String>, I: Into<String>>(token: S, page_id: I) -> Self {
+        Self::new(token, NotionSource::Page(page_id.into()))
+    }
+
+    /// Filter used when querying a database, in the format of the Notion API.
+    pub fn with_filter(mut self, filter: Value) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    pub fn with_base_url<S: Into<String>>(mut self, base_url: S) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn with_initial_backoff(mut self, initial_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Value, LoaderError> {
+        let request = request
+            .bearer_auth(&self.token)
+            .header("Notion-Version", NOTION_VERSION);
+
+        let mut attempt = 0;
+        loop {
+            let retry = request.try_clone().ok_or_else(|| {
+                LoaderError::OtherError("Notion request can't be retried".to_string())
+            })?;
+            let response = retry.send().await?;
+            let status = response.status();
+
+            if status == StatusCode::TOO_MANY_REQUESTS && attempt < self.max_retries {
+                let retry_after = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|h| h.to_str().ok())
+                    .and_then(|h| h.trim().parse::<u64>().ok());
+                let delay = backoff_delay(retry_after, self.initial_backoff, attempt);
+                log::warn!("Notion rate limit reached, retrying in {:?}", delay);
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+                continue;
+            }
+
+            let body: Value = response.json().await?;
+            if !status.is_success() {
+                return Err(LoaderError::LoadDocumentError(format!(
+                    "Notion API error {}: {}",
+                    status,
+                    body["message"].as_str().unwrap_or_default()
+                )));
+            }
+            return Ok(body);
+        }
+    }
+
+    async fn query_database(
+        &self,
+        database_id: &str,
+        start_cursor: Option<String>,
+    ) -> Result<(Vec<Value>, Option<String>), LoaderError> {
+        let mut body = json!({ "page_size": PAGE_SIZE });
+        if let Some(filter) = &self.filter {
+            body["filter"] = filter.clone();
+        }
+        if let Some(cursor) = start_cursor {
+            body["start_cursor"] = Value::from(cursor);
+        }
+
+        let response = self
+            .send(
+                self.client
+                    .post(format!("{}/databases/{}/query", self.base_url, database_id))
+                    .json(&body),
+            )
+            .await?;
+        Ok(paginated(response))
+    }
+
+    async fn retrieve_page(&self, page_id: &str) -> Result<Value, LoaderError> {
+        self.send(
+            self.client
+                .get(format!("{}/pages/{}", self.base_url, page_id)),
+        )
+        .await
+    }
+
+    #[async_recursion]
+    async fn retrieve_blocks(&self, block_id: &str) -> Result<Vec<NotionBlock>, LoaderError> {
+        let mut blocks = Vec::new();
+        let mut start_cursor: Option<String> = None;
+        loop {
+            let mut query = vec![("page_size", PAGE_SIZE.to_string())];
+            if let Some(cursor) = start_cursor.take() {
+                query.push(("start_cursor", cursor));
+            }
+            let response = self
+                .send(
+                    self.client
+                        .get(format!("{}/blocks/{}/children", self.base_url, block_id))
+                        .query(&query),
+                )
+                .await?;
+            let (results, next_cursor) = paginated(response);
+
+            for block in results {
+                // Child pages and databases are documents of their own
+                let children = if block["has_children"].as_bool().unwrap_or_default()
+                    && !matches!(
+                        block["type"].as_str(),
+                        Some("child_page") | Some("child_database")
+                    ) {
+                    let id = block["id"].as_str().unwrap_or_default().to_string();
+                    self.retrieve_blocks(&id).await?
+                } else {
+                    Vec::new()
+                };
+                blocks.push(NotionBlock { block, children });
+            }
+
+            match next_cursor {
+                Some(cursor) => start_cursor = Some(cursor),
+                None => return Ok(blocks),
+            }
+        }
+    }
+
+    async fn page_to_document(&self, page: Value) -> Result<Document, LoaderError> {
+        let page_id = page["id"].as_str().unwrap_or_default().to_string();
+        let blocks = self.retrieve_blocks(&page_id).await?;
+        Ok(Document::new(blocks_to_markdown(&blocks)).with_metadata(page_metadata(&page)))
+    }
+}
+
+/// Delay before retrying a rate limited request, `Retry-After` takes precedence over
+/// the exponential backoff.
+fn backoff_delay(retry_after: Option<u64>, initial_backoff: Duration, attempt: u32) -> Duration {
+    match retry_after {
+        Some(seconds) => Duration::from_secs(seconds),
+        None => initial_backoff * 2u32.saturating_pow(attempt),
+    }
+}
+
+fn paginated(mut response: Value) -> (Vec<Value>, Option<String>) {
+    let results = match response["results"].take() {
+        Value::Array(results) => results,
+        _ => Vec::new(),
+    };
+    let next_cursor = if response["has_more"].as_bool().unwrap_or_default() {
+        response["next_cursor"].as_str().map(String::from)
+    } else {
+        None
+    };
+    (results, next_cursor)
+}
+
+fn plain_text(rich_text: &Value) -> String {
+    rich_text
+        .as_array()
+        .map(|parts| {
+            parts
+                .iter()
+                .filter_map(|part| part["plain_text"].as_str())
+                .collect::<String>()
+        })
+        .unwrap_or_default()
+}
+
+fn rich_text_to_markdown(rich_text: &Value) -> String {
+    let Some(parts) = rich_text.as_array() else {
+        return String::new();
+    };
+    parts
+        .iter()
+        .map(|part| {
+            let mut text = part["plain_text"].as_str().unwrap_or_default().to_string();
+            if text.trim().is_empty() {
+                return text;
+            }
+            let annotations = &part["annotations"];
+            if annotations["code"].as_bool().unwrap_or_default() {
+                text = format!("`{}`", text);
+            }
+            if annotations["bold"].as_bool().unwrap_or_default() {
+                text = format!("**{}**", text);
+            }
+            if annotations["italic"].as_bool().unwrap_or_default() {
+                text = format!("*{}*", text);
+            }
+            if annotations["strikethrough"].as_bool().unwrap_or_default() {
+                text = format!("~~{}~~", text);
+            }
+            if let Some(href) = part["href"].as_str() {
+                text = format!("[{}]({})", text, href);
+            }
+            text
+        })
+        .collect()
+}
+
+fn file_url(content: &Value) -> Option<&str> {
+    content["external"]["url"]
+        .as_str()
+        .or_else(|| content["file"]["url"].as_str())
+        .or_else(|| content["url"].as_str())
+}
+
+/// Converts the blocks to markdown. Paragraph-like blocks are separated by a blank line
+/// and consecutive list items by a single newline.
+fn blocks_to_markdown(blocks: &[NotionBlock]) -> String {
+    let mut lines: Vec<(String, bool)> = Vec::new();
+    render_blocks(blocks, 0, &mut lines);
+
+    let mut markdown = String::new();
+    let mut previous_is_item = false;
+    for (line, is_item) in lines {
+        if !markdown.is_empty() {
+            markdown.push_str(if previous_is_item && is_item {
+                "\n"
+            } else {
+                "\n\n"
+            });
+        }
+        markdown.push_str(&line);
+        previous_is_item = is_item;
+    }
+    markdown
+}
+
+fn render_blocks(blocks: &[NotionBlock], depth: usize, lines: &mut Vec<(String, bool)>) {
+    let indent = "  ".repeat(depth);
+    let mut number = 0;
+
+    for NotionBlock { block, children } in blocks {
+        let block_type = block["type"].as_str().unwrap_or_default();
+        let content = &block[block_type];
+        let text = rich_text_to_markdown(&content["rich_text"]);
+
+        if block_type == "numbered_list_item" {
+            number += 1;
+        } else {
+            number = 0;
+        }
+
+        let (line, is_item, nested) = match block_type {
+            "paragraph" => (Some(text), false, false),
+            "heading_1" => (Some(format!("# {}", text)), false, false),
+            "heading_2" => (Some(format!("## {}", text)), false, false),
+            "heading_3" => (Some(format!("### {}", text)), false, false),
+            "bulleted_list_item" | "toggle" => (Some(format!("- {}", text)), true, true),
+            "numbered_list_item" => (Some(format!("{}. {}", number, text)), true, true),
+            "to_do" => {
+                let checked = if content["checked"].as_bool().unwrap_or_default() {
+                    "x"
+                } else {
+                    " "
+                };
+                (Some(format!("- [{}] {}", checked, text)), true, true)
+            }
+            "quote" => (Some(format!("> {}", text)), false, false),
+            "callout" => {
+                let icon = content["icon"]["emoji"].as_str().unwrap_or_default();
+                let callout = format!("{} {}", icon, text);
+                (Some(format!("> {}", callout.trim())), false, false)
+            }
+            "code" => (
+                Some(format!(
+                    "```{}\n{}\n```",
+                    content["language"].as_str().unwrap_or_default(),
+                    plain_text(&content["rich_text"])
+                )),
+                false,
+                false,
+            ),
+            "equation" => (
+                Some(format!(
+                    "$${}$$",
+                    content["expression"].as_str().unwrap_or_default()
+                )),
+                false,
+                false,
+            ),
+            "divider" => (Some("---".to_string()), false, false),
+            "child_page" => (
+                Some(format!(
+                    "**{}**",
+                    content["title"].as_str().unwrap_or_default()
+                )),
+                false,
+                false,
+            ),
+            "image" => (
+                file_url(content)
+                    .map(|url| format!("![{}]({})", plain_text(&content["caption"]), url)),
+                false,
+                false,
+            ),
+            "bookmark" | "embed" | "link_preview" | "file" | "pdf" | "video" | "audio" => (
+                file_url(content).map(|url| {
+                    let caption = plain_text(&content["caption"]);
+                    let title = if caption.is_empty() {
+                        url
+                    } else {
+                        caption.as_str()
+                    };
+                    format!("[{}]({})", title, url)
+                }),
+                false,
+                false,
+            ),
+            "table" => {
+                let rows = children
+                    .iter()
+                    .map(|row| {
+                        let cells: Vec<String> = row.block["table_row"]["cells"]
+                            .as_array()
+                            .map(|cells| cells.iter().map(rich_text_to_markdown).collect())
+                            .unwrap_or_default();
+                        format!("| {} |", cells.join(" | "))
+                    })
+                    .collect::<Vec<String>>();
+                let columns = content["table_width"].as_u64().unwrap_or(1) as usize;
+                let mut table = rows.first().cloned().unwrap_or_default();
+                table.push_str(&format!("\n|{}", " --- |".repeat(columns)));
+                for row in rows.iter().skip(1) {
+                    table.push('\n');
+                    table.push_str(row);
+                }
+                lines.push((indent_lines(&table, &indent), false));
+                continue;
+            }
+            // Containers such as columns and synced blocks only hold their children
+            _ => (None, false, false),
+        };
+
+        if let Some(line) = line.filter(|line| !line.trim().is_empty()) {
+            lines.push((indent_lines(&line, &indent), is_item));
+        }
+        if !children.is_empty() {
+            render_blocks(children, if nested { depth + 1 } else { depth }, lines);
+        }
+    }
+}
+
+fn indent_lines(text: &str, indent: &str) -> String {
+    if indent.is_empty() {
+        return text.to_string();
+    }
+    text.lines()
+        .map(|line| format!("{}{}", indent, line))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn property_value(property: &Value) -> Option<Value> {
+    let property_type = property["type"].as_str()?;
+    let value = &property[property_type];
+    let names = |value: &Value| -> Value {
+        value
+            .as_array()
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|item| item["name"].as_str().map(Value::from))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    let converted = match property_type {
+        "title" | "rich_text" => Value::from(plain_text(value)),
+        "select" | "status" => value["name"].clone(),
+        "multi_select" | "people" => names(value),
+        "date" => value["start"].clone(),
+        "relation" => value
+            .as_array()
+            .map(|items| items.iter().map(|item| item["id"].clone()).collect())
+            .unwrap_or_default(),
+        "formula" | "rollup" => return property_value(value),
+        "number" | "checkbox" | "url" | "email" | "phone_number" | "created_time"
+        | "last_edited_time" | "string" | "boolean" => value.clone(),
+        "array" => value
+            .as_array()
+            .map(|items| items.iter().filter_map(property_value).collect())
+            .unwrap_or_default(),
+        _ => return None,
+    };
+    (!converted.is_null()).then_some(converted)
+}
+
+fn page_metadata(page: &Value) -> HashMap<String, Value> {
+    let mut metadata = HashMap::new();
+    let mut title = String::new();
+
+    if let Some(properties) = page["properties"].as_object() {
+        for (name, property) in properties {
+            if property["type"] == "title" {
+                title = plain_text(&property["title"]);
+            }
+            if let Some(value) = property_value(property) {
+                metadata.insert(name.clone(), value);
+            }
+        }
+    }
+
+    metadata.insert("source".to_string(), page["url"].clone());
+    metadata.insert("page_id".to_string(), page["id"].clone());
+    metadata.insert("title".to_string(), Value::from(title));
+    metadata.insert("created_time".to_string(), page["created_time"].clone());
+    metadata.insert(
+        "last_edited_time".to_string(),
+        page["last_edited_time"].clone(),
+    );
+    metadata
+}
+
+#[async_trait]
+impl Loader for NotionLoader {
+    async fn load(
+        self,
+    ) -> Result<
+        Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
+        LoaderError,
+    > {
+        let stream = stream! {
+            match self.source.clone() {
+                NotionSource::Page(page_id) => {
+                    match self.retrieve_page(&page_id).await {
+                        Ok(page) => yield self.page_to_document(page).await,
+                        Err(e) => yield Err(e),
+                    }
+                }
+                NotionSource::Database(database_id) => {
+                    let mut start_cursor = None;
+                    loop {
+                        let (pages, next_cursor) =
+                            match self.query_database(&database_id, start_cursor).await {
+                                Ok(result) => result,
+                                Err(e) => {
+                                    yield Err(e);
+                                    break;
+                                }
+                            };
+                        for page in pages {
+                            yield self.page_to_document(page).await;
+                        }
+                        match next_cursor {
+                            Some(cursor) => start_cursor = Some(cursor),
+                            None => break,
+                        }
+                    }
+                }
+            }
+        };
+
+        Ok(Box::pin(stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(block_type: &str, content: Value, children: Vec<NotionBlock>) -> NotionBlock {
+        NotionBlock {
+            block: json!({ "type": block_type, block_type: content }),
+            children,
+        }
+    }
+
+    fn text(content: &str) -> Value {
+        json!({ "rich_text": [{ "plain_text": content, "annotations": {} }] })
+    }
+
+    #[test]
+    fn test_blocks_to_markdown() {
+        let blocks = vec![
+            block("heading_1", text("Title"), vec![]),
+            block(
+                "paragraph",
+                json!({ "rich_text": [
+                    { "plain_text": "Some ", "annotations": {} },
+                    { "plain_text": "bold", "annotations": { "bold": true } },
+                    { "plain_text": " link", "annotations": {}, "href": "https://example.com" }
+                ] }),
+                vec![],
+            ),
+            block(
+                "bulleted_list_item",
+                text("first"),
+                vec![block("numbered_list_item", text("nested"), vec![])],
+            ),
+            block("bulleted_list_item", text("second"), vec![]),
+            block(
+                "column_list",
+                json!({}),
+                vec![block(
+                    "column",
+                    json!({}),
+                    vec![block("quote", text("quoted"), vec![])],
+                )],
+            ),
+            block(
+                "code",
+                json!({ "language": "rust", "rich_text": [{ "plain_text": "fn main() {}" }] }),
+                vec![],
+            ),
+        ];
+
+        assert_eq!(
+            blocks_to_markdown(&blocks),
+            "# Title\n\nSome **bold**[ link](https://example.com)\n\n- first\n  1. nested\n- second\n\n> quoted\n\n```rust\nfn main() {}\n```"
+        );
+    }
+
+    #[test]
+    fn test_page_metadata() {
+        let page = json!({
+            "id": "page-id",
+            "url": "https://www.notion.so/page-id",
+            "created_time": "2024-01-01T00:00:00.000Z",
+            "last_edited_time": "2024-01-02T00:00:00.000Z",
+            "properties": {
+                "Name": { "type": "title", "title": [{ "plain_text": "My page" }] },
+                "Tags": { "type": "multi_select", "multi_select": [{ "name": "a" }, { "name": "b" }] },
+                "Score": { "type": "number", "number": 4 },
+                "Status": { "type": "select", "select": null },
+                "Total": { "type": "formula", "formula": { "type": "number", "number": 2 } }
+            }
+        });
+        let metadata = page_metadata(&page);
+
+        assert_eq!(metadata["title"], "My page");
+        assert_eq!(metadata["Name"], "My page");
+        assert_eq!(metadata["Tags"], json!(["a", "b"]));
+        assert_eq!(metadata["Score"], 4);
+        assert_eq!(metadata["Total"], 2);
+        assert!(!metadata.contains_key("Status"));
+        assert_eq!(metadata["source"], "https://www.notion.so/page-id");
+    }
+
+    #[test]
+    fn test_backoff_delay() {
+        let initial = Duration::from_millis(500);
+        assert_eq!(backoff_delay(Some(3), initial, 0), Duration::from_secs(3));
+        assert_eq!(backoff_delay(None, initial, 0), initial);
+        assert_eq!(backoff_delay(None, initial, 3), Duration::from_secs(4));
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_notion_loader() {
+        use futures_util::StreamExt;
+
+        let docs = NotionLoader::from_database(
+            std::env::var("NOTION_TOKEN").unwrap(),
+            std::env::var("NOTION_DATABASE_ID").unwrap(),
+        )
+        .load()
+        .await
+        .unwrap()
+        .collect::<Vec<_>>()
+        .await;
+        assert!(!docs.is_empty());
+    }
+}
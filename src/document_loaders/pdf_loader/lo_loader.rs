@@ -6,9 +6,8 @@ use futures::Stream;
 use serde_json::Value;
 
 use crate::{
-    document_loaders::{process_doc_stream, Loader, LoaderError},
+    document_loaders::{Loader, LoaderError},
     schemas::Document,
-    text_splitter::TextSplitter,
 };
 
 #[derive(Debug, Clone)]
@@ -70,18 +69,6 @@ impl Loader for LoPdfLoader {
 
         Ok(Box::pin(stream))
     }
-
-    async fn load_and_split<TS: TextSplitter + 'static>(
-        mut self,
-        splitter: TS,
-    ) -> Result<
-        Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
-        LoaderError,
-    > {
-        let doc_stream = self.load().await?;
-        let stream = process_doc_stream(doc_stream, splitter).await;
-        Ok(Box::pin(stream))
-    }
 }
 
 #[cfg(test)]
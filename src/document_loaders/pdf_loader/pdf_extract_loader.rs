@@ -6,9 +6,8 @@ use futures::Stream;
 use pdf_extract::{output_doc, PlainTextOutput};
 
 use crate::{
-    document_loaders::{process_doc_stream, Loader, LoaderError},
+    document_loaders::{Loader, LoaderError},
     schemas::Document,
-    text_splitter::TextSplitter,
 };
 
 #[derive(Debug, Clone)]
@@ -66,18 +65,6 @@ impl Loader for PdfExtractLoader {
 
         Ok(Box::pin(stream))
     }
-
-    async fn load_and_split<TS: TextSplitter + 'static>(
-        mut self,
-        splitter: TS,
-    ) -> Result<
-        Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
-        LoaderError,
-    > {
-        let doc_stream = self.load().await?;
-        let stream = process_doc_stream(doc_stream, splitter).await;
-        Ok(Box::pin(stream))
-    }
 }
 
 #[cfg(test)]
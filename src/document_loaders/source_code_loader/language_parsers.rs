@@ -86,7 +86,7 @@ pub fn get_language_by_filename(name: &String) -> Language {
     }
 }
 
-fn get_language_parser(language: &Language) -> Parser {
+pub(crate) fn get_language_parser(language: &Language) -> Parser {
     let mut parser = Parser::new();
     let lang = match language {
         Language::Rust => tree_sitter_rust::LANGUAGE,
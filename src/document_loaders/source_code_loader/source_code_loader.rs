@@ -1,7 +1,5 @@
-use crate::document_loaders::{
-    find_files_with_extension, process_doc_stream, DirLoaderOptions, LoaderError,
-};
-use crate::{document_loaders::Loader, schemas::Document, text_splitter::TextSplitter};
+use crate::document_loaders::{find_files_with_extension, DirLoaderOptions, LoaderError};
+use crate::{document_loaders::Loader, schemas::Document};
 use async_stream::stream;
 use async_trait::async_trait;
 use futures::Stream;
@@ -105,18 +103,6 @@ impl Loader for SourceCodeLoader {
             "No file path or string input provided".to_string(),
         ))
     }
-
-    async fn load_and_split<TS: TextSplitter + 'static>(
-        mut self,
-        splitter: TS,
-    ) -> Result<
-        Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
-        LoaderError,
-    > {
-        let doc_stream = self.load().await?;
-        let stream = process_doc_stream(doc_stream, splitter).await;
-        Ok(Box::pin(stream))
-    }
 }
 
 #[cfg(test)]
@@ -132,9 +118,8 @@ mod tests {
         let loader_with_dir =
             SourceCodeLoader::from_path("./src/document_loaders/test_data".to_string())
                 .with_dir_loader_options(DirLoaderOptions {
-                    glob: None,
                     suffixes: Some(vec!["rs".to_string()]),
-                    path_filter: None,
+                    ..Default::default()
                 });
 
         let stream = loader_with_dir.load().await.unwrap();
@@ -0,0 +1,2 @@
+mod youtube_loader;
+pub use youtube_loader::*;
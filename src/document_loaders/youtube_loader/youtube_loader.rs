@@ -0,0 +1,292 @@
+use std::{collections::HashMap, pin::Pin, time::Duration};
+
+use async_trait::async_trait;
+use futures::{stream, Stream};
+use regex::Regex;
+use serde_json::Value;
+use url::Url;
+
+use crate::{
+    document_loaders::{Loader, LoaderError},
+    schemas::Document,
+};
+
+const TIMEDTEXT_URL: &str = "https://www.youtube.com/api/timedtext";
+
+#[derive(Debug, Clone, PartialEq)]
+struct TranscriptSegment {
+    start: f64,
+    duration: f64,
+    text: String,
+}
+
+/// Loads the transcript of a YouTube video using the timedtext endpoint.
+///
+/// The languages are tried in order, first the captions uploaded for the video and then
+/// the auto-generated ones. Without a chunk duration the whole transcript is a single
+/// `Document`, otherwise a `Document` is created for each chunk. The `start` and
+/// `duration` metadata are in seconds.
+///
+/// # Example
+/// ```rust,ignore
+/// let loader = YoutubeLoader::new("https://www.youtube.com/watch?v=dQw4w9WgXcQ")?
+///     .with_languages(vec!["es".to_string(), "en".to_string()])
+///     .with_chunk_duration(Duration::from_secs(60));
+///
+/// let docs = loader.load().await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct YoutubeLoader {
+    video_id: String,
+    languages: Vec<String>,
+    chunk_duration: Option<Duration>,
+    client: reqwest::Client,
+}
+
+impl YoutubeLoader {
+    /// Creates a loader from a video URL (`watch?v=`, `youtu.be`, `shorts`, `embed`) or a
+    /// video id.
+    pub fn new<S: AsRef<str>>(video: S) -> Result<Self, LoaderError> {
+        Ok(Self {
+            video_id: extract_video_id(video.as_ref())?,
+            languages: vec!["en".to_string()],
+            chunk_duration: None,
+            client: reqwest::Client::new(),
+        })
+    }
+
+    pub fn with_languages(mut self, languages: Vec<String>) -> Self {
+        self.languages = languages;
+        self
+    }
+
+    pub fn with_chunk_duration(mut self, chunk_duration: Duration) -> Self {
+        self.chunk_duration = Some(chunk_duration);
+        self
+    }
+
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    async fn fetch_transcript(
+        &self,
+        language: &str,
+        auto_generated: bool,
+    ) -> Result<Vec<TranscriptSegment>, LoaderError> {
+        let mut query = vec![("v", self.video_id.as_str()), ("lang", language)];
+        if auto_generated {
+            query.push(("kind", "asr"));
+        }
+        let response = self
+            .client
+            .get(TIMEDTEXT_URL)
+            .query(&query)
+            .send()
+            .await?
+            .error_for_status()?;
+        parse_transcript(&response.text().await?)
+    }
+
+    async fn load_transcript(&self) -> Result<(String, Vec<TranscriptSegment>), LoaderError> {
+        for language in &self.languages {
+            for auto_generated in [false, true] {
+                let segments = self.fetch_transcript(language, auto_generated).await?;
+                if !segments.is_empty() {
+                    return Ok((language.clone(), segments));
+                }
+            }
+        }
+        Err(LoaderError::LoadDocumentError(format!(
+            "No transcript available for video {} in languages [{}], transcripts may be disabled",
+            self.video_id,
+            self.languages.join(", ")
+        )))
+    }
+
+    fn segments_to_document(&self, language: &str, segments: &[TranscriptSegment]) -> Document {
+        let start = segments.first().map(|s| s.start).unwrap_or_default();
+        let end = segments
+            .last()
+            .map(|s| s.start + s.duration)
+            .unwrap_or_default();
+        let text = segments
+            .iter()
+            .map(|s| s.text.as_str())
+            .collect::<Vec<&str>>()
+            .join(" ");
+
+        Document::new(text).with_metadata(HashMap::from([
+            (
+                "source".to_string(),
+                Value::from(format!(
+                    "https://www.youtube.com/watch?v={}&t={}s",
+                    self.video_id, start as u64
+                )),
+            ),
+            ("video_id".to_string(), Value::from(self.video_id.clone())),
+            ("language".to_string(), Value::from(language)),
+            ("start".to_string(), Value::from(start)),
+            ("duration".to_string(), Value::from(end - start)),
+        ]))
+    }
+}
+
+/// Groups the segments in chunks that don't go over `chunk_duration`. A segment longer
+/// than the duration is a chunk on its own.
+fn chunk_segments(
+    segments: Vec<TranscriptSegment>,
+    chunk_duration: Option<Duration>,
+) -> Vec<Vec<TranscriptSegment>> {
+    let Some(chunk_duration) = chunk_duration else {
+        return vec![segments];
+    };
+    let max_duration = chunk_duration.as_secs_f64();
+
+    let mut chunks: Vec<Vec<TranscriptSegment>> = Vec::new();
+    let mut current: Vec<TranscriptSegment> = Vec::new();
+    for segment in segments {
+        if let Some(first) = current.first() {
+            if segment.start + segment.duration - first.start > max_duration {
+                chunks.push(std::mem::take(&mut current));
+            }
+        }
+        current.push(segment);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+fn extract_video_id(video: &str) -> Result<String, LoaderError> {
+    let id_re = Regex::new(r"^[A-Za-z0-9_-]{11}$").unwrap();
+    if id_re.is_match(video) {
+        return Ok(video.to_string());
+    }
+
+    let invalid = || LoaderError::LoadDocumentError(format!("Invalid YouTube video: {}", video));
+    let url = Url::parse(video).map_err(|_| invalid())?;
+    let id = match url.host_str() {
+        Some("youtu.be") => url
+            .path_segments()
+            .and_then(|mut s| s.next().map(String::from)),
+        Some(host) if host.ends_with("youtube.com") => {
+            match url.query_pairs().find(|(key, _)| key == "v") {
+                Some((_, id)) => Some(id.into_owned()),
+                None => url
+                    .path_segments()
+                    .and_then(|mut segments| match segments.next() {
+                        Some("shorts") | Some("embed") | Some("live") => {
+                            segments.next().map(String::from)
+                        }
+                        _ => None,
+                    }),
+            }
+        }
+        _ => None,
+    };
+
+    id.filter(|id| id_re.is_match(id)).ok_or_else(invalid)
+}
+
+fn parse_transcript(xml: &str) -> Result<Vec<TranscriptSegment>, LoaderError> {
+    let text_re = Regex::new(r#"(?s)<text start="([\d.]+)"(?: dur="([\d.]+)")?[^>]*>(.*?)</text>"#)
+        .map_err(|e| LoaderError::OtherError(e.to_string()))?;
+
+    let segments = text_re
+        .captures_iter(xml)
+        .filter_map(|cap| {
+            // The text is escaped twice, `&amp;#39;` is an apostrophe
+            let text =
+                html_escape::decode_html_entities(&html_escape::decode_html_entities(&cap[3]))
+                    .replace('\n', " ")
+                    .trim()
+                    .to_string();
+            if text.is_empty() {
+                return None;
+            }
+            Some(TranscriptSegment {
+                start: cap[1].parse().unwrap_or_default(),
+                duration: cap
+                    .get(2)
+                    .and_then(|d| d.as_str().parse().ok())
+                    .unwrap_or_default(),
+                text,
+            })
+        })
+        .collect();
+    Ok(segments)
+}
+
+#[async_trait]
+impl Loader for YoutubeLoader {
+    async fn load(
+        self,
+    ) -> Result<
+        Pin<Box<dyn Stream<Item = Result<Document, LoaderError>> + Send + 'static>>,
+        LoaderError,
+    > {
+        let (language, segments) = self.load_transcript().await?;
+        let docs = chunk_segments(segments, self.chunk_duration)
+            .iter()
+            .map(|chunk| Ok(self.segments_to_document(&language, chunk)))
+            .collect::<Vec<_>>();
+
+        Ok(Box::pin(stream::iter(docs)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_video_id() {
+        for video in [
+            "dQw4w9WgXcQ",
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=42s",
+            "https://youtu.be/dQw4w9WgXcQ",
+            "https://www.youtube.com/shorts/dQw4w9WgXcQ",
+            "https://m.youtube.com/embed/dQw4w9WgXcQ",
+        ] {
+            assert_eq!(extract_video_id(video).unwrap(), "dQw4w9WgXcQ", "{}", video);
+        }
+        assert!(extract_video_id("https://example.com/watch?v=dQw4w9WgXcQ").is_err());
+    }
+
+    #[test]
+    fn test_parse_and_chunk_transcript() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8" ?><transcript><text start="0.5" dur="2.0">Hello &amp;amp; welcome</text><text start="2.5" dur="3">it&amp;#39;s
+me</text><text start="6" dur="4">bye</text></transcript>"#;
+        let segments = parse_transcript(xml).unwrap();
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[0].text, "Hello & welcome");
+        assert_eq!(segments[1].text, "it's me");
+
+        let chunks = chunk_segments(segments.clone(), Some(Duration::from_secs(5)));
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].len(), 2);
+        assert_eq!(chunks[1][0].text, "bye");
+
+        assert_eq!(chunk_segments(segments, None).len(), 1);
+        assert!(parse_transcript("").unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_youtube_loader() {
+        use futures_util::StreamExt;
+
+        let docs = YoutubeLoader::new("https://www.youtube.com/watch?v=dQw4w9WgXcQ")
+            .unwrap()
+            .with_chunk_duration(Duration::from_secs(30))
+            .load()
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert!(!docs.is_empty());
+    }
+}
@@ -23,6 +23,13 @@ pub enum EmbedderError {
         error_message: String,
     },
 
+    #[error("Error embedding inputs {start}..{end}: {source}")]
+    EmbedInputError {
+        start: usize,
+        end: usize,
+        source: Box<EmbedderError>,
+    },
+
     #[error("FastEmbed error: {0}")]
     FastEmbedError(String),
 
@@ -35,6 +35,9 @@ impl From<TextEmbedding> for FastEmbed {
 #[async_trait]
 impl Embedder for FastEmbed {
     async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f64>>, EmbedderError> {
+        if documents.is_empty() {
+            return Ok(Vec::new());
+        }
         let embeddings = self
             .model
             .embed(documents.to_vec(), self.batch_size)
@@ -73,4 +76,11 @@ mod tests {
             .unwrap();
         assert_eq!(embeddings.len(), 2);
     }
+
+    #[tokio::test]
+    async fn test_fastembed_no_documents() {
+        let fastembed = FastEmbed::try_new().unwrap();
+        let embeddings = fastembed.embed_documents(&[]).await.unwrap();
+        assert!(embeddings.is_empty());
+    }
 }
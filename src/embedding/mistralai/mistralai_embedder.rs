@@ -23,6 +23,9 @@ impl MistralAIEmbedder {
 #[async_trait]
 impl Embedder for MistralAIEmbedder {
     async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f64>>, EmbedderError> {
+        if documents.is_empty() {
+            return Ok(Vec::new());
+        }
         log::debug!("Embedding documents: {:?}", documents);
 
         let response = self
@@ -77,4 +80,24 @@ mod tests {
             .unwrap();
         assert_eq!(embeddings.len(), 2);
     }
+
+    #[tokio::test]
+    async fn test_mistralai_embed_no_documents() {
+        let mut server = mockito::Server::new_async().await;
+        let embeddings_endpoint = server
+            .mock("POST", mockito::Matcher::Any)
+            .expect(0)
+            .create_async()
+            .await;
+        let client = Client::new(Some("key".to_string()), Some(server.url()), None, None).unwrap();
+        let mistralai = MistralAIEmbedder {
+            client: Arc::new(client),
+            model: EmbedModel::MistralEmbed,
+        };
+
+        let embeddings = mistralai.embed_documents(&[]).await.unwrap();
+
+        assert!(embeddings.is_empty());
+        embeddings_endpoint.assert_async().await;
+    }
 }
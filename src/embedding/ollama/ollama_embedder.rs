@@ -1,20 +1,33 @@
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
 
-use crate::embedding::{embedder_trait::Embedder, EmbedderError};
+use crate::{
+    embedding::{embedder_trait::Embedder, EmbedderError},
+    schemas::ProgressCallback,
+};
 use async_trait::async_trait;
+use futures::future::join_all;
 use ollama_rs::{
     generation::{
         embeddings::request::{EmbeddingsInput, GenerateEmbeddingsRequest},
         options::GenerationOptions,
+        parameters::KeepAlive,
     },
     Ollama as OllamaClient,
 };
+use tokio::sync::Semaphore;
 
 #[derive(Debug)]
 pub struct OllamaEmbedder {
     pub(crate) client: Arc<OllamaClient>,
     pub(crate) model: String,
     pub(crate) options: Option<GenerationOptions>,
+    pub(crate) keep_alive: Option<KeepAlive>,
+    pub(crate) batch_size: usize,
+    pub(crate) max_concurrency: usize,
+    pub(crate) progress: Option<ProgressCallback>,
 }
 
 /// [nomic-embed-text](https://ollama.com/library/nomic-embed-text) is a 137M parameters, 274MB model.
@@ -30,6 +43,10 @@ impl OllamaEmbedder {
             client,
             model: model.into(),
             options,
+            keep_alive: None,
+            batch_size: 16,
+            max_concurrency: 4,
+            progress: None,
         }
     }
 
@@ -42,6 +59,52 @@ impl OllamaEmbedder {
         self.options = Some(options);
         self
     }
+
+    /// How long the model stays loaded after a request, keeping it loaded avoids
+    /// reloading it between batches.
+    pub fn with_keep_alive(mut self, keep_alive: KeepAlive) -> Self {
+        self.keep_alive = Some(keep_alive);
+        self
+    }
+
+    /// Size of the context window, added to the generation options.
+    pub fn with_num_ctx(mut self, num_ctx: u32) -> Self {
+        self.options = Some(self.options.take().unwrap_or_default().num_ctx(num_ctx));
+        self
+    }
+
+    /// Number of documents sent in each request by `embed_documents`.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Maximum number of requests `embed_documents` sends at the same time.
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = max_concurrency.max(1);
+        self
+    }
+
+    /// Called by `embed_documents` after each batch is embedded, with the number of
+    /// documents embedded and the total.
+    pub fn with_progress<F: Fn(usize, usize) + Send + Sync + 'static>(
+        mut self,
+        progress: F,
+    ) -> Self {
+        self.progress = Some(ProgressCallback::new(progress));
+        self
+    }
+
+    fn request(&self, input: EmbeddingsInput) -> GenerateEmbeddingsRequest {
+        let mut request = GenerateEmbeddingsRequest::new(self.model.clone(), input);
+        if let Some(options) = &self.options {
+            request = request.options(options.clone());
+        }
+        if let Some(keep_alive) = &self.keep_alive {
+            request = request.keep_alive(keep_alive.clone());
+        }
+        request
+    }
 }
 
 impl Default for OllamaEmbedder {
@@ -53,22 +116,48 @@ impl Default for OllamaEmbedder {
 
 #[async_trait]
 impl Embedder for OllamaEmbedder {
+    /// Embeds the documents in batches of `batch_size`, with up to `max_concurrency`
+    /// requests at the same time. The embeddings are returned in the order of the
+    /// documents, the `progress` callback is called as the batches complete.
     async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f64>>, EmbedderError> {
+        if documents.is_empty() {
+            return Ok(Vec::new());
+        }
         log::debug!("Embedding documents: {:?}", documents);
 
-        let response = self
-            .client
-            .generate_embeddings(GenerateEmbeddingsRequest::new(
-                self.model.clone(),
-                EmbeddingsInput::Multiple(documents.to_vec()),
-            ))
-            .await?;
+        let semaphore = &Semaphore::new(self.max_concurrency);
+        let embedded = &AtomicUsize::new(0);
+        let requests = documents
+            .chunks(self.batch_size)
+            .enumerate()
+            .map(|(i, batch)| async move {
+                let start = i * self.batch_size;
+                let _permit = semaphore.acquire().await;
+                let embeddings = self
+                    .client
+                    .generate_embeddings(self.request(EmbeddingsInput::Multiple(batch.to_vec())))
+                    .await
+                    .map(|response| response.embeddings)
+                    .map_err(|e| EmbedderError::EmbedInputError {
+                        start,
+                        end: start + batch.len(),
+                        source: Box::new(e.into()),
+                    })?;
+                if let Some(progress) = &self.progress {
+                    let done = embedded.fetch_add(batch.len(), Ordering::SeqCst) + batch.len();
+                    progress.report(done, documents.len());
+                }
+                Ok::<_, EmbedderError>(embeddings)
+            });
 
-        let embeddings = response
-            .embeddings
-            .into_iter()
-            .map(|embedding| embedding.into_iter().map(f64::from).collect())
-            .collect();
+        let mut embeddings = Vec::with_capacity(documents.len());
+        for batch in join_all(requests).await {
+            embeddings.extend(
+                batch?
+                    .into_iter()
+                    .map(|embedding| embedding.into_iter().map(f64::from).collect()),
+            );
+        }
 
         Ok(embeddings)
     }
@@ -78,10 +167,7 @@ impl Embedder for OllamaEmbedder {
 
         let response = self
             .client
-            .generate_embeddings(GenerateEmbeddingsRequest::new(
-                self.model.clone(),
-                EmbeddingsInput::Single(text.into()),
-            ))
+            .generate_embeddings(self.request(EmbeddingsInput::Single(text.into())))
             .await?;
 
         let embeddings = response
@@ -99,8 +185,75 @@ impl Embedder for OllamaEmbedder {
 
 #[cfg(test)]
 mod tests {
+    use serde_json::json;
+
     use super::*;
 
+    #[tokio::test]
+    async fn test_ollama_embed_documents_in_batches() {
+        let mut server = mockito::Server::new_async().await;
+        for (input, embeddings) in [
+            (json!(["a", "b"]), json!([[1.0], [2.0]])),
+            (json!(["c", "d"]), json!([[3.0], [4.0]])),
+            (json!(["e"]), json!([[5.0]])),
+        ] {
+            server
+                .mock("POST", "/api/embed")
+                .match_body(mockito::Matcher::PartialJson(json!({ "input": input })))
+                .with_body(json!({ "embeddings": embeddings }).to_string())
+                .create_async()
+                .await;
+        }
+        let url = url::Url::parse(&server.url()).unwrap();
+        let client = OllamaClient::new(
+            format!("http://{}", url.host_str().unwrap()),
+            url.port().unwrap(),
+        );
+
+        let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let embedder = OllamaEmbedder::new(Arc::new(client), DEFAULT_MODEL, None)
+            .with_batch_size(2)
+            .with_max_concurrency(2)
+            .with_keep_alive(KeepAlive::Indefinitely)
+            .with_progress({
+                let reports = reports.clone();
+                move |done, total| reports.lock().unwrap().push((done, total))
+            });
+        let documents = ["a", "b", "c", "d", "e"].map(String::from);
+
+        let embeddings = embedder.embed_documents(&documents).await.unwrap();
+        assert_eq!(
+            embeddings,
+            vec![vec![1.0], vec![2.0], vec![3.0], vec![4.0], vec![5.0]]
+        );
+        // One report per batch, in the order the batches complete
+        let reports = reports.lock().unwrap().clone();
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports.last(), Some(&(5, 5)));
+
+        let error = embedder
+            .embed_documents(&["a", "b", "x"].map(String::from))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            EmbedderError::EmbedInputError {
+                start: 2,
+                end: 3,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_ollama_embed_no_documents() {
+        // Nothing listens on the port, the server is not called
+        let client = OllamaClient::new("http://127.0.0.1", 9);
+        let embedder = OllamaEmbedder::new(Arc::new(client), DEFAULT_MODEL, None);
+        let embeddings = embedder.embed_documents(&[]).await.unwrap();
+        assert!(embeddings.is_empty());
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_ollama_embed() {
@@ -3,7 +3,7 @@
 use crate::embedding::{embedder_trait::Embedder, EmbedderError};
 pub use async_openai::config::{AzureConfig, Config, OpenAIConfig};
 use async_openai::{
-    types::{CreateEmbeddingRequestArgs, EmbeddingInput},
+    types::{CreateEmbeddingRequest, CreateEmbeddingRequestArgs, EmbeddingInput},
     Client,
 };
 use async_trait::async_trait;
@@ -12,6 +12,7 @@ use async_trait::async_trait;
 pub struct OpenAiEmbedder<C: Config> {
     config: C,
     model: String,
+    dimensions: Option<u32>,
 }
 
 impl<C: Config + Send + Sync + 'static> Into<Box<dyn Embedder>> for OpenAiEmbedder<C> {
@@ -25,6 +26,7 @@ impl<C: Config> OpenAiEmbedder<C> {
         OpenAiEmbedder {
             config,
             model: String::from("text-embedding-ada-002"),
+            dimensions: None,
         }
     }
 
@@ -37,6 +39,38 @@ impl<C: Config> OpenAiEmbedder<C> {
         self.config = config;
         self
     }
+
+    /// Number of dimensions of the embeddings, only supported by `text-embedding-3` and
+    /// later models. When not set the model default is used. It must match the
+    /// `vector_dimensions` of the vector store the embeddings are saved in.
+    pub fn with_dimensions(mut self, dimensions: u32) -> Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
+
+    fn request<I: Into<EmbeddingInput>>(
+        &self,
+        input: I,
+    ) -> Result<CreateEmbeddingRequest, EmbedderError> {
+        let mut args = CreateEmbeddingRequestArgs::default();
+        args.model(&self.model).input(input);
+        if let Some(dimensions) = self.dimensions {
+            args.dimensions(dimensions);
+        }
+        Ok(args.build()?)
+    }
+}
+
+impl OpenAiEmbedder<AzureConfig> {
+    /// Azure routes requests by deployment instead of model, this sets the deployment in
+    /// the config and uses its name as the model. The `api-version` query param comes
+    /// from `AzureConfig::with_api_version`.
+    pub fn with_deployment<S: Into<String>>(mut self, deployment_id: S) -> Self {
+        let deployment_id = deployment_id.into();
+        self.config = self.config.with_deployment_id(deployment_id.clone());
+        self.model = deployment_id;
+        self
+    }
 }
 
 impl Default for OpenAiEmbedder<OpenAIConfig> {
@@ -48,12 +82,12 @@ impl Default for OpenAiEmbedder<OpenAIConfig> {
 #[async_trait]
 impl<C: Config + Send + Sync> Embedder for OpenAiEmbedder<C> {
     async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f64>>, EmbedderError> {
+        if documents.is_empty() {
+            return Ok(Vec::new());
+        }
         let client = Client::with_config(self.config.clone());
 
-        let request = CreateEmbeddingRequestArgs::default()
-            .model(&self.model)
-            .input(EmbeddingInput::StringArray(documents.into()))
-            .build()?;
+        let request = self.request(EmbeddingInput::StringArray(documents.into()))?;
 
         let response = client.embeddings().create(request).await?;
 
@@ -75,10 +109,7 @@ impl<C: Config + Send + Sync> Embedder for OpenAiEmbedder<C> {
     async fn embed_query(&self, text: &str) -> Result<Vec<f64>, EmbedderError> {
         let client = Client::with_config(self.config.clone());
 
-        let request = CreateEmbeddingRequestArgs::default()
-            .model(&self.model)
-            .input(text)
-            .build()?;
+        let request = self.request(text)?;
 
         let mut response = client.embeddings().create(request).await?;
 
@@ -91,3 +122,42 @@ impl<C: Config + Send + Sync> Embedder for OpenAiEmbedder<C> {
             .collect::<Vec<f64>>())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_dimensions() {
+        let embedder = OpenAiEmbedder::default().with_model("text-embedding-3-small");
+        let request = serde_json::to_value(embedder.request("text").unwrap()).unwrap();
+        assert!(request.get("dimensions").is_none());
+
+        let request =
+            serde_json::to_value(embedder.with_dimensions(256).request("text").unwrap()).unwrap();
+        assert_eq!(request["dimensions"], 256);
+    }
+
+    #[tokio::test]
+    async fn test_embed_no_documents() {
+        // No API key is needed, the API is not called
+        let embeddings = OpenAiEmbedder::default()
+            .embed_documents(&[])
+            .await
+            .unwrap();
+        assert!(embeddings.is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_azure_embed_query() {
+        let config = AzureConfig::default()
+            .with_api_key(std::env::var("AZURE_OPENAI_API_KEY").unwrap())
+            .with_api_base(std::env::var("AZURE_OPENAI_ENDPOINT").unwrap())
+            .with_api_version("2024-02-01");
+        let embedder = OpenAiEmbedder::new(config).with_deployment("text-embedding-3-small");
+
+        let embedding = embedder.embed_query("Why is the sky blue?").await.unwrap();
+        assert!(!embedding.is_empty());
+    }
+}
@@ -2,7 +2,7 @@ use async_openai::error::OpenAIError;
 #[cfg(feature = "ollama")]
 use ollama_rs::error::OllamaError;
 use reqwest::Error as ReqwestError;
-use serde_json::Error as SerdeJsonError;
+use serde_json::{Error as SerdeJsonError, Value};
 use thiserror::Error;
 use tokio::time::error::Elapsed;
 
@@ -35,9 +35,196 @@ pub enum LLMError {
     #[error("Invalid URL: {0}")]
     InvalidUrl(String),
 
+    #[error("Image inputs are not supported: {0}")]
+    ImagesNotSupported(String),
+
     #[error("Content not found in response: Expected at {0}")]
     ContentNotFound(String),
 
+    #[error("{provider} API error ({status}): {message}")]
+    ApiError {
+        provider: String,
+        status: u16,
+        code: Option<String>,
+        message: String,
+        raw_body: String,
+    },
+
     #[error("Error: {0}")]
     OtherError(String),
 }
+
+impl LLMError {
+    /// Builds an `ApiError` from a failed response of a provider. The code and message are
+    /// read from the usual `{"error": {"type"|"code", "message"}}` body, the raw body is kept
+    /// as it is.
+    pub fn api_error<S: Into<String>>(provider: S, status: u16, raw_body: String) -> Self {
+        let body: Value = serde_json::from_str(&raw_body).unwrap_or_default();
+        let error = &body["error"];
+        let code = error["code"]
+            .as_str()
+            .or_else(|| error["type"].as_str())
+            .map(String::from);
+        let message = error["message"]
+            .as_str()
+            .or_else(|| error.as_str())
+            .map(String::from)
+            .unwrap_or_else(|| raw_body.clone());
+
+        LLMError::ApiError {
+            provider: provider.into(),
+            status,
+            code,
+            message,
+            raw_body,
+        }
+    }
+
+    /// Reads the body of a response that wasn't successful into an `ApiError`.
+    pub(crate) async fn from_response(provider: &str, response: reqwest::Response) -> Self {
+        let status = response.status().as_u16();
+        match response.text().await {
+            Ok(raw_body) => Self::api_error(provider, status, raw_body),
+            Err(e) => e.into(),
+        }
+    }
+
+    /// HTTP status returned by the provider, if the error comes from one. The typed
+    /// Anthropic errors are mapped to the status Anthropic documents for them.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            LLMError::ApiError { status, .. } => Some(*status),
+            LLMError::RequestError(e) => e.status().map(|status| status.as_u16()),
+            LLMError::AnthropicError(error) => Some(match error {
+                AnthropicError::InvalidRequestError(_) => 400,
+                AnthropicError::AuthenticationError(_) => 401,
+                AnthropicError::PermissionError(_) => 403,
+                AnthropicError::NotFoundError(_) => 404,
+                AnthropicError::RateLimitError(_) => 429,
+                AnthropicError::ApiError(_) => 500,
+                AnthropicError::OverloadedError(_) => 529,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Whether the provider rejected the request because of its rate limits, whichever
+    /// client returned the error.
+    pub fn is_rate_limit(&self) -> bool {
+        match self {
+            LLMError::ApiError { status, code, .. } => {
+                *status == 429 || code.as_deref() == Some("rate_limit_error")
+            }
+            LLMError::AnthropicError(AnthropicError::RateLimitError(_)) => true,
+            LLMError::OpenAIError(OpenAIError::ApiError(e)) => {
+                e.code.as_deref() == Some("rate_limit_exceeded")
+            }
+            _ => self.status() == Some(429),
+        }
+    }
+
+    /// Whether the same request may succeed later or with another provider: rate limits,
+    /// server errors, timeouts and connection failures. Bad requests and authentication
+    /// errors aren't.
+    pub fn is_retryable(&self) -> bool {
+        if self.is_rate_limit() {
+            return true;
+        }
+        match self {
+            LLMError::Timeout(_) => true,
+            LLMError::RequestError(e) => {
+                e.is_timeout() || e.is_connect() || self.status().is_some_and(|s| s >= 500)
+            }
+            LLMError::OpenAIError(OpenAIError::Reqwest(e)) => e.is_timeout() || e.is_connect(),
+            LLMError::OpenAIError(OpenAIError::ApiError(e)) => {
+                e.r#type.as_deref() == Some("server_error")
+            }
+            LLMError::AnthropicError(
+                AnthropicError::ApiError(_) | AnthropicError::OverloadedError(_),
+            ) => true,
+            _ => self
+                .status()
+                .is_some_and(|status| status == 408 || status >= 500),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_error_reads_the_body() {
+        let body = r#"{"type":"error","error":{"type":"rate_limit_error","message":"Slow down"}}"#;
+        let error = LLMError::api_error("anthropic", 429, body.to_string());
+
+        match &error {
+            LLMError::ApiError {
+                provider,
+                status,
+                code,
+                message,
+                raw_body,
+            } => {
+                assert_eq!(provider, "anthropic");
+                assert_eq!(*status, 429);
+                assert_eq!(code.as_deref(), Some("rate_limit_error"));
+                assert_eq!(message, "Slow down");
+                assert_eq!(raw_body, body);
+            }
+            _ => panic!("Expected an ApiError"),
+        }
+        assert_eq!(error.status(), Some(429));
+        assert!(error.is_rate_limit());
+    }
+
+    #[test]
+    fn test_api_error_keeps_plain_bodies() {
+        let error = LLMError::api_error("openai", 502, "Bad Gateway".to_string());
+
+        assert_eq!(error.to_string(), "openai API error (502): Bad Gateway");
+        assert!(!error.is_rate_limit());
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(LLMError::api_error("openai", 503, String::new()).is_retryable());
+        assert!(LLMError::api_error("openai", 429, String::new()).is_retryable());
+        assert!(!LLMError::api_error("openai", 400, String::new()).is_retryable());
+        assert!(!LLMError::OtherError("".into()).is_retryable());
+    }
+
+    #[test]
+    fn test_is_rate_limit_for_typed_errors() {
+        let error = LLMError::AnthropicError(AnthropicError::RateLimitError("".into()));
+        assert!(error.is_rate_limit());
+        assert_eq!(error.status(), Some(429));
+    }
+
+    #[test]
+    fn test_status_of_typed_anthropic_errors() {
+        let status = |error: AnthropicError| LLMError::AnthropicError(error).status();
+
+        assert_eq!(
+            status(AnthropicError::AuthenticationError("".into())),
+            Some(401)
+        );
+        assert_eq!(
+            status(AnthropicError::PermissionError("".into())),
+            Some(403)
+        );
+        assert_eq!(status(AnthropicError::NotFoundError("".into())), Some(404));
+        assert_eq!(
+            status(AnthropicError::OverloadedError("".into())),
+            Some(529)
+        );
+        assert_eq!(
+            status(AnthropicError::InvalidRequestError("".into())),
+            Some(400)
+        );
+        assert!(
+            !LLMError::AnthropicError(AnthropicError::InvalidRequestError("".into()))
+                .is_retryable()
+        );
+    }
+}
@@ -27,11 +27,7 @@ pub trait LLM: Sync + Send + LLMClone {
     }
     //This is usefull when using non chat models
     fn messages_to_string(&self, messages: &[Message]) -> String {
-        messages
-            .iter()
-            .map(|m| format!("{:?}: {}", m.message_type, m.content))
-            .collect::<Vec<String>>()
-            .join("\n")
+        Message::messages_to_string(messages)
     }
 }
 
@@ -48,6 +44,12 @@ where
     }
 }
 
+impl Clone for Box<dyn LLM> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
 impl<L> From<L> for Box<dyn LLM>
 where
     L: 'static + LLM,
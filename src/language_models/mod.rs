@@ -8,15 +8,42 @@ pub mod options;
 mod error;
 pub use error::*;
 
+mod rate_limited;
+pub use rate_limited::*;
+
+mod sse;
+pub use sse::*;
+
+mod stop_words;
+pub use stop_words::*;
+
+mod token_counter;
+pub use token_counter::*;
+
+mod tokenizer;
+pub use tokenizer::*;
+
 //TODO: check if its this should have a data:serde::Value to save all other things, like OpenAI
 //function responses
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct GenerateResult {
     pub tokens: Option<TokenUsage>,
     pub generation: String,
+    /// What the provider reported about the response, filled by the clients that get it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<GenerationMetadata>,
 }
 
 impl GenerateResult {
+    /// Whether the generation stopped because it reached the maximum number of tokens, the
+    /// caller can ask the model to continue it.
+    pub fn is_truncated(&self) -> bool {
+        self.metadata
+            .as_ref()
+            .and_then(|metadata| metadata.finish_reason.as_deref())
+            .is_some_and(|reason| reason == "length" || reason == "max_tokens")
+    }
+
     pub fn to_hashmap(&self) -> HashMap<String, String> {
         let mut map = HashMap::new();
 
@@ -36,10 +63,30 @@ impl GenerateResult {
             map.insert("total_tokens".to_string(), tokens.total_tokens.to_string());
         }
 
+        if let Some(finish_reason) = self
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.finish_reason.as_ref())
+        {
+            map.insert("finish_reason".to_string(), finish_reason.clone());
+        }
+
         map
     }
 }
 
+/// Details of a response, as reported by the provider.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct GenerationMetadata {
+    /// Why the generation stopped, as named by the provider: `stop`, `length` or
+    /// `tool_calls` for OpenAI, `end_turn`, `max_tokens` or `tool_use` for Claude.
+    pub finish_reason: Option<String>,
+    /// Model that served the request, which may differ from the requested one.
+    pub model: Option<String>,
+    /// Id of the response.
+    pub id: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct TokenUsage {
     pub prompt_tokens: u32,
@@ -72,3 +119,33 @@ impl TokenUsage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_truncated() {
+        let result = |finish_reason: &str| GenerateResult {
+            metadata: Some(GenerationMetadata {
+                finish_reason: Some(finish_reason.into()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        assert!(result("length").is_truncated());
+        assert!(result("max_tokens").is_truncated());
+        assert!(!result("stop").is_truncated());
+        assert!(!GenerateResult::default().is_truncated());
+    }
+
+    #[test]
+    fn test_metadata_is_optional_in_json() {
+        let result: GenerateResult =
+            serde_json::from_str(r#"{"tokens": null, "generation": "Hi"}"#).unwrap();
+
+        assert!(result.metadata.is_none());
+        assert!(!serde_json::to_string(&result).unwrap().contains("metadata"));
+    }
+}
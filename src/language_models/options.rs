@@ -2,7 +2,10 @@ use futures::Future;
 use std::{pin::Pin, sync::Arc};
 use tokio::sync::Mutex;
 
-use crate::schemas::{FunctionCallBehavior, FunctionDefinition};
+use crate::{
+    callbacks::CallbackHandler,
+    schemas::{FunctionCallBehavior, FunctionDefinition},
+};
 
 #[derive(Clone)]
 pub struct CallOptions {
@@ -27,6 +30,8 @@ pub struct CallOptions {
     pub functions: Option<Vec<FunctionDefinition>>,
     pub function_call_behavior: Option<FunctionCallBehavior>,
     pub stream_usage: Option<bool>,
+    pub json_mode: Option<bool>,
+    pub callbacks: Option<Vec<Arc<dyn CallbackHandler>>>,
 }
 
 impl Default for CallOptions {
@@ -54,6 +59,8 @@ impl CallOptions {
             functions: None,
             function_call_behavior: None,
             stream_usage: None,
+            json_mode: None,
+            callbacks: None,
         }
     }
 
@@ -154,6 +161,19 @@ impl CallOptions {
         self
     }
 
+    /// Asks the LLM to reply with a JSON object, for the providers that support it.
+    pub fn with_json_mode(mut self, json_mode: bool) -> Self {
+        self.json_mode = Some(json_mode);
+        self
+    }
+
+    /// Registers the handlers that will be notified of the LLM events,
+    /// like the start of a call or every new token while streaming.
+    pub fn with_callbacks(mut self, callbacks: Vec<Arc<dyn CallbackHandler>>) -> Self {
+        self.callbacks = Some(callbacks);
+        self
+    }
+
     pub fn merge_options(&mut self, incoming_options: CallOptions) {
         // For simple scalar types wrapped in Option, prefer incoming option if it is Some
         self.candidate_count = incoming_options.candidate_count.or(self.candidate_count);
@@ -176,6 +196,7 @@ impl CallOptions {
             .function_call_behavior
             .or(self.function_call_behavior.clone());
         self.stream_usage = incoming_options.stream_usage.or(self.stream_usage);
+        self.json_mode = incoming_options.json_mode.or(self.json_mode);
 
         // For `Vec<String>`, merge if both are Some; prefer incoming if only incoming is Some
         if let Some(mut new_stop_words) = incoming_options.stop_words {
@@ -195,6 +216,24 @@ impl CallOptions {
             }
         }
 
+        // Handlers are accumulated, so a chain can add its own without removing the LLM ones.
+        // A handler already registered is skipped, merging the same options again doesn't
+        // notify it twice.
+        if let Some(incoming_callbacks) = incoming_options.callbacks {
+            if let Some(existing_callbacks) = &mut self.callbacks {
+                for callback in incoming_callbacks {
+                    if !existing_callbacks
+                        .iter()
+                        .any(|existing| Arc::ptr_eq(existing, &callback))
+                    {
+                        existing_callbacks.push(callback);
+                    }
+                }
+            } else {
+                self.callbacks = Some(incoming_callbacks);
+            }
+        }
+
         // `streaming_func` requires a judgment call on how you want to handle merging.
         // Here, the incoming option simply replaces the existing one if it's Some.
         self.streaming_func = incoming_options
@@ -202,3 +241,25 @@ impl CallOptions {
             .or_else(|| self.streaming_func.clone());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NoopHandler;
+
+    #[async_trait::async_trait]
+    impl CallbackHandler for NoopHandler {}
+
+    #[test]
+    fn test_merge_options_skips_registered_callbacks() {
+        let first: Arc<dyn CallbackHandler> = Arc::new(NoopHandler);
+        let second: Arc<dyn CallbackHandler> = Arc::new(NoopHandler);
+        let mut options = CallOptions::new().with_callbacks(vec![first.clone()]);
+
+        options.merge_options(CallOptions::new().with_callbacks(vec![first.clone()]));
+        options.merge_options(CallOptions::new().with_callbacks(vec![first, second]));
+
+        assert_eq!(options.callbacks.unwrap().len(), 2);
+    }
+}
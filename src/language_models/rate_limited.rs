@@ -0,0 +1,257 @@
+use std::{
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use tokio::time::Instant;
+
+use crate::{
+    embedding::{Embedder, EmbedderError},
+    schemas::{Message, StreamData},
+};
+
+use super::{llm::LLM, options::CallOptions, GenerateResult, LLMError, TokenCounter};
+
+/// A token bucket refilled continuously up to its capacity.
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    refill_per_second: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    fn new(per_minute: u32, burst: u32) -> Self {
+        let capacity = burst.max(1) as f64;
+        Self {
+            capacity,
+            refill_per_second: per_minute.max(1) as f64 / 60.0,
+            state: Mutex::new((capacity, Instant::now())),
+        }
+    }
+
+    /// Takes `amount` from the bucket, or returns how long to wait until it can.
+    fn try_take(&self, amount: f64) -> Result<(), Duration> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (available, refilled_at) = &mut *state;
+        let now = Instant::now();
+        *available = (*available
+            + now.duration_since(*refilled_at).as_secs_f64() * self.refill_per_second)
+            .min(self.capacity);
+        *refilled_at = now;
+
+        if *available >= amount {
+            *available -= amount;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (amount - *available) / self.refill_per_second,
+            ))
+        }
+    }
+
+    /// Waits until the bucket has `amount` and takes it. An amount larger than the capacity
+    /// waits for a full bucket, it would never be available otherwise.
+    async fn acquire(&self, amount: usize) {
+        let amount = (amount as f64).min(self.capacity);
+        while let Err(wait) = self.try_take(amount) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Takes `amount` without waiting, the next calls wait for the debt to be refilled.
+    fn consume(&self, amount: usize) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.0 -= amount as f64;
+    }
+}
+
+/// Wraps an `LLM` or an `Embedder` to keep its calls under the requests per minute and
+/// tokens per minute limits of the provider. The calls wait, without blocking the runtime,
+/// until the limits allow them. Clones share the limits.
+///
+/// The tokens of a call are counted on its input with the token counter before the call.
+/// The completion tokens reported in the usage of an LLM are taken after it, so the next
+/// calls wait for them. For a stream, they are taken from the usage of its last chunk once
+/// it ends.
+///
+/// # Example
+/// ```rust,ignore
+/// let llm = RateLimited::new(OpenAI::default())
+///     .with_requests_per_minute(500, 20)
+///     .with_tokens_per_minute(30_000, 10_000);
+/// ```
+#[derive(Clone)]
+pub struct RateLimited<T> {
+    inner: T,
+    requests: Option<Arc<TokenBucket>>,
+    tokens: Option<Arc<TokenBucket>>,
+    token_counter: TokenCounter,
+}
+
+impl<T> RateLimited<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            requests: None,
+            tokens: None,
+            token_counter: TokenCounter::default(),
+        }
+    }
+
+    /// Limits the calls to `requests_per_minute`, with up to `burst` calls right away after
+    /// a quiet period.
+    pub fn with_requests_per_minute(mut self, requests_per_minute: u32, burst: u32) -> Self {
+        self.requests = Some(Arc::new(TokenBucket::new(requests_per_minute, burst)));
+        self
+    }
+
+    /// Limits the tokens to `tokens_per_minute`, with up to `burst` tokens right away after
+    /// a quiet period.
+    pub fn with_tokens_per_minute(mut self, tokens_per_minute: u32, burst: u32) -> Self {
+        self.tokens = Some(Arc::new(TokenBucket::new(tokens_per_minute, burst)));
+        self
+    }
+
+    /// Counter of the input tokens, the `cl100k_base` encoding by default.
+    pub fn with_token_counter(mut self, token_counter: TokenCounter) -> Self {
+        self.token_counter = token_counter;
+        self
+    }
+
+    async fn acquire(&self, tokens: impl FnOnce(&TokenCounter) -> usize) {
+        if let Some(requests) = &self.requests {
+            requests.acquire(1).await;
+        }
+        if let Some(bucket) = &self.tokens {
+            bucket.acquire(tokens(&self.token_counter)).await;
+        }
+    }
+
+    fn count_messages(&self, messages: &[Message]) -> usize {
+        messages
+            .iter()
+            .map(|message| self.token_counter.count_message(message))
+            .sum()
+    }
+}
+
+#[async_trait]
+impl<L: LLM + Clone + 'static> LLM for RateLimited<L> {
+    async fn generate(&self, messages: &[Message]) -> Result<GenerateResult, LLMError> {
+        self.acquire(|_| self.count_messages(messages)).await;
+        let result = self.inner.generate(messages).await?;
+        if let (Some(bucket), Some(usage)) = (&self.tokens, &result.tokens) {
+            bucket.consume(usage.completion_tokens as usize);
+        }
+        Ok(result)
+    }
+
+    async fn stream(
+        &self,
+        messages: &[Message],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError> {
+        self.acquire(|_| self.count_messages(messages)).await;
+        let mut stream = self.inner.stream(messages).await?;
+        let Some(bucket) = self.tokens.clone() else {
+            return Ok(stream);
+        };
+
+        Ok(Box::pin(async_stream::stream! {
+            let mut completion_tokens = None;
+            while let Some(chunk) = stream.next().await {
+                if let Ok(StreamData { tokens: Some(usage), .. }) = &chunk {
+                    completion_tokens = Some(usage.completion_tokens);
+                }
+                yield chunk;
+            }
+            if let Some(completion_tokens) = completion_tokens {
+                bucket.consume(completion_tokens as usize);
+            }
+        }))
+    }
+
+    fn add_options(&mut self, options: CallOptions) {
+        self.inner.add_options(options)
+    }
+
+    fn messages_to_string(&self, messages: &[Message]) -> String {
+        self.inner.messages_to_string(messages)
+    }
+}
+
+#[async_trait]
+impl<E: Embedder> Embedder for RateLimited<E> {
+    async fn embed_documents(&self, documents: &[String]) -> Result<Vec<Vec<f64>>, EmbedderError> {
+        self.acquire(|counter| documents.iter().map(|doc| counter.count(doc)).sum())
+            .await;
+        self.inner.embed_documents(documents).await
+    }
+
+    async fn embed_query(&self, text: &str) -> Result<Vec<f64>, EmbedderError> {
+        self.acquire(|counter| counter.count(text)).await;
+        self.inner.embed_query(text).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{language_models::TokenUsage, llm::fake::FakeLLM};
+
+    #[tokio::test(start_paused = true)]
+    async fn test_requests_wait_for_the_bucket() {
+        // 10 requests per second, one at a time
+        let bucket = TokenBucket::new(600, 1);
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            bucket.acquire(1).await;
+        }
+
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_burst_is_available_right_away() {
+        let bucket = TokenBucket::new(60, 5);
+        let start = Instant::now();
+
+        for _ in 0..5 {
+            bucket.acquire(1).await;
+        }
+
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        assert!(bucket.try_take(1.0).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_consumed_tokens_delay_the_next_calls() {
+        let bucket = TokenBucket::new(60, 10);
+        bucket.consume(15);
+
+        let wait = bucket.try_take(1.0).unwrap_err();
+
+        assert!(wait > Duration::from_secs(5));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stream_takes_the_completion_tokens_of_the_last_chunk() {
+        let llm = FakeLLM::fixed("").with_chunks([
+            StreamData::new(json!("Hel"), None, "Hel"),
+            StreamData::new(json!("lo"), Some(TokenUsage::new(0, 20)), "lo"),
+        ]);
+        let llm = RateLimited::new(llm).with_tokens_per_minute(60, 10);
+
+        let chunks = llm.stream(&[]).await.unwrap().collect::<Vec<_>>().await;
+        assert_eq!(chunks.len(), 2);
+
+        let wait = llm.tokens.as_ref().unwrap().try_take(1.0).unwrap_err();
+        assert_eq!(wait, Duration::from_secs(11));
+    }
+}
@@ -0,0 +1,94 @@
+use async_stream::stream;
+use futures::{pin_mut, Stream, StreamExt};
+
+/// Splits a byte stream of server-sent events into its events, each one as the text of its
+/// lines. The bytes are buffered until the blank line ending the event arrives, so an event
+/// split across network chunks, even inside a character, comes out whole. What's left when
+/// the stream ends is returned as the last event.
+pub fn sse_event_stream<S, B, E>(stream: S) -> impl Stream<Item = Result<String, E>> + Send
+where
+    S: Stream<Item = Result<B, E>> + Send,
+    B: AsRef<[u8]> + Send,
+    E: Send,
+{
+    stream! {
+        pin_mut!(stream);
+        let mut buffer: Vec<u8> = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            match chunk {
+                Ok(chunk) => buffer.extend_from_slice(chunk.as_ref()),
+                Err(e) => {
+                    yield Err(e);
+                    continue;
+                }
+            }
+            while let Some((end, separator_len)) = event_end(&buffer) {
+                let event: Vec<u8> = buffer.drain(..end + separator_len).collect();
+                yield Ok(String::from_utf8_lossy(&event[..end]).into_owned());
+            }
+        }
+        if !buffer.iter().all(u8::is_ascii_whitespace) {
+            yield Ok(String::from_utf8_lossy(&buffer).into_owned());
+        }
+    }
+}
+
+/// Position and length of the first blank line of the buffer.
+fn event_end(buffer: &[u8]) -> Option<(usize, usize)> {
+    [&b"\r\n\r\n"[..], b"\n\n", b"\r\r"]
+        .iter()
+        .filter_map(|separator| {
+            buffer
+                .windows(separator.len())
+                .position(|window| window == *separator)
+                .map(|position| (position, separator.len()))
+        })
+        .min_by_key(|(position, _)| *position)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+
+    use super::*;
+
+    async fn events(chunks: Vec<&'static [u8]>) -> Vec<String> {
+        sse_event_stream(stream::iter(chunks.into_iter().map(Ok::<_, ()>)))
+            .map(|event| event.unwrap())
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_event_split_across_chunks() {
+        let events = events(vec![
+            b"event: content_block_delta\ndata: {\"type\":\"content_",
+            b"block_delta\"}\n\nevent: message_stop\r\n",
+            b"data: {\"type\":\"message_stop\"}\r\n\r\n",
+        ])
+        .await;
+
+        assert_eq!(
+            events,
+            vec![
+                "event: content_block_delta\ndata: {\"type\":\"content_block_delta\"}",
+                "event: message_stop\r\ndata: {\"type\":\"message_stop\"}",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_character_split_across_chunks() {
+        let text = "data: é\n\n".as_bytes();
+        let events = events(vec![&text[..7], &text[7..]]).await;
+
+        assert_eq!(events, vec!["data: é"]);
+    }
+
+    #[tokio::test]
+    async fn test_unterminated_event_at_the_end() {
+        let events = events(vec![b"data: 1\n\n", b"{\"error\": {}}", b"\n"]).await;
+
+        assert_eq!(events, vec!["data: 1", "{\"error\": {}}\n"]);
+    }
+}
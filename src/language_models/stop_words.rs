@@ -0,0 +1,127 @@
+use std::pin::Pin;
+
+use async_stream::stream;
+use futures::{pin_mut, Stream, StreamExt};
+use serde_json::Value;
+
+use crate::schemas::StreamData;
+
+use super::LLMError;
+
+/// The position of the first stop word in the text, empty stop words are ignored.
+fn find_stop_word(text: &str, stop_words: &[String]) -> Option<usize> {
+    stop_words
+        .iter()
+        .filter(|stop| !stop.is_empty())
+        .filter_map(|stop| text.find(stop.as_str()))
+        .min()
+}
+
+/// The position of the longest end of the text that is the start of a stop word, the text
+/// that can't be sent yet in case the next chunk completes the stop word.
+fn partial_stop_word(text: &str, stop_words: &[String]) -> usize {
+    text.char_indices()
+        .map(|(i, _)| i)
+        .find(|&i| {
+            stop_words
+                .iter()
+                .any(|stop| stop.len() > text.len() - i && stop.starts_with(&text[i..]))
+        })
+        .unwrap_or(text.len())
+}
+
+/// Truncates the generation before the first stop word, for the providers and local
+/// models that ignore the stop words of the request. Returns whether it was truncated.
+pub fn apply_stop_words(generation: &mut String, stop_words: &[String]) -> bool {
+    match find_stop_word(generation, stop_words) {
+        Some(end) => {
+            generation.truncate(end);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Applies the stop words to a stream, like `apply_stop_words` on its joined content: the
+/// stream ends with the content before the first stop word, without reading the rest of
+/// the generation. The usage the provider sends after that point is lost.
+///
+/// The end of a chunk that may be the start of a stop word is held back until the next
+/// chunk tells whether it is one, the chunks are never cut inside a character.
+pub fn stop_words_stream<S>(
+    stream: S,
+    stop_words: Vec<String>,
+) -> Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>
+where
+    S: Stream<Item = Result<StreamData, LLMError>> + Send + 'static,
+{
+    if stop_words.iter().all(|stop| stop.is_empty()) {
+        return Box::pin(stream);
+    }
+
+    Box::pin(stream! {
+        pin_mut!(stream);
+        let mut pending = String::new();
+        while let Some(result) = stream.next().await {
+            let mut data = match result {
+                Ok(data) => data,
+                Err(e) => {
+                    yield Err(e);
+                    continue;
+                }
+            };
+            pending.push_str(&data.content);
+            if let Some(end) = find_stop_word(&pending, &stop_words) {
+                pending.truncate(end);
+                data.content = pending;
+                yield Ok(data);
+                return;
+            }
+            let held = partial_stop_word(&pending, &stop_words);
+            data.content = pending[..held].to_string();
+            pending.drain(..held);
+            yield Ok(data);
+        }
+        if !pending.is_empty() {
+            yield Ok(StreamData::new(Value::Null, None, pending));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+
+    use super::*;
+
+    #[test]
+    fn test_apply_stop_words() {
+        let stop_words = vec!["\nObservation:".to_string(), "".to_string()];
+        let mut generation = "Thought: ok\nObservation: 3".to_string();
+        assert!(apply_stop_words(&mut generation, &stop_words));
+        assert_eq!(generation, "Thought: ok");
+
+        let mut generation = "Observation".to_string();
+        assert!(!apply_stop_words(&mut generation, &stop_words));
+        assert_eq!(generation, "Observation");
+    }
+
+    #[tokio::test]
+    async fn test_stop_words_stream() {
+        let chunks = ["Año ", "nuev", "o: ¡h", "ola!ENDnever", " sent"]
+            .map(|content| Ok(StreamData::new(Value::Null, None, content)));
+        let stream = stop_words_stream(
+            stream::iter(chunks),
+            vec!["END".to_string(), "nuevo año".to_string()],
+        );
+        let contents: Vec<String> = stream.map(|data| data.unwrap().content).collect().await;
+        assert_eq!(contents, vec!["Año ", "", "nuevo: ¡h", "ola!"]);
+
+        // The held back text is sent when the stream ends
+        let chunks =
+            ["a", "bE", "N"].map(|content| Ok(StreamData::new(Value::Null, None, content)));
+        let stream = stop_words_stream(stream::iter(chunks), vec!["END".to_string()]);
+        let result = StreamData::collect(stream).await.unwrap();
+        assert_eq!(result.generation, "abEN");
+    }
+}
@@ -0,0 +1,91 @@
+use std::{fmt, sync::Arc};
+
+use tiktoken_rs::CoreBPE;
+
+use super::{TiktokenTokenizer, Tokenizer};
+use crate::schemas::Message;
+
+/// Counts the tokens of texts with the tokenizer of a model, the tiktoken encoding of an
+/// OpenAI model by default. With the tokenizer of another model the count is an
+/// approximation, use it to stay under context limits with a margin.
+#[derive(Clone)]
+pub struct TokenCounter {
+    tokenizer: Arc<dyn Tokenizer>,
+}
+
+impl TokenCounter {
+    pub fn new(bpe: CoreBPE) -> Self {
+        Self::from_tokenizer(TiktokenTokenizer::new(bpe))
+    }
+
+    pub fn from_tokenizer<T: Tokenizer + 'static>(tokenizer: T) -> Self {
+        Self {
+            tokenizer: Arc::new(tokenizer),
+        }
+    }
+
+    /// The encoding of the model, `cl100k_base` if the model is unknown.
+    pub fn for_model(model: &str) -> Self {
+        match TiktokenTokenizer::for_model(model) {
+            Some(tokenizer) => Self::from_tokenizer(tokenizer),
+            None => Self::default(),
+        }
+    }
+
+    pub fn count(&self, text: &str) -> usize {
+        self.tokenizer.count(text)
+    }
+
+    /// Tokens of the content and the tool calls of the message, without the few tokens
+    /// each provider adds around a message.
+    pub fn count_message(&self, message: &Message) -> usize {
+        let tool_calls = message
+            .tool_calls
+            .as_ref()
+            .map_or(0, |tool_calls| self.count(&tool_calls.to_string()));
+        self.count(&message.content) + tool_calls
+    }
+
+    /// The start of the text that fits in `max_tokens`.
+    pub fn truncate(&self, text: &str, max_tokens: usize) -> String {
+        let mut tokens = self.tokenizer.encode(text);
+        if tokens.len() <= max_tokens {
+            return text.to_string();
+        }
+        tokens.truncate(max_tokens);
+        // The cut may split a multi-byte character, drop tokens until it decodes
+        while !tokens.is_empty() {
+            if let Some(truncated) = self.tokenizer.decode(&tokens) {
+                return truncated;
+            }
+            tokens.pop();
+        }
+        String::new()
+    }
+}
+
+impl Default for TokenCounter {
+    fn default() -> Self {
+        Self::from_tokenizer(TiktokenTokenizer::cl100k())
+    }
+}
+
+impl fmt::Debug for TokenCounter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenCounter").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_counter() {
+        let counter = TokenCounter::for_model("gpt-4");
+        assert_eq!(counter.count("hello world"), 2);
+        assert_eq!(counter.truncate("hello world", 1), "hello");
+        assert_eq!(counter.truncate("hello world", 5), "hello world");
+        assert_eq!(counter.count(""), 0);
+    }
+}
@@ -0,0 +1,95 @@
+use tiktoken_rs::{cl100k_base, get_bpe_from_model, o200k_base, CoreBPE};
+
+/// Turns text into the tokens of a model. Used by the `TokenCounter` and the
+/// `TokenSplitter`, pick the tokenizer of the model the text is sent to so the counts
+/// match what the model sees.
+pub trait Tokenizer: Send + Sync {
+    fn encode(&self, text: &str) -> Vec<usize>;
+
+    /// The text of the tokens, `None` if they don't form valid UTF-8, as when a
+    /// multi-byte character is split.
+    fn decode(&self, tokens: &[usize]) -> Option<String>;
+
+    fn count(&self, text: &str) -> usize {
+        self.encode(text).len()
+    }
+}
+
+/// A tiktoken encoding, used by the OpenAI models.
+pub struct TiktokenTokenizer {
+    bpe: CoreBPE,
+}
+
+impl TiktokenTokenizer {
+    pub fn new(bpe: CoreBPE) -> Self {
+        Self { bpe }
+    }
+
+    /// The encoding of GPT-3.5 and GPT-4.
+    pub fn cl100k() -> Self {
+        Self::new(cl100k_base().expect("The cl100k_base encoding is embedded in tiktoken-rs"))
+    }
+
+    /// The encoding of GPT-4o.
+    pub fn o200k() -> Self {
+        Self::new(o200k_base().expect("The o200k_base encoding is embedded in tiktoken-rs"))
+    }
+
+    /// The encoding of the model, `None` if the model is unknown.
+    pub fn for_model(model: &str) -> Option<Self> {
+        get_bpe_from_model(model).ok().map(Self::new)
+    }
+}
+
+impl Tokenizer for TiktokenTokenizer {
+    fn encode(&self, text: &str) -> Vec<usize> {
+        self.bpe.encode_ordinary(text)
+    }
+
+    fn decode(&self, tokens: &[usize]) -> Option<String> {
+        self.bpe.decode(tokens.to_vec()).ok()
+    }
+}
+
+/// A tokenizer of the Hugging Face `tokenizers` library, for open models.
+/// # Example
+/// ```rust,ignore
+/// let tokenizer = HuggingFaceTokenizer::from_file("llama/tokenizer.json")?;
+/// let splitter = TokenSplitter::default().with_tokenizer(tokenizer);
+/// ```
+#[cfg(feature = "tokenizers")]
+pub struct HuggingFaceTokenizer {
+    tokenizer: tokenizers::Tokenizer,
+}
+
+#[cfg(feature = "tokenizers")]
+impl HuggingFaceTokenizer {
+    pub fn new(tokenizer: tokenizers::Tokenizer) -> Self {
+        Self { tokenizer }
+    }
+
+    /// Loads the `tokenizer.json` of a model.
+    pub fn from_file<P: AsRef<std::path::Path>>(
+        path: P,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self::new(tokenizers::Tokenizer::from_file(path)?))
+    }
+}
+
+#[cfg(feature = "tokenizers")]
+impl Tokenizer for HuggingFaceTokenizer {
+    fn encode(&self, text: &str) -> Vec<usize> {
+        match self.tokenizer.encode(text, false) {
+            Ok(encoding) => encoding.get_ids().iter().map(|&id| id as usize).collect(),
+            Err(e) => {
+                log::warn!("Failed to tokenize the text: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    fn decode(&self, tokens: &[usize]) -> Option<String> {
+        let ids: Vec<u32> = tokens.iter().map(|&id| id as u32).collect();
+        self.tokenizer.decode(&ids, false).ok()
+    }
+}
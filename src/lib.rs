@@ -1,5 +1,11 @@
 #![allow(dead_code)]
+// Diagnostics go through `log`, the library never writes to the user's stdout or stderr
+#![cfg_attr(
+    not(test),
+    warn(clippy::print_stdout, clippy::print_stderr, clippy::dbg_macro)
+)]
 pub mod agent;
+pub mod callbacks;
 pub mod chain;
 pub mod document_loaders;
 pub mod embedding;
@@ -8,6 +14,7 @@ pub mod llm;
 pub mod memory;
 pub mod output_parsers;
 pub mod prompt;
+pub mod retrievers;
 pub mod schemas;
 pub mod semantic_router;
 pub mod text_splitter;
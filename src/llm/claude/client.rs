@@ -1,13 +1,16 @@
 use crate::{
-    language_models::{llm::LLM, options::CallOptions, GenerateResult, LLMError, TokenUsage},
-    llm::AnthropicError,
+    language_models::{
+        apply_stop_words, llm::LLM, options::CallOptions, sse_event_stream, stop_words_stream,
+        GenerateResult, GenerationMetadata, LLMError, TokenUsage,
+    },
+    llm::{redact_headers, AnthropicError},
     schemas::{Message, MessageType, StreamData},
 };
 use async_trait::async_trait;
 use futures::{Stream, StreamExt};
 use reqwest::Client;
 use serde_json::Value;
-use std::{collections::HashMap, pin::Pin};
+use std::pin::Pin;
 
 use super::models::{ApiResponse, ClaudeMessage, Payload};
 
@@ -29,6 +32,21 @@ impl ToString for ClaudeModel {
     }
 }
 
+/// The API key of the first variable set among `ANTHROPIC_API_KEY`, `CLAUDE_API_KEY` and the
+/// deprecated `CLOUDE_API_KEY`, or an empty key.
+fn resolve_api_key<F: Fn(&str) -> Option<String>>(var: F) -> String {
+    if let Some(api_key) = var("ANTHROPIC_API_KEY").or_else(|| var("CLAUDE_API_KEY")) {
+        return api_key;
+    }
+    match var("CLOUDE_API_KEY") {
+        Some(api_key) => {
+            log::warn!("CLOUDE_API_KEY is deprecated, set ANTHROPIC_API_KEY instead");
+            api_key
+        }
+        None => String::new(),
+    }
+}
+
 #[derive(Clone)]
 pub struct Claude {
     model: String,
@@ -44,11 +62,13 @@ impl Default for Claude {
 }
 
 impl Claude {
+    /// A client for Claude 3 Opus. The API key is read from the environment, see
+    /// `resolve_api_key`.
     pub fn new() -> Self {
         Self {
             model: ClaudeModel::Claude3pus20240229.to_string(),
             options: CallOptions::default(),
-            api_key: std::env::var("CLAUDE_API_KEY").unwrap_or_default(),
+            api_key: resolve_api_key(|name| std::env::var(name).ok()),
             anthropic_version: "2023-06-01".to_string(),
         }
     }
@@ -77,39 +97,29 @@ impl Claude {
         let client = Client::new();
         let is_stream = self.options.streaming_func.is_some();
 
-        let payload = self.build_payload(messages, is_stream);
-        let res = client
+        let payload = self.build_payload(messages, is_stream)?;
+        let request = client
             .post("https://api.anthropic.com/v1/messages")
             .header("x-api-key", &self.api_key)
             .header("anthropic-version", self.anthropic_version.clone())
             .header("content-type", "application/json; charset=utf-8")
             .json(&payload)
-            .send()
-            .await?;
-        let res = match res.status().as_u16() {
-            401 => Err(LLMError::AnthropicError(
-                AnthropicError::AuthenticationError("Invalid API Key".to_string()),
-            )),
-            403 => Err(LLMError::AnthropicError(AnthropicError::PermissionError(
-                "Permission Denied".to_string(),
-            ))),
-            404 => Err(LLMError::AnthropicError(AnthropicError::NotFoundError(
-                "Not Found".to_string(),
-            ))),
-            429 => Err(LLMError::AnthropicError(AnthropicError::RateLimitError(
-                "Rate Limit Exceeded".to_string(),
-            ))),
-            503 => Err(LLMError::AnthropicError(AnthropicError::OverloadedError(
-                "Service Unavailable".to_string(),
-            ))),
-            _ => Ok(res.json::<ApiResponse>().await?),
-        }?;
-
-        let generation = res
+            .build()?;
+        log::debug!("Request headers: {:?}", redact_headers(request.headers()));
+        let res = client.execute(request).await?;
+        if !res.status().is_success() {
+            return Err(response_error(res).await);
+        }
+        let res = res.json::<ApiResponse>().await?;
+
+        let mut generation = res
             .content
             .first()
             .map(|c| c.text.clone())
             .unwrap_or_default();
+        if let Some(stop_words) = &self.options.stop_words {
+            apply_stop_words(&mut generation, stop_words);
+        }
 
         let tokens = Some(TokenUsage {
             prompt_tokens: res.usage.input_tokens,
@@ -117,10 +127,26 @@ impl Claude {
             total_tokens: res.usage.input_tokens + res.usage.output_tokens,
         });
 
-        Ok(GenerateResult { tokens, generation })
+        Ok(GenerateResult {
+            tokens,
+            generation,
+            metadata: Some(GenerationMetadata {
+                finish_reason: res.stop_reason,
+                model: Some(res.model),
+                id: Some(res.id),
+            }),
+        })
     }
 
-    fn build_payload(&self, messages: &[Message], stream: bool) -> Payload {
+    fn build_payload(&self, messages: &[Message], stream: bool) -> Result<Payload, LLMError> {
+        if messages
+            .iter()
+            .any(|m| m.images.as_ref().is_some_and(|i| !i.is_empty()))
+        {
+            return Err(LLMError::ImagesNotSupported(
+                "the Claude client only sends text content".to_string(),
+            ));
+        }
         let (system_message, other_messages): (Vec<_>, Vec<_>) = messages
             .into_iter()
             .partition(|m| m.message_type == MessageType::SystemMessage);
@@ -141,20 +167,62 @@ impl Claude {
         if stream {
             payload.stream = Some(true);
         }
-        payload
+        Ok(payload)
+    }
+}
+
+/// The error of a failed response, see `status_error`.
+async fn response_error(res: reqwest::Response) -> LLMError {
+    let status = res.status().as_u16();
+    match res.text().await {
+        Ok(raw_body) => status_error(status, raw_body),
+        Err(e) => e.into(),
     }
 }
 
+/// The typed `AnthropicError` of the statuses that have one, with the message of the body,
+/// and an `ApiError` with the status and the body for the others.
+fn status_error(status: u16, raw_body: String) -> LLMError {
+    let error = LLMError::api_error("anthropic", status, raw_body);
+    let message = match &error {
+        LLMError::ApiError { message, .. } => message.clone(),
+        _ => return error,
+    };
+    let error = match status {
+        401 => AnthropicError::AuthenticationError(message),
+        403 => AnthropicError::PermissionError(message),
+        404 => AnthropicError::NotFoundError(message),
+        429 => AnthropicError::RateLimitError(message),
+        503 | 529 => AnthropicError::OverloadedError(message),
+        _ => return error,
+    };
+    LLMError::AnthropicError(error)
+}
+
 #[async_trait]
 impl LLM for Claude {
     async fn generate(&self, messages: &[Message]) -> Result<GenerateResult, LLMError> {
         match &self.options.streaming_func {
             Some(func) => {
                 let mut complete_response = String::new();
+                let mut metadata = GenerationMetadata::default();
                 let mut stream = self.stream(messages).await?;
                 while let Some(data) = stream.next().await {
                     match data {
                         Ok(value) => {
+                            match value.value["type"].as_str() {
+                                Some("message_start") => {
+                                    let message = &value.value["message"];
+                                    metadata.id = message["id"].as_str().map(String::from);
+                                    metadata.model = message["model"].as_str().map(String::from);
+                                }
+                                Some("message_delta") => {
+                                    metadata.finish_reason = value.value["delta"]["stop_reason"]
+                                        .as_str()
+                                        .map(String::from);
+                                }
+                                _ => {}
+                            }
                             let mut func = func.lock().await;
                             complete_response.push_str(&value.content);
                             let _ = func(value.content).await;
@@ -162,9 +230,11 @@ impl LLM for Claude {
                         Err(e) => return Err(e),
                     }
                 }
-                let mut generate_result = GenerateResult::default();
-                generate_result.generation = complete_response;
-                Ok(generate_result)
+                Ok(GenerateResult {
+                    generation: complete_response,
+                    metadata: Some(metadata),
+                    ..Default::default()
+                })
             }
             None => self.generate(messages).await,
         }
@@ -174,7 +244,7 @@ impl LLM for Claude {
         messages: &[Message],
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError> {
         let client = Client::new();
-        let payload = self.build_payload(messages, true);
+        let payload = self.build_payload(messages, true)?;
         let request = client
             .post("https://api.anthropic.com/v1/messages")
             .header("x-api-key", &self.api_key)
@@ -182,31 +252,25 @@ impl LLM for Claude {
             .header("content-type", "application/json; charset=utf-8")
             .json(&payload)
             .build()?;
+        log::debug!("Request headers: {:?}", redact_headers(request.headers()));
 
         // Instead of sending the request directly, return a stream wrapper
-        let stream = client.execute(request).await?;
-        let stream = stream.bytes_stream();
-        // Process each chunk as it arrives
-        let processed_stream = stream.then(move |result| {
-            async move {
-                match result {
-                    Ok(bytes) => {
-                        let value: Value = parse_sse_to_json(&String::from_utf8_lossy(&bytes))?;
-                        if value["type"].as_str().unwrap_or("") == "content_block_delta" {
-                            let content = value["delta"]["text"].clone();
-                            // Return StreamData based on the parsed content
-                            // TODO get tokens from the response
-                            Ok(StreamData::new(value, None, content.as_str().unwrap_or("")))
-                        } else {
-                            Ok(StreamData::new(value, None, ""))
-                        }
-                    }
-                    Err(e) => Err(LLMError::RequestError(e)),
-                }
-            }
+        let res = client.execute(request).await?;
+        if !res.status().is_success() {
+            return Err(response_error(res).await);
+        }
+        let stream = sse_event_stream(res.bytes_stream());
+        // Process each event as it arrives
+        let processed_stream = stream.flat_map(|result| {
+            let events = match result {
+                Ok(event) => parse_sse_events(&event),
+                Err(e) => vec![Err(LLMError::RequestError(e))],
+            };
+            futures::stream::iter(events.into_iter().map(|event| event.map(stream_data)))
         });
 
-        Ok(Box::pin(processed_stream))
+        let stop_words = self.options.stop_words.clone().unwrap_or_default();
+        Ok(stop_words_stream(processed_stream, stop_words))
     }
 
     fn add_options(&mut self, options: CallOptions) {
@@ -214,29 +278,47 @@ impl LLM for Claude {
     }
 }
 
-fn parse_sse_to_json(sse_data: &str) -> Result<Value, LLMError> {
+/// Parses the events of the stream. Comments, events without data and data that isn't JSON
+/// are skipped, the `error` events are returned as errors.
+fn parse_sse_events(sse_data: &str) -> Vec<Result<Value, LLMError>> {
     if let Ok(json) = serde_json::from_str::<Value>(sse_data) {
-        return parse_error(&json);
+        return vec![parse_error(&json)];
     }
 
-    let lines: Vec<&str> = sse_data.trim().split('\n').collect();
-    let mut event_data: HashMap<&str, String> = HashMap::new();
-
-    for line in lines {
-        if let Some((key, value)) = line.split_once(": ") {
-            event_data.insert(key, value.to_string());
-        }
-    }
+    sse_data
+        .replace("\r\n", "\n")
+        .split("\n\n")
+        .filter_map(|event| {
+            let data: Vec<&str> = event
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                .collect();
+            if data.is_empty() {
+                return None;
+            }
+            match serde_json::from_str::<Value>(&data.join("\n")) {
+                Ok(data) => Some(match data["type"].as_str() {
+                    Some("error") => parse_error(&data),
+                    _ => Ok(data),
+                }),
+                Err(e) => {
+                    log::debug!("Skipping malformed SSE event: {}", e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
 
-    if let Some(data) = event_data.get("data") {
-        let data: Value = serde_json::from_str(data)?;
-        return match data["type"].as_str() {
-            Some("error") => parse_error(&data),
-            _ => Ok(data),
-        };
+fn stream_data(value: Value) -> StreamData {
+    if value["type"].as_str().unwrap_or("") == "content_block_delta" {
+        let content = value["delta"]["text"].clone();
+        // TODO get tokens from the response
+        StreamData::new(value, None, content.as_str().unwrap_or(""))
+    } else {
+        StreamData::new(value, None, "")
     }
-    log::error!("No data field in the SSE event");
-    Err(LLMError::ContentNotFound("data".to_string()))
 }
 
 fn parse_error(json: &Value) -> Result<Value, LLMError> {
@@ -259,6 +341,100 @@ mod tests {
     use super::*;
     use tokio::test;
 
+    #[test]
+    async fn test_resolve_api_key_order() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+
+        assert_eq!(
+            resolve_api_key(env(&[
+                ("CLOUDE_API_KEY", "cloude"),
+                ("CLAUDE_API_KEY", "claude"),
+                ("ANTHROPIC_API_KEY", "anthropic"),
+            ])),
+            "anthropic"
+        );
+        assert_eq!(
+            resolve_api_key(env(&[
+                ("CLOUDE_API_KEY", "cloude"),
+                ("CLAUDE_API_KEY", "claude")
+            ])),
+            "claude"
+        );
+        assert_eq!(
+            resolve_api_key(env(&[("CLOUDE_API_KEY", "cloude")])),
+            "cloude"
+        );
+        assert_eq!(resolve_api_key(env(&[])), "");
+    }
+
+    #[test]
+    async fn test_status_error_keeps_the_typed_errors() {
+        let body = r#"{"type":"error","error":{"type":"rate_limit_error","message":"Slow down"}}"#;
+        let error = status_error(429, body.to_string());
+        assert!(matches!(
+            &error,
+            LLMError::AnthropicError(AnthropicError::RateLimitError(message)) if message == "Slow down"
+        ));
+        assert!(error.is_rate_limit());
+        assert!(matches!(
+            status_error(401, "Unauthorized".to_string()),
+            LLMError::AnthropicError(AnthropicError::AuthenticationError(_))
+        ));
+
+        let body = r#"{"type":"error","error":{"type":"invalid_request_error","message":"Bad"}}"#;
+        match status_error(400, body.to_string()) {
+            LLMError::ApiError { status, code, .. } => {
+                assert_eq!(status, 400);
+                assert_eq!(code.as_deref(), Some("invalid_request_error"));
+            }
+            error => panic!("unexpected error: {:?}", error),
+        }
+    }
+
+    #[test]
+    async fn test_parse_sse_events_skips_malformed_events() {
+        let chunk = concat!(
+            ": keep-alive\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"delta\":{\"text\":\"Hi\"}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_de\n\n",
+            "event: message_stop\n",
+            "data: {\"type\":\"message_stop\"}\n\n",
+        );
+
+        let events: Vec<Value> = parse_sse_events(chunk)
+            .into_iter()
+            .map(|event| event.unwrap())
+            .collect();
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(stream_data(events[0].clone()).content, "Hi");
+        assert_eq!(events[1]["type"], "message_stop");
+    }
+
+    #[test]
+    async fn test_parse_sse_events_surfaces_error_events() {
+        let chunk = concat!(
+            "event: error\n",
+            "data: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}\n\n",
+        );
+
+        let events = parse_sse_events(chunk);
+
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            events[0],
+            Err(LLMError::AnthropicError(AnthropicError::OverloadedError(_)))
+        ));
+    }
+
     #[test]
     #[ignore]
     async fn test_cloudia_generate() {
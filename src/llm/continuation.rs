@@ -0,0 +1,156 @@
+use std::pin::Pin;
+
+use async_trait::async_trait;
+use futures::Stream;
+
+use crate::{
+    language_models::{llm::LLM, options::CallOptions, GenerateResult, LLMError},
+    schemas::{Message, StreamData},
+};
+
+const DEFAULT_CONTINUE_PROMPT: &str =
+    "Continue exactly where you stopped, without repeating anything you already wrote.";
+
+/// Continues the generations cut off by the maximum number of tokens: while the result is
+/// truncated, see `GenerateResult::is_truncated`, the LLM is called again with the partial
+/// output and a prompt to continue, up to `max_continuations` times. The generations are
+/// concatenated and their token usage summed, the metadata is the one of the last call.
+///
+/// Only `generate` continues, `stream` is passed to the LLM as it is.
+///
+/// # Example
+/// ```rust,ignore
+/// let llm = ContinuingLLM::new(OpenAI::default().with_options(
+///     CallOptions::default().with_max_tokens(500),
+/// ))
+/// .with_max_continuations(4);
+/// ```
+#[derive(Clone)]
+pub struct ContinuingLLM {
+    llm: Box<dyn LLM>,
+    max_continuations: usize,
+    continue_prompt: String,
+}
+
+impl ContinuingLLM {
+    pub fn new<L: Into<Box<dyn LLM>>>(llm: L) -> Self {
+        Self {
+            llm: llm.into(),
+            max_continuations: 3,
+            continue_prompt: DEFAULT_CONTINUE_PROMPT.into(),
+        }
+    }
+
+    /// Maximum number of calls after the first one, 3 by default.
+    pub fn with_max_continuations(mut self, max_continuations: usize) -> Self {
+        self.max_continuations = max_continuations;
+        self
+    }
+
+    /// Message asking the LLM to continue, sent after its partial output.
+    pub fn with_continue_prompt<S: Into<String>>(mut self, continue_prompt: S) -> Self {
+        self.continue_prompt = continue_prompt.into();
+        self
+    }
+}
+
+#[async_trait]
+impl LLM for ContinuingLLM {
+    async fn generate(&self, messages: &[Message]) -> Result<GenerateResult, LLMError> {
+        let mut result = self.llm.generate(messages).await?;
+        let mut continuations = 0;
+        while result.is_truncated() && continuations < self.max_continuations {
+            continuations += 1;
+            log::debug!("Generation truncated, continuing it ({})", continuations);
+
+            let mut continued = messages.to_vec();
+            continued.push(Message::new_ai_message(&result.generation));
+            continued.push(Message::new_human_message(&self.continue_prompt));
+            let next = self.llm.generate(&continued).await?;
+
+            result.generation.push_str(&next.generation);
+            result.tokens = match (result.tokens, next.tokens) {
+                (Some(tokens), Some(next)) => Some(tokens.sum(&next)),
+                (tokens, next) => tokens.or(next),
+            };
+            result.metadata = next.metadata;
+        }
+        Ok(result)
+    }
+
+    async fn stream(
+        &self,
+        messages: &[Message],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError> {
+        self.llm.stream(messages).await
+    }
+
+    fn add_options(&mut self, options: CallOptions) {
+        self.llm.add_options(options)
+    }
+
+    fn messages_to_string(&self, messages: &[Message]) -> String {
+        self.llm.messages_to_string(messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use crate::{
+        language_models::{GenerationMetadata, TokenUsage},
+        llm::fake::FakeLLM,
+    };
+
+    use super::*;
+
+    /// Answers with the parts in turn, truncated while there are more.
+    fn parts_llm(parts: Vec<&'static str>) -> FakeLLM {
+        let parts = Mutex::new(parts);
+        FakeLLM::new(move |_| {
+            let mut parts = parts.lock().unwrap();
+            let generation = parts.remove(0);
+            let finish_reason = if parts.is_empty() { "stop" } else { "length" };
+            Ok(GenerateResult {
+                generation: generation.into(),
+                tokens: Some(TokenUsage::new(10, 5)),
+                metadata: Some(GenerationMetadata {
+                    finish_reason: Some(finish_reason.into()),
+                    ..Default::default()
+                }),
+            })
+        })
+    }
+
+    #[tokio::test]
+    async fn test_continues_truncated_generations() {
+        let llm = parts_llm(vec!["Hel", "lo wor", "ld"]);
+
+        let result = ContinuingLLM::new(llm.clone())
+            .generate(&[Message::new_human_message("Say hello world")])
+            .await
+            .unwrap();
+
+        assert_eq!(result.generation, "Hello world");
+        assert_eq!(result.tokens.as_ref().unwrap().total_tokens, 45);
+        assert!(!result.is_truncated());
+        let prompts = llm.prompts();
+        assert_eq!(prompts.len(), 3);
+        assert_eq!(prompts[2][1].content, "Hello wor");
+    }
+
+    #[tokio::test]
+    async fn test_stops_at_max_continuations() {
+        let llm = parts_llm(vec!["a", "b", "c", "d"]);
+
+        let result = ContinuingLLM::new(llm)
+            .with_max_continuations(1)
+            .generate(&[Message::new_human_message("Write")])
+            .await
+            .unwrap();
+
+        assert_eq!(result.generation, "ab");
+        assert!(result.is_truncated());
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use futures::{stream, Stream};
+use serde_json::json;
+
+use crate::{
+    language_models::{llm::LLM, GenerateResult, LLMError},
+    schemas::{Message, StreamData},
+};
+
+type Respond = dyn Fn(&[Message]) -> Result<GenerateResult, LLMError> + Send + Sync;
+
+/// An LLM for the tests. It answers with `respond`, and keeps the prompts it gets. `stream`
+/// streams the chunks set with `with_chunks`, or else the answer as a single chunk.
+#[derive(Clone)]
+pub(crate) struct FakeLLM {
+    respond: Arc<Respond>,
+    chunks: Option<Vec<StreamData>>,
+    prompts: Arc<Mutex<Vec<Vec<Message>>>>,
+}
+
+impl FakeLLM {
+    pub(crate) fn new<F>(respond: F) -> Self
+    where
+        F: Fn(&[Message]) -> Result<GenerateResult, LLMError> + Send + Sync + 'static,
+    {
+        Self {
+            respond: Arc::new(respond),
+            chunks: None,
+            prompts: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Answers with the generation built from the messages.
+    pub(crate) fn answering<F>(answer: F) -> Self
+    where
+        F: Fn(&[Message]) -> String + Send + Sync + 'static,
+    {
+        Self::new(move |messages| {
+            Ok(GenerateResult {
+                generation: answer(messages),
+                ..Default::default()
+            })
+        })
+    }
+
+    /// Always answers `answer`.
+    pub(crate) fn fixed<S: Into<String>>(answer: S) -> Self {
+        let answer = answer.into();
+        Self::answering(move |_| answer.clone())
+    }
+
+    /// Answers with the answers in turn, and fails once they are all used.
+    pub(crate) fn scripted<I, S>(answers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let answers: Mutex<VecDeque<String>> =
+            Mutex::new(answers.into_iter().map(Into::into).collect());
+        Self::new(move |_| match answers.lock().unwrap().pop_front() {
+            Some(generation) => Ok(GenerateResult {
+                generation,
+                ..Default::default()
+            }),
+            None => Err(LLMError::OtherError("No answer left".to_string())),
+        })
+    }
+
+    /// Streams the `chunks` instead of the answer.
+    pub(crate) fn with_chunks<I: IntoIterator<Item = StreamData>>(mut self, chunks: I) -> Self {
+        self.chunks = Some(chunks.into_iter().collect());
+        self
+    }
+
+    /// The messages of each call, in order.
+    pub(crate) fn prompts(&self) -> Vec<Vec<Message>> {
+        self.prompts.lock().unwrap().clone()
+    }
+
+    pub(crate) fn calls(&self) -> usize {
+        self.prompts.lock().unwrap().len()
+    }
+}
+
+#[async_trait]
+impl LLM for FakeLLM {
+    async fn generate(&self, messages: &[Message]) -> Result<GenerateResult, LLMError> {
+        self.prompts.lock().unwrap().push(messages.to_vec());
+        (self.respond)(messages)
+    }
+
+    async fn stream(
+        &self,
+        messages: &[Message],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError> {
+        if let Some(chunks) = &self.chunks {
+            self.prompts.lock().unwrap().push(messages.to_vec());
+            return Ok(Box::pin(stream::iter(chunks.clone().into_iter().map(Ok))));
+        }
+        let result = self.generate(messages).await?;
+        let chunk = StreamData::new(json!(result.generation), result.tokens, &result.generation);
+        Ok(Box::pin(stream::once(async { Ok(chunk) })))
+    }
+}
@@ -0,0 +1,158 @@
+use std::pin::Pin;
+
+use async_trait::async_trait;
+use futures::Stream;
+
+use crate::{
+    language_models::{llm::LLM, options::CallOptions, GenerateResult, LLMError},
+    schemas::{Message, StreamData},
+};
+
+/// Calls the LLMs in order until one succeeds. The next LLM is only tried when the error is
+/// retryable, see `LLMError::is_retryable`: a bad request fails right away, as it would fail
+/// with the other LLMs too. The error of the last LLM tried is returned.
+///
+/// Streams fall back when they can't be started, not once they are running.
+///
+/// # Example
+/// ```rust,ignore
+/// let llm = FallbackLLM::new(OpenAI::default())
+///     .with_fallback(Claude::default());
+/// let answer = llm.invoke("Hi").await?;
+/// ```
+#[derive(Clone)]
+pub struct FallbackLLM {
+    llms: Vec<Box<dyn LLM>>,
+}
+
+impl FallbackLLM {
+    pub fn new<L: Into<Box<dyn LLM>>>(primary: L) -> Self {
+        Self {
+            llms: vec![primary.into()],
+        }
+    }
+
+    /// Adds an LLM tried after the ones already added.
+    pub fn with_fallback<L: Into<Box<dyn LLM>>>(mut self, llm: L) -> Self {
+        self.llms.push(llm.into());
+        self
+    }
+}
+
+#[async_trait]
+impl LLM for FallbackLLM {
+    async fn generate(&self, messages: &[Message]) -> Result<GenerateResult, LLMError> {
+        let (last, others) = self
+            .llms
+            .split_last()
+            .expect("FallbackLLM has a primary LLM");
+        for llm in others {
+            match llm.generate(messages).await {
+                Err(e) if e.is_retryable() => {
+                    log::warn!("LLM failed, falling back to the next one: {}", e);
+                }
+                result => return result,
+            }
+        }
+        last.generate(messages).await
+    }
+
+    async fn stream(
+        &self,
+        messages: &[Message],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError> {
+        let (last, others) = self
+            .llms
+            .split_last()
+            .expect("FallbackLLM has a primary LLM");
+        for llm in others {
+            match llm.stream(messages).await {
+                Err(e) if e.is_retryable() => {
+                    log::warn!("LLM stream failed, falling back to the next one: {}", e);
+                }
+                result => return result,
+            }
+        }
+        last.stream(messages).await
+    }
+
+    fn add_options(&mut self, options: CallOptions) {
+        for llm in self.llms.iter_mut() {
+            llm.add_options(options.clone());
+        }
+    }
+
+    fn messages_to_string(&self, messages: &[Message]) -> String {
+        self.llms[0].messages_to_string(messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::llm::fake::FakeLLM;
+
+    /// Fails with the `status`, or answers "ok" without one.
+    fn status_llm(status: Option<u16>) -> FakeLLM {
+        FakeLLM::new(move |_| match status {
+            Some(status) => Err(LLMError::api_error("test", status, String::new())),
+            None => Ok(GenerateResult {
+                generation: "ok".into(),
+                ..Default::default()
+            }),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_on_retryable_errors() {
+        let primary = status_llm(Some(503));
+        let secondary = status_llm(None);
+        let llm = FallbackLLM::new(primary.clone()).with_fallback(secondary.clone());
+
+        assert_eq!(llm.invoke("hi").await.unwrap(), "ok");
+        assert_eq!(primary.calls(), 1);
+        assert_eq!(secondary.calls(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_bad_requests_do_not_fall_back() {
+        let secondary = status_llm(None);
+        let llm = FallbackLLM::new(status_llm(Some(400))).with_fallback(secondary.clone());
+
+        let error = llm.invoke("hi").await.unwrap_err();
+
+        assert_eq!(error.status(), Some(400));
+        assert_eq!(secondary.calls(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_stream_falls_back_on_retryable_errors() {
+        let primary = status_llm(Some(429));
+        let secondary = status_llm(None);
+        let llm = FallbackLLM::new(primary.clone()).with_fallback(secondary.clone());
+
+        let chunks: Vec<String> = llm
+            .stream(&[Message::new_human_message("hi")])
+            .await
+            .unwrap()
+            .map(|chunk| chunk.unwrap().content)
+            .collect()
+            .await;
+
+        assert_eq!(chunks, vec!["ok"]);
+        assert_eq!(primary.calls(), 1);
+        assert_eq!(secondary.calls(), 1);
+
+        let secondary = status_llm(None);
+        let llm = FallbackLLM::new(status_llm(Some(400))).with_fallback(secondary.clone());
+        let error = llm
+            .stream(&[Message::new_human_message("hi")])
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(error.status(), Some(400));
+        assert_eq!(secondary.calls(), 0);
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use async_trait::async_trait;
+use futures::Stream;
+
+use crate::{
+    language_models::{llm::LLM, options::CallOptions, GenerateResult, LLMError},
+    schemas::{Message, MessageType, StreamData},
+};
+
+/// How a `LoadBalancedLLM` picks the LLM of each call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BalanceStrategy {
+    /// Each LLM in turn.
+    #[default]
+    RoundRobin,
+    /// The LLM whose last call started the longest time ago.
+    LeastRecentlyUsed,
+    /// The same LLM for every call of a conversation, chosen by the first human message,
+    /// so the providers can reuse their prompt cache. Conversations are spread by hash.
+    Sticky,
+}
+
+/// Spreads the calls across several LLMs, like the same model with different API keys or
+/// deployments, to share their rate limits. Clones share the rotation.
+///
+/// # Example
+/// ```rust,ignore
+/// let llm = LoadBalancedLLM::new(OpenAI::new(OpenAIConfig::new().with_api_key(first_key)))
+///     .with_llm(OpenAI::new(OpenAIConfig::new().with_api_key(second_key)))
+///     .with_strategy(BalanceStrategy::LeastRecentlyUsed);
+/// ```
+#[derive(Clone)]
+pub struct LoadBalancedLLM {
+    llms: Vec<Box<dyn LLM>>,
+    strategy: BalanceStrategy,
+    next: Arc<AtomicUsize>,
+    last_used: Arc<Mutex<Vec<Option<Instant>>>>,
+}
+
+impl LoadBalancedLLM {
+    pub fn new<L: Into<Box<dyn LLM>>>(llm: L) -> Self {
+        Self {
+            llms: vec![llm.into()],
+            strategy: BalanceStrategy::default(),
+            next: Arc::new(AtomicUsize::new(0)),
+            last_used: Arc::new(Mutex::new(vec![None])),
+        }
+    }
+
+    pub fn with_llm<L: Into<Box<dyn LLM>>>(mut self, llm: L) -> Self {
+        self.llms.push(llm.into());
+        if let Ok(mut last_used) = self.last_used.lock() {
+            last_used.push(None);
+        }
+        self
+    }
+
+    pub fn with_strategy(mut self, strategy: BalanceStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    fn pick(&self, messages: &[Message]) -> &dyn LLM {
+        let index = match self.strategy {
+            BalanceStrategy::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed),
+            BalanceStrategy::LeastRecentlyUsed => match self.last_used.lock() {
+                Ok(mut last_used) => {
+                    let index = (0..last_used.len())
+                        .min_by_key(|index| last_used[*index])
+                        .unwrap_or_default();
+                    last_used[index] = Some(Instant::now());
+                    index
+                }
+                Err(_) => self.next.fetch_add(1, Ordering::Relaxed),
+            },
+            BalanceStrategy::Sticky => {
+                let first = messages
+                    .iter()
+                    .find(|message| message.message_type == MessageType::HumanMessage)
+                    .or_else(|| messages.first());
+                let mut hasher = DefaultHasher::new();
+                if let Some(message) = first {
+                    message.content.hash(&mut hasher);
+                }
+                hasher.finish() as usize
+            }
+        };
+        self.llms[index % self.llms.len()].as_ref()
+    }
+}
+
+#[async_trait]
+impl LLM for LoadBalancedLLM {
+    async fn generate(&self, messages: &[Message]) -> Result<GenerateResult, LLMError> {
+        self.pick(messages).generate(messages).await
+    }
+
+    async fn stream(
+        &self,
+        messages: &[Message],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError> {
+        self.pick(messages).stream(messages).await
+    }
+
+    fn add_options(&mut self, options: CallOptions) {
+        for llm in self.llms.iter_mut() {
+            llm.add_options(options.clone());
+        }
+    }
+
+    fn messages_to_string(&self, messages: &[Message]) -> String {
+        self.llms[0].messages_to_string(messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::fake::FakeLLM;
+
+    fn llm(strategy: BalanceStrategy) -> LoadBalancedLLM {
+        LoadBalancedLLM::new(FakeLLM::fixed("a"))
+            .with_llm(FakeLLM::fixed("b"))
+            .with_llm(FakeLLM::fixed("c"))
+            .with_strategy(strategy)
+    }
+
+    #[tokio::test]
+    async fn test_round_robin() {
+        let llm = llm(BalanceStrategy::RoundRobin);
+        let clone = llm.clone();
+
+        let mut answers = Vec::new();
+        for llm in [&llm, &clone, &llm, &clone] {
+            answers.push(llm.invoke("hi").await.unwrap());
+        }
+
+        assert_eq!(answers, vec!["a", "b", "c", "a"]);
+    }
+
+    #[tokio::test]
+    async fn test_least_recently_used() {
+        let llm = llm(BalanceStrategy::LeastRecentlyUsed);
+
+        let mut answers = Vec::new();
+        for _ in 0..4 {
+            answers.push(llm.invoke("hi").await.unwrap());
+        }
+
+        assert_eq!(answers, vec!["a", "b", "c", "a"]);
+    }
+
+    #[tokio::test]
+    async fn test_sticky_keeps_the_conversation_on_one_llm() {
+        let llm = llm(BalanceStrategy::Sticky);
+        let mut conversation = vec![
+            Message::new_system_message("You are helpful"),
+            Message::new_human_message("Hello"),
+        ];
+
+        let first = llm.generate(&conversation).await.unwrap().generation;
+        conversation.push(Message::new_ai_message("Hi"));
+        conversation.push(Message::new_human_message("How are you?"));
+        let second = llm.generate(&conversation).await.unwrap().generation;
+
+        assert_eq!(first, second);
+    }
+}
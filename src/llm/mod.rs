@@ -6,3 +6,18 @@ pub use claude::*;
 
 pub mod ollama;
 pub use ollama::*;
+
+mod continuation;
+pub use continuation::*;
+
+mod fallback;
+pub use fallback::*;
+
+mod load_balanced;
+pub use load_balanced::*;
+
+mod redact;
+pub use redact::*;
+
+#[cfg(test)]
+pub(crate) mod fake;
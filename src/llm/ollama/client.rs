@@ -1,5 +1,8 @@
 use crate::{
-    language_models::{llm::LLM, GenerateResult, LLMError, TokenUsage},
+    language_models::{
+        apply_stop_words, llm::LLM, options::CallOptions, stop_words_stream, GenerateResult,
+        GenerationMetadata, LLMError, TokenUsage,
+    },
     schemas::{Message, MessageType, StreamData},
 };
 use async_trait::async_trait;
@@ -22,6 +25,7 @@ pub struct Ollama {
     pub(crate) client: Arc<OllamaClient>,
     pub(crate) model: String,
     pub(crate) options: Option<GenerationOptions>,
+    pub(crate) stop_words: Option<Vec<String>>,
 }
 
 /// [llama3.2](https://ollama.com/library/llama3.2) is a 3B parameters, 2.0GB model.
@@ -37,6 +41,7 @@ impl Ollama {
             client,
             model: model.into(),
             options,
+            stop_words: None,
         }
     }
 
@@ -50,6 +55,12 @@ impl Ollama {
         self
     }
 
+    /// The generation is cut before the first stop word, on the client.
+    pub fn with_stop_words(mut self, stop_words: Vec<String>) -> Self {
+        self.stop_words = Some(stop_words);
+        self
+    }
+
     fn generate_request(&self, messages: &[Message]) -> ChatMessageRequest {
         let mapped_messages = messages.iter().map(|message| message.into()).collect();
         ChatMessageRequest::new(self.model.clone(), mapped_messages)
@@ -100,7 +111,7 @@ impl LLM for Ollama {
         let request = self.generate_request(messages);
         let result = self.client.send_chat_messages(request).await?;
 
-        let generation = match result.message {
+        let mut generation = match result.message {
             Some(message) => message.content,
             None => return Err(OllamaError::from("No message in response".to_string()).into()),
         };
@@ -114,8 +125,18 @@ impl LLM for Ollama {
                 total_tokens: prompt_tokens + completion_tokens,
             }
         });
+        if let Some(stop_words) = &self.stop_words {
+            apply_stop_words(&mut generation, stop_words);
+        }
 
-        Ok(GenerateResult { tokens, generation })
+        Ok(GenerateResult {
+            tokens,
+            generation,
+            metadata: Some(GenerationMetadata {
+                model: Some(result.model),
+                ..Default::default()
+            }),
+        })
     }
 
     async fn stream(
@@ -140,7 +161,17 @@ impl LLM for Ollama {
             Err(_) => Err(OllamaError::from("Stream error".to_string()).into()),
         });
 
-        Ok(Box::pin(stream))
+        let stop_words = self.stop_words.clone().unwrap_or_default();
+        Ok(stop_words_stream(stream, stop_words))
+    }
+
+    /// Only the stop words are taken from the options, they replace the current ones. The
+    /// other fields of `CallOptions` are ignored, the generation options of Ollama are set
+    /// with `with_options`.
+    fn add_options(&mut self, options: CallOptions) {
+        if let Some(stop_words) = options.stop_words {
+            self.stop_words = Some(stop_words);
+        }
     }
 }
 
@@ -173,4 +204,16 @@ mod tests {
         stdout.write(b"\n").await.unwrap();
         stdout.flush().await.unwrap();
     }
+
+    #[test]
+    fn test_add_options_replaces_the_stop_words() {
+        let mut ollama = Ollama::default().with_stop_words(vec!["STOP".into()]);
+
+        ollama.add_options(CallOptions::new().with_stop_words(vec!["END".into()]));
+        ollama.add_options(CallOptions::new().with_stop_words(vec!["END".into()]));
+        assert_eq!(ollama.stop_words, Some(vec!["END".to_string()]));
+
+        ollama.add_options(CallOptions::new());
+        assert_eq!(ollama.stop_words, Some(vec!["END".to_string()]));
+    }
 }
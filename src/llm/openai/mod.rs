@@ -1,30 +1,44 @@
-use std::pin::Pin;
+use std::{pin::Pin, sync::Arc};
 
 pub use async_openai::config::{AzureConfig, Config, OpenAIConfig};
 use async_openai::{
-    error::OpenAIError,
     types::{
         ChatChoiceStream, ChatCompletionMessageToolCall, ChatCompletionRequestAssistantMessageArgs,
         ChatCompletionRequestMessage, ChatCompletionRequestMessageContentPartImageArgs,
-        ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestToolMessageArgs,
-        ChatCompletionRequestUserMessageArgs, ChatCompletionRequestUserMessageContent,
-        ChatCompletionRequestUserMessageContentPart, ChatCompletionStreamOptions,
-        ChatCompletionToolArgs, ChatCompletionToolType, CreateChatCompletionRequest,
-        CreateChatCompletionRequestArgs, FunctionObjectArgs,
+        ChatCompletionRequestMessageContentPartTextArgs, ChatCompletionRequestSystemMessageArgs,
+        ChatCompletionRequestToolMessageArgs, ChatCompletionRequestUserMessageArgs,
+        ChatCompletionRequestUserMessageContent, ChatCompletionRequestUserMessageContentPart,
+        ChatCompletionStreamOptions, ChatCompletionToolArgs, ChatCompletionToolType,
+        CreateChatCompletionRequest, CreateChatCompletionRequestArgs, FinishReason,
+        FunctionObjectArgs, ResponseFormat,
     },
     Client,
 };
+use async_stream::stream;
 use async_trait::async_trait;
-use futures::{Stream, StreamExt};
+use futures::{pin_mut, Stream, StreamExt};
+use serde_json::Value;
 
 use crate::{
-    language_models::{llm::LLM, options::CallOptions, GenerateResult, LLMError, TokenUsage},
+    callbacks::{CallbackHandler, RunInfo},
+    language_models::{
+        apply_stop_words, llm::LLM, options::CallOptions, stop_words_stream, GenerateResult,
+        GenerationMetadata, LLMError, TokenUsage,
+    },
     schemas::{
         messages::{Message, MessageType},
-        FunctionCallBehavior, StreamData,
+        FunctionCallBehavior, StreamData, ToolCallAccumulator,
     },
 };
 
+/// The name of the finish reason in the API, like `length`.
+fn finish_reason(reason: &FinishReason) -> Option<String> {
+    serde_json::to_value(reason)
+        .ok()?
+        .as_str()
+        .map(String::from)
+}
+
 #[derive(Clone)]
 pub enum OpenAIModel {
     Gpt35,
@@ -52,6 +66,21 @@ impl Into<String> for OpenAIModel {
     }
 }
 
+/// Models known to reject image inputs, any other model is assumed to accept them.
+fn model_supports_images(model: &str) -> bool {
+    const TEXT_ONLY_PREFIXES: [&str; 8] = [
+        "gpt-3.5",
+        "gpt-4-0",
+        "gpt-4-32k",
+        "gpt-4-1106-preview",
+        "gpt-4-turbo-preview",
+        "o1-mini",
+        "o1-preview",
+        "o3-mini",
+    ];
+    model != "gpt-4" && !TEXT_ONLY_PREFIXES.iter().any(|p| model.starts_with(p))
+}
+
 #[derive(Clone)]
 pub struct OpenAI<C: Config> {
     config: C,
@@ -84,6 +113,17 @@ impl<C: Config> OpenAI<C> {
     }
 }
 
+impl OpenAI<AzureConfig> {
+    /// Azure routes requests by deployment instead of model, this sets the deployment in
+    /// the config and uses its name as the model.
+    pub fn with_deployment<S: Into<String>>(mut self, deployment_id: S) -> Self {
+        let deployment_id = deployment_id.into();
+        self.config = self.config.with_deployment_id(deployment_id.clone());
+        self.model = deployment_id;
+        self
+    }
+}
+
 impl Default for OpenAI<OpenAIConfig> {
     fn default() -> Self {
         Self::new(OpenAIConfig::default())
@@ -93,15 +133,128 @@ impl Default for OpenAI<OpenAIConfig> {
 #[async_trait]
 impl<C: Config + Send + Sync + 'static> LLM for OpenAI<C> {
     async fn generate(&self, prompt: &[Message]) -> Result<GenerateResult, LLMError> {
+        let callbacks = self.options.callbacks.clone().unwrap_or_default();
+        let run = RunInfo::new();
+        for handler in callbacks.iter() {
+            handler.on_llm_start(&run, &self.model, prompt).await;
+        }
+
+        let result = self.generate_with_callbacks(prompt, &run, &callbacks).await;
+
+        for handler in callbacks.iter() {
+            match &result {
+                Ok(generate_result) => handler.on_llm_end(&run, generate_result).await,
+                Err(err) => handler.on_llm_error(&run, err).await,
+            }
+        }
+        result
+    }
+
+    async fn invoke(&self, prompt: &str) -> Result<String, LLMError> {
+        self.generate(&[Message::new_human_message(prompt)])
+            .await
+            .map(|res| res.generation)
+    }
+
+    async fn stream(
+        &self,
+        messages: &[Message],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError> {
+        let client = Client::with_config(self.config.clone());
+        let request = self.generate_request(messages, true)?;
+
+        let callbacks = self.options.callbacks.clone().unwrap_or_default();
+        let run = RunInfo::new();
+        for handler in callbacks.iter() {
+            handler.on_llm_start(&run, &self.model, messages).await;
+        }
+
+        let original_stream = client.chat().create_stream(request).await?;
+        let stop_words = self.options.stop_words.clone().unwrap_or_default();
+
+        let new_stream = original_stream.map(|result| match result {
+            Ok(completion) => {
+                let value_completion = serde_json::to_value(completion).map_err(LLMError::from)?;
+                let usage = value_completion.pointer("/usage");
+                if usage.is_some() && !usage.unwrap().is_null() {
+                    let usage = serde_json::from_value::<TokenUsage>(usage.unwrap().clone())
+                        .map_err(LLMError::from)?;
+                    return Ok(StreamData::new(value_completion, Some(usage), ""));
+                }
+                let content = value_completion
+                    .pointer("/choices/0/delta/content")
+                    .ok_or(LLMError::ContentNotFound(
+                        "/choices/0/delta/content".to_string(),
+                    ))?
+                    .clone();
+
+                Ok(StreamData::new(
+                    value_completion,
+                    None,
+                    content.as_str().unwrap_or(""),
+                ))
+            }
+            Err(e) => Err(LLMError::from(e)),
+        });
+        let new_stream = stop_words_stream(new_stream, stop_words);
+
+        let callback_stream = stream! {
+            pin_mut!(new_stream);
+            let mut generate_result = GenerateResult::default();
+            while let Some(result) = new_stream.next().await {
+                match &result {
+                    Ok(data) => {
+                        if !data.content.is_empty() {
+                            for handler in callbacks.iter() {
+                                handler.on_llm_new_token(&run, &data.content).await;
+                            }
+                        }
+                        generate_result.generation.push_str(&data.content);
+                        if data.tokens.is_some() {
+                            generate_result.tokens = data.tokens.clone();
+                        }
+                    }
+                    Err(err) => {
+                        for handler in callbacks.iter() {
+                            handler.on_llm_error(&run, err).await;
+                        }
+                    }
+                }
+                yield result;
+            }
+            for handler in callbacks.iter() {
+                handler.on_llm_end(&run, &generate_result).await;
+            }
+        };
+
+        Ok(Box::pin(callback_stream))
+    }
+
+    fn add_options(&mut self, options: CallOptions) {
+        self.options.merge_options(options)
+    }
+}
+
+impl<C: Config> OpenAI<C> {
+    async fn generate_with_callbacks(
+        &self,
+        prompt: &[Message],
+        run: &RunInfo,
+        callbacks: &[Arc<dyn CallbackHandler>],
+    ) -> Result<GenerateResult, LLMError> {
         let client = Client::with_config(self.config.clone());
         let request = self.generate_request(prompt, self.options.streaming_func.is_some())?;
         match &self.options.streaming_func {
             Some(func) => {
                 let mut stream = client.chat().create_stream(request).await?;
                 let mut generate_result = GenerateResult::default();
+                let mut metadata = GenerationMetadata::default();
+                let mut tool_calls = ToolCallAccumulator::new();
                 while let Some(result) = stream.next().await {
                     match result {
                         Ok(response) => {
+                            metadata.id = Some(response.id.clone());
+                            metadata.model = Some(response.model.clone());
                             if let Some(usage) = response.usage {
                                 generate_result.tokens = Some(TokenUsage {
                                     prompt_tokens: usage.prompt_tokens,
@@ -111,6 +264,9 @@ impl<C: Config + Send + Sync + 'static> LLM for OpenAI<C> {
                             }
                             for chat_choice in response.choices.iter() {
                                 let chat_choice: ChatChoiceStream = chat_choice.clone();
+                                if let Some(reason) = &chat_choice.finish_reason {
+                                    metadata.finish_reason = finish_reason(reason);
+                                }
                                 {
                                     let mut func = func.lock().await;
                                     let _ = func(
@@ -118,21 +274,48 @@ impl<C: Config + Send + Sync + 'static> LLM for OpenAI<C> {
                                     )
                                     .await;
                                 }
+                                if let Some(deltas) = &chat_choice.delta.tool_calls {
+                                    if let Value::Array(deltas) = serde_json::to_value(deltas)? {
+                                        tool_calls.push_deltas(&deltas);
+                                    }
+                                }
                                 if let Some(content) = chat_choice.delta.content {
+                                    for handler in callbacks.iter() {
+                                        handler.on_llm_new_token(run, &content).await;
+                                    }
                                     generate_result.generation.push_str(&content);
                                 }
                             }
                         }
                         Err(err) => {
-                            eprintln!("Error from streaming response: {:?}", err);
+                            log::error!("Error from streaming response: {:?}", err);
                         }
                     }
                 }
+                if let Some(stop_words) = &self.options.stop_words {
+                    apply_stop_words(&mut generate_result.generation, stop_words);
+                }
+                // Like the response without streaming, the generation is the tool calls
+                if !tool_calls.is_empty() {
+                    generate_result.generation = serde_json::to_string(&tool_calls.finish())?;
+                }
+                generate_result.metadata = Some(metadata);
                 Ok(generate_result)
             }
             None => {
                 let response = client.chat().create(request).await?;
-                let mut generate_result = GenerateResult::default();
+                let mut generate_result = GenerateResult {
+                    metadata: Some(GenerationMetadata {
+                        finish_reason: response
+                            .choices
+                            .first()
+                            .and_then(|choice| choice.finish_reason.as_ref())
+                            .and_then(finish_reason),
+                        model: Some(response.model.clone()),
+                        id: Some(response.id.clone()),
+                    }),
+                    ..Default::default()
+                };
 
                 if let Some(usage) = response.usage {
                     generate_result.tokens = Some(TokenUsage {
@@ -144,6 +327,9 @@ impl<C: Config + Send + Sync + 'static> LLM for OpenAI<C> {
 
                 if let Some(choice) = &response.choices.first() {
                     generate_result.generation = choice.message.content.clone().unwrap_or_default();
+                    if let Some(stop_words) = &self.options.stop_words {
+                        apply_stop_words(&mut generate_result.generation, stop_words);
+                    }
                     if let Some(function) = &choice.message.tool_calls {
                         generate_result.generation =
                             serde_json::to_string(&function).unwrap_or_default();
@@ -157,55 +343,6 @@ impl<C: Config + Send + Sync + 'static> LLM for OpenAI<C> {
         }
     }
 
-    async fn invoke(&self, prompt: &str) -> Result<String, LLMError> {
-        self.generate(&[Message::new_human_message(prompt)])
-            .await
-            .map(|res| res.generation)
-    }
-
-    async fn stream(
-        &self,
-        messages: &[Message],
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError> {
-        let client = Client::with_config(self.config.clone());
-        let request = self.generate_request(messages, true)?;
-
-        let original_stream = client.chat().create_stream(request).await?;
-
-        let new_stream = original_stream.map(|result| match result {
-            Ok(completion) => {
-                let value_completion = serde_json::to_value(completion).map_err(LLMError::from)?;
-                let usage = value_completion.pointer("/usage");
-                if usage.is_some() && !usage.unwrap().is_null() {
-                    let usage = serde_json::from_value::<TokenUsage>(usage.unwrap().clone())
-                        .map_err(LLMError::from)?;
-                    return Ok(StreamData::new(value_completion, Some(usage), ""));
-                }
-                let content = value_completion
-                    .pointer("/choices/0/delta/content")
-                    .ok_or(LLMError::ContentNotFound(
-                        "/choices/0/delta/content".to_string(),
-                    ))?
-                    .clone();
-
-                Ok(StreamData::new(
-                    value_completion,
-                    None,
-                    content.as_str().unwrap_or(""),
-                ))
-            }
-            Err(e) => Err(LLMError::from(e)),
-        });
-
-        Ok(Box::pin(new_stream))
-    }
-
-    fn add_options(&mut self, options: CallOptions) {
-        self.options.merge_options(options)
-    }
-}
-
-impl<C: Config> OpenAI<C> {
     fn to_openai_messages(
         &self,
         messages: &[Message],
@@ -230,23 +367,34 @@ impl<C: Config> OpenAI<C> {
                 }),
                 MessageType::HumanMessage => {
                     let content: ChatCompletionRequestUserMessageContent = match m.images.clone() {
-                        Some(images) => {
-                            let content: Result<
-                                Vec<ChatCompletionRequestUserMessageContentPart>,
-                                OpenAIError,
-                            > = images
-                                .into_iter()
-                                .map(|image| {
-                                    Ok(ChatCompletionRequestMessageContentPartImageArgs::default()
+                        Some(images) if !images.is_empty() => {
+                            if !model_supports_images(&self.model) {
+                                return Err(LLMError::ImagesNotSupported(format!(
+                                    "model {} has no vision support",
+                                    self.model
+                                )));
+                            }
+                            let mut parts: Vec<ChatCompletionRequestUserMessageContentPart> =
+                                Vec::new();
+                            if !m.content.is_empty() {
+                                parts.push(
+                                    ChatCompletionRequestMessageContentPartTextArgs::default()
+                                        .text(m.content.clone())
+                                        .build()?
+                                        .into(),
+                                );
+                            }
+                            for image in images {
+                                parts.push(
+                                    ChatCompletionRequestMessageContentPartImageArgs::default()
                                         .image_url(image.image_url)
                                         .build()?
-                                        .into())
-                                })
-                                .collect();
-
-                            content?.into()
+                                        .into(),
+                                );
+                            }
+                            parts.into()
                         }
-                        None => m.content.clone().into(),
+                        _ => m.content.clone().into(),
                     };
 
                     openai_messages.push(
@@ -298,6 +446,9 @@ impl<C: Config> OpenAI<C> {
         if let Some(stop_words) = &self.options.stop_words {
             request_builder.stop(stop_words);
         }
+        if self.options.json_mode == Some(true) {
+            request_builder.response_format(ResponseFormat::JsonObject);
+        }
 
         if let Some(behavior) = &self.options.functions {
             let mut functions = Vec::new();
@@ -507,4 +658,27 @@ mod tests {
         let response = open_ai.generate(&messages).await.unwrap();
         println!("Response: {:?}", response);
     }
+
+    #[test]
+    async fn test_image_messages_to_openai() {
+        let message = Message::new_human_message("What is in this image?")
+            .with_images(vec!["https://example.com/cat.png"]);
+
+        let messages = OpenAI::default()
+            .with_model(OpenAIModel::Gpt4o)
+            .to_openai_messages(std::slice::from_ref(&message))
+            .unwrap();
+        let value = serde_json::to_value(&messages[0]).unwrap();
+        assert_eq!(value["content"][0]["text"], "What is in this image?");
+        assert_eq!(
+            value["content"][1]["image_url"]["url"],
+            "https://example.com/cat.png"
+        );
+
+        let error = OpenAI::default()
+            .with_model(OpenAIModel::Gpt35)
+            .to_openai_messages(&[message])
+            .unwrap_err();
+        assert!(matches!(error, LLMError::ImagesNotSupported(_)));
+    }
 }
@@ -0,0 +1,56 @@
+use reqwest::header::HeaderMap;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Whether the header holds a credential, like `Authorization` or `x-api-key`.
+fn is_secret_header(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    matches!(
+        name.as_str(),
+        "authorization" | "proxy-authorization" | "cookie" | "set-cookie"
+    ) || ["key", "token", "secret"]
+        .iter()
+        .any(|part| name.contains(part))
+}
+
+/// The headers of a request with the values of the credentials replaced, to log them.
+///
+/// # Example
+/// ```rust,ignore
+/// log::debug!("Request headers: {:?}", redact_headers(request.headers()));
+/// ```
+pub fn redact_headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if is_secret_header(name.as_str()) {
+                REDACTED.to_string()
+            } else {
+                value.to_str().unwrap_or("<binary>").to_string()
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn test_redact_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", HeaderValue::from_static("sk-ant-123"));
+        headers.insert("Authorization", HeaderValue::from_static("Bearer sk-123"));
+        headers.insert("anthropic-version", HeaderValue::from_static("2023-06-01"));
+
+        let redacted = redact_headers(&headers);
+        assert_eq!(redacted.len(), 3);
+        assert!(redacted.contains(&("x-api-key".to_string(), REDACTED.to_string())));
+        assert!(redacted.contains(&("authorization".to_string(), REDACTED.to_string())));
+        assert!(redacted.contains(&("anthropic-version".to_string(), "2023-06-01".to_string())));
+        assert!(!format!("{:?}", redacted).contains("sk-"));
+    }
+}
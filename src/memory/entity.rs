@@ -0,0 +1,414 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, MutexGuard},
+};
+
+use serde_json::Value;
+use tokio::{sync::Mutex, task::JoinHandle};
+
+use crate::{
+    language_models::llm::LLM,
+    output_parsers::JsonOutputParser,
+    schemas::{
+        memory::BaseMemory,
+        messages::{Message, MessageType},
+    },
+};
+
+const DEFAULT_WINDOW_SIZE: usize = 10;
+const DEFAULT_MAX_ENTITIES: usize = 50;
+const EXTRACTION_PROMPT: &str = "You extract facts about the named entities (people, \
+organizations, places, products, projects...) of a conversation.
+
+Known entities: {names}
+
+Current facts about the entities mentioned:
+{facts}
+
+Last lines of the conversation:
+{turn}
+
+Return a JSON object whose keys are the names of the entities the last lines give new facts \
+about, and whose values are their updated summaries. An updated summary keeps the current \
+facts and adds the new ones, only changing the facts the conversation contradicts. Use the \
+known name of the entities already known. Return {} if there are no new facts.";
+const ENTITIES_PREFIX: &str = "Facts about the entities of the conversation:";
+
+struct Entity {
+    summary: String,
+    /// When the entity was last updated or mentioned, the oldest entities are dropped
+    /// first.
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct EntityState {
+    entities: HashMap<String, Entity>,
+    /// Recent messages, returned after the facts of the entities.
+    buffer: Vec<Message>,
+    /// Messages of the turn in progress, since the last human message.
+    turn: Vec<Message>,
+    /// Finished turns waiting for the extraction of their facts.
+    pending: Vec<Vec<Message>>,
+    clock: u64,
+    /// Incremented on `clear`, so the facts of the cleared turns are dropped.
+    generation: u64,
+}
+
+impl EntityState {
+    fn find(&self, name: &str) -> Option<String> {
+        self.entities
+            .keys()
+            .find(|key| key.eq_ignore_ascii_case(name.trim()))
+            .cloned()
+    }
+
+    /// Names of the known entities mentioned in the text.
+    fn mentioned(&self, text: &str) -> Vec<String> {
+        let text = text.to_lowercase();
+        let mut names: Vec<String> = self
+            .entities
+            .keys()
+            .filter(|name| text.contains(&name.to_lowercase()))
+            .cloned()
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+}
+
+/// Keeps the recent messages and a summary of the facts about each named entity of the
+/// conversation, extracted by the LLM after each turn. The facts of the entities mentioned
+/// in the recent messages are returned as a system message before them.
+///
+/// New facts are merged with the summary the entity already has. When there are more than
+/// `max_entities`, the entities not mentioned for the longest time are dropped.
+/// # Example
+/// ```rust,ignore
+/// let memory = EntityMemory::new(OpenAI::default()).with_max_entities(20);
+/// ```
+pub struct EntityMemory {
+    llm: Arc<dyn LLM>,
+    state: Arc<std::sync::Mutex<EntityState>>,
+    window_size: usize,
+    max_entities: usize,
+    task: Option<JoinHandle<()>>,
+}
+
+impl EntityMemory {
+    pub fn new<L: Into<Box<dyn LLM>>>(llm: L) -> Self {
+        Self {
+            llm: Arc::from(llm.into()),
+            state: Arc::default(),
+            window_size: DEFAULT_WINDOW_SIZE,
+            max_entities: DEFAULT_MAX_ENTITIES,
+            task: None,
+        }
+    }
+
+    /// Number of recent messages returned, 10 by default. With 0 only the entities are.
+    pub fn with_window_size(mut self, window_size: usize) -> Self {
+        self.window_size = window_size;
+        self
+    }
+
+    /// Maximum number of entities tracked, 50 by default.
+    pub fn with_max_entities(mut self, max_entities: usize) -> Self {
+        self.max_entities = max_entities.max(1);
+        self
+    }
+
+    /// The summaries of all the entities, by name.
+    pub fn entities(&self) -> HashMap<String, String> {
+        lock(&self.state)
+            .entities
+            .iter()
+            .map(|(name, entity)| (name.clone(), entity.summary.clone()))
+            .collect()
+    }
+
+    /// The summary of an entity, the name is case insensitive.
+    pub fn entity(&self, name: &str) -> Option<String> {
+        let state = lock(&self.state);
+        let name = state.find(name)?;
+        state
+            .entities
+            .get(&name)
+            .map(|entity| entity.summary.clone())
+    }
+
+    /// Forgets an entity, returning its summary.
+    pub fn remove_entity(&mut self, name: &str) -> Option<String> {
+        let mut state = lock(&self.state);
+        let name = state.find(name)?;
+        state.entities.remove(&name).map(|entity| entity.summary)
+    }
+
+    /// Forgets all the entities, the messages are kept.
+    pub fn clear_entities(&mut self) {
+        lock(&self.state).entities.clear();
+    }
+
+    /// The entities mentioned in the input with their summaries, sorted by name. Useful to
+    /// add the facts relevant to the input of the user to a prompt.
+    pub fn relevant_entities(&self, input: &str) -> Vec<(String, String)> {
+        let state = lock(&self.state);
+        state
+            .mentioned(input)
+            .into_iter()
+            .filter_map(|name| {
+                let summary = state.entities.get(&name)?.summary.clone();
+                Some((name, summary))
+            })
+            .collect()
+    }
+
+    /// Waits for the background extraction and extracts the facts of the turns still
+    /// waiting.
+    pub async fn extract(&mut self) {
+        if let Some(task) = self.task.take() {
+            let _ = task.await;
+        }
+        extract_pending(self.llm.clone(), self.state.clone(), self.max_entities).await;
+    }
+
+    fn extract_in_background(&mut self) {
+        if self.task.as_ref().is_some_and(|task| !task.is_finished()) {
+            return;
+        }
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                self.task = Some(runtime.spawn(extract_pending(
+                    self.llm.clone(),
+                    self.state.clone(),
+                    self.max_entities,
+                )))
+            }
+            Err(_) => log::warn!(
+                "No tokio runtime to extract the entities, the turns are kept until extract \
+                is called"
+            ),
+        }
+    }
+}
+
+fn lock(state: &std::sync::Mutex<EntityState>) -> MutexGuard<'_, EntityState> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Extracts the facts of the pending turns, one turn at a time. A turn stays pending if the
+/// LLM fails, it's retried on the next extraction.
+async fn extract_pending(
+    llm: Arc<dyn LLM>,
+    state: Arc<std::sync::Mutex<EntityState>>,
+    max_entities: usize,
+) {
+    loop {
+        let (prompt, generation) = {
+            let state = lock(&state);
+            let Some(turn) = state.pending.first() else {
+                return;
+            };
+            let turn = Message::messages_to_string(turn);
+            let mut names: Vec<&String> = state.entities.keys().collect();
+            names.sort();
+            let facts = state
+                .mentioned(&turn)
+                .iter()
+                .map(|name| format!("{}: {}", name, state.entities[name].summary))
+                .collect::<Vec<_>>();
+            let prompt = EXTRACTION_PROMPT
+                .replace(
+                    "{names}",
+                    &names
+                        .iter()
+                        .map(|name| name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                )
+                .replace("{facts}", &facts.join("\n"))
+                .replace("{turn}", &turn);
+            (prompt, state.generation)
+        };
+
+        let output = match llm.invoke(&prompt).await {
+            Ok(output) => output,
+            Err(e) => {
+                log::warn!("Failed to extract the entities: {}", e);
+                return;
+            }
+        };
+        let updates = match JsonOutputParser::new().parse_value(&output) {
+            Ok(Value::Object(updates)) => updates,
+            _ => {
+                log::warn!("The entities extracted are not a JSON object: {}", output);
+                serde_json::Map::new()
+            }
+        };
+
+        let mut state = lock(&state);
+        if state.generation != generation {
+            return;
+        }
+        state.pending.remove(0);
+        for (name, summary) in updates {
+            let Some(summary) = summary.as_str().map(str::trim).filter(|s| !s.is_empty()) else {
+                continue;
+            };
+            let name = state.find(&name).unwrap_or_else(|| name.trim().to_string());
+            let last_used = state.tick();
+            state.entities.insert(
+                name,
+                Entity {
+                    summary: summary.to_string(),
+                    last_used,
+                },
+            );
+        }
+        while state.entities.len() > max_entities {
+            let oldest = state
+                .entities
+                .iter()
+                .min_by_key(|(_, entity)| entity.last_used)
+                .map(|(name, _)| name.clone());
+            if let Some(oldest) = oldest {
+                state.entities.remove(&oldest);
+            }
+        }
+    }
+}
+
+impl Into<Arc<Mutex<dyn BaseMemory>>> for EntityMemory {
+    fn into(self) -> Arc<Mutex<dyn BaseMemory>> {
+        Arc::new(Mutex::new(self))
+    }
+}
+
+impl BaseMemory for EntityMemory {
+    fn messages(&self) -> Vec<Message> {
+        let state = lock(&self.state);
+        let recent = Message::messages_to_string(&state.buffer);
+        let facts: Vec<String> = state
+            .mentioned(&recent)
+            .iter()
+            .map(|name| format!("- {}: {}", name, state.entities[name].summary))
+            .collect();
+
+        let mut messages = Vec::with_capacity(state.buffer.len() + 1);
+        if !facts.is_empty() {
+            messages.push(Message::new_system_message(format!(
+                "{}\n{}",
+                ENTITIES_PREFIX,
+                facts.join("\n")
+            )));
+        }
+        messages.extend(state.buffer.iter().cloned());
+        messages
+    }
+
+    fn add_message(&mut self, message: Message) {
+        let turn_finished = {
+            let mut state = lock(&self.state);
+            // The entities mentioned again are kept over the forgotten ones
+            for name in state.mentioned(&message.content) {
+                let last_used = state.tick();
+                if let Some(entity) = state.entities.get_mut(&name) {
+                    entity.last_used = last_used;
+                }
+            }
+
+            if message.message_type == MessageType::HumanMessage {
+                state.turn.clear();
+            }
+            state.turn.push(message.clone());
+            let turn_finished =
+                message.message_type == MessageType::AIMessage && message.tool_calls.is_none();
+            if turn_finished {
+                let turn = std::mem::take(&mut state.turn);
+                state.pending.push(turn);
+            }
+
+            state.buffer.push(message);
+            let forgotten = state.buffer.len().saturating_sub(self.window_size);
+            state.buffer.drain(..forgotten);
+            turn_finished
+        };
+        if turn_finished {
+            self.extract_in_background();
+        }
+    }
+
+    fn clear(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+        let mut state = lock(&self.state);
+        *state = EntityState {
+            generation: state.generation + 1,
+            ..Default::default()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::fake::FakeLLM;
+
+    #[tokio::test]
+    async fn test_entity_memory() {
+        let llm = FakeLLM::scripted([
+            r#"{"Luis": "Lives in Lima.", "Acme": "Company of Luis."}"#,
+            r#"```json
+            {"luis": "Lives in Lima. Has a dog named Rex."}
+            ```"#,
+        ]);
+        let mut memory = EntityMemory::new(llm.clone())
+            .with_window_size(2)
+            .with_max_entities(2);
+
+        memory.add_user_message(&"I'm Luis, I live in Lima and work at Acme");
+        memory.add_ai_message(&"Nice to meet you");
+        memory.extract().await;
+        assert_eq!(memory.entity("luis").unwrap(), "Lives in Lima.");
+
+        memory.add_user_message(&"Luis here again, I have a dog named Rex");
+        memory.add_ai_message(&"Cute");
+        memory.extract().await;
+        assert_eq!(memory.entities().len(), 2);
+        assert_eq!(
+            memory.entity("Luis").unwrap(),
+            "Lives in Lima. Has a dog named Rex."
+        );
+        // The current facts are sent to be merged with the new ones
+        assert!(llm.prompts()[1][0].content.contains("Luis: Lives in Lima."));
+
+        let messages = memory.messages();
+        assert_eq!(messages.len(), 3);
+        assert!(messages[0].content.contains("- Luis: Lives in Lima."));
+        assert!(!messages[0].content.contains("Acme"));
+        assert_eq!(
+            memory.relevant_entities("What does Acme do?"),
+            vec![("Acme".to_string(), "Company of Luis.".to_string())]
+        );
+
+        assert_eq!(memory.remove_entity("ACME").unwrap(), "Company of Luis.");
+        assert_eq!(memory.entities().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_window_size_zero_keeps_no_message() {
+        let mut memory =
+            EntityMemory::new(FakeLLM::scripted(Vec::<String>::new())).with_window_size(0);
+
+        memory.add_user_message(&"I'm Luis");
+        memory.add_user_message(&"I live in Lima");
+
+        assert!(memory.messages().is_empty());
+    }
+}
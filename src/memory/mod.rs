@@ -1,7 +1,11 @@
 mod dummy_memory;
+mod entity;
 mod simple_memory;
+mod summary_buffer;
 mod window_buffer;
 
 pub use dummy_memory::*;
+pub use entity::*;
 pub use simple_memory::*;
+pub use summary_buffer::*;
 pub use window_buffer::*;
@@ -0,0 +1,280 @@
+use std::sync::{Arc, MutexGuard};
+
+use tokio::{sync::Mutex, task::JoinHandle};
+
+use crate::{
+    language_models::{llm::LLM, TokenCounter},
+    schemas::{
+        memory::BaseMemory,
+        messages::{Message, MessageType},
+    },
+};
+
+const DEFAULT_MAX_TOKENS: usize = 2000;
+const DEFAULT_KEEP_LAST_TURNS: usize = 2;
+const SUMMARY_PROMPT: &str = "Progressively summarize the lines of conversation provided, \
+adding onto the previous summary and returning a new summary. Keep the names, facts and \
+decisions that may matter later in the conversation.
+
+Current summary:
+{summary}
+
+New lines of conversation:
+{new_lines}
+
+New summary:";
+const SUMMARY_PREFIX: &str = "Summary of the earlier conversation:\n";
+
+#[derive(Default)]
+struct SummaryState {
+    summary: String,
+    /// Older messages waiting to be folded into the summary, they are returned as they are
+    /// until the summary includes them.
+    pending: Vec<Message>,
+    /// Recent messages, kept as they are.
+    buffer: Vec<Message>,
+    /// Incremented on `clear`, so a summary of the cleared messages is dropped.
+    generation: u64,
+}
+
+/// Keeps the last turns of the conversation as they are and folds the older ones into a
+/// summary written by the LLM, returned as a system message before the recent messages.
+///
+/// When the recent messages go over `max_tokens`, the turns before the last
+/// `keep_last_turns` are summarized in the background, together with the current summary,
+/// so each message is summarized only once. A turn starts with a human message.
+/// # Example
+/// ```rust,ignore
+/// let memory = SummaryBufferMemory::new(OpenAI::default())
+///     .with_max_tokens(1000)
+///     .with_keep_last_turns(3);
+/// ```
+pub struct SummaryBufferMemory {
+    llm: Arc<dyn LLM>,
+    state: Arc<std::sync::Mutex<SummaryState>>,
+    max_tokens: usize,
+    keep_last_turns: usize,
+    token_counter: TokenCounter,
+    task: Option<JoinHandle<()>>,
+}
+
+impl SummaryBufferMemory {
+    pub fn new<L: Into<Box<dyn LLM>>>(llm: L) -> Self {
+        Self {
+            llm: Arc::from(llm.into()),
+            state: Arc::default(),
+            max_tokens: DEFAULT_MAX_TOKENS,
+            keep_last_turns: DEFAULT_KEEP_LAST_TURNS,
+            token_counter: TokenCounter::default(),
+            task: None,
+        }
+    }
+
+    /// Tokens of the recent messages that trigger the summarization, 2000 by default.
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    /// Number of turns never summarized, 2 by default.
+    pub fn with_keep_last_turns(mut self, keep_last_turns: usize) -> Self {
+        self.keep_last_turns = keep_last_turns;
+        self
+    }
+
+    pub fn with_token_counter(mut self, token_counter: TokenCounter) -> Self {
+        self.token_counter = token_counter;
+        self
+    }
+
+    /// The current summary of the older messages, empty if nothing was summarized yet.
+    pub fn summary(&self) -> String {
+        lock(&self.state).summary.clone()
+    }
+
+    /// Waits for the background summarization and folds the messages still waiting into
+    /// the summary.
+    pub async fn summarize(&mut self) {
+        if let Some(task) = self.task.take() {
+            let _ = task.await;
+        }
+        fold_pending(self.llm.clone(), self.state.clone()).await;
+    }
+
+    fn summarize_in_background(&mut self) {
+        if self.task.as_ref().is_some_and(|task| !task.is_finished()) {
+            return;
+        }
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                self.task = Some(runtime.spawn(fold_pending(self.llm.clone(), self.state.clone())))
+            }
+            Err(_) => log::warn!(
+                "No tokio runtime to summarize the memory, the older messages are kept until \
+                summarize is called"
+            ),
+        }
+    }
+}
+
+fn lock(state: &std::sync::Mutex<SummaryState>) -> MutexGuard<'_, SummaryState> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Folds the pending messages into the summary until there are none left. The messages
+/// stay pending if the LLM fails, they are retried on the next summarization.
+async fn fold_pending(llm: Arc<dyn LLM>, state: Arc<std::sync::Mutex<SummaryState>>) {
+    loop {
+        let (summary, pending, generation) = {
+            let state = lock(&state);
+            if state.pending.is_empty() {
+                return;
+            }
+            (
+                state.summary.clone(),
+                state.pending.clone(),
+                state.generation,
+            )
+        };
+
+        let prompt = SUMMARY_PROMPT
+            .replace("{summary}", &summary)
+            .replace("{new_lines}", &Message::messages_to_string(&pending));
+        let new_summary = match llm.invoke(&prompt).await {
+            Ok(new_summary) => new_summary,
+            Err(e) => {
+                log::warn!("Failed to summarize the memory: {}", e);
+                return;
+            }
+        };
+
+        let mut state = lock(&state);
+        if state.generation != generation {
+            return;
+        }
+        state.summary = new_summary.trim().to_string();
+        state.pending.drain(..pending.len());
+    }
+}
+
+impl Into<Arc<Mutex<dyn BaseMemory>>> for SummaryBufferMemory {
+    fn into(self) -> Arc<Mutex<dyn BaseMemory>> {
+        Arc::new(Mutex::new(self))
+    }
+}
+
+impl BaseMemory for SummaryBufferMemory {
+    fn messages(&self) -> Vec<Message> {
+        let state = lock(&self.state);
+        let mut messages = Vec::with_capacity(state.pending.len() + state.buffer.len() + 1);
+        if !state.summary.is_empty() {
+            messages.push(Message::new_system_message(format!(
+                "{}{}",
+                SUMMARY_PREFIX, state.summary
+            )));
+        }
+        messages.extend(state.pending.iter().cloned());
+        messages.extend(state.buffer.iter().cloned());
+        messages
+    }
+
+    fn add_message(&mut self, message: Message) {
+        let overflow = {
+            let mut state = lock(&self.state);
+            state.buffer.push(message);
+            let tokens: usize = state
+                .buffer
+                .iter()
+                .map(|message| self.token_counter.count(&message.content))
+                .sum();
+            if tokens <= self.max_tokens {
+                return;
+            }
+
+            // The older turns are moved whole, a tool call is never split from its result
+            let turn_starts: Vec<usize> = state
+                .buffer
+                .iter()
+                .enumerate()
+                .filter(|(_, message)| message.message_type == MessageType::HumanMessage)
+                .map(|(i, _)| i)
+                .collect();
+            let split = match self.keep_last_turns {
+                0 => state.buffer.len(),
+                keep => turn_starts
+                    .len()
+                    .checked_sub(keep)
+                    .map_or(0, |turn| turn_starts[turn]),
+            };
+            let overflow: Vec<Message> = state.buffer.drain(..split).collect();
+            state.pending.extend(overflow.iter().cloned());
+            !overflow.is_empty()
+        };
+        if overflow {
+            self.summarize_in_background();
+        }
+    }
+
+    fn clear(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+        let mut state = lock(&self.state);
+        *state = SummaryState {
+            generation: state.generation + 1,
+            ..Default::default()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::llm::fake::FakeLLM;
+
+    /// Answers with the number of prompts it got.
+    fn counting_llm() -> FakeLLM {
+        let count = AtomicUsize::new(0);
+        FakeLLM::answering(move |_| format!("summary {}", count.fetch_add(1, Ordering::SeqCst) + 1))
+    }
+
+    #[tokio::test]
+    async fn test_summary_buffer_memory() {
+        let llm = counting_llm();
+        let mut memory = SummaryBufferMemory::new(llm.clone())
+            .with_max_tokens(8)
+            .with_keep_last_turns(1);
+
+        memory.add_user_message(&"My name is Luis");
+        memory.add_ai_message(&"Hello Luis");
+        memory.summarize().await;
+        assert_eq!(memory.messages().len(), 2);
+        assert_eq!(memory.summary(), "");
+
+        memory.add_user_message(&"I live in Lima");
+        memory.add_ai_message(&"Nice city");
+        memory.summarize().await;
+        assert_eq!(memory.summary(), "summary 1");
+        let messages = memory.messages();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[0].message_type, MessageType::SystemMessage);
+        assert_eq!(messages[1].content, "I live in Lima");
+
+        memory.add_user_message(&"What's my name?");
+        memory.summarize().await;
+        assert_eq!(memory.summary(), "summary 2");
+
+        // The second summary only gets the new lines with the previous summary
+        let prompts: Vec<String> = llm
+            .prompts()
+            .into_iter()
+            .map(|messages| messages[0].content.clone())
+            .collect();
+        assert!(prompts[0].contains("My name is Luis"));
+        assert!(prompts[1].contains("summary 1"));
+        assert!(prompts[1].contains("I live in Lima"));
+        assert!(!prompts[1].contains("My name is Luis"));
+    }
+}
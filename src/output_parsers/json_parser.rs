@@ -0,0 +1,81 @@
+use async_trait::async_trait;
+use serde_json::Value;
+
+use super::{OutputParser, OutputParserError};
+
+/// Finds the JSON in the output of an LLM, either the whole output, a markdown code block
+/// or the first JSON object or array in the text. `parse` returns the JSON compacted, and
+/// `parse_value` the parsed value.
+pub struct JsonOutputParser {}
+
+impl JsonOutputParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn parse_value(&self, output: &str) -> Result<Value, OutputParserError> {
+        let output = output.trim();
+        if let Ok(value) = serde_json::from_str(output) {
+            return Ok(value);
+        }
+
+        // The first value starting at an opening bracket, the text after it is ignored
+        for (start, _) in output.match_indices(['{', '[']) {
+            let mut values = serde_json::Deserializer::from_str(&output[start..]).into_iter();
+            if let Some(Ok(value)) = values.next() {
+                return Ok(value);
+            }
+        }
+
+        Err(OutputParserError::ParsingError(format!(
+            "No JSON found in output: {}",
+            output
+        )))
+    }
+}
+
+impl Default for JsonOutputParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl OutputParser for JsonOutputParser {
+    async fn parse(&self, output: &str) -> Result<String, OutputParserError> {
+        Ok(self.parse_value(output)?.to_string())
+    }
+
+    fn format_instructions(&self) -> String {
+        "Reply only with valid JSON, without any explanation.".into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_json_parser() {
+        let parser = JsonOutputParser::new();
+        assert_eq!(
+            parser.parse_value(r#" {"name": "Ana"} "#).unwrap(),
+            json!({"name": "Ana"})
+        );
+        assert_eq!(
+            parser
+                .parse_value("Here it is:\n```json\n[1, 2]\n```\nAnything else?")
+                .unwrap(),
+            json!([1, 2])
+        );
+        assert_eq!(
+            parser
+                .parse_value(r#"The {person} is {"name": "Ana", "tags": ["a}"]} as asked"#)
+                .unwrap(),
+            json!({"name": "Ana", "tags": ["a}"]})
+        );
+        assert!(parser.parse_value("no json here").is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use async_trait::async_trait;
+use regex::Regex;
+
+use super::{OutputParser, OutputParserError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListFormat {
+    /// `red, green, blue`
+    Comma,
+    /// `1. red` or `1) red`, one item per line
+    Numbered,
+    /// `- red`, `* red` or `• red`, one item per line
+    Bulleted,
+}
+
+/// Finds the list in the output of an LLM. `parse_list` returns the trimmed items, without
+/// the empty ones, and `parse` the items as a JSON array.
+///
+/// By default the format is detected: the numbered or bulleted lines if there are any, the
+/// text around them is ignored, otherwise the line with the most commas, without the text
+/// before a colon. `with_format` forces a format.
+pub struct ListOutputParser {
+    format: Option<ListFormat>,
+}
+
+impl ListOutputParser {
+    pub fn new() -> Self {
+        Self { format: None }
+    }
+
+    pub fn with_format(mut self, format: ListFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    pub fn parse_list(&self, output: &str) -> Result<Vec<String>, OutputParserError> {
+        let numbered = line_items(output, r"^\s*\d+[.)]\s+(.*)$")?;
+        let bulleted = line_items(output, r"^\s*[-*•]\s+(.*)$")?;
+
+        let items = match self.format {
+            Some(ListFormat::Numbered) => numbered,
+            Some(ListFormat::Bulleted) => bulleted,
+            Some(ListFormat::Comma) => comma_items(output),
+            None if !numbered.is_empty() && numbered.len() >= bulleted.len() => numbered,
+            None if !bulleted.is_empty() => bulleted,
+            None => comma_items(output),
+        };
+
+        if items.is_empty() {
+            return Err(OutputParserError::ParsingError(format!(
+                "No list found in output: {}",
+                output
+            )));
+        }
+        Ok(items)
+    }
+}
+
+impl Default for ListOutputParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The items of the lines matching the expression, its first group is the item.
+fn line_items(output: &str, expression: &str) -> Result<Vec<String>, OutputParserError> {
+    let re = Regex::new(expression)?;
+    Ok(output
+        .lines()
+        .filter_map(|line| re.captures(line))
+        .map(|cap| cap[1].trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect())
+}
+
+fn comma_items(output: &str) -> Vec<String> {
+    let Some(line) = output
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .max_by_key(|line| line.matches(',').count())
+    else {
+        return Vec::new();
+    };
+    let line = line.rsplit_once(':').map_or(line, |(_, list)| list);
+    line.trim()
+        .trim_end_matches('.')
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+#[async_trait]
+impl OutputParser for ListOutputParser {
+    async fn parse(&self, output: &str) -> Result<String, OutputParserError> {
+        let items = self.parse_list(output)?;
+        serde_json::to_string(&items).map_err(|e| OutputParserError::ParsingError(e.to_string()))
+    }
+
+    fn format_instructions(&self) -> String {
+        match self.format {
+            Some(ListFormat::Comma) => {
+                "Reply only with the items separated by commas, like: first, second, third".into()
+            }
+            Some(ListFormat::Bulleted) => {
+                "Reply only with a bulleted list, one item per line, like:\n- first\n- second"
+                    .into()
+            }
+            Some(ListFormat::Numbered) | None => {
+                "Reply only with a numbered list, one item per line, like:\n1. first\n2. second"
+                    .into()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_parser() {
+        let parser = ListOutputParser::new();
+        assert_eq!(
+            parser
+                .parse_list("Sure! Here are three colors: red, green ,, blue.\n")
+                .unwrap(),
+            vec!["red", "green", "blue"]
+        );
+        assert_eq!(
+            parser
+                .parse_list("The colors:\n1. red\n2)  green \n3.\n10. blue\nHope it helps")
+                .unwrap(),
+            vec!["red", "green", "blue"]
+        );
+        assert_eq!(
+            parser
+                .parse_list("Colors:\n- red\n* green, light\n• blue")
+                .unwrap(),
+            vec!["red", "green, light", "blue"]
+        );
+        assert!(parser.parse_list("\n \n").is_err());
+
+        let output = "1. Primary colors\n- red\n- blue";
+        assert_eq!(parser.parse_list(output).unwrap(), vec!["red", "blue"]);
+        assert_eq!(
+            ListOutputParser::new()
+                .with_format(ListFormat::Numbered)
+                .parse_list(output)
+                .unwrap(),
+            vec!["Primary colors"]
+        );
+    }
+}
@@ -48,6 +48,10 @@ impl OutputParser for MarkdownParser {
             ))
         }
     }
+
+    fn format_instructions(&self) -> String {
+        "Reply with the answer in a markdown code block.".into()
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use super::{OutputParser, OutputParserError};
+
+/// Finds the first GitHub flavored markdown table in the output of an LLM, the text around
+/// it is ignored. `parse_rows` returns its rows keyed by header, and `parse` the rows as a
+/// JSON array of objects.
+///
+/// Escaped pipes (`\|`) are kept in the cells as `|`. Rows with fewer cells than the header
+/// are completed with empty cells, the extra cells are ignored.
+pub struct MarkdownTableParser {}
+
+impl MarkdownTableParser {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    pub fn parse_rows(
+        &self,
+        output: &str,
+    ) -> Result<Vec<HashMap<String, String>>, OutputParserError> {
+        let lines: Vec<&str> = output.lines().map(str::trim).collect();
+
+        let start = lines
+            .windows(2)
+            .position(|pair| match (split_row(pair[0]), split_row(pair[1])) {
+                (Some(header), Some(delimiter)) => {
+                    header.len() == delimiter.len() && delimiter.iter().all(|c| is_delimiter(c))
+                }
+                _ => false,
+            })
+            .ok_or_else(|| {
+                OutputParserError::ParsingError(format!(
+                    "No markdown table found in output: {}",
+                    output
+                ))
+            })?;
+
+        let headers = split_row(lines[start]).unwrap_or_default();
+        let rows = lines[start + 2..]
+            .iter()
+            .map_while(|line| split_row(line))
+            .map(|cells| {
+                let mut cells = cells.into_iter();
+                headers
+                    .iter()
+                    .map(|header| (header.clone(), cells.next().unwrap_or_default()))
+                    .collect()
+            })
+            .collect();
+        Ok(rows)
+    }
+}
+
+impl Default for MarkdownTableParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The trimmed cells of a table line, `None` if the line has no unescaped pipe.
+fn split_row(line: &str) -> Option<Vec<String>> {
+    let mut cells = vec![String::new()];
+    let mut has_pipe = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                chars.next();
+                cells.last_mut()?.push('|');
+            }
+            '|' => {
+                has_pipe = true;
+                cells.push(String::new());
+            }
+            c => cells.last_mut()?.push(c),
+        }
+    }
+    if !has_pipe {
+        return None;
+    }
+
+    // The leading and trailing pipes are optional
+    if line.starts_with('|') {
+        cells.remove(0);
+    }
+    if line.ends_with('|') && !line.ends_with("\\|") {
+        cells.pop();
+    }
+    Some(cells.into_iter().map(|c| c.trim().to_string()).collect())
+}
+
+/// Whether a cell of the line under the header is like `---`, `:--` or `:-:`.
+fn is_delimiter(cell: &str) -> bool {
+    let dashes = cell.strip_prefix(':').unwrap_or(cell);
+    let dashes = dashes.strip_suffix(':').unwrap_or(dashes);
+    !dashes.is_empty() && dashes.chars().all(|c| c == '-')
+}
+
+#[async_trait]
+impl OutputParser for MarkdownTableParser {
+    async fn parse(&self, output: &str) -> Result<String, OutputParserError> {
+        let rows = self.parse_rows(output)?;
+        serde_json::to_string(&rows).map_err(|e| OutputParserError::ParsingError(e.to_string()))
+    }
+
+    fn format_instructions(&self) -> String {
+        "Reply with a markdown table, with a header row naming the columns.".into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown_table_parser() {
+        let output = r#"Here are the languages you asked for:
+
+| Language | Operator | Typing |
+|:---------|:--------:|-------:|
+| Rust     | `a \| b` | static |
+| Python   | `a or b`
+| Ruby | `a \|\| b` | dynamic | extra |
+
+And another table:
+
+| Language | Year |
+| --- | --- |
+| Go | 2009 |
+"#;
+        let rows = MarkdownTableParser::new().parse_rows(output).unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0]["Language"], "Rust");
+        assert_eq!(rows[0]["Operator"], "`a | b`");
+        assert_eq!(rows[0]["Typing"], "static");
+        assert_eq!(rows[1]["Typing"], "");
+        assert_eq!(rows[2]["Operator"], "`a || b`");
+        assert_eq!(rows[2].len(), 3);
+
+        let rows = MarkdownTableParser::new()
+            .parse_rows("Name | Age\n--- | ---\nAna | 31")
+            .unwrap();
+        assert_eq!(rows[0]["Name"], "Ana");
+        assert_eq!(rows[0]["Age"], "31");
+
+        assert!(MarkdownTableParser::new()
+            .parse_rows("No table | here")
+            .is_err());
+    }
+}
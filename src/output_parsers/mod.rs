@@ -4,8 +4,17 @@ pub use output_parser::*;
 mod markdown_parser;
 pub use markdown_parser::*;
 
+mod markdown_table_parser;
+pub use markdown_table_parser::*;
+
 mod simple_parser;
 pub use simple_parser::*;
 
+mod json_parser;
+pub use json_parser::*;
+
+mod list_parser;
+pub use list_parser::*;
+
 mod error;
 pub use error::*;
@@ -2,9 +2,18 @@ use async_trait::async_trait;
 
 use super::OutputParserError;
 
+/// The prompt variable the chains fill with the format instructions of their parser.
+pub const FORMAT_INSTRUCTIONS_KEY: &str = "format_instructions";
+
 #[async_trait]
 pub trait OutputParser: Send + Sync {
     async fn parse(&self, output: &str) -> Result<String, OutputParserError>;
+
+    /// Tells the model how to format its answer so that the parser can parse it, empty when
+    /// the parser accepts any text.
+    fn format_instructions(&self) -> String {
+        String::new()
+    }
 }
 
 impl<P> From<P> for Box<dyn OutputParser>
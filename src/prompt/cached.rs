@@ -0,0 +1,226 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+    sync::Mutex,
+};
+
+use serde_json::Value;
+
+use crate::schemas::PromptValue;
+
+use super::{FormatPrompter, PromptArgs, PromptError};
+
+const DEFAULT_CAPACITY: usize = 128;
+
+/// Wraps a `FormatPrompter` to reuse the prompts already formatted with the same input
+/// variables, for example in agent loops formatting the same prefix again and again. The
+/// prompts are keyed by a hash of the variables, the message lists of the placeholders
+/// included, so any change of a value formats the prompt again. Errors are not cached.
+///
+/// When the cache is full, the oldest prompt is dropped.
+///
+/// # Example
+/// ```rust,ignore
+/// let prompt = CachedPrompter::new(message_formatter![
+///     fmt_message!(Message::new_system_message("You are a helpful assistant")),
+///     fmt_placeholder!("history"),
+///     fmt_template!(HumanMessagePromptTemplate::new(template_fstring!("{input}", "input"))),
+/// ])
+/// .with_capacity(32);
+/// ```
+pub struct CachedPrompter<P> {
+    prompter: P,
+    capacity: usize,
+    cache: Mutex<Cache>,
+}
+
+#[derive(Default)]
+struct Cache {
+    prompts: HashMap<u64, PromptValue>,
+    order: VecDeque<u64>,
+}
+
+impl<P: FormatPrompter> CachedPrompter<P> {
+    pub fn new(prompter: P) -> Self {
+        Self {
+            prompter,
+            capacity: DEFAULT_CAPACITY,
+            cache: Mutex::new(Cache::default()),
+        }
+    }
+
+    /// Maximum number of prompts kept, 128 by default.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Drops the cached prompts.
+    pub fn clear(&self) {
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.prompts.clear();
+            cache.order.clear();
+        }
+    }
+}
+
+impl<P: FormatPrompter> FormatPrompter for CachedPrompter<P> {
+    fn format_prompt(&self, input_variables: PromptArgs) -> Result<PromptValue, PromptError> {
+        let key = hash_args(&input_variables);
+        if let Some(prompt) = self
+            .cache
+            .lock()
+            .ok()
+            .and_then(|cache| cache.prompts.get(&key).cloned())
+        {
+            return Ok(prompt);
+        }
+
+        let prompt = self.prompter.format_prompt(input_variables)?;
+        if let Ok(mut cache) = self.cache.lock() {
+            if cache.prompts.insert(key, prompt.clone()).is_none() {
+                cache.order.push_back(key);
+            }
+            while cache.order.len() > self.capacity {
+                if let Some(oldest) = cache.order.pop_front() {
+                    cache.prompts.remove(&oldest);
+                }
+            }
+        }
+        Ok(prompt)
+    }
+
+    fn get_input_variables(&self) -> Vec<String> {
+        self.prompter.get_input_variables()
+    }
+}
+
+/// Hashes the variables whatever the order of their keys, in the arguments and in the
+/// JSON objects of their values.
+fn hash_args(input_variables: &PromptArgs) -> u64 {
+    let mut keys: Vec<&String> = input_variables.keys().collect();
+    keys.sort();
+
+    let mut hasher = DefaultHasher::new();
+    for key in keys {
+        key.hash(&mut hasher);
+        hash_value(&input_variables[key], &mut hasher);
+    }
+    hasher.finish()
+}
+
+fn hash_value<H: Hasher>(value: &Value, hasher: &mut H) {
+    match value {
+        Value::Null => 0u8.hash(hasher),
+        Value::Bool(value) => {
+            1u8.hash(hasher);
+            value.hash(hasher);
+        }
+        Value::Number(value) => {
+            2u8.hash(hasher);
+            value.to_string().hash(hasher);
+        }
+        Value::String(value) => {
+            3u8.hash(hasher);
+            value.hash(hasher);
+        }
+        Value::Array(values) => {
+            4u8.hash(hasher);
+            values.len().hash(hasher);
+            for value in values {
+                hash_value(value, hasher);
+            }
+        }
+        Value::Object(map) => {
+            5u8.hash(hasher);
+            map.len().hash(hasher);
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+            for (key, value) in entries {
+                key.hash(hasher);
+                hash_value(value, hasher);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::{prompt_args, schemas::Message};
+
+    /// Formats the messages of the `history` variable and counts the calls.
+    struct CountingPrompter {
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl FormatPrompter for CountingPrompter {
+        fn format_prompt(&self, input_variables: PromptArgs) -> Result<PromptValue, PromptError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let history = input_variables.get("history").cloned().unwrap_or_default();
+            let messages: Vec<Message> = serde_json::from_value(history).unwrap_or_default();
+            Ok(PromptValue::from_messages(messages))
+        }
+
+        fn get_input_variables(&self) -> Vec<String> {
+            vec!["history".into()]
+        }
+    }
+
+    #[test]
+    fn test_reuses_prompts_of_the_same_variables() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let prompt = CachedPrompter::new(CountingPrompter {
+            calls: calls.clone(),
+        });
+        let mut history = vec![Message::new_human_message("Hi")];
+
+        prompt
+            .format_prompt(prompt_args! {"history" => history.clone(), "name" => "Luis"})
+            .unwrap();
+        let cached = prompt
+            .format_prompt(prompt_args! {"name" => "Luis", "history" => history.clone()})
+            .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(cached.to_chat_messages().len(), 1);
+
+        history.push(Message::new_ai_message("Hello"));
+        let formatted = prompt
+            .format_prompt(prompt_args! {"history" => history, "name" => "Luis"})
+            .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(formatted.to_chat_messages().len(), 2);
+    }
+
+    #[test]
+    fn test_drops_the_oldest_prompt_when_full() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let prompt = CachedPrompter::new(CountingPrompter {
+            calls: calls.clone(),
+        })
+        .with_capacity(1);
+
+        prompt.format_prompt(prompt_args! {"a" => 1}).unwrap();
+        prompt.format_prompt(prompt_args! {"a" => 2}).unwrap();
+        prompt.format_prompt(prompt_args! {"a" => 1}).unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_hash_ignores_the_order_of_object_keys() {
+        let first = prompt_args! {"tool" => json!({"name": "search", "args": [1, 2]})};
+        let second = prompt_args! {"tool" => json!({"args": [1, 2], "name": "search"})};
+        let other = prompt_args! {"tool" => json!({"args": [2, 1], "name": "search"})};
+
+        assert_eq!(hash_args(&first), hash_args(&second));
+        assert_ne!(hash_args(&first), hash_args(&other));
+    }
+}
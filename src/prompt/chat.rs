@@ -1,11 +1,60 @@
-use crate::schemas::{messages::Message, prompt::PromptValue};
+use serde_json::Value;
+
+use crate::schemas::{
+    messages::{ImageContent, Message},
+    prompt::PromptValue,
+};
 
 use super::{
     FormatPrompter, MessageFormatter, PromptArgs, PromptError, PromptFromatter, PromptTemplate,
 };
 
+/// Input variable with the images of the human message.
+pub const IMAGES_INPUT_VARIABLE: &str = "images";
+
+/// Reads the images of the `images` input variable. It can hold an image or a list of
+/// images, each one an `http(s)` url, a `data:image/...` uri or an `ImageContent`.
+///
+/// # Usage
+/// ```rust,ignore
+/// let input_variables = prompt_args! {
+///     "input" => "What is in this image?",
+///     "images" => vec!["https://example.com/cat.png"],
+/// };
+/// let images = images_from_args(&input_variables)?;
+/// ```
+pub fn images_from_args(input_variables: &PromptArgs) -> Result<Vec<ImageContent>, PromptError> {
+    let values = match input_variables.get(IMAGES_INPUT_VARIABLE) {
+        None | Some(Value::Null) => return Ok(Vec::new()),
+        Some(Value::Array(values)) => values.clone(),
+        Some(value) => vec![value.clone()],
+    };
+
+    values
+        .into_iter()
+        .map(|value| {
+            let image = match value {
+                Value::String(url) => ImageContent::from(url),
+                value => serde_json::from_value::<ImageContent>(value)?,
+            };
+            let url = image.image_url.as_str();
+            if !(url.starts_with("http://")
+                || url.starts_with("https://")
+                || url.starts_with("data:image/"))
+            {
+                return Err(PromptError::OtherError(format!(
+                    "Invalid image, expected an url or a data uri: {}",
+                    url.chars().take(50).collect::<String>()
+                )));
+            }
+            Ok(image)
+        })
+        .collect()
+}
+
 /// Struct `HumanMessagePromptTemplate` defines a template for creating human (user) messages.
-/// `PromptTemplate` is used to generate the message template.
+/// `PromptTemplate` is used to generate the message template. The images of the `images`
+/// input variable are added to the message, see [`images_from_args`].
 ///
 /// # Usage
 /// ```rust,ignore
@@ -26,7 +75,9 @@ impl HumanMessagePromptTemplate {
 }
 impl MessageFormatter for HumanMessagePromptTemplate {
     fn format_messages(&self, input_variables: PromptArgs) -> Result<Vec<Message>, PromptError> {
-        let message = Message::new_human_message(self.prompt.format(input_variables)?);
+        let images = images_from_args(&input_variables)?;
+        let message =
+            Message::new_human_message(self.prompt.format(input_variables)?).with_images(images);
         log::debug!("message: {:?}", message);
         Ok(vec![message])
     }
@@ -352,4 +403,31 @@ mod tests {
         assert_eq!(formatted_messages[2].content, "Placeholder message 1");
         assert_eq!(formatted_messages[3].content, "Placeholder message 2");
     }
+
+    #[test]
+    fn test_human_message_template_with_images() {
+        use crate::prompt::{chat::HumanMessagePromptTemplate, MessageFormatter};
+
+        let template =
+            HumanMessagePromptTemplate::new(template_fstring!("Describe: {input}", "input"));
+        let messages = template
+            .format_messages(prompt_args! {
+                "input" => "these images",
+                "images" => vec!["https://example.com/cat.png", "data:image/png;base64,iVBORw0KGgo="],
+            })
+            .unwrap();
+        let images = messages[0].images.as_ref().unwrap();
+        assert_eq!(messages[0].content, "Describe: these images");
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[1].image_url, "data:image/png;base64,iVBORw0KGgo=");
+
+        let messages = template
+            .format_messages(prompt_args! { "input" => "no images" })
+            .unwrap();
+        assert!(messages[0].images.is_none());
+
+        assert!(template
+            .format_messages(prompt_args! { "input" => "x", "images" => "cat.png" })
+            .is_err());
+    }
 }
@@ -9,6 +9,12 @@ pub enum PromptError {
     #[error("Serialization error: {0}")]
     SerializationError(#[from] SerdeJsonError),
 
+    #[error("Template error: {0}")]
+    TemplateError(#[from] minijinja::Error),
+
+    #[error("IO error: {0}")]
+    IOError(#[from] std::io::Error),
+
     #[error("Error: {0}")]
     OtherError(String),
 }
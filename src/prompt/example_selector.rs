@@ -0,0 +1,172 @@
+use crate::language_models::TokenCounter;
+
+use super::{PromptArgs, PromptError, PromptFromatter, PromptTemplate};
+
+/// Chooses the examples of a few-shot prompt for the input variables of the prompt.
+pub trait ExampleSelector: Send + Sync {
+    fn add_example(&mut self, example: PromptArgs);
+
+    fn select_examples(&self, input_variables: &PromptArgs)
+        -> Result<Vec<PromptArgs>, PromptError>;
+}
+
+/// Which examples the `LengthBasedExampleSelector` tries first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExampleOrder {
+    /// The examples in the order they were added, the selection stops at the first example
+    /// that doesn't fit.
+    #[default]
+    InOrder,
+    /// The shortest examples first, to fit as many as possible. The selected examples are
+    /// returned in the order they were added.
+    ShortestFirst,
+}
+
+/// Selects the examples that fit in `max_tokens` with the input, each example counted
+/// formatted with the example prompt. At least one example is selected when there are
+/// examples, even if it doesn't fit.
+pub struct LengthBasedExampleSelector {
+    examples: Vec<PromptArgs>,
+    example_prompt: PromptTemplate,
+    max_tokens: usize,
+    token_counter: TokenCounter,
+    order: ExampleOrder,
+}
+
+impl LengthBasedExampleSelector {
+    pub fn new(
+        examples: Vec<PromptArgs>,
+        example_prompt: PromptTemplate,
+        max_tokens: usize,
+    ) -> Self {
+        Self {
+            examples,
+            example_prompt,
+            max_tokens,
+            token_counter: TokenCounter::default(),
+            order: ExampleOrder::default(),
+        }
+    }
+
+    pub fn with_token_counter(mut self, token_counter: TokenCounter) -> Self {
+        self.token_counter = token_counter;
+        self
+    }
+
+    pub fn with_order(mut self, order: ExampleOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    fn count_example(&self, example: &PromptArgs) -> Result<usize, PromptError> {
+        let formatted = self.example_prompt.format(example.clone())?;
+        Ok(self.token_counter.count(&formatted))
+    }
+
+    /// Tokens of the text values of the input.
+    fn count_input(&self, input_variables: &PromptArgs) -> usize {
+        let input = input_variables
+            .values()
+            .filter_map(|value| value.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.token_counter.count(&input)
+    }
+}
+
+impl ExampleSelector for LengthBasedExampleSelector {
+    fn add_example(&mut self, example: PromptArgs) {
+        self.examples.push(example);
+    }
+
+    fn select_examples(
+        &self,
+        input_variables: &PromptArgs,
+    ) -> Result<Vec<PromptArgs>, PromptError> {
+        let mut candidates = Vec::with_capacity(self.examples.len());
+        for (i, example) in self.examples.iter().enumerate() {
+            candidates.push((i, self.count_example(example)?));
+        }
+        if self.order == ExampleOrder::ShortestFirst {
+            candidates.sort_by_key(|&(_, tokens)| tokens);
+        }
+
+        let mut remaining = self
+            .max_tokens
+            .saturating_sub(self.count_input(input_variables));
+        let mut selected = Vec::new();
+        for (i, tokens) in candidates {
+            if tokens > remaining && !selected.is_empty() {
+                break;
+            }
+            remaining = remaining.saturating_sub(tokens);
+            selected.push(i);
+        }
+
+        selected.sort_unstable();
+        Ok(selected
+            .into_iter()
+            .map(|i| self.examples[i].clone())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{prompt_args, template_fstring};
+
+    #[test]
+    fn test_length_based_example_selector() {
+        let example_prompt = template_fstring!("Q: {question}\nA: {answer}", "question", "answer");
+        let examples = vec![
+            prompt_args! {"question" => "What is the capital of France?", "answer" => "Paris"},
+            prompt_args! {
+                "question" => "Largest planet of the solar system?",
+                "answer" => "Jupiter, by far the largest of the eight planets",
+            },
+            prompt_args! {"question" => "2 + 2?", "answer" => "4"},
+        ];
+        let counter = TokenCounter::default();
+        let tokens: Vec<usize> = examples
+            .iter()
+            .map(|e| counter.count(&example_prompt.format(e.clone()).unwrap()))
+            .collect();
+        let questions = |examples: Vec<PromptArgs>| {
+            examples
+                .into_iter()
+                .map(|e| e["question"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        let input = prompt_args! {"question" => "Capital of Spain?"};
+        let input_tokens = counter.count("Capital of Spain?");
+
+        // The second example doesn't fit, the selection stops there
+        let mut selector = LengthBasedExampleSelector::new(
+            examples.clone(),
+            example_prompt.clone(),
+            input_tokens + tokens[0] + tokens[2],
+        );
+        let selected = selector.select_examples(&input).unwrap();
+        assert_eq!(questions(selected), vec!["What is the capital of France?"]);
+
+        selector = selector.with_order(ExampleOrder::ShortestFirst);
+        let selected = selector.select_examples(&input).unwrap();
+        assert_eq!(
+            questions(selected),
+            vec!["What is the capital of France?", "2 + 2?"]
+        );
+
+        // The first example is kept even if it doesn't fit
+        let mut selector = LengthBasedExampleSelector::new(examples, example_prompt, 1);
+        let selected = selector.select_examples(&input).unwrap();
+        assert_eq!(questions(selected), vec!["What is the capital of France?"]);
+
+        selector.add_example(prompt_args! {"question" => "?", "answer" => "."});
+        let selected = selector
+            .with_order(ExampleOrder::ShortestFirst)
+            .select_examples(&input)
+            .unwrap();
+        assert_eq!(questions(selected), vec!["?"]);
+    }
+}
@@ -1,11 +1,15 @@
+mod cached;
 mod chat;
 mod error;
+mod example_selector;
 mod prompt;
 
 use std::collections::HashMap;
 
+pub use cached::*;
 pub use chat::*;
 pub use error::*;
+pub use example_selector::*;
 pub use prompt::*;
 use serde_json::Value;
 
@@ -1,3 +1,8 @@
+use std::{fs, path::Path};
+
+use minijinja::{Environment, UndefinedBehavior};
+use regex::Regex;
+
 use crate::schemas::{messages::Message, prompt::PromptValue};
 
 use super::{FormatPrompter, PromptArgs, PromptError, PromptFromatter};
@@ -23,6 +28,144 @@ impl PromptTemplate {
             format,
         }
     }
+
+    /// Creates a template with the variables found in it, in the order they first appear.
+    ///
+    /// In f-strings the variables are `{name}`, the escaped braces `{{` and `}}` are not
+    /// variables. In Jinja2 they are the names the template reads without defining them, in
+    /// expressions like `{{ name }}` or `{{ user.name|upper }}` and in blocks like
+    /// `{% for note in notes %}`, but not the names it binds, like the item of the loop.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let template = PromptTemplate::from_str(
+    ///     "Translate {text} to {language}, answer as {{\"translation\": ...}}",
+    ///     TemplateFormat::FString,
+    /// );
+    /// assert_eq!(template.variables(), vec!["text", "language"]);
+    /// ```
+    pub fn from_str(template: &str, format: TemplateFormat) -> Self {
+        let variables = match format {
+            TemplateFormat::FString => fstring_variables(template),
+            TemplateFormat::Jinja2 => jinja2_variables(template),
+        };
+        Self::new(template.to_string(), variables, format)
+    }
+
+    /// Reads the template from a file, see `from_str`.
+    pub fn from_file<P: AsRef<Path>>(path: P, format: TemplateFormat) -> Result<Self, PromptError> {
+        let template = fs::read_to_string(path)?;
+        Ok(Self::from_str(&template, format))
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+fn push_unique(variables: &mut Vec<String>, name: &str) {
+    if !variables.iter().any(|v| v == name) {
+        variables.push(name.to_string());
+    }
+}
+
+/// A part of an f-string template.
+enum FStringPiece<'a> {
+    Text(&'a str),
+    Variable(&'a str),
+}
+
+/// Splits an f-string template in its text and its `{name}`. The escaped braces `{{` and
+/// `}}` are text with a single brace, and so are the braces that don't hold a name, like the
+/// ones of a JSON example.
+fn fstring_pieces(template: &str) -> Vec<FStringPiece<'_>> {
+    let mut pieces = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find(['{', '}']) {
+        pieces.push(FStringPiece::Text(&rest[..start]));
+        let brace = &rest[start..start + 1];
+        let after = &rest[start + 1..];
+        if let Some(escaped) = after.strip_prefix(brace) {
+            pieces.push(FStringPiece::Text(brace));
+            rest = escaped;
+            continue;
+        }
+        if brace == "{" {
+            if let Some(end) = after.find(['{', '}']) {
+                let name = after[..end].trim();
+                if after[end..].starts_with('}') && is_identifier(name) {
+                    pieces.push(FStringPiece::Variable(name));
+                    rest = &after[end + 1..];
+                    continue;
+                }
+            }
+        }
+        pieces.push(FStringPiece::Text(brace));
+        rest = after;
+    }
+    pieces.push(FStringPiece::Text(rest));
+    pieces
+}
+
+/// The `{name}` of the template, in the order they first appear.
+fn fstring_variables(template: &str) -> Vec<String> {
+    let mut variables = Vec::new();
+    for piece in fstring_pieces(template) {
+        if let FStringPiece::Variable(name) = piece {
+            push_unique(&mut variables, name);
+        }
+    }
+    variables
+}
+
+/// Replaces the `{name}` of the template by their value, the names without a value are
+/// kept as they are.
+fn render_fstring(template: &str, input_variables: &PromptArgs) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    for piece in fstring_pieces(template) {
+        match piece {
+            FStringPiece::Text(text) => rendered.push_str(text),
+            FStringPiece::Variable(name) => match input_variables.get(name) {
+                Some(serde_json::Value::String(s)) => rendered.push_str(s),
+                Some(value) => rendered.push_str(&value.to_string()),
+                None => {
+                    rendered.push('{');
+                    rendered.push_str(name);
+                    rendered.push('}');
+                }
+            },
+        }
+    }
+    rendered
+}
+
+/// The variables the template reads without defining them, in the order they first appear
+/// in its expressions and blocks. The names bound by the template, like the item of a `for`
+/// loop, and the globals of Jinja2 are not variables. Only the root of `{{ user.name }}` is.
+/// A template that doesn't parse has no variables, formatting it returns the error.
+fn jinja2_variables(template: &str) -> Vec<String> {
+    let env = Environment::new();
+    let Ok(parsed) = env.template_from_str(template) else {
+        return Vec::new();
+    };
+    let globals: Vec<&str> = env.globals().map(|(name, _)| name).collect();
+    let mut variables: Vec<String> = parsed
+        .undeclared_variables(false)
+        .into_iter()
+        .filter(|name| !globals.contains(&name.as_str()))
+        .collect();
+
+    let tags = Regex::new(r"(?s)\{\{.*?\}\}|\{%.*?%\}").expect("valid regex");
+    let first_use = |name: &str| {
+        let word = Regex::new(&format!(r"\b{}\b", regex::escape(name))).expect("valid regex");
+        tags.find_iter(template)
+            .find_map(|tag| word.find(tag.as_str()).map(|m| tag.start() + m.start()))
+            .unwrap_or(usize::MAX)
+    };
+    variables.sort_by_cached_key(|name| (first_use(name), name.clone()));
+    variables
 }
 
 //PromptTemplate will be default transformed to an Human Input when used as FromatPrompter
@@ -55,16 +198,17 @@ impl PromptFromatter for PromptTemplate {
             }
         }
 
-        for (key, value) in input_variables {
-            let key = match self.format {
-                TemplateFormat::FString => format!("{{{}}}", key),
-                TemplateFormat::Jinja2 => format!("{{{{{}}}}}", key),
-            };
-            let value_str = match &value {
-                serde_json::Value::String(s) => s.clone(),
-                _ => value.to_string(),
-            };
-            prompt = prompt.replace(&key, &value_str);
+        match self.format {
+            TemplateFormat::FString => {
+                prompt = render_fstring(&prompt, &input_variables);
+            }
+            TemplateFormat::Jinja2 => {
+                let mut env = Environment::new();
+                env.set_keep_trailing_newline(true);
+                // A variable without a value is an error, not an empty string
+                env.set_undefined_behavior(UndefinedBehavior::Strict);
+                prompt = env.render_str(&prompt, &input_variables)?;
+            }
         }
 
         log::debug!("Formatted prompt: {}", prompt);
@@ -155,7 +299,6 @@ macro_rules! template_jinja2 {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::prompt_args;
 
     #[test]
     fn should_format_jinja2_template() {
@@ -198,6 +341,106 @@ mod tests {
         assert_eq!(result.unwrap(), "Hello world!");
     }
 
+    #[test]
+    fn test_from_str_detects_the_variables() {
+        let template = PromptTemplate::from_str(
+            "Translate {text} to { language }, as {{\"translation\": \"...\"}}. {text}",
+            TemplateFormat::FString,
+        );
+        assert_eq!(template.variables(), vec!["text", "language"]);
+
+        let template = PromptTemplate::from_str(
+            "{# The notes #}Hello {{ name }}!\n\
+            {% for note in notes %}- {{note}}\n{% endfor %}\
+            {% set total = 3 %}{{ total }} {{- topic -}}",
+            TemplateFormat::Jinja2,
+        );
+        assert_eq!(template.variables(), vec!["name", "notes", "topic"]);
+        let formatted = template
+            .format(prompt_args! {"name" => "Ana", "topic" => "rust", "notes" => ["a", "b"]})
+            .unwrap();
+        assert_eq!(formatted, "Hello Ana!\n- a\n- b\n3rust");
+
+        let template = PromptTemplate::from_str(
+            "{% if formal %}Dear {{ user.name|upper }}{% else %}Hi {{ user.name }}{% endif %}, \
+            {% for i in range(count) %}{{ loop.index }}{{ items[i] }}{% endfor %}",
+            TemplateFormat::Jinja2,
+        );
+        assert_eq!(
+            template.variables(),
+            vec!["formal", "user", "count", "items"]
+        );
+    }
+
+    #[test]
+    fn test_jinja2_undefined_variable_is_an_error() {
+        let template = template_jinja2!("Hi {{ name }}, {{ greeting }}", "name");
+        let result = template.format(prompt_args! {"name" => "Ana"});
+        assert!(matches!(result, Err(PromptError::TemplateError(_))));
+
+        let template = template_jinja2!("{% if formal %}Dear {% endif %}{{ name }}", "name");
+        assert!(template.format(prompt_args! {"name" => "Ana"}).is_err());
+    }
+
+    #[test]
+    fn test_fstring_escaped_and_whole_names() {
+        let template = template_fstring!(
+            "Hi {name} ({name_full}), answer as {{\"greeting\": {{\"to\": \"{name}\"}}}}",
+            "name",
+            "name_full"
+        );
+        let formatted = template
+            .format(prompt_args! {"name" => "Ana", "name_full" => "Ana Diaz"})
+            .unwrap();
+        assert_eq!(
+            formatted,
+            "Hi Ana (Ana Diaz), answer as {\"greeting\": {\"to\": \"Ana\"}}"
+        );
+
+        let template = template_fstring!(
+            "Reply like {\"answer\": 42} about {topic}, not {other}",
+            "topic"
+        );
+        let formatted = template.format(prompt_args! {"topic" => "{rust}"}).unwrap();
+        assert_eq!(
+            formatted,
+            "Reply like {\"answer\": 42} about {rust}, not {other}"
+        );
+    }
+
+    #[test]
+    fn test_jinja2_renders_loops_and_conditionals() {
+        let template = template_jinja2!(
+            "{% if urgent %}URGENT: {% endif %}{{ title }}\n\
+            {% for item in items %}{{ loop.index }}. {{ item.name }}\n{% endfor %}",
+            "urgent",
+            "title",
+            "items"
+        );
+        let items = serde_json::json!([{"name": "milk"}, {"name": "eggs"}]);
+        let formatted = template
+            .format(prompt_args! {"urgent" => true, "title" => "Groceries", "items" => items})
+            .unwrap();
+        assert_eq!(formatted, "URGENT: Groceries\n1. milk\n2. eggs\n");
+
+        // Simple templates render as before
+        let template = template_jinja2!("Hi {{name}}, {{ age }} years.\n", "name", "age");
+        let formatted = template
+            .format(prompt_args! {"name" => "Ana", "age" => 31})
+            .unwrap();
+        assert_eq!(formatted, "Hi Ana, 31 years.\n");
+
+        assert!(matches!(
+            template.format(prompt_args! {"name" => "Ana"}),
+            Err(PromptError::MissingVariable(_))
+        ));
+        assert!(matches!(
+            PromptTemplate::new("{% if %}".into(), vec![], TemplateFormat::Jinja2)
+                .format(prompt_args! {}),
+            Err(PromptError::TemplateError(_))
+        ));
+    }
+
     #[test]
     fn should_prompt_macro_work() {
         let args = prompt_args! {};
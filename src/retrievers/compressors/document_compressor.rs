@@ -0,0 +1,206 @@
+use std::error::Error;
+
+use async_trait::async_trait;
+
+use crate::schemas::{Document, Retriever, RetrieverError};
+
+/// Transforms the documents retrieved for a query: drops the irrelevant ones, reorders
+/// them or shortens their content.
+#[async_trait]
+pub trait DocumentCompressor: Send + Sync {
+    async fn compress_documents(
+        &self,
+        documents: Vec<Document>,
+        query: &str,
+    ) -> Result<Vec<Document>, Box<dyn Error>>;
+}
+
+impl<C> From<C> for Box<dyn DocumentCompressor>
+where
+    C: DocumentCompressor + 'static,
+{
+    fn from(compressor: C) -> Self {
+        Box::new(compressor)
+    }
+}
+
+/// Applies compressors one after the other, each one gets the documents of the previous
+/// one in the order it returned them. Once a compressor returns no documents, the next
+/// ones are not called.
+/// # Example
+/// ```rust,ignore
+/// let pipeline = DocumentCompressorPipeline::new()
+///     .add_compressor(EmbeddingsFilter::new(OpenAiEmbedder::default()))
+///     .add_compressor(LLMReranker::new(OpenAI::default()).with_top_n(5))
+///     .add_compressor(LLMChainExtractor::new(OpenAI::default()));
+/// let retriever = ContextualCompressionRetriever::new(Retriever::new(store, 20), pipeline);
+/// ```
+#[derive(Default)]
+pub struct DocumentCompressorPipeline {
+    compressors: Vec<Box<dyn DocumentCompressor>>,
+}
+
+impl DocumentCompressorPipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_compressor<C: Into<Box<dyn DocumentCompressor>>>(mut self, compressor: C) -> Self {
+        self.compressors.push(compressor.into());
+        self
+    }
+}
+
+#[async_trait]
+impl DocumentCompressor for DocumentCompressorPipeline {
+    async fn compress_documents(
+        &self,
+        mut documents: Vec<Document>,
+        query: &str,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        for compressor in self.compressors.iter() {
+            if documents.is_empty() {
+                log::debug!("No documents left, the remaining compressors are skipped");
+                break;
+            }
+            documents = compressor.compress_documents(documents, query).await?;
+        }
+        Ok(documents)
+    }
+}
+
+/// Retrieves the documents with a base retriever and compresses them, usually with a
+/// `DocumentCompressorPipeline`. Retrieve more documents than needed from the base
+/// retriever and let the compressors keep the relevant ones.
+pub struct ContextualCompressionRetriever {
+    retriever: Box<dyn Retriever>,
+    compressor: Box<dyn DocumentCompressor>,
+}
+
+impl ContextualCompressionRetriever {
+    pub fn new<R: Into<Box<dyn Retriever>>, C: Into<Box<dyn DocumentCompressor>>>(
+        retriever: R,
+        compressor: C,
+    ) -> Self {
+        Self {
+            retriever: retriever.into(),
+            compressor: compressor.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Retriever for ContextualCompressionRetriever {
+    async fn get_relevant_documents(&self, query: &str) -> Result<Vec<Document>, RetrieverError> {
+        let documents = self.retriever.get_relevant_documents(query).await?;
+        if documents.is_empty() {
+            return Ok(documents);
+        }
+        self.compressor
+            .compress_documents(documents, query)
+            .await
+            .map_err(|e| RetrieverError::OtherError(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    /// Keeps the documents containing a word, counting its calls.
+    struct ContainsFilter {
+        word: &'static str,
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl DocumentCompressor for ContainsFilter {
+        async fn compress_documents(
+            &self,
+            documents: Vec<Document>,
+            _query: &str,
+        ) -> Result<Vec<Document>, Box<dyn Error>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(documents
+                .into_iter()
+                .filter(|d| d.page_content.contains(self.word))
+                .collect())
+        }
+    }
+
+    struct Reverse {}
+
+    #[async_trait]
+    impl DocumentCompressor for Reverse {
+        async fn compress_documents(
+            &self,
+            mut documents: Vec<Document>,
+            _query: &str,
+        ) -> Result<Vec<Document>, Box<dyn Error>> {
+            documents.reverse();
+            Ok(documents)
+        }
+    }
+
+    struct RetrieverTest {}
+
+    #[async_trait]
+    impl Retriever for RetrieverTest {
+        async fn get_relevant_documents(
+            &self,
+            _query: &str,
+        ) -> Result<Vec<Document>, RetrieverError> {
+            Ok(["a b", "a c", "b c", "a b c"]
+                .map(Document::new)
+                .into_iter()
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_keeps_the_order_of_each_stage() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let retriever = ContextualCompressionRetriever::new(
+            RetrieverTest {},
+            DocumentCompressorPipeline::new()
+                .add_compressor(ContainsFilter {
+                    word: "a",
+                    calls: calls.clone(),
+                })
+                .add_compressor(Reverse {})
+                .add_compressor(ContainsFilter {
+                    word: "b",
+                    calls: calls.clone(),
+                }),
+        );
+        let documents = retriever.get_relevant_documents("query").await.unwrap();
+        let contents: Vec<&str> = documents.iter().map(|d| d.page_content.as_str()).collect();
+        assert_eq!(contents, vec!["a b c", "a b"]);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_pipeline_stops_when_no_documents_are_left() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let pipeline = DocumentCompressorPipeline::new()
+            .add_compressor(ContainsFilter {
+                word: "z",
+                calls: calls.clone(),
+            })
+            .add_compressor(ContainsFilter {
+                word: "a",
+                calls: calls.clone(),
+            });
+        let documents = pipeline
+            .compress_documents(vec![Document::new("a b")], "query")
+            .await
+            .unwrap();
+        assert!(documents.is_empty());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
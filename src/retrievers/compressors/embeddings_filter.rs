@@ -0,0 +1,137 @@
+use std::error::Error;
+
+use async_trait::async_trait;
+
+use crate::{
+    embedding::embedder_trait::Embedder, schemas::Document,
+    semantic_router::utils::cosine_similarity,
+};
+
+use super::DocumentCompressor;
+
+/// Drops the documents whose embedding is not similar enough to the embedding of the
+/// query. The documents keep their order, their `score` is set to the cosine similarity.
+///
+/// The embeddings the vector store returned with the documents are reused, see
+/// `VecStoreOptions::with_include_embeddings`, the other documents are embedded.
+pub struct EmbeddingsFilter {
+    embedder: Box<dyn Embedder>,
+    similarity_threshold: f64,
+}
+
+impl EmbeddingsFilter {
+    pub fn new<E: Embedder + 'static>(embedder: E) -> Self {
+        Self {
+            embedder: Box::new(embedder),
+            similarity_threshold: 0.76,
+        }
+    }
+
+    /// Minimum cosine similarity of the documents kept, 0.76 by default.
+    pub fn with_similarity_threshold(mut self, similarity_threshold: f64) -> Self {
+        self.similarity_threshold = similarity_threshold;
+        self
+    }
+}
+
+#[async_trait]
+impl DocumentCompressor for EmbeddingsFilter {
+    async fn compress_documents(
+        &self,
+        documents: Vec<Document>,
+        query: &str,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let query_embedding = self.embedder.embed_query(query).await?;
+
+        let embeddings = embed_documents(self.embedder.as_ref(), &documents).await?;
+
+        let mut filtered = Vec::with_capacity(documents.len());
+        for (mut document, embedding) in documents.into_iter().zip(embeddings) {
+            let similarity = cosine_similarity(&query_embedding, &embedding);
+            if similarity >= self.similarity_threshold {
+                document.score = similarity;
+                filtered.push(document);
+            }
+        }
+        Ok(filtered)
+    }
+}
+
+/// The embeddings of the documents, reusing the ones they have. The others are embedded
+/// in a single call.
+pub(crate) async fn embed_documents(
+    embedder: &dyn Embedder,
+    documents: &[Document],
+) -> Result<Vec<Vec<f64>>, Box<dyn Error>> {
+    let missing: Vec<String> = documents
+        .iter()
+        .filter(|d| d.embedding.is_none())
+        .map(|d| d.page_content.clone())
+        .collect();
+    let mut missing_embeddings = if missing.is_empty() {
+        Vec::new()
+    } else {
+        embedder.embed_documents(&missing).await?
+    }
+    .into_iter();
+
+    let mut embeddings = Vec::with_capacity(documents.len());
+    for document in documents {
+        let embedding = match &document.embedding {
+            Some(embedding) => embedding.clone(),
+            None => missing_embeddings
+                .next()
+                .ok_or("The embedder returned fewer embeddings than documents")?,
+        };
+        embeddings.push(embedding);
+    }
+    Ok(embeddings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedding::EmbedderError;
+
+    /// Embeds the texts as the count of `a` and `b` they contain.
+    struct CountEmbedder {}
+
+    #[async_trait]
+    impl Embedder for CountEmbedder {
+        async fn embed_documents(
+            &self,
+            documents: &[String],
+        ) -> Result<Vec<Vec<f64>>, EmbedderError> {
+            let mut embeddings = Vec::new();
+            for document in documents {
+                embeddings.push(self.embed_query(document).await?);
+            }
+            Ok(embeddings)
+        }
+
+        async fn embed_query(&self, text: &str) -> Result<Vec<f64>, EmbedderError> {
+            Ok(vec![
+                text.matches('a').count() as f64,
+                text.matches('b').count() as f64,
+            ])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_embeddings_filter() {
+        let filter = EmbeddingsFilter::new(CountEmbedder {}).with_similarity_threshold(0.9);
+        let mut stored = Document::new("stored");
+        stored.embedding = Some(vec![1.0, 0.1]);
+        let documents = vec![
+            Document::new("aaa"),
+            Document::new("bbb"),
+            stored,
+            Document::new("aab"),
+        ];
+
+        let filtered = filter.compress_documents(documents, "a").await.unwrap();
+        let contents: Vec<&str> = filtered.iter().map(|d| d.page_content.as_str()).collect();
+        assert_eq!(contents, vec!["aaa", "stored"]);
+        assert_eq!(filtered[0].score, 1.0);
+    }
+}
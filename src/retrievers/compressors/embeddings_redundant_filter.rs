@@ -0,0 +1,106 @@
+use std::error::Error;
+
+use async_trait::async_trait;
+
+use crate::{
+    embedding::embedder_trait::Embedder, schemas::Document,
+    semantic_router::utils::cosine_similarity,
+};
+
+use super::{embed_documents, DocumentCompressor};
+
+/// Drops the documents that are near duplicates of a document before them. Since the
+/// documents come ranked, the highest ranked document of each group of duplicates is kept.
+/// The documents without embedding are embedded in a single call.
+pub struct EmbeddingsRedundantFilter {
+    embedder: Box<dyn Embedder>,
+    similarity_threshold: f64,
+}
+
+impl EmbeddingsRedundantFilter {
+    pub fn new<E: Embedder + 'static>(embedder: E) -> Self {
+        Self {
+            embedder: Box::new(embedder),
+            similarity_threshold: 0.95,
+        }
+    }
+
+    /// Cosine similarity above which a document is a duplicate, 0.95 by default.
+    pub fn with_similarity_threshold(mut self, similarity_threshold: f64) -> Self {
+        self.similarity_threshold = similarity_threshold;
+        self
+    }
+}
+
+#[async_trait]
+impl DocumentCompressor for EmbeddingsRedundantFilter {
+    async fn compress_documents(
+        &self,
+        documents: Vec<Document>,
+        _query: &str,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let embeddings = embed_documents(self.embedder.as_ref(), &documents).await?;
+
+        let mut kept: Vec<(Document, Vec<f64>)> = Vec::with_capacity(documents.len());
+        for (document, embedding) in documents.into_iter().zip(embeddings) {
+            let duplicate = kept.iter().any(|(_, kept_embedding)| {
+                cosine_similarity(kept_embedding, &embedding) > self.similarity_threshold
+            });
+            if !duplicate {
+                kept.push((document, embedding));
+            }
+        }
+        Ok(kept.into_iter().map(|(document, _)| document).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+    use crate::embedding::EmbedderError;
+
+    /// Embeds the texts as the count of `a` and `b` they contain, counting the batches.
+    struct CountEmbedder {
+        batches: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Embedder for CountEmbedder {
+        async fn embed_documents(
+            &self,
+            documents: &[String],
+        ) -> Result<Vec<Vec<f64>>, EmbedderError> {
+            self.batches.fetch_add(1, Ordering::SeqCst);
+            Ok(documents
+                .iter()
+                .map(|d| vec![d.matches('a').count() as f64, d.matches('b').count() as f64])
+                .collect())
+        }
+
+        async fn embed_query(&self, _text: &str) -> Result<Vec<f64>, EmbedderError> {
+            unimplemented!()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_redundant_documents_are_dropped() {
+        let batches = Arc::new(AtomicUsize::new(0));
+        let filter = EmbeddingsRedundantFilter::new(CountEmbedder {
+            batches: batches.clone(),
+        });
+        let documents = ["ab", "b", "aabb", "bb", "a"]
+            .map(Document::new)
+            .into_iter()
+            .collect();
+
+        let filtered = filter.compress_documents(documents, "").await.unwrap();
+        let contents: Vec<&str> = filtered.iter().map(|d| d.page_content.as_str()).collect();
+        assert_eq!(contents, vec!["ab", "b", "a"]);
+        assert_eq!(batches.load(Ordering::SeqCst), 1);
+    }
+}
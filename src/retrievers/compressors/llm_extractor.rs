@@ -0,0 +1,67 @@
+use std::error::Error;
+
+use async_trait::async_trait;
+use futures::future::join_all;
+
+use crate::{language_models::llm::LLM, schemas::Document};
+
+use super::DocumentCompressor;
+
+const NO_OUTPUT: &str = "NO_OUTPUT";
+const EXTRACT_PROMPT: &str = r#"Given the following query and context, extract as is any part of the context that is relevant to answer the query. Don't change the extracted parts.
+If none of the context is relevant, answer NO_OUTPUT.
+
+Query: {query}
+
+Context:
+>>>
+{context}
+>>>
+
+Extracted relevant parts:"#;
+
+/// Asks the LLM to extract the parts of each document relevant to the query, the
+/// documents without relevant parts are dropped. The documents are compressed
+/// concurrently and keep their order.
+pub struct LLMChainExtractor {
+    llm: Box<dyn LLM>,
+}
+
+impl LLMChainExtractor {
+    pub fn new<L: Into<Box<dyn LLM>>>(llm: L) -> Self {
+        Self { llm: llm.into() }
+    }
+
+    async fn extract(&self, document: &Document, query: &str) -> Result<String, String> {
+        let prompt = EXTRACT_PROMPT
+            .replace("{query}", query)
+            .replace("{context}", &document.page_content);
+        self.llm
+            .invoke(&prompt)
+            .await
+            .map(|output| output.trim().to_string())
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[async_trait]
+impl DocumentCompressor for LLMChainExtractor {
+    async fn compress_documents(
+        &self,
+        documents: Vec<Document>,
+        query: &str,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let extracts = join_all(documents.iter().map(|d| self.extract(d, query))).await;
+
+        let mut compressed = Vec::with_capacity(documents.len());
+        for (mut document, extract) in documents.into_iter().zip(extracts) {
+            let extract = extract?;
+            if extract.is_empty() || extract == NO_OUTPUT {
+                continue;
+            }
+            document.page_content = extract;
+            compressed.push(document);
+        }
+        Ok(compressed)
+    }
+}
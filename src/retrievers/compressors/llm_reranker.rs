@@ -0,0 +1,116 @@
+use std::error::Error;
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::{language_models::llm::LLM, output_parsers::JsonOutputParser, schemas::Document};
+
+use super::DocumentCompressor;
+
+const RERANK_PROMPT: &str = r#"Rank the following documents by how useful they are to answer the query, the most useful first.
+
+Query: {query}
+
+Documents:
+{documents}
+
+Answer only with a JSON array of the numbers of the useful documents, for example [3, 1]. Leave out the documents that don't help to answer the query."#;
+
+/// Asks the LLM to sort the documents by relevance to the query and keeps the `top_n`
+/// first. The documents the LLM leaves out are dropped. If the answer of the LLM can't be
+/// parsed, the documents are returned in their order.
+pub struct LLMReranker {
+    llm: Box<dyn LLM>,
+    top_n: Option<usize>,
+}
+
+impl LLMReranker {
+    pub fn new<L: Into<Box<dyn LLM>>>(llm: L) -> Self {
+        Self {
+            llm: llm.into(),
+            top_n: None,
+        }
+    }
+
+    /// Maximum number of documents returned.
+    pub fn with_top_n(mut self, top_n: usize) -> Self {
+        self.top_n = Some(top_n);
+        self
+    }
+
+    fn build_prompt(&self, documents: &[Document], query: &str) -> String {
+        let documents = documents
+            .iter()
+            .enumerate()
+            .map(|(i, d)| format!("[{}] {}", i + 1, d.page_content))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        RERANK_PROMPT
+            .replace("{query}", query)
+            .replace("{documents}", &documents)
+    }
+}
+
+/// The 0-based positions of the documents in the ranking, without duplicates or numbers
+/// that are not documents.
+fn parse_ranking(output: &str, num_documents: usize) -> Option<Vec<usize>> {
+    let Ok(Value::Array(ranking)) = JsonOutputParser::new().parse_value(output) else {
+        return None;
+    };
+    let mut positions: Vec<usize> = Vec::with_capacity(ranking.len());
+    for number in ranking.iter().filter_map(Value::as_u64) {
+        let position = number as usize;
+        if (1..=num_documents).contains(&position) && !positions.contains(&(position - 1)) {
+            positions.push(position - 1);
+        }
+    }
+    Some(positions)
+}
+
+#[async_trait]
+impl DocumentCompressor for LLMReranker {
+    async fn compress_documents(
+        &self,
+        documents: Vec<Document>,
+        query: &str,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let output = self
+            .llm
+            .invoke(&self.build_prompt(&documents, query))
+            .await?;
+        let mut ranked = match parse_ranking(&output, documents.len()) {
+            Some(positions) => {
+                let mut documents: Vec<Option<Document>> =
+                    documents.into_iter().map(Some).collect();
+                positions
+                    .into_iter()
+                    .filter_map(|position| documents[position].take())
+                    .collect()
+            }
+            None => {
+                log::warn!("Failed to parse the ranking of the documents: {}", output);
+                documents
+            }
+        };
+        if let Some(top_n) = self.top_n {
+            ranked.truncate(top_n);
+        }
+        Ok(ranked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ranking() {
+        assert_eq!(parse_ranking("[3, 1]", 3), Some(vec![2, 0]));
+        assert_eq!(
+            parse_ranking("```json\n[2, 2, 7, 0, \"1\", 1]\n```", 3),
+            Some(vec![1, 0])
+        );
+        assert_eq!(parse_ranking("[]", 3), Some(vec![]));
+        assert_eq!(parse_ranking("The first one", 3), None);
+    }
+}
@@ -0,0 +1,14 @@
+mod document_compressor;
+pub use document_compressor::*;
+
+mod embeddings_filter;
+pub use embeddings_filter::*;
+
+mod embeddings_redundant_filter;
+pub use embeddings_redundant_filter::*;
+
+mod llm_reranker;
+pub use llm_reranker::*;
+
+mod llm_extractor;
+pub use llm_extractor::*;
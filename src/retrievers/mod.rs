@@ -0,0 +1,11 @@
+mod self_query;
+pub use self_query::*;
+
+mod compressors;
+pub use compressors::*;
+
+mod reranker;
+pub use reranker::*;
+
+mod time_weighted;
+pub use time_weighted::*;
@@ -0,0 +1,164 @@
+use async_trait::async_trait;
+use futures::future::join_all;
+use regex::Regex;
+
+use crate::{
+    language_models::llm::LLM,
+    schemas::{Document, Retriever, RetrieverError},
+};
+
+const SCORE_PROMPT: &str = r#"Rate how relevant the following document is to answer the query, from 0 (not relevant) to 10 (fully answers the query).
+
+Query: {query}
+
+Document:
+>>>
+{document}
+>>>
+
+Answer only with the number."#;
+
+/// Retrieves the documents with a base retriever and asks the LLM to score the relevance
+/// of each one to the query, from 0 to 10. The documents are scored concurrently and
+/// returned by decreasing score, the `score` of each document is set to its score.
+///
+/// A document whose score can't be parsed, or whose scoring call fails, gets a score of 0
+/// and is kept. Unlike `LLMReranker`, which ranks all the documents in a single call, each
+/// document gets its own call.
+/// # Example
+/// ```rust,ignore
+/// let retriever = LLMScoreReranker::new(Retriever::new(store, 20), OpenAI::default()).with_top_n(5);
+/// ```
+pub struct LLMScoreReranker {
+    retriever: Box<dyn Retriever>,
+    llm: Box<dyn LLM>,
+    top_n: Option<usize>,
+}
+
+impl LLMScoreReranker {
+    pub fn new<R: Into<Box<dyn Retriever>>, L: Into<Box<dyn LLM>>>(retriever: R, llm: L) -> Self {
+        Self {
+            retriever: retriever.into(),
+            llm: llm.into(),
+            top_n: None,
+        }
+    }
+
+    /// Maximum number of documents returned.
+    pub fn with_top_n(mut self, top_n: usize) -> Self {
+        self.top_n = Some(top_n);
+        self
+    }
+
+    async fn score(&self, document: &Document, query: &str) -> f64 {
+        let prompt = SCORE_PROMPT
+            .replace("{query}", query)
+            .replace("{document}", &document.page_content);
+        match self.llm.invoke(&prompt).await {
+            Ok(output) => parse_score(&output).unwrap_or_else(|| {
+                log::warn!("Failed to parse the relevance score: {}", output);
+                0.0
+            }),
+            Err(e) => {
+                log::warn!("Failed to score the relevance of a document: {}", e);
+                0.0
+            }
+        }
+    }
+}
+
+/// The first number of the output, clamped between 0 and 10.
+fn parse_score(output: &str) -> Option<f64> {
+    let number = Regex::new(r"\d+(?:\.\d+)?").ok()?;
+    let score: f64 = number.find(output)?.as_str().parse().ok()?;
+    Some(score.clamp(0.0, 10.0))
+}
+
+#[async_trait]
+impl Retriever for LLMScoreReranker {
+    async fn get_relevant_documents(&self, query: &str) -> Result<Vec<Document>, RetrieverError> {
+        let documents = self.retriever.get_relevant_documents(query).await?;
+        let scores = join_all(documents.iter().map(|d| self.score(d, query))).await;
+
+        let mut ranked: Vec<Document> = documents
+            .into_iter()
+            .zip(scores)
+            .map(|(mut document, score)| {
+                document.score = score;
+                document
+            })
+            .collect();
+        // Stable, the documents with the same score keep the order of the base retriever
+        ranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+        if let Some(top_n) = self.top_n {
+            ranked.truncate(top_n);
+        }
+        Ok(ranked)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        language_models::{GenerateResult, LLMError},
+        llm::fake::FakeLLM,
+    };
+
+    /// Answers with the text after `score:` in the document, fails without it.
+    fn score_llm() -> FakeLLM {
+        FakeLLM::new(|messages| {
+            let document = messages[0].content.split(">>>").nth(1).unwrap_or_default();
+            let Some((_, score)) = document.trim().split_once("score:") else {
+                return Err(LLMError::OtherError("No score".to_string()));
+            };
+            Ok(GenerateResult {
+                generation: score.to_string(),
+                ..Default::default()
+            })
+        })
+    }
+
+    struct RetrieverTest {}
+
+    #[async_trait]
+    impl Retriever for RetrieverTest {
+        async fn get_relevant_documents(
+            &self,
+            _query: &str,
+        ) -> Result<Vec<Document>, RetrieverError> {
+            Ok([
+                "a score:3",
+                "b score:Relevance: 9/10",
+                "c",
+                "d score:not relevant",
+                "e score:42",
+            ]
+            .map(Document::new)
+            .into_iter()
+            .collect())
+        }
+    }
+
+    #[test]
+    fn test_parse_score() {
+        assert_eq!(parse_score("7"), Some(7.0));
+        assert_eq!(parse_score("Score: 8.5 out of 10"), Some(8.5));
+        assert_eq!(parse_score("11"), Some(10.0));
+        assert_eq!(parse_score("none"), None);
+    }
+
+    #[tokio::test]
+    async fn test_llm_reranker() {
+        let retriever = LLMScoreReranker::new(RetrieverTest {}, score_llm());
+        let documents = retriever.get_relevant_documents("query").await.unwrap();
+        let contents: Vec<&str> = documents.iter().map(|d| &d.page_content[..1]).collect();
+        assert_eq!(contents, vec!["e", "b", "a", "c", "d"]);
+        assert_eq!(documents[0].score, 10.0);
+        assert_eq!(documents[3].score, 0.0);
+
+        let retriever = LLMScoreReranker::new(RetrieverTest {}, score_llm()).with_top_n(2);
+        let documents = retriever.get_relevant_documents("query").await.unwrap();
+        assert_eq!(documents.len(), 2);
+    }
+}
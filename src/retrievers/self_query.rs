@@ -0,0 +1,309 @@
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::{
+    language_models::llm::LLM,
+    output_parsers::{MarkdownParser, OutputParser},
+    schemas::{Document, Retriever, RetrieverError},
+    vectorstore::{MetadataFilter, VecStoreOptions, VectorStore},
+};
+
+const SELF_QUERY_PROMPT: &str = r#"Your goal is to structure the user's query to match the request schema provided below.
+
+The documents are: {document_contents}
+
+The metadata fields you can filter on are:
+{metadata_fields}
+
+Answer with a JSON object with the following keys:
+- "query": the text to compare to the document contents, without the parts used in the filter.
+- "filter": an object mapping metadata fields to the exact value the documents must have, or to an object of comparisons among "$eq", "$ne", "$gt", "$lt" and "$in" (a list of values), like {"year": {"$gt": 2020}}. Use {} if no filter applies.
+
+Only use the metadata fields listed above. Answer only with the JSON object.
+
+User query: {query}"#;
+
+/// Description of a metadata field the `SelfQueryRetriever` can filter on.
+#[derive(Debug, Clone)]
+pub struct AttributeInfo {
+    pub name: String,
+    pub description: String,
+    pub attribute_type: String,
+}
+
+impl AttributeInfo {
+    pub fn new<S: Into<String>>(name: S, description: S, attribute_type: S) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            attribute_type: attribute_type.into(),
+        }
+    }
+}
+
+/// A retriever that uses an LLM to turn a natural language query into a metadata
+/// filter plus a semantic query, then runs `similarity_search` on the vector store.
+///
+/// The filter is searched as the `metadata_filter` of `VecStoreOptions`, so it is
+/// translated by each store. The LLM maps each field to the value it must match, or to
+/// comparisons like `{"$gt": 2020}`. If the LLM answer can't be parsed, the original query
+/// is searched without a filter.
+///
+/// # Example
+/// ```rust,ignore
+/// let retriever = SelfQueryRetriever::new(
+///     OpenAI::default(),
+///     store,
+///     "Scientific papers",
+///     vec![AttributeInfo::new("year", "Year the paper was published", "integer")],
+/// )
+/// .with_num_docs(5);
+///
+/// let docs = retriever.get_relevant_documents("papers about ANI from 2021").await?;
+/// ```
+pub struct SelfQueryRetriever {
+    llm: Box<dyn LLM>,
+    vstore: Box<dyn VectorStore>,
+    document_contents: String,
+    metadata_fields: Vec<AttributeInfo>,
+    num_docs: usize,
+}
+
+impl SelfQueryRetriever {
+    pub fn new<L: Into<Box<dyn LLM>>, V: Into<Box<dyn VectorStore>>, S: Into<String>>(
+        llm: L,
+        vstore: V,
+        document_contents: S,
+        metadata_fields: Vec<AttributeInfo>,
+    ) -> Self {
+        Self {
+            llm: llm.into(),
+            vstore: vstore.into(),
+            document_contents: document_contents.into(),
+            metadata_fields,
+            num_docs: 4,
+        }
+    }
+
+    pub fn with_num_docs(mut self, num_docs: usize) -> Self {
+        self.num_docs = num_docs;
+        self
+    }
+
+    fn build_prompt(&self, query: &str) -> String {
+        let metadata_fields = self
+            .metadata_fields
+            .iter()
+            .map(|field| {
+                format!(
+                    "- {} ({}): {}",
+                    field.name, field.attribute_type, field.description
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+
+        SELF_QUERY_PROMPT
+            .replace("{document_contents}", &self.document_contents)
+            .replace("{metadata_fields}", &metadata_fields)
+            .replace("{query}", query)
+    }
+
+    /// Parses the LLM answer into the query and the filter. Returns `None` if the answer
+    /// is not the expected JSON. Fields that are not in `metadata_fields` and comparisons
+    /// that are not understood are dropped from the filter.
+    async fn parse_structured_query(
+        &self,
+        output: &str,
+    ) -> Option<(String, Option<MetadataFilter>)> {
+        let json = MarkdownParser::new()
+            .with_trim(true)
+            .parse(output)
+            .await
+            .unwrap_or_else(|_| output.trim().to_string());
+        let structured: Value = serde_json::from_str(&json).ok()?;
+
+        let query = structured.get("query")?.as_str()?.to_string();
+        let mut filters = match structured.get("filter") {
+            Some(Value::Object(filter)) => filter
+                .iter()
+                .filter(|(key, _)| self.metadata_fields.iter().any(|field| &field.name == *key))
+                .flat_map(|(key, value)| comparisons(key, value))
+                .collect::<Vec<MetadataFilter>>(),
+            Some(Value::Null) | None => Vec::new(),
+            Some(_) => return None,
+        };
+
+        let filter = match filters.len() {
+            0 => None,
+            1 => filters.pop(),
+            _ => Some(MetadataFilter::And(filters)),
+        };
+        Some((query, filter))
+    }
+}
+
+/// The filters of a field: a scalar is the value it must match, an object holds
+/// comparisons like `{"$gt": 2020, "$lt": 2023}`.
+fn comparisons(key: &str, value: &Value) -> Vec<MetadataFilter> {
+    let is_scalar = |value: &Value| value.is_string() || value.is_number() || value.is_boolean();
+    let Value::Object(comparisons) = value else {
+        return match is_scalar(value) {
+            true => vec![MetadataFilter::eq(key, value.clone())],
+            false => Vec::new(),
+        };
+    };
+    comparisons
+        .iter()
+        .filter_map(|(operator, value)| match (operator.as_str(), value) {
+            ("$eq", value) if is_scalar(value) => Some(MetadataFilter::eq(key, value.clone())),
+            ("$ne", value) if is_scalar(value) => Some(MetadataFilter::ne(key, value.clone())),
+            ("$gt", value) if is_scalar(value) => Some(MetadataFilter::gt(key, value.clone())),
+            ("$lt", value) if is_scalar(value) => Some(MetadataFilter::lt(key, value.clone())),
+            ("$in", Value::Array(values)) if values.iter().all(is_scalar) => {
+                Some(MetadataFilter::is_in(key, values.clone()))
+            }
+            _ => {
+                log::debug!("Ignoring the self query comparison {} on {}", operator, key);
+                None
+            }
+        })
+        .collect()
+}
+
+#[async_trait]
+impl Retriever for SelfQueryRetriever {
+    async fn get_relevant_documents(&self, query: &str) -> Result<Vec<Document>, RetrieverError> {
+        let output = self.llm.invoke(&self.build_prompt(query)).await?;
+
+        let mut options = VecStoreOptions::default();
+        let search_query = match self.parse_structured_query(&output).await {
+            Some((structured_query, filter)) => {
+                if let Some(filter) = filter {
+                    options = options.with_metadata_filter(filter);
+                }
+                // An empty query means everything was moved to the filter
+                if structured_query.trim().is_empty() {
+                    query.to_string()
+                } else {
+                    structured_query
+                }
+            }
+            None => {
+                log::warn!(
+                    "Could not parse the self query output, searching without filter: {}",
+                    output
+                );
+                query.to_string()
+            }
+        };
+
+        Ok(self
+            .vstore
+            .similarity_search(&search_query, self.num_docs, &options)
+            .await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        error::Error,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+    use crate::llm::fake::FakeLLM;
+
+    /// The query and metadata filter of each search.
+    type Searches = Arc<Mutex<Vec<(String, Option<MetadataFilter>)>>>;
+
+    /// Records the query and filters of the searches.
+    struct RecordingStore {
+        searches: Searches,
+    }
+
+    #[async_trait]
+    impl VectorStore for RecordingStore {
+        async fn add_documents(
+            &self,
+            _docs: &[Document],
+            _opt: &VecStoreOptions,
+        ) -> Result<Vec<String>, Box<dyn Error>> {
+            Ok(vec![])
+        }
+
+        async fn similarity_search(
+            &self,
+            query: &str,
+            _limit: usize,
+            opt: &VecStoreOptions,
+        ) -> Result<Vec<Document>, Box<dyn Error>> {
+            self.searches
+                .lock()
+                .unwrap()
+                .push((query.to_string(), opt.metadata_filter.clone()));
+            Ok(vec![])
+        }
+    }
+
+    async fn search(answer: &str) -> (String, Option<MetadataFilter>) {
+        let searches = Arc::new(Mutex::new(Vec::new()));
+        let retriever = SelfQueryRetriever::new(
+            FakeLLM::fixed(answer),
+            RecordingStore {
+                searches: searches.clone(),
+            },
+            "Scientific papers",
+            vec![AttributeInfo::new(
+                "year",
+                "Year the paper was published",
+                "integer",
+            )],
+        );
+        retriever
+            .get_relevant_documents("papers about ANI from 2021")
+            .await
+            .unwrap();
+        let search = searches.lock().unwrap()[0].clone();
+        search
+    }
+
+    #[tokio::test]
+    async fn test_self_query_uses_the_filter() {
+        let (query, filter) = search(
+            "```json\n{\"query\": \"ANI\", \"filter\": {\"year\": 2021, \"author\": \"x\"}}\n```",
+        )
+        .await;
+        assert_eq!(query, "ANI");
+        assert_eq!(filter, Some(MetadataFilter::eq("year", 2021)));
+    }
+
+    #[tokio::test]
+    async fn test_self_query_maps_the_comparisons() {
+        let (query, filter) = search(
+            "{\"query\": \"ANI\", \"filter\": {\"year\": {\"$gt\": 2020, \"$lt\": 2023, \"$like\": 1}}}",
+        )
+        .await;
+        assert_eq!(query, "ANI");
+        assert_eq!(
+            filter,
+            Some(MetadataFilter::gt("year", 2020).and(MetadataFilter::lt("year", 2023)))
+        );
+
+        let (_, filter) =
+            search("{\"query\": \"ANI\", \"filter\": {\"year\": {\"$in\": [2020, 2021]}}}").await;
+        assert_eq!(filter, Some(MetadataFilter::is_in("year", [2020, 2021])));
+
+        let (_, filter) =
+            search("{\"query\": \"ANI\", \"filter\": {\"year\": {\"$ne\": 2020}}}").await;
+        assert_eq!(filter, Some(MetadataFilter::ne("year", 2020)));
+    }
+
+    #[tokio::test]
+    async fn test_self_query_falls_back_without_filter() {
+        let (query, filter) = search("I can't do that").await;
+        assert_eq!(query, "papers about ANI from 2021");
+        assert_eq!(filter, None);
+    }
+}
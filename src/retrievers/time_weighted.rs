@@ -0,0 +1,258 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::{
+    schemas::{Document, Retriever, RetrieverError},
+    vectorstore::{VecStoreOptions, VectorStore},
+};
+
+/// Metadata key of the time, in seconds since the Unix epoch, the document was last
+/// returned by the retriever.
+pub const LAST_ACCESSED_AT_KEY: &str = "last_accessed_at";
+/// Metadata key of the time, in seconds since the Unix epoch, the document was added.
+pub const CREATED_AT_KEY: &str = "created_at";
+
+/// Retrieves documents from a vector store ranked by their similarity plus a recency bonus,
+/// so the documents that haven't been used for a long time rank lower:
+/// `score = similarity + decay_rate ^ hours_since_last_access`.
+///
+/// The last access of a document is read from its `last_accessed_at` metadata, then its
+/// `created_at`, both in seconds since the Unix epoch; `add_documents` sets both. A document
+/// without a timestamp gets no recency bonus. Vector stores can't update the metadata of a
+/// stored document, so the retriever also remembers when it returned each document, by
+/// content, and the returned documents have their `last_accessed_at` set to now.
+///
+/// # Example
+/// ```rust,ignore
+/// let retriever = TimeWeightedRetriever::new(store, 4).with_decay_rate(0.95);
+/// retriever.add_documents(&documents).await?;
+/// let documents = retriever.get_relevant_documents("What did we talk about?").await?;
+/// ```
+pub struct TimeWeightedRetriever {
+    store: Box<dyn VectorStore>,
+    num_docs: usize,
+    fetch_k: usize,
+    decay_rate: f64,
+    options: VecStoreOptions,
+    last_accessed: Mutex<HashMap<String, u64>>,
+}
+
+impl TimeWeightedRetriever {
+    pub fn new<V: Into<Box<dyn VectorStore>>>(store: V, num_docs: usize) -> Self {
+        Self {
+            store: store.into(),
+            num_docs,
+            fetch_k: 20,
+            decay_rate: 0.99,
+            options: VecStoreOptions::default(),
+            last_accessed: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Share of the recency bonus kept after each hour without access, between 0 and 1,
+    /// 0.99 by default. The lower it is, the faster the old documents rank down.
+    pub fn with_decay_rate(mut self, decay_rate: f64) -> Self {
+        self.decay_rate = decay_rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Number of documents fetched by similarity before they are ranked with their recency,
+    /// 20 by default.
+    pub fn with_fetch_k(mut self, fetch_k: usize) -> Self {
+        self.fetch_k = fetch_k;
+        self
+    }
+
+    pub fn with_options(mut self, options: VecStoreOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Adds the documents to the store, with their `created_at` and `last_accessed_at` set
+    /// to now unless they already have them.
+    pub async fn add_documents(
+        &self,
+        documents: &[Document],
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let added_at = Value::from(now());
+        let documents: Vec<Document> = documents
+            .iter()
+            .cloned()
+            .map(|mut document| {
+                document
+                    .metadata
+                    .entry(CREATED_AT_KEY.to_string())
+                    .or_insert_with(|| added_at.clone());
+                document
+                    .metadata
+                    .entry(LAST_ACCESSED_AT_KEY.to_string())
+                    .or_insert_with(|| added_at.clone());
+                document
+            })
+            .collect();
+        self.store.add_documents(&documents, &self.options).await
+    }
+
+    fn last_accessed_at(&self, document: &Document) -> Option<u64> {
+        let remembered = self
+            .last_accessed
+            .lock()
+            .ok()
+            .and_then(|last_accessed| last_accessed.get(&document.page_content).copied());
+        remembered.or_else(|| {
+            [LAST_ACCESSED_AT_KEY, CREATED_AT_KEY]
+                .iter()
+                .find_map(|key| timestamp(document.metadata.get(*key)?))
+        })
+    }
+
+    /// Ranks the documents by their combined score, keeps the top ones and marks them as
+    /// accessed at `now`.
+    fn rank(&self, documents: Vec<Document>, now: u64) -> Vec<Document> {
+        let mut documents: Vec<Document> = documents
+            .into_iter()
+            .map(|document| {
+                let recency = self
+                    .last_accessed_at(&document)
+                    .map(|last_accessed_at| {
+                        let hours = now.saturating_sub(last_accessed_at) as f64 / 3600.0;
+                        self.decay_rate.powf(hours)
+                    })
+                    .unwrap_or(0.0);
+                let score = document.score + recency;
+                document.with_score(score)
+            })
+            .collect();
+        documents.sort_by(|a, b| b.score.total_cmp(&a.score));
+        documents.truncate(self.num_docs);
+
+        if let Ok(mut last_accessed) = self.last_accessed.lock() {
+            for document in documents.iter_mut() {
+                last_accessed.insert(document.page_content.clone(), now);
+                document
+                    .metadata
+                    .insert(LAST_ACCESSED_AT_KEY.to_string(), Value::from(now));
+            }
+        }
+        documents
+    }
+}
+
+/// A timestamp in seconds, as a number or a numeric string.
+fn timestamp(value: &Value) -> Option<u64> {
+    match value {
+        Value::Number(number) => number
+            .as_u64()
+            .or_else(|| number.as_f64().map(|seconds| seconds as u64)),
+        Value::String(string) => string
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .map(|seconds| seconds as u64),
+        _ => None,
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[async_trait]
+impl Retriever for TimeWeightedRetriever {
+    async fn get_relevant_documents(&self, query: &str) -> Result<Vec<Document>, RetrieverError> {
+        let documents = self
+            .store
+            .similarity_search(query, self.fetch_k.max(self.num_docs), &self.options)
+            .await?;
+        Ok(self.rank(documents, now()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    struct StaticStore(Vec<Document>);
+
+    #[async_trait]
+    impl VectorStore for StaticStore {
+        async fn add_documents(
+            &self,
+            docs: &[Document],
+            _opt: &VecStoreOptions,
+        ) -> Result<Vec<String>, Box<dyn Error>> {
+            Ok(docs.iter().map(|doc| doc.page_content.clone()).collect())
+        }
+
+        async fn similarity_search(
+            &self,
+            _query: &str,
+            limit: usize,
+            _opt: &VecStoreOptions,
+        ) -> Result<Vec<Document>, Box<dyn Error>> {
+            Ok(self.0.iter().take(limit).cloned().collect())
+        }
+    }
+
+    fn document(content: &str, score: f64, last_accessed_at: Option<Value>) -> Document {
+        let mut document = Document::new(content).with_score(score);
+        if let Some(last_accessed_at) = last_accessed_at {
+            document
+                .metadata
+                .insert(LAST_ACCESSED_AT_KEY.to_string(), last_accessed_at);
+        }
+        document
+    }
+
+    #[test]
+    fn test_recent_documents_rank_higher() {
+        let now = 1_000_000;
+        let retriever = TimeWeightedRetriever::new(StaticStore(vec![]), 3).with_decay_rate(0.5);
+        let documents = vec![
+            document("old", 0.8, Some(json!(now - 10 * 3600))),
+            document("recent", 0.7, Some(json!((now - 3600).to_string()))),
+            document("no timestamp", 0.75, None),
+        ];
+
+        let ranked = retriever.rank(documents, now);
+
+        let contents: Vec<&str> = ranked.iter().map(|doc| doc.page_content.as_str()).collect();
+        assert_eq!(contents, vec!["recent", "old", "no timestamp"]);
+        assert_eq!(ranked[0].score, 0.7 + 0.5);
+        assert_eq!(ranked[2].metadata[LAST_ACCESSED_AT_KEY], json!(now));
+    }
+
+    #[tokio::test]
+    async fn test_retrieval_updates_the_last_access() {
+        let retriever = TimeWeightedRetriever::new(
+            StaticStore(vec![
+                document("a", 0.9, Some(json!(0))),
+                document("b", 0.5, Some(json!(0))),
+            ]),
+            1,
+        );
+
+        let documents = retriever.get_relevant_documents("query").await.unwrap();
+
+        assert_eq!(documents.len(), 1);
+        assert_eq!(documents[0].page_content, "a");
+        let last_accessed_at = retriever.last_accessed_at(&document("a", 0.0, None));
+        assert!(last_accessed_at.unwrap() > 0);
+        assert_eq!(
+            retriever.last_accessed_at(&document("b", 0.0, Some(json!(0)))),
+            Some(0)
+        );
+    }
+}
@@ -1,7 +1,11 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, pin::Pin};
 
+use futures::Stream;
 use serde::{Deserialize, Serialize};
-use tokio::sync::mpsc;
+
+use crate::chain::ChainError;
+
+use super::StreamData;
 
 pub enum ToolInput {
     //Will implement this in the future
@@ -34,7 +38,9 @@ pub enum AgentEvent {
     Finish(AgentFinish),
 }
 
+/// Result of a streamed planning step. When the agent decides to answer, the final
+/// answer can be returned as a stream instead of a complete `AgentFinish`.
 pub enum AgentPlan {
     Text(AgentEvent),
-    Stream(mpsc::Receiver<Result<String, reqwest_eventsource::Error>>),
+    Stream(Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>),
 }
@@ -7,6 +7,8 @@ use serde_json::Value;
 /// The `page_content` field is a string that contains the content of the document.
 /// The `metadata` field is a `HashMap` where the keys represent metadata properties and the values represent property values.
 /// The `score` field represents a relevance score for the document and is a floating point number.
+/// The `embedding` field holds the stored vector of the document, vector stores only fill it when
+/// `VecStoreOptions::include_embeddings` is set.
 ///
 /// # Usage
 /// ```rust,ignore
@@ -23,6 +25,8 @@ pub struct Document {
     pub page_content: String,
     pub metadata: HashMap<String, Value>,
     pub score: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<Vec<f64>>,
 }
 
 impl Document {
@@ -32,6 +36,7 @@ impl Document {
             page_content: page_content.into(),
             metadata: HashMap::new(),
             score: 0.0,
+            embedding: None,
         }
     }
 
@@ -46,6 +51,12 @@ impl Document {
         self.score = score;
         self
     }
+
+    /// Sets the `embedding` of the `Document` to the provided vector.
+    pub fn with_embedding(mut self, embedding: Vec<f64>) -> Self {
+        self.embedding = Some(embedding);
+        self
+    }
 }
 
 impl Default for Document {
@@ -55,6 +66,7 @@ impl Default for Document {
             page_content: "".to_string(),
             metadata: HashMap::new(),
             score: 0.0,
+            embedding: None,
         }
     }
 }
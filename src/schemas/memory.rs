@@ -22,7 +22,13 @@ pub trait BaseMemory: Send + Sync {
     fn to_string(&self) -> String {
         self.messages()
             .iter()
-            .map(|msg| format!("{}: {}", msg.message_type.to_string(), msg.content))
+            .map(|msg| {
+                format!(
+                    "{}: {}",
+                    msg.message_type.to_string(),
+                    msg.content_with_image_placeholders()
+                )
+            })
             .collect::<Vec<String>>()
             .join("\n")
     }
@@ -1,6 +1,8 @@
 use serde::Deserialize;
 use serde::Serialize;
-use serde_json::Value;
+use serde_json::{json, Value};
+
+use super::FunctionCallResponse;
 
 /// Enum `MessageType` represents the type of a message.
 /// It can be a `SystemMessage`, `AIMessage`, or `HumanMessage`.
@@ -47,6 +49,28 @@ pub struct ImageContent {
     pub detail: Option<String>,
 }
 
+impl ImageContent {
+    /// Short text representation of the image, `[image: <url>]`. Inline `data:` urls are
+    /// replaced by their media type and a hash of the data, which is the same across Rust
+    /// versions and platforms.
+    pub fn to_placeholder(&self) -> String {
+        match self.image_url.strip_prefix("data:") {
+            Some(data) => {
+                let media_type = data.split([';', ',']).next().unwrap_or_default();
+                format!("[image: data:{};hash={:016x}]", media_type, fnv1a(data))
+            }
+            None => format!("[image: {}]", self.image_url),
+        }
+    }
+}
+
+// 64 bits FNV-1a, unlike `DefaultHasher` its output is specified
+fn fnv1a(data: &str) -> u64 {
+    data.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
 impl<S: AsRef<str>> From<S> for ImageContent {
     fn from(image_url: S) -> Self {
         ImageContent {
@@ -128,6 +152,42 @@ impl Message {
         }
     }
 
+    /// Creates the tool message with the result of a tool call.
+    pub fn new_tool_result<T: std::fmt::Display>(
+        tool_call: &FunctionCallResponse,
+        content: T,
+    ) -> Self {
+        Self::new_tool_message(content, tool_call.id.clone())
+    }
+
+    /// Creates the AI message that requests the tool calls, stored in `tool_calls` in the
+    /// OpenAI-like format.
+    pub fn new_ai_tool_calls_message(tool_calls: &[FunctionCallResponse]) -> Self {
+        Self::new_ai_message("").with_tool_calls(json!(tool_calls))
+    }
+
+    /// Sets the id of the tool call this message answers.
+    pub fn with_tool_call_id<S: Into<String>>(mut self, tool_call_id: S) -> Self {
+        self.id = Some(tool_call_id.into());
+        self
+    }
+
+    /// The id of the tool call answered by a tool message.
+    pub fn tool_call_id(&self) -> Option<&str> {
+        match self.message_type {
+            MessageType::ToolMessage => self.id.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Parses the tool calls of the message, an empty list if it has none.
+    pub fn parsed_tool_calls(&self) -> Result<Vec<FunctionCallResponse>, serde_json::Error> {
+        match &self.tool_calls {
+            Some(tool_calls) => serde_json::from_value(tool_calls.clone()),
+            None => Ok(Vec::new()),
+        }
+    }
+
     /// Sets the tool calls for the OpenAI-like API call.
     ///
     /// Use this method when you need to specify tool calls in the configuration.
@@ -142,15 +202,133 @@ impl Message {
         self
     }
 
+    /// Adds the images to the message, an empty list leaves the message without images.
+    pub fn with_images<T: Into<ImageContent>>(mut self, images: Vec<T>) -> Self {
+        self.images = if images.is_empty() {
+            None
+        } else {
+            Some(images.into_iter().map(|i| i.into()).collect())
+        };
+        self
+    }
+
     pub fn messages_from_value(value: &Value) -> Result<Vec<Message>, serde_json::error::Error> {
         serde_json::from_value(value.clone())
     }
 
+    /// The content of the message followed by a placeholder for each image, see
+    /// [`ImageContent::to_placeholder`].
+    pub fn content_with_image_placeholders(&self) -> String {
+        self.content_with(|image| image.to_placeholder())
+    }
+
+    fn content_with<F: Fn(&ImageContent) -> String>(&self, image_to_string: F) -> String {
+        self.images.iter().flatten().map(image_to_string).fold(
+            self.content.clone(),
+            |content, image| {
+                if content.is_empty() {
+                    image
+                } else {
+                    format!("{} {}", content, image)
+                }
+            },
+        )
+    }
+
+    /// Serializes the messages as text, images are replaced by a short placeholder.
     pub fn messages_to_string(messages: &[Message]) -> String {
         messages
             .iter()
-            .map(|m| format!("{:?}: {}", m.message_type, m.content))
+            .map(|m| {
+                format!(
+                    "{:?}: {}",
+                    m.message_type,
+                    m.content_with_image_placeholders()
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// Serializes the messages as text keeping the full url of the images, including
+    /// inline `data:` urls.
+    pub fn messages_to_string_with_images(messages: &[Message]) -> String {
+        messages
+            .iter()
+            .map(|m| {
+                format!(
+                    "{:?}: {}",
+                    m.message_type,
+                    m.content_with(|image| format!("[image: {}]", image.image_url))
+                )
+            })
             .collect::<Vec<String>>()
             .join("\n")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_to_string_with_images() {
+        let mut message = Message::new_human_message("What is in these images?");
+        message.images = Some(vec![
+            ImageContent::from("https://example.com/cat.png"),
+            ImageContent::from("data:image/png;base64,iVBORw0KGgo="),
+        ]);
+        let messages = vec![
+            message,
+            Message::new_human_message_with_images(vec!["https://example.com/dog.png"]),
+            Message::new_ai_message("A cat and a dog"),
+        ];
+
+        let text = Message::messages_to_string(&messages);
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            "HumanMessage: What is in these images? [image: https://example.com/cat.png] [image: data:image/png;hash=bf7639701d088384]"
+        );
+        assert_eq!(
+            lines[1],
+            "HumanMessage: [image: https://example.com/dog.png]"
+        );
+        assert_eq!(lines[2], "AIMessage: A cat and a dog");
+
+        assert_eq!(
+            Message::messages_to_string_with_images(&messages[..1]),
+            "HumanMessage: What is in these images? [image: https://example.com/cat.png] [image: data:image/png;base64,iVBORw0KGgo=]"
+        );
+    }
+
+    #[test]
+    fn test_tool_messages_round_trip() {
+        use crate::schemas::FunctionDetail;
+        use async_openai::types::ChatCompletionMessageToolCall;
+
+        let tool_call = FunctionCallResponse {
+            id: "call_1".to_string(),
+            type_field: "function".to_string(),
+            function: FunctionDetail {
+                name: "Calculator".to_string(),
+                arguments: r#"{"input":"2+2"}"#.to_string(),
+            },
+        };
+        let messages = vec![
+            Message::new_ai_tool_calls_message(std::slice::from_ref(&tool_call)),
+            Message::new_tool_result(&tool_call, 4),
+        ];
+
+        let value = serde_json::to_value(&messages).unwrap();
+        let messages = Message::messages_from_value(&value).unwrap();
+        assert_eq!(messages[0].parsed_tool_calls().unwrap(), vec![tool_call]);
+        assert_eq!(messages[0].tool_call_id(), None);
+        assert_eq!(messages[1].tool_call_id(), Some("call_1"));
+        assert_eq!(messages[1].content, "4");
+
+        let openai_tool_calls: Vec<ChatCompletionMessageToolCall> =
+            serde_json::from_value(messages[0].tool_calls.clone().unwrap()).unwrap();
+        assert_eq!(openai_tool_calls[0].function.name, "Calculator");
+    }
+}
@@ -7,6 +7,9 @@ pub use memory::*;
 pub mod messages;
 pub use messages::*;
 
+mod trim_messages;
+pub use trim_messages::*;
+
 pub mod prompt;
 pub use prompt::*;
 
@@ -21,3 +24,6 @@ pub use tools_openai_like::*;
 
 mod stream;
 pub use stream::*;
+
+mod progress;
+pub use progress::*;
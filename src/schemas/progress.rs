@@ -0,0 +1,30 @@
+use std::{fmt, sync::Arc};
+
+/// Called after each batch of a long operation, like embedding or storing documents, with
+/// the number of items processed so far and the total, for example to render a progress
+/// bar.
+///
+/// # Example
+/// ```rust,ignore
+/// let options = VecStoreOptions::new()
+///     .with_batch_size(100)
+///     .with_progress(|done, total| println!("{}/{} documents stored", done, total));
+/// ```
+#[derive(Clone)]
+pub struct ProgressCallback(Arc<dyn Fn(usize, usize) + Send + Sync>);
+
+impl ProgressCallback {
+    pub fn new<F: Fn(usize, usize) + Send + Sync + 'static>(callback: F) -> Self {
+        Self(Arc::new(callback))
+    }
+
+    pub fn report(&self, done: usize, total: usize) {
+        (self.0)(done, total)
+    }
+}
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
@@ -1,12 +1,93 @@
 use std::error::Error;
 
 use async_trait::async_trait;
+use thiserror::Error;
+
+use crate::{
+    embedding::EmbedderError, language_models::LLMError, output_parsers::OutputParserError,
+    vectorstore::SearchType,
+};
 
 use super::Document;
 
 #[async_trait]
 pub trait Retriever: Sync + Send {
-    async fn get_relevant_documents(&self, query: &str) -> Result<Vec<Document>, Box<dyn Error>>;
+    async fn get_relevant_documents(&self, query: &str) -> Result<Vec<Document>, RetrieverError>;
+
+    /// Retrieves the documents with the parameters of `options` instead of the ones of the
+    /// retriever. By default the documents of `get_relevant_documents` are filtered by
+    /// the score threshold and truncated to the top k, and the search type is ignored.
+    async fn get_relevant_documents_with_options(
+        &self,
+        query: &str,
+        options: &RetrievalOptions,
+    ) -> Result<Vec<Document>, RetrieverError> {
+        let mut documents = self.get_relevant_documents(query).await?;
+        if let Some(score_threshold) = options.score_threshold {
+            documents.retain(|doc| doc.score >= score_threshold as f64);
+        }
+        if let Some(top_k) = options.top_k {
+            documents.truncate(top_k);
+        }
+        Ok(documents)
+    }
+}
+
+/// Parameters of a retrieval overriding the ones of the retriever, see
+/// `Retriever::get_relevant_documents_with_options`. Unset parameters keep the ones of the
+/// retriever.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct RetrievalOptions {
+    /// Maximum number of documents returned.
+    pub top_k: Option<usize>,
+    /// Minimum score of the documents returned, higher is more similar.
+    pub score_threshold: Option<f32>,
+    /// How the documents are picked, only supported by the vector store retriever.
+    pub search_type: Option<SearchType>,
+}
+
+impl RetrievalOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_top_k(mut self, top_k: usize) -> Self {
+        self.top_k = Some(top_k);
+        self
+    }
+
+    pub fn with_score_threshold(mut self, score_threshold: f32) -> Self {
+        self.score_threshold = Some(score_threshold);
+        self
+    }
+
+    pub fn with_search_type(mut self, search_type: SearchType) -> Self {
+        self.search_type = Some(search_type);
+        self
+    }
+
+    /// Checks the parameters: the top k must be positive, the score threshold a number,
+    /// and for maximal marginal relevance the lambda between 0 and 1.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.top_k == Some(0) {
+            return Err("top_k must be greater than 0".into());
+        }
+        if self
+            .score_threshold
+            .is_some_and(|threshold| !threshold.is_finite())
+        {
+            return Err("score_threshold must be a finite number".into());
+        }
+        if let Some(SearchType::Mmr { fetch_k, lambda }) = self.search_type {
+            if fetch_k == 0 {
+                return Err("fetch_k must be greater than 0".into());
+            }
+            if !(0.0..=1.0).contains(&lambda) {
+                return Err("lambda must be between 0 and 1".into());
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<R> From<R> for Box<dyn Retriever>
@@ -17,3 +98,102 @@ where
         Box::new(retriever)
     }
 }
+
+#[derive(Error, Debug)]
+pub enum RetrieverError {
+    #[error("Embedder error: {0}")]
+    EmbedderError(#[from] EmbedderError),
+
+    #[error("LLM error: {0}")]
+    LLMError(#[from] LLMError),
+
+    #[error("Vector store error: {0}")]
+    StoreError(String),
+
+    #[error("Parse error: {0}")]
+    ParseError(String),
+
+    #[error("Error: {0}")]
+    OtherError(String),
+}
+
+/// The errors of the vector stores, which return a `Box<dyn Error>`.
+impl From<Box<dyn Error>> for RetrieverError {
+    fn from(error: Box<dyn Error>) -> Self {
+        RetrieverError::StoreError(error.to_string())
+    }
+}
+
+impl From<OutputParserError> for RetrieverError {
+    fn from(error: OutputParserError) -> Self {
+        RetrieverError::ParseError(error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FailingRetriever;
+
+    #[async_trait]
+    impl Retriever for FailingRetriever {
+        async fn get_relevant_documents(
+            &self,
+            _query: &str,
+        ) -> Result<Vec<Document>, RetrieverError> {
+            let store_error: Box<dyn Error> = "connection refused".into();
+            Err(store_error)?
+        }
+    }
+
+    struct ScoredRetriever;
+
+    #[async_trait]
+    impl Retriever for ScoredRetriever {
+        async fn get_relevant_documents(
+            &self,
+            _query: &str,
+        ) -> Result<Vec<Document>, RetrieverError> {
+            Ok([0.9, 0.8, 0.3, 0.7]
+                .into_iter()
+                .map(|score| Document::new(score.to_string()).with_score(score))
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_default_options_filter_and_truncate() {
+        let options = RetrievalOptions::new()
+            .with_top_k(2)
+            .with_score_threshold(0.75);
+
+        let documents = ScoredRetriever
+            .get_relevant_documents_with_options("query", &options)
+            .await
+            .unwrap();
+
+        let contents: Vec<&str> = documents.iter().map(|d| d.page_content.as_str()).collect();
+        assert_eq!(contents, vec!["0.9", "0.8"]);
+        assert_eq!(
+            ScoredRetriever
+                .get_relevant_documents_with_options("query", &RetrievalOptions::default())
+                .await
+                .unwrap()
+                .len(),
+            4
+        );
+    }
+
+    #[tokio::test]
+    async fn test_store_errors_convert_to_retriever_errors() {
+        let retriever: Box<dyn Retriever> = FailingRetriever.into();
+
+        let error = retriever.get_relevant_documents("query").await.unwrap_err();
+
+        assert!(matches!(error, RetrieverError::StoreError(_)));
+        assert_eq!(error.to_string(), "Vector store error: connection refused");
+        let error: RetrieverError = EmbedderError::FastEmbedError("no model".into()).into();
+        assert!(matches!(error, RetrieverError::EmbedderError(_)));
+    }
+}
@@ -1,7 +1,9 @@
+use futures::{pin_mut, Stream, StreamExt};
 use serde_json::Value;
 use std::io::{self, Write};
+use thiserror::Error;
 
-use crate::language_models::TokenUsage;
+use crate::language_models::{GenerateResult, LLMError, TokenUsage};
 
 #[derive(Debug, Clone)]
 pub struct StreamData {
@@ -25,4 +27,92 @@ impl StreamData {
         write!(handle, "{}", self.content)?;
         handle.flush()
     }
+
+    /// Consumes a stream, joining the content of the chunks into the generation. The
+    /// tokens are the usage of the last chunk that has one, providers send the usage of
+    /// the whole generation in the final chunk, and they are `None` if no chunk has usage.
+    ///
+    /// On the first error the stream is dropped, and the error has the content received
+    /// until then.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let stream = llm.stream(&messages).await?;
+    /// let result = StreamData::collect(stream).await?;
+    /// println!("{}", result.generation);
+    /// ```
+    pub async fn collect<S>(stream: S) -> Result<GenerateResult, StreamCollectError>
+    where
+        S: Stream<Item = Result<StreamData, LLMError>>,
+    {
+        pin_mut!(stream);
+        let mut result = GenerateResult::default();
+        while let Some(data) = stream.next().await {
+            match data {
+                Ok(data) => {
+                    result.generation.push_str(&data.content);
+                    if data.tokens.is_some() {
+                        result.tokens = data.tokens;
+                    }
+                }
+                Err(source) => {
+                    return Err(StreamCollectError {
+                        partial: result,
+                        source,
+                    })
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Error of `StreamData::collect`, `partial` has what was received before the error.
+#[derive(Debug, Error)]
+#[error("Stream error: {source}")]
+pub struct StreamCollectError {
+    pub partial: GenerateResult,
+    pub source: LLMError,
+}
+
+impl From<StreamCollectError> for LLMError {
+    fn from(error: StreamCollectError) -> Self {
+        error.source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_collect() {
+        let chunks = vec![
+            Ok(StreamData::new(Value::Null, None, "Hello")),
+            Ok(StreamData::new(Value::Null, None, " world")),
+            Ok(StreamData::new(
+                Value::Null,
+                Some(TokenUsage::new(3, 2)),
+                "",
+            )),
+        ];
+        let result = StreamData::collect(stream::iter(chunks)).await.unwrap();
+        assert_eq!(result.generation, "Hello world");
+        assert_eq!(result.tokens.unwrap().total_tokens, 5);
+
+        let chunks = vec![Ok(StreamData::new(Value::Null, None, "Hello"))];
+        let result = StreamData::collect(stream::iter(chunks)).await.unwrap();
+        assert!(result.tokens.is_none());
+
+        let chunks = vec![
+            Ok(StreamData::new(Value::Null, None, "Hel")),
+            Err(LLMError::OtherError("connection reset".to_string())),
+            Ok(StreamData::new(Value::Null, None, "lo")),
+        ];
+        let error = StreamData::collect(stream::iter(chunks)).await.unwrap_err();
+        assert_eq!(error.partial.generation, "Hel");
+        assert!(matches!(error.source, LLMError::OtherError(_)));
+    }
 }
@@ -1,10 +1,13 @@
 use std::ops::Deref;
 
+use async_openai::types::ChatCompletionMessageToolCall;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
 
 use crate::tools::Tool;
 
+use super::StreamData;
+
 #[derive(Clone, Debug)]
 pub enum FunctionCallBehavior {
     None,
@@ -29,6 +32,10 @@ impl FunctionDefinition {
     }
 
     /// Generic function that can be used with both Arc<Tool>, Box<Tool>, and direct references
+    ///
+    /// The `parameters` of the tool are its JSON schema. A tool without a schema, returning
+    /// null or something other than an object, takes no arguments: its schema is an empty
+    /// object schema. A schema without a type is an object schema.
     pub fn from_langchain_tool<T>(tool: &T) -> FunctionDefinition
     where
         T: Deref<Target = dyn Tool> + ?Sized,
@@ -36,12 +43,29 @@ impl FunctionDefinition {
         FunctionDefinition {
             name: tool.name().trim().replace(" ", "_"),
             description: tool.description(),
-            parameters: tool.parameters(),
+            parameters: parameters_schema(tool.parameters()),
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+fn parameters_schema(parameters: Value) -> Value {
+    match parameters {
+        Value::Object(mut schema) => {
+            schema
+                .entry("type")
+                .or_insert_with(|| Value::String("object".into()));
+            if schema.get("type") == Some(&json!("object")) {
+                schema
+                    .entry("properties")
+                    .or_insert_with(|| Value::Object(Default::default()));
+            }
+            Value::Object(schema)
+        }
+        _ => json!({"type": "object", "properties": {}}),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct FunctionCallResponse {
     pub id: String,
     #[serde(rename = "type")]
@@ -49,7 +73,7 @@ pub struct FunctionCallResponse {
     pub function: FunctionDetail,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct FunctionDetail {
     pub name: String,
     ///this should be an string, and this should be passed to the tool, to
@@ -62,3 +86,262 @@ impl FunctionCallResponse {
         serde_json::from_str(s)
     }
 }
+
+/// Merges the tool call deltas of a stream into complete tool calls. It understands the
+/// chunks of OpenAI-like streams, whose `choices[0].delta.tool_calls` have the deltas, and
+/// the `tool_use` content blocks of Anthropic streams. The deltas of parallel tool calls
+/// are merged by their index in the stream.
+///
+/// # Usage
+/// ```rust,ignore
+/// let mut accumulator = ToolCallAccumulator::new();
+/// while let Some(data) = stream.next().await {
+///     let data = data?;
+///     if !accumulator.push_stream_data(&data) {
+///         print!("{}", data.content);
+///     }
+/// }
+/// let message = Message::new_ai_tool_calls_message(&accumulator.finish());
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct ToolCallAccumulator {
+    tool_calls: Vec<FunctionCallResponse>,
+    /// The stream index of each tool call of `tool_calls`.
+    indexes: Vec<usize>,
+}
+
+impl ToolCallAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Merges the tool call deltas of a stream chunk, from `choices[0].delta.tool_calls`
+    /// or from an Anthropic `content_block_start` or `content_block_delta` event. Returns
+    /// `true` if the chunk had tool call deltas.
+    pub fn push_stream_data(&mut self, data: &StreamData) -> bool {
+        if let Some(deltas) = data
+            .value
+            .pointer("/choices/0/delta/tool_calls")
+            .and_then(|v| v.as_array())
+        {
+            self.push_deltas(deltas);
+            return true;
+        }
+        self.push_anthropic_event(&data.value)
+    }
+
+    /// Merges tool call deltas, the id, name and arguments of each delta are appended to
+    /// the tool call at its `index`.
+    pub fn push_deltas(&mut self, deltas: &[Value]) {
+        for delta in deltas {
+            self.push_delta(
+                delta["index"].as_u64().unwrap_or(0) as usize,
+                delta["id"].as_str(),
+                delta.pointer("/function/name").and_then(|v| v.as_str()),
+                delta
+                    .pointer("/function/arguments")
+                    .and_then(|v| v.as_str()),
+            );
+        }
+    }
+
+    /// Appends the parts of a delta to the tool call at `index`, a new tool call is started
+    /// for an index not seen yet.
+    pub fn push_delta(
+        &mut self,
+        index: usize,
+        id: Option<&str>,
+        name: Option<&str>,
+        arguments: Option<&str>,
+    ) {
+        let position = match self.indexes.iter().position(|i| *i == index) {
+            Some(position) => position,
+            None => {
+                self.indexes.push(index);
+                self.tool_calls.push(FunctionCallResponse {
+                    id: String::new(),
+                    type_field: "function".to_string(),
+                    function: FunctionDetail {
+                        name: String::new(),
+                        arguments: String::new(),
+                    },
+                });
+                self.tool_calls.len() - 1
+            }
+        };
+        let tool_call = &mut self.tool_calls[position];
+        if let Some(id) = id {
+            tool_call.id.push_str(id);
+        }
+        if let Some(name) = name {
+            tool_call.function.name.push_str(name);
+        }
+        if let Some(arguments) = arguments {
+            tool_call.function.arguments.push_str(arguments);
+        }
+    }
+
+    /// Merges an Anthropic stream event: the start of a `tool_use` block has the id and
+    /// the name of the tool, and its `input_json_delta`s the parts of the arguments.
+    fn push_anthropic_event(&mut self, event: &Value) -> bool {
+        let Some(index) = event["index"].as_u64().map(|i| i as usize) else {
+            return false;
+        };
+        match event["type"].as_str() {
+            Some("content_block_start") if event["content_block"]["type"] == "tool_use" => {
+                let block = &event["content_block"];
+                self.push_delta(index, block["id"].as_str(), block["name"].as_str(), None);
+                true
+            }
+            Some("content_block_delta") if event["delta"]["type"] == "input_json_delta" => {
+                self.push_delta(index, None, None, event["delta"]["partial_json"].as_str());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tool_calls.is_empty()
+    }
+
+    pub fn tool_calls(&self) -> &[FunctionCallResponse] {
+        &self.tool_calls
+    }
+
+    /// Takes the complete tool calls at the end of the stream, in the order they started.
+    /// The tool calls streamed without arguments get an empty JSON object, as providers
+    /// send them for tools without parameters.
+    pub fn finish(&mut self) -> Vec<FunctionCallResponse> {
+        self.indexes.clear();
+        let mut tool_calls = std::mem::take(&mut self.tool_calls);
+        for tool_call in tool_calls.iter_mut() {
+            if tool_call.function.arguments.trim().is_empty() {
+                tool_call.function.arguments = "{}".to_string();
+            }
+        }
+        tool_calls
+    }
+
+    pub fn into_tool_calls(mut self) -> Vec<FunctionCallResponse> {
+        self.finish()
+    }
+
+    /// The tool calls as the `async_openai` type, for code that calls the OpenAI client
+    /// directly.
+    pub fn into_openai_tool_calls(
+        self,
+    ) -> Result<Vec<ChatCompletionMessageToolCall>, serde_json::Error> {
+        serde_json::from_value(serde_json::to_value(self.into_tool_calls())?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, sync::Arc};
+
+    use async_trait::async_trait;
+    use serde_json::json;
+
+    use super::*;
+
+    struct Schemaless {}
+
+    #[async_trait]
+    impl Tool for Schemaless {
+        fn name(&self) -> String {
+            "Current time".to_string()
+        }
+        fn description(&self) -> String {
+            "Returns the current time".to_string()
+        }
+        fn parameters(&self) -> Value {
+            Value::Null
+        }
+        async fn run(&self, _input: Value) -> Result<String, Box<dyn Error>> {
+            Ok("12:00".to_string())
+        }
+    }
+
+    #[test]
+    fn test_function_definition_from_langchain_tool() {
+        let tool: Arc<dyn Tool> = Arc::new(Schemaless {});
+        let function = FunctionDefinition::from_langchain_tool(&tool);
+        assert_eq!(function.name, "Current_time");
+        assert_eq!(
+            function.parameters,
+            json!({"type": "object", "properties": {}})
+        );
+
+        assert_eq!(
+            parameters_schema(json!({"properties": {"city": {"type": "string"}}})),
+            json!({"type": "object", "properties": {"city": {"type": "string"}}})
+        );
+        let schema = json!({"type": "object", "properties": {"q": {"type": "string"}},
+            "required": ["q"]});
+        assert_eq!(parameters_schema(schema.clone()), schema);
+    }
+
+    #[test]
+    fn test_tool_call_accumulator() {
+        let mut accumulator = ToolCallAccumulator::new();
+        accumulator.push_deltas(&[json!({"index": 0, "id": "call_1", "type": "function",
+            "function": {"name": "Calculator", "arguments": ""}})]);
+        accumulator.push_deltas(&[json!({"index": 0, "function": {"arguments": "{\"input\":"}})]);
+        accumulator.push_deltas(&[json!({"index": 0, "function": {"arguments": "\"2+2\"}"}})]);
+        let has_deltas = accumulator.push_stream_data(&StreamData::new(
+            json!({"choices": [{"delta": {"tool_calls": [{"index": 1, "id": "call_2",
+                "function": {"name": "Search", "arguments": "{}"}}]}}]}),
+            None,
+            "",
+        ));
+        assert!(has_deltas);
+        assert!(!accumulator.push_stream_data(&StreamData::new(json!({}), None, "Hi")));
+
+        let tool_calls = accumulator.tool_calls();
+        assert_eq!(tool_calls.len(), 2);
+        assert_eq!(tool_calls[0].id, "call_1");
+        assert_eq!(tool_calls[0].function.name, "Calculator");
+        assert_eq!(tool_calls[0].function.arguments, r#"{"input":"2+2"}"#);
+
+        let openai_tool_calls = accumulator.into_openai_tool_calls().unwrap();
+        assert_eq!(openai_tool_calls[1].id, "call_2");
+        assert_eq!(openai_tool_calls[1].function.name, "Search");
+    }
+
+    #[test]
+    fn test_tool_call_accumulator_anthropic_events() {
+        let events = [
+            json!({"type": "content_block_start", "index": 0,
+                "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "content_block_delta", "index": 0,
+                "delta": {"type": "text_delta", "text": "Let me check."}}),
+            json!({"type": "content_block_start", "index": 1,
+                "content_block": {"type": "tool_use", "id": "toolu_1", "name": "Weather", "input": {}}}),
+            json!({"type": "content_block_start", "index": 2,
+                "content_block": {"type": "tool_use", "id": "toolu_2", "name": "Time", "input": {}}}),
+            json!({"type": "content_block_delta", "index": 1,
+                "delta": {"type": "input_json_delta", "partial_json": "{\"city\": "}}),
+            json!({"type": "content_block_delta", "index": 1,
+                "delta": {"type": "input_json_delta", "partial_json": "\"Lima\"}"}}),
+            json!({"type": "message_stop"}),
+        ];
+        let mut accumulator = ToolCallAccumulator::new();
+        let has_deltas: Vec<bool> = events
+            .into_iter()
+            .map(|event| accumulator.push_stream_data(&StreamData::new(event, None, "")))
+            .collect();
+        assert_eq!(
+            has_deltas,
+            vec![false, false, true, true, true, true, false]
+        );
+
+        let tool_calls = accumulator.finish();
+        assert_eq!(tool_calls.len(), 2);
+        assert_eq!(tool_calls[0].id, "toolu_1");
+        assert_eq!(tool_calls[0].function.arguments, r#"{"city": "Lima"}"#);
+        assert_eq!(tool_calls[1].function.name, "Time");
+        assert_eq!(tool_calls[1].function.arguments, "{}");
+        assert!(accumulator.is_empty());
+    }
+}
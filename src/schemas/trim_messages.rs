@@ -0,0 +1,151 @@
+use crate::language_models::TokenCounter;
+
+use super::{Message, MessageType};
+
+/// Messages kept by `trim_messages` when they don't all fit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TrimStrategy {
+    /// Keeps the most recent messages.
+    #[default]
+    KeepLast,
+    /// Keeps the oldest messages.
+    KeepFirst,
+}
+
+/// Trims the messages to fit in `max_tokens`, counted with the default `TokenCounter`.
+/// See `trim_messages_with_counter`.
+pub fn trim_messages(
+    messages: &[Message],
+    max_tokens: usize,
+    strategy: TrimStrategy,
+) -> Vec<Message> {
+    trim_messages_with_counter(messages, max_tokens, strategy, &TokenCounter::default())
+}
+
+/// Trims the messages to fit in `max_tokens`. The system messages are always kept, and
+/// the other messages are kept from the end or from the start, depending on the strategy,
+/// until one doesn't fit. An AI message with tool calls and the tool messages with their
+/// results are kept or dropped together. The kept messages are in their original order.
+/// # Example
+/// ```rust,ignore
+/// let messages = trim_messages(&memory.messages(), 3000, TrimStrategy::KeepLast);
+/// ```
+pub fn trim_messages_with_counter(
+    messages: &[Message],
+    max_tokens: usize,
+    strategy: TrimStrategy,
+    token_counter: &TokenCounter,
+) -> Vec<Message> {
+    let tokens: Vec<usize> = messages
+        .iter()
+        .map(|message| token_counter.count_message(message))
+        .collect();
+
+    let mut budget = max_tokens;
+    let mut blocks = Vec::new();
+    let mut i = 0;
+    while i < messages.len() {
+        if messages[i].message_type == MessageType::SystemMessage {
+            budget = budget.saturating_sub(tokens[i]);
+            i += 1;
+            continue;
+        }
+        let start = i;
+        i += 1;
+        if messages[start].tool_calls.is_some() {
+            while i < messages.len() && messages[i].message_type == MessageType::ToolMessage {
+                i += 1;
+            }
+        }
+        blocks.push(start..i);
+    }
+
+    let ordered: Box<dyn Iterator<Item = &std::ops::Range<usize>>> = match strategy {
+        TrimStrategy::KeepLast => Box::new(blocks.iter().rev()),
+        TrimStrategy::KeepFirst => Box::new(blocks.iter()),
+    };
+    let mut keep = vec![false; messages.len()];
+    for block in ordered {
+        let block_tokens: usize = tokens[block.clone()].iter().sum();
+        if block_tokens > budget {
+            break;
+        }
+        budget -= block_tokens;
+        keep[block.clone()].fill(true);
+    }
+
+    messages
+        .iter()
+        .zip(keep)
+        .filter(|(message, keep)| *keep || message.message_type == MessageType::SystemMessage)
+        .map(|(message, _)| message.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_trim_messages() {
+        let counter = TokenCounter::default();
+        let messages = vec![
+            Message::new_system_message("You are helpful"),
+            Message::new_human_message("What time is it?"),
+            Message::new_ai_message("").with_tool_calls(json!([{"id": "1", "name": "clock"}])),
+            Message::new_tool_message("10:00", "1"),
+            Message::new_ai_message("It's ten"),
+            Message::new_human_message("Thanks"),
+        ];
+        let tokens: Vec<usize> = messages.iter().map(|m| counter.count_message(m)).collect();
+        let contents =
+            |messages: Vec<Message>| messages.into_iter().map(|m| m.content).collect::<Vec<_>>();
+
+        // The system message and the last two messages fit exactly
+        let max_tokens = tokens[0] + tokens[4] + tokens[5];
+        let trimmed =
+            trim_messages_with_counter(&messages, max_tokens, TrimStrategy::KeepLast, &counter);
+        assert_eq!(
+            contents(trimmed),
+            vec!["You are helpful", "It's ten", "Thanks"]
+        );
+
+        // The tool call doesn't fit without its result
+        let trimmed = trim_messages_with_counter(
+            &messages,
+            max_tokens + tokens[3],
+            TrimStrategy::KeepLast,
+            &counter,
+        );
+        assert_eq!(
+            contents(trimmed),
+            vec!["You are helpful", "It's ten", "Thanks"]
+        );
+
+        let trimmed = trim_messages_with_counter(
+            &messages,
+            max_tokens + tokens[2] + tokens[3],
+            TrimStrategy::KeepLast,
+            &counter,
+        );
+        assert_eq!(trimmed.len(), 5);
+        assert_eq!(trimmed[1].content, "");
+
+        let trimmed = trim_messages_with_counter(
+            &messages,
+            tokens[0] + tokens[1] + tokens[2] + tokens[3] - 1,
+            TrimStrategy::KeepFirst,
+            &counter,
+        );
+        assert_eq!(
+            contents(trimmed),
+            vec!["You are helpful", "What time is it?"]
+        );
+
+        // The system messages are kept even if they don't fit
+        let trimmed = trim_messages_with_counter(&messages, 0, TrimStrategy::KeepLast, &counter);
+        assert_eq!(contents(trimmed), vec!["You are helpful"]);
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use serde_json::Value;
+use tree_sitter::Node;
+
+use crate::{
+    document_loaders::{get_language_parser, Language},
+    schemas::Document,
+};
+
+use super::{TextSplitter, TextSplitterError};
+
+#[derive(Debug, Clone, PartialEq)]
+struct CodeChunk {
+    text: String,
+    symbols: Vec<String>,
+}
+
+/// A top level node of the syntax tree, together with the comments right before it.
+#[derive(Debug)]
+struct CodeUnit {
+    start: usize,
+    end: usize,
+    symbol: Option<String>,
+}
+
+/// Splits source code at the boundaries of functions, classes and other top level
+/// definitions using the tree-sitter grammars of the `SourceCodeLoader`.
+///
+/// Consecutive definitions are grouped while they fit in `chunk_size` characters, a
+/// definition is never split unless it's longer than `chunk_size` on its own, then it's
+/// split by lines. The documents created by the splitter have the `language` and the
+/// `symbols` defined in the chunk in their metadata.
+///
+/// # Example
+/// ```rust,ignore
+/// let splitter = CodeSplitter::new(Language::Python).with_chunk_size(1500);
+/// let docs = splitter.split_documents(&documents).await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct CodeSplitter {
+    language: Language,
+    chunk_size: usize,
+}
+
+impl CodeSplitter {
+    pub fn new(language: Language) -> Self {
+        Self {
+            language,
+            chunk_size: 1000,
+        }
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    fn split_code(&self, code: &str) -> Result<Vec<CodeChunk>, TextSplitterError> {
+        if self.chunk_size == 0 {
+            return Err(TextSplitterError::InvalidSplitterOptions);
+        }
+        let tree = get_language_parser(&self.language)
+            .parse(code, None)
+            .ok_or_else(|| TextSplitterError::OtherError("Failed to parse code".to_string()))?;
+
+        let mut units = Vec::new();
+        let mut comments_start = None;
+        let mut cursor = tree.root_node().walk();
+        for node in tree.root_node().children(&mut cursor) {
+            if node.kind().contains("comment") {
+                comments_start.get_or_insert(node.start_byte());
+                continue;
+            }
+            units.push(CodeUnit {
+                start: comments_start.take().unwrap_or(node.start_byte()),
+                end: node.end_byte(),
+                symbol: symbol_name(node, code.as_bytes()),
+            });
+        }
+        if let Some(start) = comments_start {
+            units.push(CodeUnit {
+                start,
+                end: tree.root_node().end_byte(),
+                symbol: None,
+            });
+        }
+
+        let mut chunks = Vec::new();
+        let mut current: Vec<CodeUnit> = Vec::new();
+        for unit in units {
+            if char_count(code, unit.start, unit.end) > self.chunk_size {
+                flush_units(code, &mut current, &mut chunks);
+                chunks.extend(
+                    split_lines(&code[unit.start..unit.end], self.chunk_size)
+                        .into_iter()
+                        .map(|text| CodeChunk {
+                            text,
+                            symbols: unit.symbol.iter().cloned().collect(),
+                        }),
+                );
+                continue;
+            }
+            if let Some(first) = current.first() {
+                if char_count(code, first.start, unit.end) > self.chunk_size {
+                    flush_units(code, &mut current, &mut chunks);
+                }
+            }
+            current.push(unit);
+        }
+        flush_units(code, &mut current, &mut chunks);
+
+        Ok(chunks)
+    }
+}
+
+fn char_count(code: &str, start: usize, end: usize) -> usize {
+    code[start..end].chars().count()
+}
+
+fn flush_units(code: &str, units: &mut Vec<CodeUnit>, chunks: &mut Vec<CodeChunk>) {
+    if let (Some(first), Some(last)) = (units.first(), units.last()) {
+        chunks.push(CodeChunk {
+            text: code[first.start..last.end].to_string(),
+            symbols: units.iter().filter_map(|u| u.symbol.clone()).collect(),
+        });
+    }
+    units.clear();
+}
+
+/// Splits the text in chunks of whole lines, a line longer than `chunk_size` is split by
+/// characters.
+fn split_lines(text: &str, chunk_size: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+    for line in text.split_inclusive('\n') {
+        let line_len = line.chars().count();
+        if current_len + line_len > chunk_size && !current.is_empty() {
+            chunks.push(current.trim_end_matches('\n').to_string());
+            current.clear();
+            current_len = 0;
+        }
+        if line_len > chunk_size {
+            let chars = line.chars().collect::<Vec<char>>();
+            for part in chars.chunks(chunk_size) {
+                chunks.push(
+                    part.iter()
+                        .collect::<String>()
+                        .trim_end_matches('\n')
+                        .to_string(),
+                );
+            }
+            continue;
+        }
+        current.push_str(line);
+        current_len += line_len;
+    }
+    if !current.trim().is_empty() {
+        chunks.push(current.trim_end_matches('\n').to_string());
+    }
+    chunks.retain(|c| !c.trim().is_empty());
+    chunks
+}
+
+/// Name of the function, class or type defined by the node, if any.
+fn symbol_name(node: Node, code: &[u8]) -> Option<String> {
+    for field in ["name", "declarator", "type", "declaration", "definition"] {
+        if let Some(child) = node.child_by_field_name(field) {
+            if child.named_child_count() == 0 {
+                return child.utf8_text(code).ok().map(String::from);
+            }
+            return symbol_name(child, code);
+        }
+    }
+    // Go type declarations hold their name in a `type_spec`
+    if node.kind() == "type_declaration" {
+        return node.named_child(0).and_then(|spec| symbol_name(spec, code));
+    }
+    None
+}
+
+#[async_trait]
+impl TextSplitter for CodeSplitter {
+    async fn split_text(&self, text: &str) -> Result<Vec<String>, TextSplitterError> {
+        Ok(self
+            .split_code(text)?
+            .into_iter()
+            .map(|chunk| chunk.text)
+            .collect())
+    }
+
+    async fn create_documents(
+        &self,
+        text: &[String],
+        metadatas: &[HashMap<String, Value>],
+    ) -> Result<Vec<Document>, TextSplitterError> {
+        let mut metadatas = metadatas.to_vec();
+        if metadatas.is_empty() {
+            metadatas = vec![HashMap::new(); text.len()];
+        }
+
+        if text.len() != metadatas.len() {
+            return Err(TextSplitterError::MetadataTextMismatch);
+        }
+
+        let mut documents: Vec<Document> = Vec::new();
+        for (text, metadata) in text.iter().zip(metadatas) {
+            for chunk in self.split_code(text)? {
+                let mut metadata = metadata.clone();
+                metadata.insert(
+                    "language".to_string(),
+                    Value::from(self.language.to_string()),
+                );
+                if !chunk.symbols.is_empty() {
+                    metadata.insert("symbols".to_string(), Value::from(chunk.symbols));
+                }
+                documents.push(Document::new(chunk.text).with_metadata(metadata));
+            }
+        }
+
+        Ok(documents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODE: &str = r#"use std::fmt;
+
+/// A person
+pub struct Person {
+    name: String,
+}
+
+impl Person {
+    pub fn new(name: String) -> Self {
+        Self { name }
+    }
+}
+
+fn main() {
+    let person = Person::new("Ana".to_string());
+    println!("{}", person.name);
+}
+"#;
+
+    #[tokio::test]
+    async fn test_code_splitter() {
+        let splitter = CodeSplitter::new(Language::Rust).with_chunk_size(100);
+        let docs = splitter
+            .create_documents(&[CODE.to_string()], &[])
+            .await
+            .unwrap();
+
+        assert_eq!(docs.len(), 3);
+        assert_eq!(
+            docs[0].page_content,
+            "use std::fmt;\n\n/// A person\npub struct Person {\n    name: String,\n}"
+        );
+        assert_eq!(docs[0].metadata["symbols"], Value::from(vec!["Person"]));
+        assert_eq!(docs[0].metadata["language"], Value::from("Rust"));
+        assert!(docs[1].page_content.starts_with("impl Person {"));
+        assert!(docs[1].page_content.ends_with('}'));
+        assert_eq!(docs[1].metadata["symbols"], Value::from(vec!["Person"]));
+        assert_eq!(docs[2].metadata["symbols"], Value::from(vec!["main"]));
+    }
+
+    #[tokio::test]
+    async fn test_code_splitter_long_unit() {
+        let splitter = CodeSplitter::new(Language::Python).with_chunk_size(40);
+        let code = "def long_function():\n    a = 1\n    b = 2\n    c = 3\n    return a + b + c\n";
+        let chunks = splitter.split_code(code).unwrap();
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].text, "def long_function():\n    a = 1");
+        assert!(chunks
+            .iter()
+            .all(|chunk| chunk.symbols == vec!["long_function".to_string()]));
+        assert!(chunks.iter().all(|chunk| chunk.text.chars().count() <= 40));
+    }
+}
@@ -1,3 +1,5 @@
+#[cfg(feature = "tree-sitter")]
+mod code_splitter;
 mod error;
 mod markdown_splitter;
 mod options;
@@ -5,6 +7,8 @@ mod plain_text_splitter;
 mod text_splitter;
 mod token_splitter;
 
+#[cfg(feature = "tree-sitter")]
+pub use code_splitter::*;
 pub use error::*;
 pub use markdown_splitter::*;
 pub use options::*;
@@ -60,6 +60,7 @@ impl SplitterOptions {
 
     pub fn get_tokenizer_from_str(s: &str) -> Option<Tokenizer> {
         match s.to_lowercase().as_str() {
+            "o200k_base" => Some(Tokenizer::O200kBase),
             "cl100k_base" => Some(Tokenizer::Cl100kBase),
             "p50k_base" => Some(Tokenizer::P50kBase),
             "r50k_base" => Some(Tokenizer::R50kBase),
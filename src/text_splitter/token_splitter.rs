@@ -1,12 +1,16 @@
 use async_trait::async_trait;
-use text_splitter::ChunkConfig;
+use text_splitter::{ChunkConfig, ChunkSizer};
 use tiktoken_rs::tokenizer::Tokenizer;
 
 use super::{SplitterOptions, TextSplitter, TextSplitterError};
+use crate::language_models::{self, TokenCounter};
 
+/// Splits the text in chunks of at most `chunk_size` tokens. The tokens are the ones of the
+/// encoding of the options, or of the tokenizer given with `with_tokenizer`.
 #[derive(Debug, Clone)]
 pub struct TokenSplitter {
     splitter_options: SplitterOptions,
+    token_counter: Option<TokenCounter>,
 }
 
 impl Default for TokenSplitter {
@@ -19,9 +23,17 @@ impl TokenSplitter {
     pub fn new(options: SplitterOptions) -> TokenSplitter {
         TokenSplitter {
             splitter_options: options,
+            token_counter: None,
         }
     }
 
+    /// Counts the tokens with the tokenizer of the model the chunks are sent to, instead of
+    /// the `encoding_name` or `model_name` of the options.
+    pub fn with_tokenizer<T: language_models::Tokenizer + 'static>(mut self, tokenizer: T) -> Self {
+        self.token_counter = Some(TokenCounter::from_tokenizer(tokenizer));
+        self
+    }
+
     #[deprecated = "Use `SplitterOptions::get_tokenizer_from_str` instead"]
     pub fn get_tokenizer_from_str(&self, s: &str) -> Option<Tokenizer> {
         match s.to_lowercase().as_str() {
@@ -38,6 +50,17 @@ impl TokenSplitter {
 #[async_trait]
 impl TextSplitter for TokenSplitter {
     async fn split_text(&self, text: &str) -> Result<Vec<String>, TextSplitterError> {
+        if let Some(token_counter) = &self.token_counter {
+            let chunk_config = ChunkConfig::new(self.splitter_options.chunk_size)
+                .with_sizer(token_counter.clone())
+                .with_trim(self.splitter_options.trim_chunks)
+                .with_overlap(self.splitter_options.chunk_overlap)?;
+            return Ok(text_splitter::TextSplitter::new(chunk_config)
+                .chunks(text)
+                .map(|x| x.to_string())
+                .collect());
+        }
+
         let chunk_config = ChunkConfig::try_from(&self.splitter_options)?;
         Ok(text_splitter::TextSplitter::new(chunk_config)
             .chunks(text)
@@ -45,3 +68,47 @@ impl TextSplitter for TokenSplitter {
             .collect())
     }
 }
+
+impl ChunkSizer for TokenCounter {
+    fn size(&self, chunk: &str) -> usize {
+        self.count(chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One token per character.
+    struct CharTokenizer {}
+
+    impl language_models::Tokenizer for CharTokenizer {
+        fn encode(&self, text: &str) -> Vec<usize> {
+            text.chars().map(|c| c as usize).collect()
+        }
+
+        fn decode(&self, tokens: &[usize]) -> Option<String> {
+            tokens
+                .iter()
+                .map(|&token| char::from_u32(token as u32))
+                .collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_split_with_tokenizer() {
+        let text = "hello world hello world";
+        let splitter = TokenSplitter::new(SplitterOptions::new().with_chunk_size(10))
+            .with_tokenizer(CharTokenizer {});
+        let chunks = splitter.split_text(text).await.unwrap();
+        assert!(chunks.len() >= 3);
+        assert!(chunks.iter().all(|chunk| chunk.chars().count() <= 10));
+
+        // The 4 tokens of cl100k fit in a single chunk
+        let chunks = TokenSplitter::new(SplitterOptions::new().with_chunk_size(10))
+            .split_text(text)
+            .await
+            .unwrap();
+        assert_eq!(chunks, vec![text]);
+    }
+}
@@ -0,0 +1,303 @@
+use std::{error::Error, path::PathBuf, process::Stdio, time::Duration};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+
+use crate::tools::Tool;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_MAX_OUTPUT_BYTES: usize = 10_000;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CodeLanguage {
+    Python,
+    JavaScript,
+}
+
+impl CodeLanguage {
+    fn default_interpreter(&self) -> &'static str {
+        match self {
+            CodeLanguage::Python => "python3",
+            CodeLanguage::JavaScript => "node",
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            CodeLanguage::Python => "Python",
+            CodeLanguage::JavaScript => "JavaScript",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionStatus {
+    Success,
+    /// The program exited with an error, the details are in `stderr`.
+    Error,
+    Timeout,
+}
+
+/// Result of a program run by the `CodeInterpreterTool`, sent to the agent as JSON.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExecutionResult {
+    pub status: ExecutionStatus,
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    /// Whether the output went over the maximum size and was cut.
+    pub truncated: bool,
+}
+
+/// Runs Python or JavaScript code written by the agent in a subprocess of the interpreter.
+/// The code is passed on stdin, the subprocess gets an empty environment besides the allowed
+/// variables, and it's killed with its process group when the timeout is reached.
+///
+/// Errors of the program are part of the `ExecutionResult`, the tool only fails when the
+/// interpreter can't be run.
+///
+/// This is not a sandbox, the code has the permissions of the user running the agent.
+/// # Example
+/// ```rust,ignore
+/// let tool = CodeInterpreterTool::python()
+///     .with_working_dir("/srv/agent")
+///     .with_timeout(Duration::from_secs(10));
+/// ```
+#[derive(Debug, Clone)]
+pub struct CodeInterpreterTool {
+    language: CodeLanguage,
+    interpreter: String,
+    working_dir: Option<PathBuf>,
+    allowed_env_vars: Vec<String>,
+    timeout: Duration,
+    max_output_bytes: usize,
+}
+
+impl CodeInterpreterTool {
+    pub fn new(language: CodeLanguage) -> Self {
+        Self {
+            interpreter: language.default_interpreter().to_string(),
+            language,
+            working_dir: None,
+            allowed_env_vars: vec!["PATH".to_string()],
+            timeout: DEFAULT_TIMEOUT,
+            max_output_bytes: DEFAULT_MAX_OUTPUT_BYTES,
+        }
+    }
+
+    pub fn python() -> Self {
+        Self::new(CodeLanguage::Python)
+    }
+
+    pub fn javascript() -> Self {
+        Self::new(CodeLanguage::JavaScript)
+    }
+
+    /// Command of the interpreter, `python3` or `node` by default. It must read the
+    /// program from stdin when given `-` as argument.
+    pub fn with_interpreter<S: Into<String>>(mut self, interpreter: S) -> Self {
+        self.interpreter = interpreter.into();
+        self
+    }
+
+    pub fn with_working_dir<P: Into<PathBuf>>(mut self, working_dir: P) -> Self {
+        self.working_dir = Some(working_dir.into());
+        self
+    }
+
+    /// Environment variables passed to the interpreter, only `PATH` by default.
+    pub fn with_allowed_env_vars<S: AsRef<str>>(mut self, env_vars: &[S]) -> Self {
+        self.allowed_env_vars = env_vars.iter().map(|v| v.as_ref().to_string()).collect();
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Maximum size of stdout and stderr, each, sent to the agent.
+    pub fn with_max_output_bytes(mut self, max_output_bytes: usize) -> Self {
+        self.max_output_bytes = max_output_bytes;
+        self
+    }
+
+    pub async fn execute(&self, code: &str) -> Result<ExecutionResult, Box<dyn Error>> {
+        let mut command = tokio::process::Command::new(&self.interpreter);
+        command
+            .arg("-")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .env_clear()
+            .envs(std::env::vars().filter(|(name, _)| self.allowed_env_vars.contains(name)));
+        if let Some(working_dir) = &self.working_dir {
+            command.current_dir(working_dir);
+        }
+        // The interpreter leads its own group, so the processes it starts are killed too
+        #[cfg(unix)]
+        command.process_group(0);
+
+        let mut child = command
+            .spawn()
+            .map_err(|e| format!("Failed to run {}: {}", self.interpreter, e))?;
+        let pid = child.id();
+        let mut stdin = child.stdin.take().ok_or("Failed to open stdin")?;
+        let stdout = child.stdout.take().ok_or("Failed to open stdout")?;
+        let stderr = child.stderr.take().ok_or("Failed to open stderr")?;
+
+        let code = code.to_string();
+        let write_code = async move {
+            // The interpreter may exit before reading all the code, which is not an error
+            let _ = stdin.write_all(code.as_bytes()).await;
+        };
+        let run = async {
+            let (_, stdout, stderr, status) = tokio::join!(
+                write_code,
+                read_capped(stdout, self.max_output_bytes),
+                read_capped(stderr, self.max_output_bytes),
+                child.wait(),
+            );
+            Ok::<_, std::io::Error>((stdout?, stderr?, status?))
+        };
+
+        let outcome = tokio::time::timeout(self.timeout, run).await;
+        match outcome {
+            Ok(result) => {
+                let ((stdout, stdout_truncated), (stderr, stderr_truncated), status) = result?;
+                Ok(ExecutionResult {
+                    status: if status.success() {
+                        ExecutionStatus::Success
+                    } else {
+                        ExecutionStatus::Error
+                    },
+                    exit_code: status.code(),
+                    stdout,
+                    stderr,
+                    truncated: stdout_truncated || stderr_truncated,
+                })
+            }
+            Err(_) => {
+                if let Some(pid) = pid {
+                    kill_process_group(pid);
+                }
+                let _ = child.kill().await;
+                Ok(ExecutionResult {
+                    status: ExecutionStatus::Timeout,
+                    exit_code: None,
+                    stdout: String::new(),
+                    stderr: format!("The program timed out after {:?}", self.timeout),
+                    truncated: false,
+                })
+            }
+        }
+    }
+}
+
+/// Reads up to `max_bytes` of the output, the rest is read and dropped so the process
+/// doesn't block on a full pipe. Returns whether the output was cut.
+async fn read_capped<R: AsyncRead + Unpin>(
+    reader: R,
+    max_bytes: usize,
+) -> std::io::Result<(String, bool)> {
+    let mut output = Vec::new();
+    let mut reader = reader.take(max_bytes as u64);
+    reader.read_to_end(&mut output).await?;
+    let dropped = tokio::io::copy(&mut reader.into_inner(), &mut tokio::io::sink()).await?;
+    Ok((String::from_utf8_lossy(&output).into_owned(), dropped > 0))
+}
+
+#[cfg(unix)]
+fn kill_process_group(pid: u32) {
+    // SAFETY: killpg has no memory safety requirements, it fails if the group is gone
+    unsafe {
+        libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_process_group(_pid: u32) {}
+
+#[async_trait]
+impl Tool for CodeInterpreterTool {
+    fn name(&self) -> String {
+        String::from("Code_Interpreter")
+    }
+
+    fn description(&self) -> String {
+        format!(
+            "Runs a {} program and returns a JSON with its status, stdout and stderr. \
+            Print the values you need, only the output of the program is returned. \
+            The input should be the code of the program.",
+            self.language.name()
+        )
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "code": {
+                    "type": "string",
+                    "description": format!("The {} code to run", self.language.name())
+                }
+            },
+            "required": ["code"]
+        })
+    }
+
+    async fn parse_input(&self, input: &str) -> Value {
+        match serde_json::from_str::<Value>(input) {
+            Ok(input) if input["code"].is_string() => input["code"].clone(),
+            Ok(input) if input["input"].is_string() => input["input"].clone(),
+            _ => Value::String(input.to_string()),
+        }
+    }
+
+    async fn run(&self, input: Value) -> Result<String, Box<dyn Error>> {
+        let code = input.as_str().ok_or("Input should be the code to run")?;
+        let result = self.execute(code).await?;
+        Ok(serde_json::to_string(&result)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_capped() {
+        let (output, truncated) = read_capped(&b"hello world"[..], 5).await.unwrap();
+        assert_eq!(output, "hello");
+        assert!(truncated);
+
+        let (output, truncated) = read_capped(&b"hello"[..], 5).await.unwrap();
+        assert_eq!(output, "hello");
+        assert!(!truncated);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_python_interpreter() {
+        let tool = CodeInterpreterTool::python().with_timeout(Duration::from_secs(2));
+
+        let result = tool.execute("print(1 + 1)").await.unwrap();
+        assert_eq!(result.status, ExecutionStatus::Success);
+        assert_eq!(result.stdout.trim(), "2");
+
+        let result = tool
+            .execute("import os\nprint(os.environ.get('HOME'))\n1 / 0")
+            .await
+            .unwrap();
+        assert_eq!(result.status, ExecutionStatus::Error);
+        assert_eq!(result.stdout.trim(), "None");
+        assert!(result.stderr.contains("ZeroDivisionError"));
+
+        let result = tool.execute("while True: pass").await.unwrap();
+        assert_eq!(result.status, ExecutionStatus::Timeout);
+    }
+}
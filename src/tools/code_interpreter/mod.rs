@@ -0,0 +1,2 @@
+mod code_interpreter;
+pub use code_interpreter::*;
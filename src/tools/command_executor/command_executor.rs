@@ -1,4 +1,8 @@
-use std::error::Error;
+use std::{
+    error::Error,
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
 
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
@@ -6,8 +10,18 @@ use serde_json::{json, Value};
 
 use crate::tools::Tool;
 
+const STRICT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Runs commands for the agent, without a shell. By default any command can be run with the
+/// environment and working directory of the process; use `CommandExecutor::strict` or the
+/// `with_*` methods to restrict what the agent can do.
+#[derive(Debug, Clone)]
 pub struct CommandExecutor {
     platform: String,
+    allowed_commands: Option<Vec<String>>,
+    working_dir: Option<PathBuf>,
+    allowed_env_vars: Option<Vec<String>>,
+    timeout: Option<Duration>,
 }
 
 impl CommandExecutor {
@@ -19,10 +33,137 @@ impl CommandExecutor {
     pub fn new<S: Into<String>>(platform: S) -> Self {
         Self {
             platform: platform.into(),
+            allowed_commands: None,
+            working_dir: None,
+            allowed_env_vars: None,
+            timeout: None,
+        }
+    }
+
+    /// A CommandExecutor that can't run any command until they are allowed with
+    /// `with_allowed_commands`. Only the `PATH` environment variable is passed to the
+    /// commands, and they are stopped after 30 seconds.
+    /// # Example
+    /// ```rust,ignore
+    /// let tool = CommandExecutor::strict("linux")
+    ///     .with_allowed_commands(&["ls", "cat"])
+    ///     .with_working_dir("/srv/agent");
+    /// ```
+    pub fn strict<S: Into<String>>(platform: S) -> Self {
+        Self::new(platform)
+            .with_allowed_commands::<&str>(&[])
+            .with_allowed_env_vars(&["PATH"])
+            .with_timeout(STRICT_TIMEOUT)
+    }
+
+    /// Names of the binaries the agent can run, like `ls`. Commands given as a path are
+    /// rejected when an allowlist is set.
+    pub fn with_allowed_commands<S: AsRef<str>>(mut self, commands: &[S]) -> Self {
+        self.allowed_commands = Some(commands.iter().map(|c| c.as_ref().to_string()).collect());
+        self
+    }
+
+    /// Directory the commands run in. Arguments that are paths outside of it, absolute or
+    /// with `..`, are rejected, and so are the values of options like `--file=../x`,
+    /// `-o/etc/x` or `if=/etc/x`.
+    ///
+    /// This is not a sandbox: only the arguments are checked, a command can still reach
+    /// files outside of the directory on its own, for example through a symlink or a
+    /// script. Run the agent in a container to isolate it.
+    pub fn with_working_dir<P: Into<PathBuf>>(mut self, working_dir: P) -> Self {
+        self.working_dir = Some(working_dir.into());
+        self
+    }
+
+    /// Environment variables passed to the commands, the rest of the environment of the
+    /// process is removed.
+    pub fn with_allowed_env_vars<S: AsRef<str>>(mut self, env_vars: &[S]) -> Self {
+        self.allowed_env_vars = Some(env_vars.iter().map(|v| v.as_ref().to_string()).collect());
+        self
+    }
+
+    /// Maximum time a command can run, it is killed after it.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Checks the command against the allowlist and the working directory.
+    fn validate(&self, command: &CommandInput) -> Result<(), String> {
+        if let Some(allowed_commands) = &self.allowed_commands {
+            if !allowed_commands.contains(&command.cmd) {
+                return Err(format!(
+                    "Command {} is not allowed, the allowed commands are: {}",
+                    command.cmd,
+                    allowed_commands.join(", ")
+                ));
+            }
         }
+        if let Some(working_dir) = &self.working_dir {
+            for arg in &command.args {
+                if arg_paths(arg).any(|path| escapes_dir(working_dir, path)) {
+                    return Err(format!(
+                        "Argument {} of command {} is outside of the working directory",
+                        arg, command.cmd
+                    ));
+                }
+            }
+        }
+        Ok(())
     }
 }
 
+/// The parts of an argument that can be paths: the argument itself, the value of a
+/// `--name=value` or `name=value` argument, and the value attached to a short option like
+/// `-o/etc/x`.
+fn arg_paths(arg: &str) -> impl Iterator<Item = &str> {
+    let value = arg.split_once('=').map(|(_, value)| value);
+    let attached = match arg.strip_prefix('-') {
+        Some(option) if !option.starts_with('-') && option.len() > 1 => {
+            option.get(1..).filter(|value| !value.is_empty())
+        }
+        _ => None,
+    };
+    std::iter::once(arg).chain(value).chain(attached)
+}
+
+/// Whether the argument, read as a path relative to `dir`, points outside of it. The check
+/// is lexical, symlinks inside of the directory are not followed.
+fn escapes_dir(dir: &Path, arg: &str) -> bool {
+    let path = Path::new(arg);
+    if path.is_absolute() {
+        return !normalize(path).starts_with(normalize(dir));
+    }
+    let mut depth = 0usize;
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                if depth == 0 {
+                    return true;
+                }
+                depth -= 1;
+            }
+            Component::Normal(_) => depth += 1,
+            _ => {}
+        }
+    }
+    false
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
 impl Default for CommandExecutor {
     fn default() -> Self {
         Self::new("linux")
@@ -100,7 +241,7 @@ impl Tool for CommandExecutor {
     }
 
     async fn parse_input(&self, input: &str) -> Value {
-        log::info!("Parsing input: {}", input);
+        log::debug!("Parsing input: {}", input);
 
         // Attempt to parse input string into CommandsWrapper struct first
         let wrapper_result = serde_json::from_str::<CommandsWrapper>(input);
@@ -129,13 +270,32 @@ impl Tool for CommandExecutor {
 
     async fn run(&self, input: Value) -> Result<String, Box<dyn Error>> {
         let commands: Vec<CommandInput> = serde_json::from_value(input)?;
+        // Nothing runs if one of the commands is not allowed
+        for command in &commands {
+            self.validate(command)?;
+        }
         let mut result = String::new();
 
         for command in commands {
-            let mut command_to_execute = std::process::Command::new(&command.cmd);
-            command_to_execute.args(&command.args);
+            let mut command_to_execute = tokio::process::Command::new(&command.cmd);
+            command_to_execute.args(&command.args).kill_on_drop(true);
+            if let Some(working_dir) = &self.working_dir {
+                command_to_execute.current_dir(working_dir);
+            }
+            if let Some(allowed_env_vars) = &self.allowed_env_vars {
+                command_to_execute
+                    .env_clear()
+                    .envs(std::env::vars().filter(|(name, _)| allowed_env_vars.contains(name)));
+            }
 
-            let output = command_to_execute.output()?;
+            let output = match self.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, command_to_execute.output())
+                    .await
+                    .map_err(|_| {
+                        format!("Command {} timed out after {:?}", command.cmd, timeout)
+                    })??,
+                None => command_to_execute.output().await?,
+            };
 
             result.push_str(&format!(
                 "Command: {}\nOutput: {}",
@@ -177,4 +337,57 @@ mod test {
         let result = tool.call(&input.to_string()).await.unwrap();
         println!("Res: {}", result);
     }
+
+    #[test]
+    fn test_escapes_dir() {
+        let dir = Path::new("/srv/agent");
+        assert!(!escapes_dir(dir, "notes/todo.txt"));
+        assert!(!escapes_dir(dir, "notes/../todo.txt"));
+        assert!(!escapes_dir(dir, "/srv/agent/todo.txt"));
+        assert!(escapes_dir(dir, "../secrets"));
+        assert!(escapes_dir(dir, "notes/../../secrets"));
+        assert!(escapes_dir(dir, "/etc/passwd"));
+        assert!(escapes_dir(dir, "/srv/agent/../other"));
+    }
+
+    #[test]
+    fn test_option_values_are_checked() {
+        let tool = CommandExecutor::new("linux").with_working_dir("/srv/agent");
+        let validate = |args: &[&str]| {
+            tool.validate(&CommandInput {
+                cmd: "cat".to_string(),
+                args: args.iter().map(|arg| arg.to_string()).collect(),
+            })
+        };
+
+        assert!(validate(&["-n", "--file=notes/todo.txt", "-onotes/out"]).is_ok());
+        assert!(validate(&["-rf", "notes"]).is_ok());
+        assert!(validate(&["--file=../../etc/passwd"]).is_err());
+        assert!(validate(&["-o/etc/x"]).is_err());
+        assert!(validate(&["-o../x"]).is_err());
+        assert!(validate(&["if=/etc/passwd"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_strict_executor() {
+        let tool = CommandExecutor::strict("linux").with_allowed_commands(&["echo"]);
+
+        let error = tool
+            .call(&json!([{ "cmd": "rm", "args": ["-rf", "x"] }]).to_string())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("rm is not allowed"));
+
+        let error = tool
+            .call(&json!([{ "cmd": "/bin/echo", "args": [] }]).to_string())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("is not allowed"));
+
+        let result = tool
+            .call(&json!([{ "cmd": "echo", "args": ["hi"] }]).to_string())
+            .await
+            .unwrap();
+        assert!(result.contains("hi"));
+    }
 }
@@ -9,6 +9,7 @@ use url::Url;
 
 use crate::tools::Tool;
 
+#[derive(Debug, Clone)]
 pub struct DuckDuckGoSearchResults {
     url: String,
     client: Client,
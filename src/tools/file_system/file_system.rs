@@ -0,0 +1,340 @@
+use std::{
+    error::Error,
+    io::ErrorKind,
+    path::{Component, Path, PathBuf},
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::io::AsyncReadExt;
+
+use crate::tools::Tool;
+
+const DEFAULT_MAX_READ_BYTES: usize = 20_000;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum FileSystemOperation {
+    Read,
+    Write,
+    List,
+    Exists,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct FileSystemInput {
+    op: FileSystemOperation,
+    #[serde(default)]
+    path: String,
+    content: Option<String>,
+}
+
+/// Lets the agent read, write and list files under a root directory. Paths are relative
+/// to the root, and paths that point outside of it, with `..` or through symlinks, are
+/// rejected.
+/// # Example
+/// ```rust,ignore
+/// let tool = FileSystemTool::new("/srv/agent")
+///     .with_read_only(true)
+///     .with_max_read_bytes(10_000);
+/// ```
+#[derive(Debug, Clone)]
+pub struct FileSystemTool {
+    root: PathBuf,
+    read_only: bool,
+    max_read_bytes: usize,
+}
+
+impl FileSystemTool {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self {
+            root: root.into(),
+            read_only: false,
+            max_read_bytes: DEFAULT_MAX_READ_BYTES,
+        }
+    }
+
+    /// Rejects the `write` operation.
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Maximum number of bytes returned by `read`, longer files are truncated.
+    pub fn with_max_read_bytes(mut self, max_read_bytes: usize) -> Self {
+        self.max_read_bytes = max_read_bytes;
+        self
+    }
+
+    /// Resolves the path of the agent to a path inside of the root, following the symlinks
+    /// of the part of the path that exists.
+    async fn resolve(&self, path: &str) -> Result<PathBuf, Box<dyn Error>> {
+        let root = tokio::fs::canonicalize(&self.root).await.map_err(|e| {
+            format!(
+                "Root directory {} is not available: {}",
+                self.root.display(),
+                e
+            )
+        })?;
+        let outside_root = || format!("Path {} is outside of the root directory", path);
+
+        let requested = normalize(&root.join(path));
+        if !requested.starts_with(&root) {
+            return Err(outside_root().into());
+        }
+
+        let mut existing = requested.clone();
+        let mut missing = Vec::new();
+        let resolved = loop {
+            match tokio::fs::canonicalize(&existing).await {
+                Ok(resolved) => break resolved,
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    // A broken symlink could point anywhere once the file is created
+                    if tokio::fs::symlink_metadata(&existing).await.is_ok() {
+                        return Err(format!("Path {} is a broken link", path).into());
+                    }
+                    match (existing.file_name(), existing.parent()) {
+                        (Some(name), Some(parent)) => {
+                            missing.push(name.to_os_string());
+                            existing = parent.to_path_buf();
+                        }
+                        _ => return Err(io_error(path, e).into()),
+                    }
+                }
+                Err(e) => return Err(io_error(path, e).into()),
+            }
+        };
+        if !resolved.starts_with(&root) {
+            return Err(outside_root().into());
+        }
+
+        Ok(missing
+            .iter()
+            .rev()
+            .fold(resolved, |resolved, name| resolved.join(name)))
+    }
+
+    async fn read(&self, path: &str) -> Result<String, Box<dyn Error>> {
+        let resolved = self.resolve(path).await?;
+        let metadata = tokio::fs::metadata(&resolved)
+            .await
+            .map_err(|e| io_error(path, e))?;
+        if metadata.is_dir() {
+            return Err(format!("Path {} is a directory, use list", path).into());
+        }
+
+        let file = tokio::fs::File::open(&resolved)
+            .await
+            .map_err(|e| io_error(path, e))?;
+        let mut content = Vec::new();
+        file.take(self.max_read_bytes as u64)
+            .read_to_end(&mut content)
+            .await
+            .map_err(|e| io_error(path, e))?;
+
+        let mut content = String::from_utf8_lossy(&content).into_owned();
+        if metadata.len() > self.max_read_bytes as u64 {
+            content.push_str(&format!(
+                "\n[Truncated: the file has {} bytes, only the first {} are shown]",
+                metadata.len(),
+                self.max_read_bytes
+            ));
+        }
+        Ok(content)
+    }
+
+    async fn write(&self, path: &str, content: Option<&str>) -> Result<String, Box<dyn Error>> {
+        if self.read_only {
+            return Err("Permission denied: the file system is read only".into());
+        }
+        let content = content.ok_or("The write operation requires a content")?;
+        let resolved = self.resolve(path).await?;
+        if let Some(parent) = resolved.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| io_error(path, e))?;
+        }
+        tokio::fs::write(&resolved, content)
+            .await
+            .map_err(|e| io_error(path, e))?;
+        Ok(format!("Wrote {} bytes to {}", content.len(), path))
+    }
+
+    async fn list(&self, path: &str) -> Result<String, Box<dyn Error>> {
+        let resolved = self.resolve(path).await?;
+        let mut entries = tokio::fs::read_dir(&resolved)
+            .await
+            .map_err(|e| io_error(path, e))?;
+        let mut names = Vec::new();
+        while let Some(entry) = entries.next_entry().await.map_err(|e| io_error(path, e))? {
+            let mut name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type().await.map(|t| t.is_dir()).unwrap_or(false) {
+                name.push('/');
+            }
+            names.push(name);
+        }
+        if names.is_empty() {
+            return Ok(format!("The directory {} is empty", path));
+        }
+        names.sort();
+        Ok(names.join("\n"))
+    }
+
+    async fn exists(&self, path: &str) -> Result<String, Box<dyn Error>> {
+        let resolved = self.resolve(path).await?;
+        Ok(tokio::fs::try_exists(&resolved)
+            .await
+            .map_err(|e| io_error(path, e))?
+            .to_string())
+    }
+}
+
+fn io_error(path: &str, error: std::io::Error) -> String {
+    match error.kind() {
+        ErrorKind::PermissionDenied => format!("Permission denied: {}", path),
+        ErrorKind::NotFound => format!("Path {} does not exist", path),
+        _ => format!("Path {}: {}", path, error),
+    }
+}
+
+/// Removes the `.` and `..` of the path without reading the file system.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[async_trait]
+impl Tool for FileSystemTool {
+    fn name(&self) -> String {
+        String::from("File_System")
+    }
+
+    fn description(&self) -> String {
+        String::from(
+            r#"This tool lets you read, write and list files.
+            The input should be a JSON object with the operation "op" (read, write, list or exists),
+            the "path" relative to the working directory and the "content" to write.
+            Example of input: { "op": "write", "path": "notes/todo.txt", "content": "Buy milk" }"#,
+        )
+    }
+
+    fn parameters(&self) -> Value {
+        json!({
+            "description": "Reads, writes and lists files of the working directory",
+            "type": "object",
+            "properties": {
+                "op": {
+                    "type": "string",
+                    "enum": ["read", "write", "list", "exists"],
+                    "description": "The operation to do"
+                },
+                "path": {
+                    "type": "string",
+                    "description": "Path of the file or directory, relative to the working directory"
+                },
+                "content": {
+                    "type": "string",
+                    "description": "The content of the file, for the write operation"
+                }
+            },
+            "required": ["op", "path"],
+            "additionalProperties": false
+        })
+    }
+
+    async fn parse_input(&self, input: &str) -> Value {
+        serde_json::from_str(input).unwrap_or_else(|_| Value::String(input.to_string()))
+    }
+
+    async fn run(&self, input: Value) -> Result<String, Box<dyn Error>> {
+        let input: FileSystemInput = serde_json::from_value(input)
+            .map_err(|e| format!("Invalid input, expected {{ op, path, content }}: {}", e))?;
+        match input.op {
+            FileSystemOperation::Read => self.read(&input.path).await,
+            FileSystemOperation::Write => self.write(&input.path, input.content.as_deref()).await,
+            FileSystemOperation::List => self.list(&input.path).await,
+            FileSystemOperation::Exists => self.exists(&input.path).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    async fn call(tool: &FileSystemTool, input: Value) -> Result<String, String> {
+        tool.call(&input.to_string())
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    #[tokio::test]
+    async fn test_file_system_tool() {
+        let root = env::temp_dir().join("file_system_tool_test");
+        let _ = tokio::fs::remove_dir_all(&root).await;
+        tokio::fs::create_dir_all(&root).await.unwrap();
+        let tool = FileSystemTool::new(&root).with_max_read_bytes(5);
+
+        let result = call(
+            &tool,
+            json!({"op": "write", "path": "notes/todo.txt", "content": "Buy milk"}),
+        )
+        .await;
+        assert_eq!(result.unwrap(), "Wrote 8 bytes to notes/todo.txt");
+
+        let result = call(
+            &tool,
+            json!({"op": "read", "path": "notes/../notes/todo.txt"}),
+        )
+        .await;
+        assert!(result.unwrap().starts_with("Buy m\n[Truncated"));
+
+        let result = call(&tool, json!({"op": "list", "path": ""})).await;
+        assert_eq!(result.unwrap(), "notes/");
+
+        let result = call(&tool, json!({"op": "exists", "path": "notes/done.txt"})).await;
+        assert_eq!(result.unwrap(), "false");
+
+        for path in ["../outside.txt", "notes/../../outside.txt", "/etc/passwd"] {
+            let result = call(&tool, json!({"op": "read", "path": path})).await;
+            assert!(
+                result.unwrap_err().contains("outside of the root"),
+                "{}",
+                path
+            );
+        }
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(env::temp_dir(), root.join("link")).unwrap();
+            let result = call(
+                &tool,
+                json!({"op": "write", "path": "link/escaped.txt", "content": "x"}),
+            )
+            .await;
+            assert!(result.unwrap_err().contains("outside of the root"));
+        }
+
+        let tool = FileSystemTool::new(&root).with_read_only(true);
+        let result = call(
+            &tool,
+            json!({"op": "write", "path": "notes/todo.txt", "content": ""}),
+        )
+        .await;
+        assert!(result.unwrap_err().contains("Permission denied"));
+
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+}
@@ -0,0 +1,2 @@
+mod file_system;
+pub use file_system::*;
@@ -19,5 +19,11 @@ pub use serpapi::*;
 mod command_executor;
 pub use command_executor::*;
 
+mod file_system;
+pub use file_system::*;
+
+mod code_interpreter;
+pub use code_interpreter::*;
+
 mod text2speech;
 pub use text2speech::*;
@@ -6,6 +6,7 @@ use std::{error::Error, sync::Arc};
 
 use crate::tools::Tool;
 
+#[derive(Debug, Clone)]
 pub struct WebScrapper {}
 
 impl WebScrapper {
@@ -1,10 +1,11 @@
-use std::error::Error;
+use std::{error::Error, fmt};
 
 use async_trait::async_trait;
 use serde_json::Value;
 
 use crate::tools::Tool;
 
+#[derive(Clone)]
 pub struct SerpApi {
     api_key: String,
     location: Option<String>,
@@ -13,6 +14,17 @@ pub struct SerpApi {
     google_domain: Option<String>,
 }
 
+impl fmt::Debug for SerpApi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SerpApi")
+            .field("location", &self.location)
+            .field("hl", &self.hl)
+            .field("gl", &self.gl)
+            .field("google_domain", &self.google_domain)
+            .finish_non_exhaustive()
+    }
+}
+
 impl SerpApi {
     pub fn new(api_key: String) -> Self {
         Self {
@@ -4,6 +4,7 @@ use std::error::Error;
 
 use crate::tools::{Dialect, Engine};
 
+#[derive(Debug, Clone)]
 pub struct PostgreSQLEngine {
     pool: Pool<Postgres>,
 }
@@ -1,9 +1,9 @@
-use std::{collections::HashSet, error::Error};
+use std::{collections::HashSet, error::Error, fmt};
 
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Dialect {
     #[serde(rename = "mysql")]
     MySQL,
@@ -43,6 +43,16 @@ pub struct SQLDatabase {
     pub all_tables: HashSet<String>,
 }
 
+impl fmt::Debug for SQLDatabase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SQLDatabase")
+            .field("dialect", &self.engine.dialect())
+            .field("sample_rows_number", &self.sample_rows_number)
+            .field("all_tables", &self.all_tables)
+            .finish()
+    }
+}
+
 pub struct SQLDatabaseBuilder {
     engine: Box<dyn Engine>,
     sample_rows_number: i32,
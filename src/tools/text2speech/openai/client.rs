@@ -1,4 +1,4 @@
-use std::{error::Error, sync::Arc};
+use std::{error::Error, fmt, sync::Arc};
 
 use async_openai::types::CreateSpeechRequestArgs;
 use async_openai::Client;
@@ -21,6 +21,17 @@ pub struct Text2SpeechOpenAI<C: Config> {
     path: String,
 }
 
+impl<C: Config> fmt::Debug for Text2SpeechOpenAI<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Text2SpeechOpenAI")
+            .field("model", &self.model)
+            .field("voice", &self.voice)
+            .field("response_format", &self.response_format)
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
 impl<C: Config> Text2SpeechOpenAI<C> {
     pub fn new(config: C) -> Self {
         Self {
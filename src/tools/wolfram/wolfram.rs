@@ -2,7 +2,7 @@ use async_trait::async_trait;
 use serde_json::Value;
 
 use crate::tools::Tool;
-use std::error::Error;
+use std::{error::Error, fmt};
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 struct WolframError {
@@ -77,12 +77,21 @@ impl From<Subpod> for String {
 }
 
 /// When being used within agents GPT4 is recommended
+#[derive(Clone)]
 pub struct Wolfram {
     app_id: String,
     exclude_pods: Vec<String>,
     client: reqwest::Client,
 }
 
+impl fmt::Debug for Wolfram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Wolfram")
+            .field("exclude_pods", &self.exclude_pods)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Wolfram {
     pub fn new(app_id: String) -> Self {
         Self {
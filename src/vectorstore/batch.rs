@@ -0,0 +1,183 @@
+use std::{error::Error, future::Future, ops::Range};
+
+use futures::{stream, StreamExt};
+use thiserror::Error;
+
+use crate::schemas::Document;
+
+use super::VecStoreOptions;
+
+/// Returned by `add_documents` when some batches of documents could not be added. Each
+/// batch is added on its own, the documents of the other batches are stored.
+#[derive(Debug, Error)]
+#[error("{} batches of documents failed to be added: {:?}", .errors.len(), .errors)]
+pub struct AddDocumentsError {
+    /// The id of each document, in the order of the documents. `None` for the documents of
+    /// the batches that failed.
+    pub ids: Vec<Option<String>>,
+    /// The range of the documents of each failed batch, with its error.
+    pub errors: Vec<(Range<usize>, String)>,
+}
+
+/// Splits the documents in batches of `batch_size` of the options and adds them with
+/// `add_batch`, at most `max_concurrency` batches at a time. `add_batch` is given its own
+/// copy of the documents of the batch, returns their ids in order, and should store all of
+/// them or none.
+///
+/// The `progress` of the options is called after each batch, failed or not, in the order
+/// of the batches.
+///
+/// Returns the ids of all the documents in order, or an `AddDocumentsError` listing the
+/// ids of the batches that were added.
+pub async fn add_documents_in_batches<F, Fut>(
+    docs: &[Document],
+    opt: &VecStoreOptions,
+    add_batch: F,
+) -> Result<Vec<String>, Box<dyn Error>>
+where
+    F: Fn(Vec<Document>) -> Fut,
+    Fut: Future<Output = Result<Vec<String>, String>>,
+{
+    add_in_batches(docs, opt, add_batch, String::clone).await
+}
+
+/// Like `add_documents_in_batches`, for the stores that embed the documents themselves:
+/// `add_batch` returns the id of each document of the batch with the embedding it was
+/// stored with.
+pub async fn add_documents_with_embeddings_in_batches<F, Fut>(
+    docs: &[Document],
+    opt: &VecStoreOptions,
+    add_batch: F,
+) -> Result<Vec<(String, Vec<f64>)>, Box<dyn Error>>
+where
+    F: Fn(Vec<Document>) -> Fut,
+    Fut: Future<Output = Result<Vec<(String, Vec<f64>)>, String>>,
+{
+    add_in_batches(docs, opt, add_batch, |(id, _)| id.clone()).await
+}
+
+async fn add_in_batches<T, F, Fut>(
+    docs: &[Document],
+    opt: &VecStoreOptions,
+    add_batch: F,
+    id_of: fn(&T) -> String,
+) -> Result<Vec<T>, Box<dyn Error>>
+where
+    F: Fn(Vec<Document>) -> Fut,
+    Fut: Future<Output = Result<Vec<T>, String>>,
+{
+    if docs.is_empty() {
+        return Ok(Vec::new());
+    }
+    let batch_size = opt.batch_size.unwrap_or(docs.len()).max(1);
+
+    // The batches are owned, the futures of `add_batch` don't borrow the documents
+    let batches: Vec<Vec<Document>> = docs.chunks(batch_size).map(<[Document]>::to_vec).collect();
+    let mut results = stream::iter(batches)
+        .map(|batch| {
+            let len = batch.len();
+            let add = add_batch(batch);
+            async move {
+                let added = add.await?;
+                if added.len() != len {
+                    return Err("Number of ids and documents do not match".to_string());
+                }
+                Ok(added)
+            }
+        })
+        .buffered(opt.max_concurrency.max(1))
+        .enumerate();
+
+    let mut added = Vec::with_capacity(docs.len());
+    let mut errors = Vec::new();
+    while let Some((i, result)) = results.next().await {
+        let start = i * batch_size;
+        let end = (start + batch_size).min(docs.len());
+        match result {
+            Ok(batch) => added.extend(batch.into_iter().map(Some)),
+            Err(error) => {
+                log::warn!("Failed to add documents {}..{}: {}", start, end, error);
+                added.resize_with(end, || None);
+                errors.push((start..end, error));
+            }
+        }
+        if let Some(progress) = &opt.progress {
+            progress.report(end, docs.len());
+        }
+    }
+
+    if !errors.is_empty() {
+        let ids = added.iter().map(|item| item.as_ref().map(id_of)).collect();
+        return Err(Box::new(AddDocumentsError { ids, errors }));
+    }
+    Ok(added.into_iter().flatten().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_add_documents_in_batches() {
+        let docs: Vec<Document> = ["a", "b", "c", "d", "e"]
+            .map(Document::new)
+            .into_iter()
+            .collect();
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let opt = VecStoreOptions::new()
+            .with_batch_size(2)
+            .with_max_concurrency(2)
+            .with_progress({
+                let reports = reports.clone();
+                move |done, total| reports.lock().unwrap().push((done, total))
+            });
+        let add_batch = |batch: Vec<Document>| {
+            let (running, max_running) = (&running, &max_running);
+            let contents: Vec<String> = batch.iter().map(|d| d.page_content.clone()).collect();
+            async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(now, Ordering::SeqCst);
+                tokio::task::yield_now().await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                if contents.contains(&"c".to_string()) {
+                    return Err("c can't be stored".to_string());
+                }
+                Ok(contents.iter().map(|c| format!("id-{}", c)).collect())
+            }
+        };
+
+        let ids = add_documents_in_batches(&docs[..2], &opt, add_batch)
+            .await
+            .unwrap();
+        assert_eq!(ids, vec!["id-a", "id-b"]);
+
+        let error = add_documents_in_batches(&docs, &opt, add_batch)
+            .await
+            .unwrap_err();
+        let error = error.downcast_ref::<AddDocumentsError>().unwrap();
+        assert_eq!(
+            error.ids,
+            vec![
+                Some("id-a".to_string()),
+                Some("id-b".to_string()),
+                None,
+                None,
+                Some("id-e".to_string())
+            ]
+        );
+        assert_eq!(error.errors.len(), 1);
+        assert_eq!(error.errors[0].0, 2..4);
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            *reports.lock().unwrap(),
+            vec![(2, 2), (2, 5), (4, 5), (5, 5)]
+        );
+    }
+}
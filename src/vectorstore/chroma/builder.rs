@@ -0,0 +1,134 @@
+use std::{error::Error, sync::Arc};
+
+use reqwest::{Client, Method};
+use serde_json::{json, Value};
+
+use crate::{embedding::embedder_trait::Embedder, vectorstore::DistanceMetric};
+
+use super::Store;
+
+const DEFAULT_URL: &str = "http://localhost:8000";
+const DEFAULT_TENANT: &str = "default_tenant";
+const DEFAULT_DATABASE: &str = "default_database";
+const DEFAULT_COLLECTION_NAME: &str = "langchain";
+
+pub struct StoreBuilder {
+    client: Option<Client>,
+    url: String,
+    auth_token: Option<String>,
+    tenant: String,
+    database: String,
+    collection_name: String,
+    embedder: Option<Arc<dyn Embedder>>,
+    distance_metric: DistanceMetric,
+}
+
+impl StoreBuilder {
+    // Returns a new StoreBuilder instance with default values for each option
+    pub fn new() -> Self {
+        StoreBuilder {
+            client: None,
+            url: DEFAULT_URL.into(),
+            auth_token: None,
+            tenant: DEFAULT_TENANT.into(),
+            database: DEFAULT_DATABASE.into(),
+            collection_name: DEFAULT_COLLECTION_NAME.into(),
+            embedder: None,
+            distance_metric: DistanceMetric::default(),
+        }
+    }
+
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Base url of the Chroma server, `http://localhost:8000` by default as in `chroma run`.
+    pub fn url(mut self, url: &str) -> Self {
+        self.url = url.trim_end_matches('/').into();
+        self
+    }
+
+    pub fn auth_token(mut self, auth_token: &str) -> Self {
+        self.auth_token = Some(auth_token.into());
+        self
+    }
+
+    pub fn tenant(mut self, tenant: &str) -> Self {
+        self.tenant = tenant.into();
+        self
+    }
+
+    pub fn database(mut self, database: &str) -> Self {
+        self.database = database.into();
+        self
+    }
+
+    pub fn collection_name(mut self, collection_name: &str) -> Self {
+        self.collection_name = collection_name.into();
+        self
+    }
+
+    /// The embeddings are always computed with this embedder, never by Chroma.
+    pub fn embedder<E: Embedder + 'static>(mut self, embedder: E) -> Self {
+        self.embedder = Some(Arc::new(embedder));
+        self
+    }
+
+    /// Distance used by the collection when it's created by the builder, and to normalize
+    /// the scores of the search results.
+    pub fn distance_metric(mut self, distance_metric: DistanceMetric) -> Self {
+        self.distance_metric = distance_metric;
+        self
+    }
+
+    // Finalize the builder and construct the Store object, the collection is created if it
+    // doesn't exist
+    pub async fn build(self) -> Result<Store, Box<dyn Error>> {
+        if self.embedder.is_none() {
+            return Err("Embedder is required".into());
+        }
+
+        let mut store = Store {
+            client: self.client.unwrap_or_default(),
+            url: self.url,
+            auth_token: self.auth_token,
+            tenant: self.tenant,
+            database: self.database,
+            collection_name: self.collection_name,
+            collection_id: String::new(),
+            embedder: self.embedder.unwrap(),
+            distance_metric: self.distance_metric,
+        };
+
+        let space = match store.distance_metric {
+            DistanceMetric::Cosine => "cosine",
+            DistanceMetric::L2 => "l2",
+            DistanceMetric::InnerProduct => "ip",
+        };
+        let collection = store
+            .request(Method::POST, "/collections")
+            .json(&json!({
+                "name": store.collection_name,
+                "metadata": { "hnsw:space": space },
+                "get_or_create": true,
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?;
+        store.collection_id = collection["id"]
+            .as_str()
+            .ok_or("Chroma didn't return a collection id")?
+            .to_string();
+
+        Ok(store)
+    }
+}
+
+impl Default for StoreBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,370 @@
+use std::{collections::HashMap, error::Error, sync::Arc};
+
+use async_trait::async_trait;
+use reqwest::{Client, Method, RequestBuilder};
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+
+use crate::{
+    embedding::embedder_trait::Embedder,
+    schemas::Document,
+    vectorstore::{DistanceMetric, VecStoreOptions, VectorStore},
+};
+
+// https://docs.trychroma.com/reference/python/client
+// The store uses the v2 HTTP API of Chroma, the one served by `chroma run`
+
+pub struct Store {
+    pub(crate) client: Client,
+    pub(crate) url: String,
+    pub(crate) auth_token: Option<String>,
+    pub(crate) tenant: String,
+    pub(crate) database: String,
+    pub(crate) collection_name: String,
+    pub(crate) collection_id: String,
+    pub(crate) embedder: Arc<dyn Embedder>,
+    pub(crate) distance_metric: DistanceMetric,
+}
+
+impl Store {
+    /// Request to a path relative to the tenant and database of the store.
+    pub(crate) fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self.client.request(
+            method,
+            format!(
+                "{}/api/v2/tenants/{}/databases/{}{}",
+                self.url, self.tenant, self.database, path
+            ),
+        );
+        match &self.auth_token {
+            Some(auth_token) => request.bearer_auth(auth_token),
+            None => request,
+        }
+    }
+
+    async fn collection_request(
+        &self,
+        operation: &str,
+        body: Value,
+    ) -> Result<Value, Box<dyn Error>> {
+        let response = self
+            .request(
+                Method::POST,
+                &format!("/collections/{}/{}", self.collection_id, operation),
+            )
+            .json(&body)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await?;
+            return Err(format!("Chroma {} failed: {} {}", operation, status, body).into());
+        }
+        Ok(response.json::<Value>().await?)
+    }
+
+    /// Chroma is queried with distances, converted to scores where higher is more similar.
+    /// Chroma's `ip` distance is `1 - inner product`, and its `l2` distance is squared.
+    fn score(&self, distance: f64) -> f64 {
+        match self.distance_metric {
+            DistanceMetric::InnerProduct => 1.0 - distance,
+            DistanceMetric::L2 => DistanceMetric::squared_l2_score(distance),
+            metric => metric.score(distance),
+        }
+    }
+}
+
+/// Chroma only accepts strings, numbers and booleans as metadata values, other values are
+/// stored as JSON strings and null values are dropped.
+fn chroma_metadata(metadata: &HashMap<String, Value>) -> Value {
+    if metadata.is_empty() {
+        return Value::Null;
+    }
+    let metadata = metadata
+        .iter()
+        .filter(|(_, value)| !value.is_null())
+        .map(|(key, value)| {
+            let value = match value {
+                Value::Array(_) | Value::Object(_) => Value::from(value.to_string()),
+                value => value.clone(),
+            };
+            (key.clone(), value)
+        })
+        .collect::<Map<_, _>>();
+    Value::Object(metadata)
+}
+
+fn document_from_result(content: &Value, metadata: &Value) -> Document {
+    let metadata = serde_json::from_value(metadata.clone()).unwrap_or_default();
+    Document::new(content.as_str().unwrap_or_default()).with_metadata(metadata)
+}
+
+#[async_trait]
+impl VectorStore for Store {
+    /// Add documents to the store, the embeddings are computed with the embedder of the
+    /// store or the one in the options.
+    /// Returns a list of the ids of the documents added to the collection.
+    async fn add_documents(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let added = self.add_documents_with_embeddings(docs, opt).await?;
+        Ok(added.into_iter().map(|(id, _)| id).collect())
+    }
+
+    async fn add_documents_with_embeddings(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<(String, Vec<f64>)>, Box<dyn Error>> {
+        if docs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let texts: Vec<String> = docs.iter().map(|d| d.page_content.clone()).collect();
+        let embeddings = embedder.embed_documents(&texts).await?;
+
+        if embeddings.len() != docs.len() {
+            return Err("Number of vectors and documents do not match".into());
+        }
+
+        let ids: Vec<String> = docs.iter().map(|_| Uuid::new_v4().to_string()).collect();
+        let metadatas: Vec<Value> = docs.iter().map(|d| chroma_metadata(&d.metadata)).collect();
+
+        self.collection_request(
+            "add",
+            json!({
+                "ids": ids,
+                "embeddings": embeddings,
+                "documents": texts,
+                "metadatas": metadatas,
+            }),
+        )
+        .await?;
+
+        Ok(ids.into_iter().zip(embeddings).collect())
+    }
+
+    /// Perform a similarity search on the store.
+    /// `filters` are Chroma `where` filters, for example `{"source": "a.txt"}` or
+    /// `{"$and": [{"page": {"$gt": 2}}, {"source": "a.txt"}]}`.
+    async fn similarity_search(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        if opt.name_space.is_some() {
+            return Err("Chroma doesn't support namespaces, use a collection instead".into());
+        }
+
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let query_embedding = embedder.embed_query(query).await?;
+
+        let mut include = vec!["documents", "metadatas", "distances"];
+        if opt.include_embeddings {
+            include.push("embeddings");
+        }
+        let mut body = json!({
+            "query_embeddings": [query_embedding],
+            "n_results": limit,
+            "include": include,
+        });
+        let metadata_filter = opt
+            .metadata_filter
+            .as_ref()
+            .map(|filter| filter.to_operator_json("Chroma"))
+            .transpose()?;
+        match (&opt.filters, metadata_filter) {
+            (Some(filters), Some(filter)) => body["where"] = json!({ "$and": [filters, filter] }),
+            (Some(filters), None) => body["where"] = filters.clone(),
+            (None, Some(filter)) => body["where"] = filter,
+            (None, None) => {}
+        }
+
+        let response = self.collection_request("query", body).await?;
+
+        // The results are lists with one entry per query embedding
+        let ids = response["ids"][0].as_array().cloned().unwrap_or_default();
+        let mut documents = Vec::with_capacity(ids.len());
+        for i in 0..ids.len() {
+            let distance = response["distances"][0][i].as_f64().unwrap_or_default();
+            let mut doc =
+                document_from_result(&response["documents"][0][i], &response["metadatas"][0][i])
+                    .with_score(self.score(distance));
+            doc.embedding = serde_json::from_value(response["embeddings"][0][i].clone()).ok();
+
+            if let Some(score_threshold) = opt.score_threshold {
+                if doc.score < score_threshold as f64 {
+                    continue;
+                }
+            }
+            documents.push(doc);
+        }
+
+        Ok(documents)
+    }
+
+    async fn get_by_ids(&self, ids: &[String]) -> Result<Vec<Document>, Box<dyn Error>> {
+        let response = self
+            .collection_request(
+                "get",
+                json!({ "ids": ids, "include": ["documents", "metadatas"] }),
+            )
+            .await?;
+
+        let found = response["ids"].as_array().cloned().unwrap_or_default();
+        let mut docs = found
+            .iter()
+            .enumerate()
+            .map(|(i, id)| {
+                let mut doc =
+                    document_from_result(&response["documents"][i], &response["metadatas"][i]);
+                doc.metadata.insert("id".to_string(), id.clone());
+                doc
+            })
+            .collect::<Vec<_>>();
+
+        // Keep the order of the requested ids
+        docs.sort_by_key(|doc| ids.iter().position(|id| doc.metadata["id"] == *id));
+        Ok(docs)
+    }
+
+    async fn delete_documents(&self, ids: &[String]) -> Result<(), Box<dyn Error>> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        self.collection_request("delete", json!({ "ids": ids }))
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use mockito::Matcher;
+
+    use super::*;
+    use crate::{embedding::EmbedderError, vectorstore::chroma::StoreBuilder};
+
+    struct FakeEmbedder {}
+
+    #[async_trait]
+    impl Embedder for FakeEmbedder {
+        async fn embed_documents(
+            &self,
+            documents: &[String],
+        ) -> Result<Vec<Vec<f64>>, EmbedderError> {
+            Ok(documents.iter().map(|_| vec![1.0, 0.0]).collect())
+        }
+
+        async fn embed_query(&self, _text: &str) -> Result<Vec<f64>, EmbedderError> {
+            Ok(vec![0.0, 1.0])
+        }
+    }
+
+    #[test]
+    fn test_chroma_metadata() {
+        assert_eq!(chroma_metadata(&HashMap::new()), Value::Null);
+        let metadata = HashMap::from([
+            ("source".to_string(), json!("a.txt")),
+            ("page".to_string(), json!(2)),
+            ("tags".to_string(), json!(["a", "b"])),
+            ("empty".to_string(), Value::Null),
+        ]);
+        assert_eq!(
+            chroma_metadata(&metadata),
+            json!({"source": "a.txt", "page": 2, "tags": "[\"a\",\"b\"]"})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chroma_similarity_search() {
+        let mut server = mockito::Server::new_async().await;
+        let collections = "/api/v2/tenants/default_tenant/databases/default_database/collections";
+        server
+            .mock("POST", collections)
+            .match_body(Matcher::PartialJson(
+                json!({"name": "docs", "get_or_create": true}),
+            ))
+            .with_body(json!({"id": "c1", "name": "docs"}).to_string())
+            .create_async()
+            .await;
+        server
+            .mock("POST", format!("{}/c1/query", collections).as_str())
+            .match_body(Matcher::PartialJson(json!({
+                "query_embeddings": [[0.0, 1.0]],
+                "n_results": 2,
+                "where": {"source": "a.txt"},
+            })))
+            .with_body(
+                json!({
+                    "ids": [["1", "2"]],
+                    "documents": [["first", "second"]],
+                    "metadatas": [[{"source": "a.txt"}, null]],
+                    "distances": [[0.25, 0.75]],
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let store = StoreBuilder::new()
+            .url(&server.url())
+            .collection_name("docs")
+            .embedder(FakeEmbedder {})
+            .build()
+            .await
+            .unwrap();
+
+        let docs = store
+            .similarity_search(
+                "query",
+                2,
+                &VecStoreOptions::default().with_filters(json!({"source": "a.txt"})),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(docs.len(), 2);
+        assert_eq!(docs[0].page_content, "first");
+        assert_eq!(docs[0].score, 0.75);
+        assert_eq!(docs[0].metadata["source"], json!("a.txt"));
+        assert!(docs[1].metadata.is_empty());
+        assert_eq!(docs[1].score, 0.25);
+        assert!(docs[0].embedding.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_chroma_add_no_documents() {
+        let mut server = mockito::Server::new_async().await;
+        let collections = "/api/v2/tenants/default_tenant/databases/default_database/collections";
+        server
+            .mock("POST", collections)
+            .with_body(json!({"id": "c1", "name": "docs"}).to_string())
+            .create_async()
+            .await;
+        let add = server
+            .mock("POST", Matcher::Regex(format!("^{}/c1/", collections)))
+            .expect(0)
+            .create_async()
+            .await;
+
+        let store = StoreBuilder::new()
+            .url(&server.url())
+            .collection_name("docs")
+            .embedder(FakeEmbedder {})
+            .build()
+            .await
+            .unwrap();
+        let ids = store
+            .add_documents(&[], &VecStoreOptions::default())
+            .await
+            .unwrap();
+
+        assert!(ids.is_empty());
+        add.assert_async().await;
+    }
+}
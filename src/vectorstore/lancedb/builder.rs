@@ -0,0 +1,130 @@
+use std::{error::Error, sync::Arc};
+
+use ::lancedb::connect;
+use arrow_schema::{DataType, Field, Schema};
+
+use crate::{embedding::embedder_trait::Embedder, vectorstore::DistanceMetric};
+
+use super::{Store, CONTENT_COLUMN, ID_COLUMN, METADATA_COLUMN, VECTOR_COLUMN};
+
+const DEFAULT_TABLE_NAME: &str = "langchain";
+
+pub struct StoreBuilder {
+    path: Option<String>,
+    table_name: String,
+    vector_dimensions: i32,
+    embedder: Option<Arc<dyn Embedder>>,
+    filter_fields: Vec<String>,
+    distance_metric: DistanceMetric,
+}
+
+impl StoreBuilder {
+    // Returns a new StoreBuilder instance with default values for each option
+    pub fn new() -> Self {
+        StoreBuilder {
+            path: None,
+            table_name: DEFAULT_TABLE_NAME.into(),
+            vector_dimensions: 0,
+            embedder: None,
+            filter_fields: Vec::new(),
+            distance_metric: DistanceMetric::default(),
+        }
+    }
+
+    /// Local directory of the database, created if it doesn't exist.
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn table_name(mut self, table_name: &str) -> Self {
+        self.table_name = table_name.into();
+        self
+    }
+
+    /// Size of the embeddings, required to create the table.
+    pub fn vector_dimensions(mut self, vector_dimensions: i32) -> Self {
+        self.vector_dimensions = vector_dimensions;
+        self
+    }
+
+    pub fn embedder<E: Embedder + 'static>(mut self, embedder: E) -> Self {
+        self.embedder = Some(Arc::new(embedder));
+        self
+    }
+
+    /// Top level metadata keys also stored as columns of their own, so the filters can use
+    /// them, like `source = 'a.txt'`. Strings are stored as they are, the other values as
+    /// JSON. The fields are part of the table when it's created.
+    pub fn filter_fields(mut self, filter_fields: &[&str]) -> Self {
+        self.filter_fields = filter_fields.iter().map(|f| f.to_string()).collect();
+        self
+    }
+
+    /// Distance used by the searches, cosine by default.
+    pub fn distance_metric(mut self, distance_metric: DistanceMetric) -> Self {
+        self.distance_metric = distance_metric;
+        self
+    }
+
+    // Finalize the builder and construct the Store object, the table is created if it
+    // doesn't exist
+    pub async fn build(self) -> Result<Store, Box<dyn Error>> {
+        let path = self.path.ok_or("Path is required")?;
+        let embedder = self.embedder.ok_or("Embedder is required")?;
+        if self.vector_dimensions <= 0 {
+            return Err("Vector dimensions are required".into());
+        }
+        for field in &self.filter_fields {
+            if [ID_COLUMN, CONTENT_COLUMN, METADATA_COLUMN, VECTOR_COLUMN].contains(&field.as_str())
+            {
+                return Err(format!("The filter field {} is a column of the store", field).into());
+            }
+        }
+
+        let mut fields = vec![
+            Field::new(ID_COLUMN, DataType::Utf8, false),
+            Field::new(CONTENT_COLUMN, DataType::Utf8, false),
+            Field::new(METADATA_COLUMN, DataType::Utf8, false),
+            Field::new(
+                VECTOR_COLUMN,
+                DataType::FixedSizeList(
+                    Arc::new(Field::new("item", DataType::Float32, true)),
+                    self.vector_dimensions,
+                ),
+                false,
+            ),
+        ];
+        fields.extend(
+            self.filter_fields
+                .iter()
+                .map(|field| Field::new(field, DataType::Utf8, true)),
+        );
+        let schema = Arc::new(Schema::new(fields));
+
+        let db = connect(&path).execute().await?;
+        let table = match db.open_table(&self.table_name).execute().await {
+            Ok(table) => table,
+            Err(::lancedb::Error::TableNotFound { .. }) => {
+                db.create_empty_table(&self.table_name, schema)
+                    .execute()
+                    .await?
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Store {
+            schema: table.schema().await?,
+            table,
+            embedder,
+            vector_dimensions: self.vector_dimensions,
+            distance_metric: self.distance_metric,
+        })
+    }
+}
+
+impl Default for StoreBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
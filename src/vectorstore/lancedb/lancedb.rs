@@ -0,0 +1,492 @@
+use std::{error::Error, sync::Arc};
+
+use ::lancedb::{
+    query::{ExecutableQuery, QueryBase},
+    DistanceType, Table,
+};
+use arrow_array::{
+    types::Float32Type, Array, ArrayRef, FixedSizeListArray, Float32Array, RecordBatch,
+    RecordBatchIterator, StringArray,
+};
+use arrow_schema::SchemaRef;
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use serde_json::Value;
+
+use crate::{
+    embedding::embedder_trait::Embedder,
+    schemas::Document,
+    vectorstore::{
+        add_documents_with_embeddings_in_batches, DistanceMetric, IdStrategy, MetadataFilter,
+        UnsupportedFilterError, VecStoreOptions, VectorStore,
+    },
+};
+
+pub(crate) const ID_COLUMN: &str = "id";
+pub(crate) const CONTENT_COLUMN: &str = "text";
+/// Column holding the whole metadata of a document as a JSON string.
+pub(crate) const METADATA_COLUMN: &str = "metadata";
+pub(crate) const VECTOR_COLUMN: &str = "vector";
+/// Column LanceDB adds to the results of a vector search.
+const DISTANCE_COLUMN: &str = "_distance";
+
+// https://lancedb.github.io/lancedb/
+
+/// An embedded store, the table is kept in a local directory and no server is needed.
+pub struct Store {
+    pub(crate) table: Table,
+    pub(crate) schema: SchemaRef,
+    pub(crate) embedder: Arc<dyn Embedder>,
+    pub(crate) vector_dimensions: i32,
+    pub(crate) distance_metric: DistanceMetric,
+}
+
+impl Store {
+    /// The columns of the table holding top level metadata values, to filter on them.
+    fn filter_fields(&self) -> impl Iterator<Item = &str> {
+        self.schema
+            .fields()
+            .iter()
+            .map(|field| field.name().as_str())
+            .filter(|name| {
+                ![ID_COLUMN, CONTENT_COLUMN, METADATA_COLUMN, VECTOR_COLUMN].contains(name)
+            })
+    }
+
+    fn record_batch(
+        &self,
+        ids: &[String],
+        docs: &[Document],
+        vectors: &[Vec<f64>],
+    ) -> Result<RecordBatch, Box<dyn Error>> {
+        let metadata = docs
+            .iter()
+            .map(|doc| serde_json::to_string(&doc.metadata))
+            .collect::<Result<Vec<_>, _>>()?;
+        let vectors = FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+            vectors
+                .iter()
+                .map(|vector| Some(vector.iter().map(|x| Some(*x as f32)).collect::<Vec<_>>())),
+            self.vector_dimensions,
+        );
+
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from_iter_values(ids)),
+            Arc::new(StringArray::from_iter_values(
+                docs.iter().map(|doc| &doc.page_content),
+            )),
+            Arc::new(StringArray::from(metadata)),
+            Arc::new(vectors),
+        ];
+        for field in self.filter_fields() {
+            let values = docs.iter().map(|doc| match doc.metadata.get(field) {
+                Some(Value::String(value)) => Some(value.clone()),
+                Some(Value::Null) | None => None,
+                Some(value) => Some(value.to_string()),
+            });
+            columns.push(Arc::new(values.collect::<StringArray>()));
+        }
+        Ok(RecordBatch::try_new(self.schema.clone(), columns)?)
+    }
+
+    async fn add_batch(
+        &self,
+        docs: &[Document],
+        embedder: &dyn Embedder,
+        id_strategy: &IdStrategy,
+    ) -> Result<Vec<(String, Vec<f64>)>, Box<dyn Error>> {
+        let texts: Vec<String> = docs.iter().map(|d| d.page_content.clone()).collect();
+        let vectors = embedder.embed_documents(&texts).await?;
+
+        if vectors.len() != docs.len() {
+            return Err("Number of vectors and documents do not match".into());
+        }
+        if let Some(vector) = vectors
+            .iter()
+            .find(|v| v.len() != self.vector_dimensions as usize)
+        {
+            return Err(format!(
+                "Embedding has {} dimensions, the store expects {}",
+                vector.len(),
+                self.vector_dimensions
+            )
+            .into());
+        }
+
+        let ids: Vec<String> = docs
+            .iter()
+            .map(|doc| id_strategy.document_id(doc, self.table.name()))
+            .collect();
+        let batch = self.record_batch(&ids, docs, &vectors)?;
+        let reader = Box::new(RecordBatchIterator::new(
+            vec![Ok(batch)],
+            self.schema.clone(),
+        ));
+
+        match id_strategy {
+            IdStrategy::Random => {
+                self.table.add(reader).execute().await?;
+            }
+            IdStrategy::ContentHash { .. } => {
+                let mut merge = self.table.merge_insert(&[ID_COLUMN]);
+                merge
+                    .when_matched_update_all(None)
+                    .when_not_matched_insert_all();
+                merge.execute(reader).await?;
+            }
+        }
+
+        Ok(ids.into_iter().zip(vectors).collect())
+    }
+
+    fn distance_type(&self) -> DistanceType {
+        match self.distance_metric {
+            DistanceMetric::Cosine => DistanceType::Cosine,
+            DistanceMetric::L2 => DistanceType::L2,
+            DistanceMetric::InnerProduct => DistanceType::Dot,
+        }
+    }
+
+    /// LanceDB returns `1 - similarity` for the cosine and dot metrics, and the squared
+    /// distance for the euclidean metric.
+    fn score(distance_metric: DistanceMetric, distance: f64) -> f64 {
+        match distance_metric {
+            DistanceMetric::Cosine | DistanceMetric::InnerProduct => 1.0 - distance,
+            DistanceMetric::L2 => DistanceMetric::squared_l2_score(distance),
+        }
+    }
+
+    /// The documents of the batches of a query, with their score when the query was a
+    /// vector search.
+    fn documents(
+        batches: &[RecordBatch],
+        distance_metric: DistanceMetric,
+        include_embeddings: bool,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let mut docs = Vec::new();
+        for batch in batches {
+            let ids = string_column(batch, ID_COLUMN)?;
+            let contents = string_column(batch, CONTENT_COLUMN)?;
+            let metadata = string_column(batch, METADATA_COLUMN)?;
+            let distances = batch
+                .column_by_name(DISTANCE_COLUMN)
+                .and_then(|c| c.as_any().downcast_ref::<Float32Array>());
+            let vectors = batch
+                .column_by_name(VECTOR_COLUMN)
+                .and_then(|c| c.as_any().downcast_ref::<FixedSizeListArray>());
+
+            for row in 0..batch.num_rows() {
+                let mut doc = Document::new(contents.value(row))
+                    .with_metadata(serde_json::from_str(metadata.value(row)).unwrap_or_default());
+                doc.metadata
+                    .insert("id".to_string(), Value::from(ids.value(row)));
+                if let Some(distances) = distances {
+                    doc.score = Self::score(distance_metric, distances.value(row) as f64);
+                }
+                if let (true, Some(vectors)) = (include_embeddings, vectors) {
+                    let vector = vectors.value(row);
+                    doc.embedding = vector
+                        .as_any()
+                        .downcast_ref::<Float32Array>()
+                        .map(|values| values.values().iter().map(|x| *x as f64).collect());
+                }
+                docs.push(doc);
+            }
+        }
+        Ok(docs)
+    }
+}
+
+fn string_column<'a>(
+    batch: &'a RecordBatch,
+    name: &str,
+) -> Result<&'a StringArray, Box<dyn Error>> {
+    batch
+        .column_by_name(name)
+        .and_then(|c| c.as_any().downcast_ref::<StringArray>())
+        .ok_or_else(|| format!("The table has no {} column of strings", name).into())
+}
+
+/// `id IN ('a', 'b')`, with the quotes of the ids escaped.
+fn ids_predicate(ids: &[String]) -> String {
+    let ids = ids
+        .iter()
+        .map(|id| format!("'{}'", id.replace('\'', "''")))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("{} IN ({})", ID_COLUMN, ids)
+}
+
+/// The SQL predicate of the `MetadataFilter` on the filter fields, see
+/// `StoreBuilder::filter_fields`. The fields hold the values as strings, so `Gt` and `Lt`
+/// are not supported.
+fn filter_predicate(
+    filter: &MetadataFilter,
+    filter_fields: &[&str],
+) -> Result<String, Box<dyn Error>> {
+    let field = |key: &str| -> Result<String, Box<dyn Error>> {
+        if !filter_fields.contains(&key) {
+            return Err(format!("{} is not a filter field of the table", key).into());
+        }
+        Ok(key.to_string())
+    };
+    // The values as they are stored in the filter fields
+    let literal = |value: &Value| {
+        let value = match value {
+            Value::String(value) => value.clone(),
+            value => value.to_string(),
+        };
+        format!("'{}'", value.replace('\'', "''"))
+    };
+    let group = |filters: &[MetadataFilter],
+                 operator: &str,
+                 empty: &str|
+     -> Result<String, Box<dyn Error>> {
+        let predicates = filters
+            .iter()
+            .map(|filter| filter_predicate(filter, filter_fields))
+            .collect::<Result<Vec<_>, _>>()?;
+        if predicates.is_empty() {
+            return Ok(empty.to_string());
+        }
+        Ok(format!("({})", predicates.join(operator)))
+    };
+    match filter {
+        MetadataFilter::Eq(key, Value::Null) => Ok(format!("{} IS NULL", field(key)?)),
+        MetadataFilter::Ne(key, Value::Null) => Ok(format!("{} IS NOT NULL", field(key)?)),
+        MetadataFilter::Eq(key, value) => Ok(format!("{} = {}", field(key)?, literal(value))),
+        MetadataFilter::Ne(key, value) => Ok(format!("{} <> {}", field(key)?, literal(value))),
+        MetadataFilter::Gt(..) | MetadataFilter::Lt(..) => {
+            Err(UnsupportedFilterError::new("LanceDB", filter).into())
+        }
+        MetadataFilter::In(_, values) if values.is_empty() => Ok("FALSE".to_string()),
+        MetadataFilter::In(key, values) => Ok(format!(
+            "{} IN ({})",
+            field(key)?,
+            values.iter().map(literal).collect::<Vec<_>>().join(", ")
+        )),
+        MetadataFilter::And(filters) => group(filters, " AND ", "TRUE"),
+        MetadataFilter::Or(filters) => group(filters, " OR ", "FALSE"),
+    }
+}
+
+#[async_trait]
+impl VectorStore for Store {
+    /// Add documents to the table.
+    /// Returns a list of the ids of the rows, they are also in the `id` metadata of the
+    /// documents returned by the searches.
+    async fn add_documents(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let added = self.add_documents_with_embeddings(docs, opt).await?;
+        Ok(added.into_iter().map(|(id, _)| id).collect())
+    }
+
+    async fn add_documents_with_embeddings(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<(String, Vec<f64>)>, Box<dyn Error>> {
+        if docs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        add_documents_with_embeddings_in_batches(docs, opt, |batch| async move {
+            self.add_batch(&batch, embedder.as_ref(), &opt.id_strategy)
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await
+    }
+
+    /// Perform an approximate nearest neighbor search on the table, with the distance of
+    /// the store. `filters` are SQL predicates on the columns of the table, written as
+    /// a JSON string, for example `"source = 'a.txt'"`, see `StoreBuilder::filter_fields`.
+    async fn similarity_search(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        if opt.name_space.is_some() {
+            return Err("LanceDB doesn't support namespaces, use a table instead".into());
+        }
+
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let query_vector: Vec<f32> = embedder
+            .embed_query(query)
+            .await?
+            .into_iter()
+            .map(|x| x as f32)
+            .collect();
+
+        let mut query = self
+            .table
+            .query()
+            .nearest_to(query_vector)?
+            .column(VECTOR_COLUMN)
+            .distance_type(self.distance_type())
+            .limit(limit);
+        let filters = match &opt.filters {
+            Some(Value::String(filter)) => Some(filter.clone()),
+            Some(_) => return Err("LanceDB filters must be a SQL predicate string".into()),
+            None => None,
+        };
+        let metadata_filter = match &opt.metadata_filter {
+            Some(filter) => Some(filter_predicate(
+                filter,
+                &self.filter_fields().collect::<Vec<_>>(),
+            )?),
+            None => None,
+        };
+        match (filters, metadata_filter) {
+            (Some(filters), Some(filter)) => {
+                query = query.only_if(format!("({}) AND {}", filters, filter))
+            }
+            (filters, filter) => {
+                if let Some(filter) = filters.or(filter) {
+                    query = query.only_if(filter);
+                }
+            }
+        }
+        let batches: Vec<RecordBatch> = query.execute().await?.try_collect().await?;
+
+        let mut documents =
+            Self::documents(&batches, self.distance_metric, opt.include_embeddings)?;
+        if let Some(score_threshold) = opt.score_threshold {
+            documents.retain(|doc| doc.score >= score_threshold as f64);
+        }
+        Ok(documents)
+    }
+
+    async fn get_by_ids(&self, ids: &[String]) -> Result<Vec<Document>, Box<dyn Error>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let batches: Vec<RecordBatch> = self
+            .table
+            .query()
+            .only_if(ids_predicate(ids))
+            .execute()
+            .await?
+            .try_collect()
+            .await?;
+        let mut docs = Self::documents(&batches, self.distance_metric, false)?;
+        // In the order of the ids
+        docs.sort_by_key(|doc| {
+            ids.iter()
+                .position(|id| doc.metadata["id"].as_str() == Some(id.as_str()))
+        });
+        Ok(docs)
+    }
+
+    async fn delete_documents(&self, ids: &[String]) -> Result<(), Box<dyn Error>> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        self.table.delete(&ids_predicate(ids)).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{embedding::EmbedderError, vectorstore::lancedb::StoreBuilder};
+
+    /// Embeds the texts as the count of `a` and `b` they contain.
+    struct CountEmbedder {}
+
+    #[async_trait]
+    impl Embedder for CountEmbedder {
+        async fn embed_documents(
+            &self,
+            documents: &[String],
+        ) -> Result<Vec<Vec<f64>>, EmbedderError> {
+            let mut embeddings = Vec::new();
+            for document in documents {
+                embeddings.push(self.embed_query(document).await?);
+            }
+            Ok(embeddings)
+        }
+
+        async fn embed_query(&self, text: &str) -> Result<Vec<f64>, EmbedderError> {
+            Ok(vec![
+                text.matches('a').count() as f64,
+                text.matches('b').count() as f64,
+            ])
+        }
+    }
+
+    #[test]
+    fn test_ids_predicate() {
+        assert_eq!(
+            ids_predicate(&["a".to_string(), "it's".to_string()]),
+            "id IN ('a', 'it''s')"
+        );
+    }
+
+    #[test]
+    fn test_filter_predicate() {
+        let filter = MetadataFilter::eq("source", "it's.txt")
+            .and(MetadataFilter::ne("page", 2).or(MetadataFilter::is_in("lang", ["en"])));
+
+        assert_eq!(
+            filter_predicate(&filter, &["source", "page", "lang"]).unwrap(),
+            "(source = 'it''s.txt' AND (page <> '2' OR lang IN ('en')))"
+        );
+        assert!(filter_predicate(&filter, &["source"]).is_err());
+        assert!(filter_predicate(&MetadataFilter::gt("page", 2), &["page"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_lancedb_store() {
+        let path = std::env::temp_dir().join(format!("lancedb-{}", uuid::Uuid::new_v4()));
+        let store = StoreBuilder::new()
+            .path(path.to_str().unwrap())
+            .table_name("docs")
+            .vector_dimensions(2)
+            .filter_fields(&["source"])
+            .embedder(CountEmbedder {})
+            .build()
+            .await
+            .unwrap();
+
+        let docs = [
+            Document::new("aaa").with_metadata([("source".to_string(), json!("a.txt"))].into()),
+            Document::new("bbb").with_metadata([("source".to_string(), json!("b.txt"))].into()),
+            Document::new("aab").with_metadata([("page".to_string(), json!(2))].into()),
+        ];
+        let ids = store
+            .add_documents(&docs, &VecStoreOptions::default())
+            .await
+            .unwrap();
+
+        let results = store
+            .similarity_search("a", 2, &VecStoreOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(results[0].page_content, "aaa");
+        assert!((results[0].score - 1.0).abs() < 1e-6);
+        assert!(results[0].score > results[1].score);
+        assert_eq!(results[0].metadata["source"], json!("a.txt"));
+
+        let options = VecStoreOptions::default().with_filters(json!("source = 'b.txt'"));
+        let results = store.similarity_search("a", 2, &options).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].page_content, "bbb");
+
+        store.delete_documents(&ids[..1]).await.unwrap();
+        let stored = store.get_by_ids(&ids).await.unwrap();
+        let contents: Vec<&str> = stored.iter().map(|d| d.page_content.as_str()).collect();
+        assert_eq!(contents, vec!["bbb", "aab"]);
+        assert_eq!(stored[1].metadata["page"], json!(2));
+
+        std::fs::remove_dir_all(path).ok();
+    }
+}
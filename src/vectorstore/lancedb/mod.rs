@@ -0,0 +1,5 @@
+mod builder;
+mod lancedb;
+
+pub use self::lancedb::*;
+pub use builder::*;
@@ -0,0 +1,124 @@
+use std::{error::Error, sync::Arc};
+
+use reqwest::Client;
+use serde_json::json;
+
+use crate::{embedding::embedder_trait::Embedder, vectorstore::DistanceMetric};
+
+use super::Store;
+
+const DEFAULT_URL: &str = "http://localhost:19530";
+const DEFAULT_COLLECTION_NAME: &str = "langchain";
+const DEFAULT_CONSISTENCY_LEVEL: &str = "Strong";
+
+pub struct StoreBuilder {
+    client: Option<Client>,
+    url: String,
+    token: Option<String>,
+    collection_name: String,
+    vector_dimensions: i32,
+    embedder: Option<Arc<dyn Embedder>>,
+    distance_metric: DistanceMetric,
+    consistency_level: String,
+}
+
+impl StoreBuilder {
+    // Returns a new StoreBuilder instance with default values for each option
+    pub fn new() -> Self {
+        StoreBuilder {
+            client: None,
+            url: DEFAULT_URL.into(),
+            token: None,
+            collection_name: DEFAULT_COLLECTION_NAME.into(),
+            vector_dimensions: 0,
+            embedder: None,
+            distance_metric: DistanceMetric::default(),
+            consistency_level: DEFAULT_CONSISTENCY_LEVEL.into(),
+        }
+    }
+
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Address of the Milvus RESTful API, `http://localhost:19530` by default.
+    pub fn url(mut self, url: &str) -> Self {
+        self.url = url.trim_end_matches('/').into();
+        self
+    }
+
+    /// `user:password` or the API key of a Zilliz Cloud cluster, none by default.
+    pub fn token(mut self, token: &str) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    pub fn collection_name(mut self, collection_name: &str) -> Self {
+        self.collection_name = collection_name.into();
+        self
+    }
+
+    /// Size of the embeddings, required to create the collection.
+    pub fn vector_dimensions(mut self, vector_dimensions: i32) -> Self {
+        self.vector_dimensions = vector_dimensions;
+        self
+    }
+
+    pub fn embedder<E: Embedder + 'static>(mut self, embedder: E) -> Self {
+        self.embedder = Some(Arc::new(embedder));
+        self
+    }
+
+    /// Metric of the index of the collection, cosine by default. It's only set when the
+    /// collection is created.
+    pub fn distance_metric(mut self, distance_metric: DistanceMetric) -> Self {
+        self.distance_metric = distance_metric;
+        self
+    }
+
+    /// Consistency level of the searches and reads, `Strong` by default so they see the
+    /// documents just added. `Bounded` or `Eventually` are faster but may miss them.
+    pub fn consistency_level(mut self, consistency_level: &str) -> Self {
+        self.consistency_level = consistency_level.into();
+        self
+    }
+
+    // Finalize the builder and construct the Store object. The collection is created if it
+    // doesn't exist, and loaded into memory so it can be searched
+    pub async fn build(self) -> Result<Store, Box<dyn Error>> {
+        let embedder = self.embedder.ok_or("Embedder is required")?;
+        if self.vector_dimensions <= 0 {
+            return Err("Vector dimensions are required".into());
+        }
+
+        let store = Store {
+            client: self.client.unwrap_or_default(),
+            url: self.url,
+            token: self.token,
+            collection_name: self.collection_name,
+            embedder,
+            distance_metric: self.distance_metric,
+            consistency_level: self.consistency_level,
+        };
+
+        let exists = store
+            .post(
+                "/v2/vectordb/collections/has",
+                json!({ "collectionName": store.collection_name }),
+            )
+            .await?;
+        if exists["has"].as_bool() != Some(true) {
+            store.create_collection(self.vector_dimensions).await?;
+        }
+        store.load_collection().await?;
+
+        Ok(store)
+    }
+}
+
+impl Default for StoreBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,548 @@
+use std::{error::Error, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::{json, Value};
+
+use crate::{
+    embedding::embedder_trait::Embedder,
+    schemas::Document,
+    vectorstore::{
+        add_documents_with_embeddings_in_batches, DistanceMetric, IdStrategy, MetadataFilter,
+        UnsupportedFilterError, VecStoreOptions, VectorStore,
+    },
+};
+
+pub(crate) const ID_FIELD: &str = "id";
+pub(crate) const CONTENT_FIELD: &str = "text";
+/// JSON field holding the metadata of a document, filters use its keys like
+/// `metadata["source"] == "a.txt"`.
+pub(crate) const METADATA_FIELD: &str = "metadata";
+pub(crate) const VECTOR_FIELD: &str = "vector";
+
+/// Maximum length of the ids, the content hash ids are uuids.
+const MAX_ID_LENGTH: usize = 64;
+/// Maximum length of a Milvus VarChar field, longer documents can't be stored.
+const MAX_CONTENT_LENGTH: usize = 65535;
+
+/// Time waited between the checks of the load state of the collection.
+const LOAD_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const LOAD_POLL_ATTEMPTS: usize = 120;
+
+// https://milvus.io/api-reference/restful/v2.4.x/About.md
+
+pub struct Store {
+    pub(crate) client: Client,
+    pub(crate) url: String,
+    pub(crate) token: Option<String>,
+    pub(crate) collection_name: String,
+    pub(crate) embedder: Arc<dyn Embedder>,
+    pub(crate) distance_metric: DistanceMetric,
+    pub(crate) consistency_level: String,
+}
+
+impl Store {
+    /// Sends a request to the RESTful API and returns the `data` of the response. Milvus
+    /// answers the failed requests with a non zero `code` and a `message`.
+    pub(crate) async fn post(&self, path: &str, body: Value) -> Result<Value, Box<dyn Error>> {
+        let mut request = self
+            .client
+            .post(format!("{}{}", self.url, path))
+            .json(&body);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(format!("Milvus error {}: {}", status, body).into());
+        }
+        let mut response: Value = serde_json::from_str(&body)?;
+        match response["code"].as_i64() {
+            Some(0) => Ok(response
+                .get_mut("data")
+                .map(Value::take)
+                .unwrap_or_default()),
+            _ => Err(format!("Milvus error {}: {}", response["code"], response["message"]).into()),
+        }
+    }
+
+    pub(crate) async fn create_collection(
+        &self,
+        vector_dimensions: i32,
+    ) -> Result<(), Box<dyn Error>> {
+        let metric_type = match self.distance_metric {
+            DistanceMetric::Cosine => "COSINE",
+            DistanceMetric::L2 => "L2",
+            DistanceMetric::InnerProduct => "IP",
+        };
+        self.post(
+            "/v2/vectordb/collections/create",
+            json!({
+                "collectionName": self.collection_name,
+                "schema": {
+                    "autoId": false,
+                    "enableDynamicField": false,
+                    "fields": [
+                        {
+                            "fieldName": ID_FIELD,
+                            "dataType": "VarChar",
+                            "isPrimary": true,
+                            "elementTypeParams": { "max_length": MAX_ID_LENGTH },
+                        },
+                        {
+                            "fieldName": CONTENT_FIELD,
+                            "dataType": "VarChar",
+                            "elementTypeParams": { "max_length": MAX_CONTENT_LENGTH },
+                        },
+                        { "fieldName": METADATA_FIELD, "dataType": "JSON" },
+                        {
+                            "fieldName": VECTOR_FIELD,
+                            "dataType": "FloatVector",
+                            "elementTypeParams": { "dim": vector_dimensions },
+                        },
+                    ],
+                },
+                "indexParams": [{
+                    "fieldName": VECTOR_FIELD,
+                    "indexName": VECTOR_FIELD,
+                    "metricType": metric_type,
+                    "indexType": "AUTOINDEX",
+                }],
+            }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Loads the collection into memory and waits until it's loaded, a collection can
+    /// only be searched once loaded.
+    pub(crate) async fn load_collection(&self) -> Result<(), Box<dyn Error>> {
+        let collection = json!({ "collectionName": self.collection_name });
+        self.post("/v2/vectordb/collections/load", collection.clone())
+            .await?;
+        for _ in 0..LOAD_POLL_ATTEMPTS {
+            let state = self
+                .post(
+                    "/v2/vectordb/collections/get_load_state",
+                    collection.clone(),
+                )
+                .await?;
+            if state["loadState"] == "LoadStateLoaded" {
+                return Ok(());
+            }
+            tokio::time::sleep(LOAD_POLL_INTERVAL).await;
+        }
+        Err(format!("Collection {} was not loaded in time", self.collection_name).into())
+    }
+
+    fn output_fields(include_embeddings: bool) -> Vec<&'static str> {
+        let mut fields = vec![ID_FIELD, CONTENT_FIELD, METADATA_FIELD];
+        if include_embeddings {
+            fields.push(VECTOR_FIELD);
+        }
+        fields
+    }
+
+    /// The document of an entity of a search or a get.
+    fn document(entity: &Value) -> Document {
+        let metadata = match &entity[METADATA_FIELD] {
+            Value::String(metadata) => serde_json::from_str(metadata).unwrap_or_default(),
+            metadata => serde_json::from_value(metadata.clone()).unwrap_or_default(),
+        };
+        let mut doc = Document::new(entity[CONTENT_FIELD].as_str().unwrap_or_default())
+            .with_metadata(metadata);
+        doc.metadata
+            .insert("id".to_string(), entity[ID_FIELD].clone());
+        doc.embedding = serde_json::from_value(entity[VECTOR_FIELD].clone()).ok();
+        doc
+    }
+
+    /// Milvus returns the similarity for the cosine and inner product metrics, and the
+    /// squared distance for the euclidean metric.
+    fn score(&self, distance: f64) -> f64 {
+        match self.distance_metric {
+            DistanceMetric::Cosine | DistanceMetric::InnerProduct => distance,
+            DistanceMetric::L2 => DistanceMetric::squared_l2_score(distance),
+        }
+    }
+
+    async fn add_batch(
+        &self,
+        docs: &[Document],
+        embedder: &dyn Embedder,
+        id_strategy: &IdStrategy,
+    ) -> Result<Vec<(String, Vec<f64>)>, Box<dyn Error>> {
+        let texts: Vec<String> = docs.iter().map(|d| d.page_content.clone()).collect();
+        let vectors = embedder.embed_documents(&texts).await?;
+
+        if vectors.len() != docs.len() {
+            return Err("Number of vectors and documents do not match".into());
+        }
+
+        let mut added = Vec::with_capacity(docs.len());
+        let mut entities = Vec::with_capacity(docs.len());
+        for (doc, vector) in docs.iter().zip(vectors) {
+            let id = id_strategy.document_id(doc, &self.collection_name);
+            entities.push(json!({
+                ID_FIELD: id,
+                CONTENT_FIELD: doc.page_content,
+                METADATA_FIELD: doc.metadata,
+                VECTOR_FIELD: vector,
+            }));
+            added.push((id, vector));
+        }
+
+        // Upserting replaces the documents already stored with the same content hash
+        let path = match id_strategy {
+            IdStrategy::Random => "/v2/vectordb/entities/insert",
+            IdStrategy::ContentHash { .. } => "/v2/vectordb/entities/upsert",
+        };
+        self.post(
+            path,
+            json!({ "collectionName": self.collection_name, "data": entities }),
+        )
+        .await?;
+
+        Ok(added)
+    }
+}
+
+/// `id in ["a", "b"]`, the ids are quoted as JSON strings.
+fn ids_filter(ids: &[String]) -> String {
+    format!("{} in {}", ID_FIELD, json!(ids))
+}
+
+/// The boolean expression of the `MetadataFilter` on the keys of the metadata field, the
+/// values are written as JSON. The null values and the empty groups are not supported.
+fn filter_expression(filter: &MetadataFilter) -> Result<String, UnsupportedFilterError> {
+    let field = |key: &str| format!("{}[{}]", METADATA_FIELD, json!(key));
+    let group =
+        |filters: &[MetadataFilter], operator: &str| -> Result<String, UnsupportedFilterError> {
+            if filters.is_empty() {
+                return Err(UnsupportedFilterError::new("Milvus", filter));
+            }
+            let expressions = filters
+                .iter()
+                .map(filter_expression)
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(format!("({})", expressions.join(operator)))
+        };
+    match filter {
+        MetadataFilter::Eq(_, Value::Null)
+        | MetadataFilter::Ne(_, Value::Null)
+        | MetadataFilter::Gt(_, Value::Null)
+        | MetadataFilter::Lt(_, Value::Null) => Err(UnsupportedFilterError::new("Milvus", filter)),
+        MetadataFilter::Eq(key, value) => Ok(format!("{} == {}", field(key), value)),
+        MetadataFilter::Ne(key, value) => Ok(format!("{} != {}", field(key), value)),
+        MetadataFilter::Gt(key, value) => Ok(format!("{} > {}", field(key), value)),
+        MetadataFilter::Lt(key, value) => Ok(format!("{} < {}", field(key), value)),
+        MetadataFilter::In(key, values) => Ok(format!("{} in {}", field(key), json!(values))),
+        MetadataFilter::And(filters) => group(filters, " and "),
+        MetadataFilter::Or(filters) => group(filters, " or "),
+    }
+}
+
+#[async_trait]
+impl VectorStore for Store {
+    /// Add documents to the collection.
+    /// Returns a list of the ids of the entities.
+    async fn add_documents(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let added = self.add_documents_with_embeddings(docs, opt).await?;
+        Ok(added.into_iter().map(|(id, _)| id).collect())
+    }
+
+    async fn add_documents_with_embeddings(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<(String, Vec<f64>)>, Box<dyn Error>> {
+        if docs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        add_documents_with_embeddings_in_batches(docs, opt, |batch| async move {
+            self.add_batch(&batch, embedder.as_ref(), &opt.id_strategy)
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await
+    }
+
+    /// Perform an approximate nearest neighbor search on the collection. `filters` are
+    /// Milvus boolean expressions written as a JSON string, for example
+    /// `"metadata[\"source\"] == \"a.txt\""`.
+    async fn similarity_search(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        if opt.name_space.is_some() {
+            return Err("Milvus doesn't support namespaces, use a collection instead".into());
+        }
+
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let query_vector = embedder.embed_query(query).await?;
+
+        let mut body = json!({
+            "collectionName": self.collection_name,
+            "data": [query_vector],
+            "annsField": VECTOR_FIELD,
+            "limit": limit,
+            "outputFields": Self::output_fields(opt.include_embeddings),
+            "consistencyLevel": self.consistency_level,
+        });
+        let filters = match &opt.filters {
+            Some(Value::String(filter)) => Some(filter.clone()),
+            Some(_) => return Err("Milvus filters must be a boolean expression string".into()),
+            None => None,
+        };
+        let metadata_filter = opt
+            .metadata_filter
+            .as_ref()
+            .map(filter_expression)
+            .transpose()?;
+        match (filters, metadata_filter) {
+            (Some(filters), Some(filter)) => {
+                body["filter"] = Value::from(format!("({}) and {}", filters, filter))
+            }
+            (filters, filter) => {
+                if let Some(filter) = filters.or(filter) {
+                    body["filter"] = Value::from(filter);
+                }
+            }
+        }
+        let results = self.post("/v2/vectordb/entities/search", body).await?;
+
+        let mut documents = results
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|entity| {
+                let distance = entity["distance"].as_f64().unwrap_or_default();
+                Self::document(entity).with_score(self.score(distance))
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(score_threshold) = opt.score_threshold {
+            documents.retain(|doc| doc.score >= score_threshold as f64);
+        }
+
+        Ok(documents)
+    }
+
+    async fn get_by_ids(&self, ids: &[String]) -> Result<Vec<Document>, Box<dyn Error>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let results = self
+            .post(
+                "/v2/vectordb/entities/get",
+                json!({
+                    "collectionName": self.collection_name,
+                    "id": ids,
+                    "outputFields": Self::output_fields(false),
+                    "consistencyLevel": self.consistency_level,
+                }),
+            )
+            .await?;
+        let entities = results.as_array().cloned().unwrap_or_default();
+
+        // In the order of the ids
+        Ok(ids
+            .iter()
+            .filter_map(|id| entities.iter().find(|entity| entity[ID_FIELD] == **id))
+            .map(Self::document)
+            .collect())
+    }
+
+    async fn delete_documents(&self, ids: &[String]) -> Result<(), Box<dyn Error>> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        self.post(
+            "/v2/vectordb/entities/delete",
+            json!({ "collectionName": self.collection_name, "filter": ids_filter(ids) }),
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use async_trait::async_trait;
+    use mockito::Matcher;
+
+    use super::*;
+    use crate::{embedding::EmbedderError, vectorstore::milvus::StoreBuilder};
+
+    struct FakeEmbedder {}
+
+    #[async_trait]
+    impl Embedder for FakeEmbedder {
+        async fn embed_documents(
+            &self,
+            documents: &[String],
+        ) -> Result<Vec<Vec<f64>>, EmbedderError> {
+            Ok(documents.iter().map(|_| vec![1.0, 0.0]).collect())
+        }
+
+        async fn embed_query(&self, _text: &str) -> Result<Vec<f64>, EmbedderError> {
+            Ok(vec![1.0, 0.0])
+        }
+    }
+
+    fn ok(data: Value) -> String {
+        json!({ "code": 0, "data": data }).to_string()
+    }
+
+    #[test]
+    fn test_ids_filter() {
+        assert_eq!(
+            ids_filter(&["a".to_string(), "b\"c".to_string()]),
+            r#"id in ["a","b\"c"]"#
+        );
+    }
+
+    #[test]
+    fn test_filter_expression() {
+        let filter = MetadataFilter::eq("source", "a.txt")
+            .and(MetadataFilter::lt("page", 3).or(MetadataFilter::is_in("lang", ["en", "es"])));
+
+        assert_eq!(
+            filter_expression(&filter).unwrap(),
+            r#"(metadata["source"] == "a.txt" and (metadata["page"] < 3 or metadata["lang"] in ["en","es"]))"#
+        );
+        assert!(filter_expression(&MetadataFilter::Or(vec![])).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_milvus_build_creates_and_loads_collection() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v2/vectordb/collections/has")
+            .match_header("authorization", "Bearer root:Milvus")
+            .with_body(ok(json!({"has": false})))
+            .create_async()
+            .await;
+        let create = server
+            .mock("POST", "/v2/vectordb/collections/create")
+            .match_body(Matcher::PartialJson(json!({
+                "collectionName": "docs",
+                "indexParams": [{"fieldName": "vector", "metricType": "L2"}],
+            })))
+            .with_body(ok(json!({})))
+            .create_async()
+            .await;
+        let load = server
+            .mock("POST", "/v2/vectordb/collections/load")
+            .with_body(ok(json!({})))
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/v2/vectordb/collections/get_load_state")
+            .with_body(ok(json!({"loadState": "LoadStateLoaded"})))
+            .create_async()
+            .await;
+
+        StoreBuilder::new()
+            .url(&server.url())
+            .token("root:Milvus")
+            .collection_name("docs")
+            .vector_dimensions(2)
+            .distance_metric(DistanceMetric::L2)
+            .embedder(FakeEmbedder {})
+            .build()
+            .await
+            .unwrap();
+        create.assert_async().await;
+        load.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_milvus_store() {
+        let mut server = mockito::Server::new_async().await;
+        let store = Store {
+            client: Client::new(),
+            url: server.url(),
+            token: None,
+            collection_name: "docs".to_string(),
+            embedder: Arc::new(FakeEmbedder {}),
+            distance_metric: DistanceMetric::Cosine,
+            consistency_level: "Strong".to_string(),
+        };
+
+        let metadata = HashMap::from([("source".to_string(), json!("a.txt"))]);
+        let insert = server
+            .mock("POST", "/v2/vectordb/entities/insert")
+            .match_body(Matcher::PartialJson(json!({
+                "collectionName": "docs",
+                "data": [{"text": "first", "metadata": {"source": "a.txt"}, "vector": [1.0, 0.0]}],
+            })))
+            .with_body(ok(json!({"insertCount": 1})))
+            .create_async()
+            .await;
+        let ids = store
+            .add_documents(
+                &[Document::new("first").with_metadata(metadata)],
+                &VecStoreOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(ids.len(), 1);
+        insert.assert_async().await;
+
+        server
+            .mock("POST", "/v2/vectordb/entities/search")
+            .match_body(Matcher::PartialJson(json!({
+                "filter": "metadata[\"source\"] == \"a.txt\"",
+                "consistencyLevel": "Strong",
+                "limit": 2,
+            })))
+            .with_body(ok(json!([
+                {"id": "1", "distance": 0.9, "text": "first", "metadata": {"source": "a.txt"}},
+                {"id": "2", "distance": 0.2, "text": "second", "metadata": {}},
+            ])))
+            .create_async()
+            .await;
+        let opt = VecStoreOptions::default()
+            .with_filters(json!("metadata[\"source\"] == \"a.txt\""))
+            .with_score_threshold(0.5);
+        let docs = store.similarity_search("query", 2, &opt).await.unwrap();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].page_content, "first");
+        assert_eq!(docs[0].score, 0.9);
+        assert_eq!(docs[0].metadata["source"], json!("a.txt"));
+        assert_eq!(docs[0].metadata["id"], json!("1"));
+
+        let error = store
+            .similarity_search(
+                "query",
+                2,
+                &VecStoreOptions::default().with_filters(json!({"source": "a.txt"})),
+            )
+            .await;
+        assert!(error.is_err());
+
+        server
+            .mock("POST", "/v2/vectordb/entities/delete")
+            .match_body(Matcher::PartialJson(json!({"filter": "id in [\"1\"]"})))
+            .with_body(json!({"code": 1100, "message": "collection not loaded"}).to_string())
+            .create_async()
+            .await;
+        let error = store
+            .delete_documents(&["1".to_string()])
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("collection not loaded"));
+    }
+}
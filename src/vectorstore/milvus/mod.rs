@@ -0,0 +1,5 @@
+mod builder;
+mod milvus;
+
+pub use builder::*;
+pub use milvus::*;
@@ -1,5 +1,9 @@
+mod batch;
 mod options;
 
+#[cfg(feature = "chroma")]
+pub mod chroma;
+
 #[cfg(feature = "postgres")]
 pub mod pgvector;
 
@@ -12,13 +16,29 @@ pub mod sqlite_vec;
 #[cfg(feature = "surrealdb")]
 pub mod surrealdb;
 
+#[cfg(feature = "lancedb")]
+pub mod lancedb;
+
+#[cfg(feature = "milvus")]
+pub mod milvus;
+
 #[cfg(feature = "opensearch")]
 pub mod opensearch;
 
+#[cfg(feature = "pinecone")]
+pub mod pinecone;
+
 #[cfg(feature = "qdrant")]
 pub mod qdrant;
 
+#[cfg(feature = "redis")]
+pub mod redis;
+
+#[cfg(feature = "weaviate")]
+pub mod weaviate;
+
 mod vectorstore;
 
+pub use batch::*;
 pub use options::*;
 pub use vectorstore::*;
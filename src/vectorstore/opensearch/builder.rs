@@ -1,7 +1,9 @@
 use crate::embedding::Embedder;
 use crate::vectorstore::opensearch::Store;
+use crate::vectorstore::DistanceMetric;
 use opensearch::OpenSearch;
 use std::error::Error;
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 
 pub struct StoreBuilder {
@@ -11,6 +13,9 @@ pub struct StoreBuilder {
     index: Option<String>,
     vector_field: String,
     content_field: String,
+    vector_dimensions: i32,
+    distance_metric: DistanceMetric,
+    ef_search: Option<usize>,
 }
 
 impl StoreBuilder {
@@ -23,6 +28,9 @@ impl StoreBuilder {
             index: None,
             vector_field: "vector_field".to_string(),
             content_field: "page_content".to_string(),
+            vector_dimensions: 1536,
+            distance_metric: DistanceMetric::L2,
+            ef_search: None,
         }
     }
 
@@ -56,6 +64,27 @@ impl StoreBuilder {
         self
     }
 
+    /// Size of the embeddings, 1536 by default. Used when the index is created.
+    pub fn vector_dimensions(mut self, vector_dimensions: i32) -> Self {
+        self.vector_dimensions = vector_dimensions;
+        self
+    }
+
+    /// Space of the index, l2 by default. Used when the index is created, and to convert
+    /// the scores of the searches to the `DistanceMetric` contract.
+    pub fn distance_metric(mut self, distance_metric: DistanceMetric) -> Self {
+        self.distance_metric = distance_metric;
+        self
+    }
+
+    /// Size of the candidate list of the searches, higher is more accurate but slower.
+    /// Sent with each query, which requires OpenSearch 2.16 or later, and set on the
+    /// index when it's created. The index uses 512 by default.
+    pub fn ef_search(mut self, ef_search: usize) -> Self {
+        self.ef_search = Some(ef_search);
+        self
+    }
+
     // Finalize the builder and construct the Store object
     pub async fn build(self) -> Result<Store, Box<dyn Error>> {
         if self.client.is_none() {
@@ -77,6 +106,10 @@ impl StoreBuilder {
             index: self.index.unwrap(),
             vector_field: self.vector_field,
             content_field: self.content_field,
+            vector_dimensions: self.vector_dimensions,
+            distance_metric: self.distance_metric,
+            ef_search: self.ef_search,
+            index_created: AtomicBool::new(false),
         })
     }
 }
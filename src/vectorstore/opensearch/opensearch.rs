@@ -1,11 +1,12 @@
 use async_trait::async_trait;
 use opensearch::http::request::JsonBody;
 use opensearch::http::response::Response;
-use opensearch::indices::{IndicesCreateParts, IndicesDeleteParts};
+use opensearch::indices::{IndicesCreateParts, IndicesDeleteParts, IndicesExistsParts};
 use opensearch::{BulkParts, SearchParts};
-use serde_json::{json, Value};
+use serde_json::{json, Map, Value};
 use std::collections::HashMap;
 use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 pub use opensearch::auth::Credentials;
@@ -16,9 +17,27 @@ pub use opensearch::OpenSearch;
 use crate::{
     embedding::embedder_trait::Embedder,
     schemas::Document,
-    vectorstore::{VecStoreOptions, VectorStore},
+    vectorstore::{
+        DistanceMetric, MetadataFilter, UnsupportedFilterError, VecStoreOptions, VectorStore,
+    },
 };
 
+/// `ef_search` of the index when the builder doesn't set one.
+const DEFAULT_EF_SEARCH: usize = 512;
+
+/// Keys of the OpenSearch queries accepted as filters as they are, the other filters are
+/// metadata conditions, see `Store::similarity_search`.
+const QUERY_KEYS: [&str; 8] = [
+    "bool",
+    "term",
+    "terms",
+    "range",
+    "match",
+    "match_phrase",
+    "exists",
+    "ids",
+];
+
 pub struct Store {
     pub client: OpenSearch,
     pub embedder: Arc<dyn Embedder>,
@@ -26,6 +45,11 @@ pub struct Store {
     pub index: String,
     pub vector_field: String,
     pub content_field: String,
+    pub vector_dimensions: i32,
+    pub distance_metric: DistanceMetric,
+    pub ef_search: Option<usize>,
+    /// Whether the index is known to exist, so `add_documents` doesn't check it again.
+    pub(crate) index_created: AtomicBool,
 }
 
 // https://opensearch.org/docs/latest/search-plugins/knn/approximate-knn/
@@ -42,27 +66,43 @@ impl Store {
             .await?;
 
         let result = response.error_for_status_code().map_err(|e| Box::new(e))?;
+        self.index_created.store(false, Ordering::Relaxed);
 
         Ok(result)
     }
 
+    /// Creates the index with a `knn_vector` field of the dimensions and the metric of the
+    /// store. The string metadata values are mapped as keywords so the filters can match
+    /// them exactly.
     pub async fn create_index(&self) -> Result<Response, Box<dyn Error>> {
+        let space_type = match self.distance_metric {
+            DistanceMetric::Cosine => "cosinesimil",
+            DistanceMetric::L2 => "l2",
+            DistanceMetric::InnerProduct => "innerproduct",
+        };
         let body = json!({
             "settings": {
                 "index.knn": true,
                 "knn.algo_param": {
-                    "ef_search": "512"
+                    "ef_search": self.ef_search.unwrap_or(DEFAULT_EF_SEARCH).to_string()
                 },
             },
             "mappings": {
+                "dynamic_templates": [{
+                    "metadata_strings": {
+                        "path_match": "metadata.*",
+                        "match_mapping_type": "string",
+                        "mapping": { "type": "keyword" }
+                    }
+                }],
                 "properties": {
                     &self.vector_field: {
                         "type": "knn_vector",
-                        "dimension": 1536,
+                        "dimension": self.vector_dimensions,
                         "method": {
                             "engine": "faiss",
                             "name": "hnsw",
-                            "space_type": "l2",
+                            "space_type": space_type,
                             "parameters": {
                                 "ef_construction": 512,
                                 "m": 16
@@ -73,11 +113,7 @@ impl Store {
                         "type": "text"
                     },
                     "metadata": {
-                        "properties": {
-                            "source": {
-                                "type": "text",
-                            }
-                        }
+                        "type": "object"
                     }
                 }
             }
@@ -92,9 +128,29 @@ impl Store {
             .await?;
 
         let result = response.error_for_status_code().map_err(|e| Box::new(e))?;
+        self.index_created.store(true, Ordering::Relaxed);
 
         Ok(result)
     }
+
+    /// Creates the index the first time documents are added, if it doesn't exist.
+    async fn ensure_index(&self) -> Result<(), Box<dyn Error>> {
+        if self.index_created.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let response = self
+            .client
+            .indices()
+            .exists(IndicesExistsParts::Index(&[&self.index]))
+            .send()
+            .await?;
+        if response.status_code().is_success() {
+            self.index_created.store(true, Ordering::Relaxed);
+        } else {
+            self.create_index().await?;
+        }
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -104,6 +160,20 @@ impl VectorStore for Store {
         docs: &[Document],
         opt: &VecStoreOptions,
     ) -> Result<Vec<String>, Box<dyn Error>> {
+        let added = self.add_documents_with_embeddings(docs, opt).await?;
+        Ok(added.into_iter().map(|(id, _)| id).collect())
+    }
+
+    async fn add_documents_with_embeddings(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<(String, Vec<f64>)>, Box<dyn Error>> {
+        if docs.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.ensure_index().await?;
+
         let texts: Vec<String> = docs.iter().map(|d| d.page_content.clone()).collect();
         let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
         let vectors = embedder.embed_documents(&texts).await?;
@@ -147,101 +217,296 @@ impl VectorStore for Store {
             .map(|item| serde_json::from_value::<String>(item["index"]["_id"].clone()).unwrap())
             .collect::<Vec<_>>();
 
-        Ok(ids)
+        Ok(ids.into_iter().zip(vectors).collect())
     }
 
+    /// Perform an approximate k-NN search on the index. `filters` are either an OpenSearch
+    /// query, like `{"bool": {...}}`, or conditions on the metadata: a value matches the
+    /// metadata value exactly, a list matches any of its values and an object with `gt`,
+    /// `gte`, `lt` or `lte` is a range, for example `{"source": "a.txt", "page": {"gte": 2}}`.
     async fn similarity_search(
         &self,
         query: &str,
         limit: usize,
         opt: &VecStoreOptions,
     ) -> Result<Vec<Document>, Box<dyn Error>> {
-        let query_vector = self.embedder.embed_query(query).await?;
-        let query = build_similarity_search_query(
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let query_vector = embedder.embed_query(query).await?;
+        let filters = opt.filters.as_ref().map(metadata_filter).transpose()?;
+        let filter = match (filters, &opt.metadata_filter) {
+            (Some(filters), Some(filter)) => {
+                Some(json!({ "bool": { "filter": [filters, filter_query(filter)?] } }))
+            }
+            (filters, filter) => filters.or(filter.as_ref().map(filter_query).transpose()?),
+        };
+        let mut query = build_similarity_search_query(
             query_vector,
             &self.vector_field,
             limit,
-            self.k,
-            opt.filters.clone(),
+            (self.k.max(0) as usize).max(limit),
+            self.ef_search,
+            filter,
         );
+        if !opt.include_embeddings {
+            query["_source"] = json!({ "excludes": [&self.vector_field] });
+        }
 
         let response = self
             .client
             .search(SearchParts::Index(&[&self.index]))
-            .from(0)
-            .size(3)
             .body(query)
             .send()
-            .await?;
+            .await?
+            .error_for_status_code()
+            .map_err(Box::new)?;
 
         let response_body = response.json::<Value>().await?;
 
-        let aoss_documents = response_body["hits"]["hits"]
+        let mut documents = response_body["hits"]["hits"]
             .as_array()
-            .unwrap()
-            .iter()
-            .map(|raw_value| {
-                serde_json::from_value::<HashMap<String, Value>>(raw_value.clone()).unwrap()
-            })
-            .collect::<Vec<_>>();
-
-        let documents = aoss_documents
             .into_iter()
+            .flatten()
             .map(|item| {
-                let page_content =
-                    serde_json::from_value::<String>(item["_source"][&self.content_field].clone())
-                        .unwrap();
-                let metadata = serde_json::from_value::<HashMap<String, Value>>(
-                    item["_source"]["metadata"].clone(),
-                )
-                .unwrap();
-                let score = serde_json::from_value::<f64>(item["_score"].clone()).unwrap();
+                let source = &item["_source"];
+                let page_content = source[&self.content_field]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string();
+                let metadata =
+                    serde_json::from_value::<HashMap<String, Value>>(source["metadata"].clone())
+                        .unwrap_or_default();
+                let score = score(
+                    self.distance_metric,
+                    item["_score"].as_f64().unwrap_or_default(),
+                );
+                let embedding =
+                    serde_json::from_value::<Vec<f64>>(source[&self.vector_field].clone()).ok();
                 Document {
                     page_content,
                     metadata,
                     score,
+                    embedding,
                 }
             })
-            .collect();
+            .collect::<Vec<_>>();
+
+        if let Some(score_threshold) = opt.score_threshold {
+            documents.retain(|doc| doc.score >= score_threshold as f64);
+        }
 
         Ok(documents)
     }
+
+    async fn delete_documents(&self, ids: &[String]) -> Result<(), Box<dyn Error>> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let body: Vec<JsonBody<_>> = ids
+            .iter()
+            .map(|id| json!({"delete": {"_id": id}}).into())
+            .collect();
+
+        let response = self
+            .client
+            .bulk(BulkParts::Index(&self.index))
+            .body(body)
+            .send()
+            .await?
+            .error_for_status_code()
+            .map_err(Box::new)?;
+
+        // Deleting a missing document is not an error
+        let response_body = response.json::<Value>().await?;
+        if response_body["errors"].as_bool() == Some(true) {
+            return Err(format!("Failed to delete the documents: {}", response_body).into());
+        }
+        Ok(())
+    }
+}
+
+/// OpenSearch scores are `1 / (1 + d)` of the distance `d` of the space, `1 - cos` for
+/// cosine and the squared distance for l2. The inner product `p` is scored `p + 1` when
+/// positive and `1 / (1 - p)` otherwise. The scores are converted to the `DistanceMetric`
+/// contract of the metric.
+fn score(distance_metric: DistanceMetric, score: f64) -> f64 {
+    match distance_metric {
+        DistanceMetric::Cosine => 2.0 - 1.0 / score,
+        DistanceMetric::L2 => DistanceMetric::squared_l2_score(1.0 / score - 1.0),
+        DistanceMetric::InnerProduct if score >= 1.0 => score - 1.0,
+        DistanceMetric::InnerProduct => 1.0 - 1.0 / score,
+    }
+}
+
+/// The filter of the k-NN query for the `filters` of the options.
+fn metadata_filter(filters: &Value) -> Result<Value, Box<dyn Error>> {
+    let Value::Object(conditions) = filters else {
+        return Err("OpenSearch filters must be an object".into());
+    };
+    if conditions.len() == 1
+        && conditions
+            .keys()
+            .all(|key| QUERY_KEYS.contains(&key.as_str()))
+    {
+        return Ok(filters.clone());
+    }
+
+    let mut clauses = Vec::with_capacity(conditions.len());
+    for (key, value) in conditions {
+        let field = format!("metadata.{}", key);
+        let clause = match value {
+            Value::Array(values) => json!({ "terms": { field: values } }),
+            Value::Object(range) if is_range(range) => json!({ "range": { field: range } }),
+            Value::Object(_) | Value::Null => {
+                return Err(format!("Unsupported filter for the metadata {}", key).into())
+            }
+            value => json!({ "term": { field: value } }),
+        };
+        clauses.push(clause);
+    }
+    Ok(json!({ "bool": { "filter": clauses } }))
+}
+
+/// The OpenSearch query of the `MetadataFilter`, the null values are not supported.
+fn filter_query(filter: &MetadataFilter) -> Result<Value, UnsupportedFilterError> {
+    let field = |key: &str| format!("metadata.{}", key);
+    let queries = |filters: &[MetadataFilter]| {
+        filters
+            .iter()
+            .map(filter_query)
+            .collect::<Result<Vec<_>, _>>()
+    };
+    match filter {
+        MetadataFilter::Eq(_, Value::Null) | MetadataFilter::Ne(_, Value::Null) => {
+            Err(UnsupportedFilterError::new("OpenSearch", filter))
+        }
+        MetadataFilter::Eq(key, value) => Ok(json!({ "term": { field(key): value } })),
+        MetadataFilter::Ne(key, value) => {
+            Ok(json!({ "bool": { "must_not": { "term": { field(key): value } } } }))
+        }
+        MetadataFilter::Gt(key, value) => Ok(json!({ "range": { field(key): { "gt": value } } })),
+        MetadataFilter::Lt(key, value) => Ok(json!({ "range": { field(key): { "lt": value } } })),
+        MetadataFilter::In(key, values) => Ok(json!({ "terms": { field(key): values } })),
+        MetadataFilter::And(filters) => Ok(json!({ "bool": { "filter": queries(filters)? } })),
+        MetadataFilter::Or(filters) => Ok(json!({
+            "bool": { "should": queries(filters)?, "minimum_should_match": 1 }
+        })),
+    }
+}
+
+fn is_range(range: &Map<String, Value>) -> bool {
+    !range.is_empty()
+        && range
+            .keys()
+            .all(|key| ["gt", "gte", "lt", "lte"].contains(&key.as_str()))
 }
 
 fn build_similarity_search_query(
     embedded_query: Vec<f64>,
     vector_field: &str,
     size: usize,
-    k: i32,
+    k: usize,
+    ef_search: Option<usize>,
     maybe_filter: Option<Value>,
 ) -> Value {
-    match maybe_filter {
-        Some(filter) => {
-            json!({
-              "size": size,
-              "query": {
-                "knn": {
-                  vector_field: {
-                    "vector": embedded_query,
-                    "k": k,
-                    "filter": filter,
-                  }
-                }
-              }
-            })
+    let mut knn = json!({
+        "vector": embedded_query,
+        "k": k,
+    });
+    if let Some(filter) = maybe_filter {
+        knn["filter"] = filter;
+    }
+    if let Some(ef_search) = ef_search {
+        knn["method_parameters"] = json!({ "ef_search": ef_search });
+    }
+    json!({
+      "size": size,
+      "query": {
+        "knn": {
+          vector_field: knn
         }
-        None => {
+      }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        // cos = 0.8
+        assert!(close(score(DistanceMetric::Cosine, 1.0 / 1.2), 0.8));
+        assert_eq!(score(DistanceMetric::L2, 0.5), 0.5);
+        assert!(close(score(DistanceMetric::L2, 0.2), 1.0 / 3.0));
+        assert!(close(score(DistanceMetric::InnerProduct, 4.0), 3.0));
+        assert!(close(score(DistanceMetric::InnerProduct, 0.5), -1.0));
+    }
+
+    #[test]
+    fn test_metadata_filter() {
+        let filter = metadata_filter(&json!({
+            "source": "a.txt",
+            "page": {"gte": 2, "lt": 5},
+            "lang": ["en", "es"],
+        }))
+        .unwrap();
+        let clauses = filter["bool"]["filter"].as_array().unwrap();
+        assert_eq!(clauses.len(), 3);
+        assert!(clauses.contains(&json!({"term": {"metadata.source": "a.txt"}})));
+        assert!(clauses.contains(&json!({"range": {"metadata.page": {"gte": 2, "lt": 5}}})));
+        assert!(clauses.contains(&json!({"terms": {"metadata.lang": ["en", "es"]}})));
+
+        // OpenSearch queries are kept as they are
+        let query = json!({"bool": {"must_not": {"term": {"metadata.source": "b.txt"}}}});
+        assert_eq!(metadata_filter(&query).unwrap(), query);
+
+        assert!(metadata_filter(&json!({"author": {"name": "Ana"}})).is_err());
+        assert!(metadata_filter(&json!("source = 'a.txt'")).is_err());
+    }
+
+    #[test]
+    fn test_filter_query() {
+        let filter = MetadataFilter::ne("source", "a.txt")
+            .and(MetadataFilter::gt("page", 2).or(MetadataFilter::is_in("lang", ["en"])));
+
+        assert_eq!(
+            filter_query(&filter).unwrap(),
+            json!({"bool": {"filter": [
+                {"bool": {"must_not": {"term": {"metadata.source": "a.txt"}}}},
+                {"bool": {
+                    "should": [
+                        {"range": {"metadata.page": {"gt": 2}}},
+                        {"terms": {"metadata.lang": ["en"]}},
+                    ],
+                    "minimum_should_match": 1,
+                }},
+            ]}})
+        );
+        assert!(filter_query(&MetadataFilter::eq("author", Value::Null)).is_err());
+    }
+
+    #[test]
+    fn test_build_similarity_search_query() {
+        let query = build_similarity_search_query(
+            vec![1.0, 0.0],
+            "vector",
+            3,
+            10,
+            Some(100),
+            Some(json!({"term": {"metadata.source": "a.txt"}})),
+        );
+        assert_eq!(
+            query,
             json!({
-              "size": size,
-              "query": {
-                "knn": {
-                  vector_field: {
-                    "vector": embedded_query,
-                    "k": k,
-                  }
-                }
-              }
+                "size": 3,
+                "query": {"knn": {"vector": {
+                    "vector": [1.0, 0.0],
+                    "k": 10,
+                    "filter": {"term": {"metadata.source": "a.txt"}},
+                    "method_parameters": {"ef_search": 100},
+                }}}
             })
-        }
+        );
     }
 }
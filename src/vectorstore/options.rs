@@ -1,12 +1,18 @@
 use std::sync::Arc;
 
-use serde_json::Value;
+use serde_json::{json, Value};
 
-use crate::embedding::embedder_trait::Embedder;
+#[cfg(feature = "uuid")]
+use uuid::Uuid;
+
+#[cfg(feature = "uuid")]
+use crate::schemas::Document;
+use crate::{embedding::embedder_trait::Embedder, schemas::ProgressCallback};
 
 /// The `VecStoreOptions` struct is responsible for determining options when
 /// interacting with a Vector Store. The options include `name_space`, `score_threshold`,
-/// `filters`, and `embedder`.
+/// `filters`, `metadata_filter`, `embedder`, `distance_metric`, `include_embeddings`,
+/// `batch_size`, `max_concurrency`, `progress` and `id_strategy`.
 ///
 /// # Usage
 /// ```rust,ignore
@@ -14,13 +20,244 @@ use crate::embedding::embedder_trait::Embedder;
 ///     .with_name_space("my_custom_namespace")
 ///     .with_score_threshold(0.5)
 ///     .with_filters(json!({"genre": "Sci-Fi"}))
-///     .with_embedder(my_embedder);
+///     .with_metadata_filter(MetadataFilter::gt("year", 1990))
+///     .with_embedder(my_embedder)
+///     .with_distance_metric(DistanceMetric::Cosine)
+///     .with_include_embeddings(true)
+///     .with_batch_size(500)
+///     .with_max_concurrency(4);
 /// ```
+#[derive(Clone)]
 pub struct VecStoreOptions {
     pub name_space: Option<String>,
     pub score_threshold: Option<f32>,
     pub filters: Option<Value>,
+    /// A filter on the metadata translated by each store, so it works whatever the backend,
+    /// except sqlite-vss which doesn't filter. When `filters` is also set, the documents
+    /// must match both.
+    pub metadata_filter: Option<MetadataFilter>,
     pub embedder: Option<Arc<dyn Embedder>>,
+    /// Metric of the search, the stores whose builder has a `distance_metric` use that one
+    /// so the searches match the index.
+    pub distance_metric: DistanceMetric,
+    /// Whether the documents returned by a search have their stored `embedding`, off by
+    /// default to keep responses small. Supported by qdrant, opensearch,
+    /// lancedb and milvus.
+    pub include_embeddings: bool,
+    /// Number of documents embedded and stored together by `add_documents`, all of them by
+    /// default. Supported by pgvector, surrealdb, pinecone, lancedb and milvus.
+    pub batch_size: Option<usize>,
+    /// Number of batches `add_documents` embeds and stores at the same time, 1 by default.
+    pub max_concurrency: usize,
+    /// Called by `add_documents` after each batch is stored, with the number of documents
+    /// processed and the total.
+    pub progress: Option<ProgressCallback>,
+    /// How `add_documents` chooses the ids of the documents. Supported by pgvector,
+    /// surrealdb, pinecone, lancedb and milvus, the other stores always use random ids.
+    pub id_strategy: IdStrategy,
+}
+
+/// The metric used to compare embeddings in a similarity search.
+///
+/// Whatever the metric, the `score` of the documents returned by a `VectorStore` follows
+/// the same contract: higher is more similar. Stores convert their raw distances with
+/// `DistanceMetric::score`, and `score_threshold` is compared against that score.
+///
+/// Stores where the metric is fixed when the index is created (qdrant, opensearch,
+/// sqlite) ignore this option and only normalize the score they get.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DistanceMetric {
+    /// Cosine similarity, the score is `1 - cosine distance`.
+    #[default]
+    Cosine,
+    /// Euclidean distance, the score is `1 / (1 + distance)`.
+    L2,
+    /// Inner product, the score is the inner product itself.
+    InnerProduct,
+}
+
+impl DistanceMetric {
+    /// Converts a raw distance into a score where higher is more similar. For
+    /// `InnerProduct` the distance is the negative inner product, as returned by pgvector.
+    pub fn score(&self, distance: f64) -> f64 {
+        match self {
+            DistanceMetric::Cosine => 1.0 - distance,
+            DistanceMetric::L2 => 1.0 / (1.0 + distance),
+            DistanceMetric::InnerProduct => -distance,
+        }
+    }
+
+    /// The `L2` score of a squared euclidean distance, as returned by the stores that skip
+    /// the square root, so the score is the same as on the stores returning the distance.
+    pub fn squared_l2_score(squared_distance: f64) -> f64 {
+        DistanceMetric::L2.score(squared_distance.max(0.0).sqrt())
+    }
+}
+
+/// How `add_documents` chooses the id of each document.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum IdStrategy {
+    /// A new random id for each document, adding a document again duplicates it.
+    #[default]
+    Random,
+    /// An id derived from the content of the document, and from the value of the metadata
+    /// key when set, for example the source of the document. Adding a document again
+    /// replaces the stored one, so ingestion can be repeated.
+    ContentHash { metadata_key: Option<String> },
+}
+
+#[cfg(feature = "uuid")]
+impl IdStrategy {
+    /// The id of the document in the name space, a collection of the store.
+    pub fn document_id(&self, doc: &Document, name_space: &str) -> String {
+        match self {
+            IdStrategy::Random => Uuid::new_v4().to_string(),
+            IdStrategy::ContentHash { metadata_key } => {
+                let mut name = format!("{}\0{}", name_space, doc.page_content);
+                if let Some(key) = metadata_key {
+                    let value = doc.metadata.get(key).map(|v| v.to_string());
+                    name.push('\0');
+                    name.push_str(&value.unwrap_or_default());
+                }
+                Uuid::new_v5(&Uuid::NAMESPACE_OID, name.as_bytes()).to_string()
+            }
+        }
+    }
+}
+
+/// A filter on the metadata of the documents, written once and translated by each store
+/// into its own filters. The keys are top level metadata keys.
+///
+/// A store that can't express an operation returns an `UnsupportedFilterError` from the
+/// search.
+///
+/// # Usage
+/// ```rust,ignore
+/// let filter = MetadataFilter::eq("genre", "Sci-Fi")
+///     .and(MetadataFilter::gt("year", 1990).or(MetadataFilter::is_in("author", ["Asimov"])));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetadataFilter {
+    Eq(String, Value),
+    Ne(String, Value),
+    Gt(String, Value),
+    Lt(String, Value),
+    In(String, Vec<Value>),
+    /// Matches the documents matching all the filters, all of them when empty.
+    And(Vec<MetadataFilter>),
+    /// Matches the documents matching any of the filters, none when empty.
+    Or(Vec<MetadataFilter>),
+}
+
+impl MetadataFilter {
+    pub fn eq<K: Into<String>, V: Into<Value>>(key: K, value: V) -> Self {
+        Self::Eq(key.into(), value.into())
+    }
+
+    pub fn ne<K: Into<String>, V: Into<Value>>(key: K, value: V) -> Self {
+        Self::Ne(key.into(), value.into())
+    }
+
+    pub fn gt<K: Into<String>, V: Into<Value>>(key: K, value: V) -> Self {
+        Self::Gt(key.into(), value.into())
+    }
+
+    pub fn lt<K: Into<String>, V: Into<Value>>(key: K, value: V) -> Self {
+        Self::Lt(key.into(), value.into())
+    }
+
+    pub fn is_in<K, V, I>(key: K, values: I) -> Self
+    where
+        K: Into<String>,
+        V: Into<Value>,
+        I: IntoIterator<Item = V>,
+    {
+        Self::In(key.into(), values.into_iter().map(Into::into).collect())
+    }
+
+    /// Both filters, flattening the `And` filters.
+    pub fn and(self, other: MetadataFilter) -> Self {
+        match self {
+            Self::And(mut filters) => {
+                filters.push(other);
+                Self::And(filters)
+            }
+            filter => Self::And(vec![filter, other]),
+        }
+    }
+
+    /// Either filter, flattening the `Or` filters.
+    pub fn or(self, other: MetadataFilter) -> Self {
+        match self {
+            Self::Or(mut filters) => {
+                filters.push(other);
+                Self::Or(filters)
+            }
+            filter => Self::Or(vec![filter, other]),
+        }
+    }
+
+    /// Name of the operation, for the errors.
+    pub fn operation(&self) -> &'static str {
+        match self {
+            Self::Eq(..) => "Eq",
+            Self::Ne(..) => "Ne",
+            Self::Gt(..) => "Gt",
+            Self::Lt(..) => "Lt",
+            Self::In(..) => "In",
+            Self::And(..) => "And",
+            Self::Or(..) => "Or",
+        }
+    }
+
+    /// The filter in the `$eq`, `$ne`, `$gt`, `$lt`, `$in`, `$and` and `$or` operators
+    /// syntax shared by Chroma and Pinecone. Groups of one filter are written as the
+    /// filter, the empty groups can't be written.
+    pub fn to_operator_json(&self, store: &'static str) -> Result<Value, UnsupportedFilterError> {
+        let group =
+            |operator: &str, filters: &[MetadataFilter]| -> Result<Value, UnsupportedFilterError> {
+                match filters {
+                    [] => Err(UnsupportedFilterError::new(store, self)),
+                    [filter] => filter.to_operator_json(store),
+                    filters => {
+                        let filters = filters
+                            .iter()
+                            .map(|filter| filter.to_operator_json(store))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        Ok(json!({ operator: filters }))
+                    }
+                }
+            };
+        match self {
+            Self::Eq(key, value) => Ok(json!({ key: { "$eq": value } })),
+            Self::Ne(key, value) => Ok(json!({ key: { "$ne": value } })),
+            Self::Gt(key, value) => Ok(json!({ key: { "$gt": value } })),
+            Self::Lt(key, value) => Ok(json!({ key: { "$lt": value } })),
+            Self::In(key, values) => Ok(json!({ key: { "$in": values } })),
+            Self::And(filters) => group("$and", filters),
+            Self::Or(filters) => group("$or", filters),
+        }
+    }
+}
+
+/// Returned by the searches when the store can't express an operation of the
+/// `MetadataFilter`.
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("{store} doesn't support the {operation} metadata filter: {filter}")]
+pub struct UnsupportedFilterError {
+    pub store: &'static str,
+    pub operation: &'static str,
+    pub filter: String,
+}
+
+impl UnsupportedFilterError {
+    pub fn new(store: &'static str, filter: &MetadataFilter) -> Self {
+        Self {
+            store,
+            operation: filter.operation(),
+            filter: format!("{:?}", filter),
+        }
+    }
 }
 
 impl Default for VecStoreOptions {
@@ -35,7 +272,14 @@ impl VecStoreOptions {
             name_space: None,
             score_threshold: None,
             filters: None,
+            metadata_filter: None,
             embedder: None,
+            distance_metric: DistanceMetric::default(),
+            include_embeddings: false,
+            batch_size: None,
+            max_concurrency: 1,
+            progress: None,
+            id_strategy: IdStrategy::default(),
         }
     }
 
@@ -54,8 +298,124 @@ impl VecStoreOptions {
         self
     }
 
+    pub fn with_metadata_filter(mut self, metadata_filter: MetadataFilter) -> Self {
+        self.metadata_filter = Some(metadata_filter);
+        self
+    }
+
     pub fn with_embedder<E: Embedder + 'static>(mut self, embedder: E) -> Self {
         self.embedder = Some(Arc::new(embedder));
         self
     }
+
+    pub fn with_distance_metric(mut self, distance_metric: DistanceMetric) -> Self {
+        self.distance_metric = distance_metric;
+        self
+    }
+
+    pub fn with_include_embeddings(mut self, include_embeddings: bool) -> Self {
+        self.include_embeddings = include_embeddings;
+        self
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = Some(batch_size);
+        self
+    }
+
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = max_concurrency;
+        self
+    }
+
+    pub fn with_progress<F: Fn(usize, usize) + Send + Sync + 'static>(
+        mut self,
+        progress: F,
+    ) -> Self {
+        self.progress = Some(ProgressCallback::new(progress));
+        self
+    }
+
+    pub fn with_id_strategy(mut self, id_strategy: IdStrategy) -> Self {
+        self.id_strategy = id_strategy;
+        self
+    }
+
+    /// Derives the ids from the content of the documents, so adding a document again
+    /// replaces it instead of duplicating it.
+    pub fn with_upsert(self) -> Self {
+        self.with_id_strategy(IdStrategy::ContentHash { metadata_key: None })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_is_higher_for_closer_documents() {
+        for metric in [
+            DistanceMetric::Cosine,
+            DistanceMetric::L2,
+            DistanceMetric::InnerProduct,
+        ] {
+            assert!(metric.score(0.1) > metric.score(0.5), "{:?}", metric);
+        }
+        assert_eq!(DistanceMetric::Cosine.score(0.25), 0.75);
+        assert_eq!(DistanceMetric::L2.score(1.0), 0.5);
+        assert_eq!(DistanceMetric::InnerProduct.score(-3.0), 3.0);
+        assert_eq!(
+            DistanceMetric::squared_l2_score(4.0),
+            DistanceMetric::L2.score(2.0)
+        );
+    }
+
+    #[test]
+    fn test_metadata_filter_to_operator_json() {
+        let filter = MetadataFilter::eq("genre", "Sci-Fi")
+            .and(MetadataFilter::gt("year", 1990).or(MetadataFilter::is_in("author", ["Asimov"])));
+
+        assert_eq!(
+            filter.to_operator_json("Chroma").unwrap(),
+            json!({"$and": [
+                {"genre": {"$eq": "Sci-Fi"}},
+                {"$or": [{"year": {"$gt": 1990}}, {"author": {"$in": ["Asimov"]}}]},
+            ]})
+        );
+        assert_eq!(
+            MetadataFilter::And(vec![MetadataFilter::ne("genre", "Drama")])
+                .to_operator_json("Chroma")
+                .unwrap(),
+            json!({"genre": {"$ne": "Drama"}})
+        );
+        let error = MetadataFilter::Or(vec![])
+            .to_operator_json("Pinecone")
+            .unwrap_err();
+        assert_eq!(error.operation, "Or");
+        assert!(error.to_string().starts_with("Pinecone doesn't support"));
+    }
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn test_content_hash_ids() {
+        let strategy = IdStrategy::ContentHash {
+            metadata_key: Some("source".into()),
+        };
+        let doc = |content: &str, source: &str| {
+            Document::new(content).with_metadata(
+                [("source".to_string(), serde_json::json!(source))]
+                    .into_iter()
+                    .collect(),
+            )
+        };
+
+        let id = strategy.document_id(&doc("text", "a.txt"), "docs");
+        assert_eq!(id, strategy.document_id(&doc("text", "a.txt"), "docs"));
+        assert_ne!(id, strategy.document_id(&doc("text", "b.txt"), "docs"));
+        assert_ne!(id, strategy.document_id(&doc("text", "a.txt"), "notes"));
+        assert_ne!(
+            IdStrategy::Random.document_id(&doc("text", "a.txt"), "docs"),
+            IdStrategy::Random.document_id(&doc("text", "a.txt"), "docs")
+        );
+    }
 }
@@ -3,13 +3,15 @@ use std::{collections::HashMap, error::Error, sync::Arc};
 use async_trait::async_trait;
 use pgvector::Vector;
 use serde_json::{json, Value};
-use sqlx::{Pool, Postgres, Row};
-use uuid::Uuid;
+use sqlx::{postgres::PgRow, Pool, Postgres, Row};
 
 use crate::{
     embedding::embedder_trait::Embedder,
     schemas::Document,
-    vectorstore::{VecStoreOptions, VectorStore},
+    vectorstore::{
+        add_documents_with_embeddings_in_batches, DistanceMetric, IdStrategy, MetadataFilter,
+        UnsupportedFilterError, VecStoreOptions, VectorStore,
+    },
 };
 
 pub struct Store {
@@ -57,6 +59,159 @@ impl Store {
         }
     }
 
+    fn get_where_querys(&self, opt: &VecStoreOptions) -> Result<String, Box<dyn Error>> {
+        let filter = self.get_filters(opt)?;
+        let where_querys = filter
+            .iter()
+            .map(|(k, v)| {
+                format!(
+                    "(data.cmetadata ->> '{}') = '{}'",
+                    k,
+                    v.to_string().trim_matches('"')
+                )
+            })
+            .chain(
+                opt.metadata_filter
+                    .as_ref()
+                    .map(metadata_filter_sql)
+                    .transpose()?,
+            )
+            .collect::<Vec<String>>()
+            .join(" AND ");
+
+        if where_querys.is_empty() {
+            return Ok("TRUE".to_string());
+        }
+        Ok(where_querys)
+    }
+
+    fn rows_to_documents(rows: Vec<PgRow>) -> Result<Vec<Document>, sqlx::Error> {
+        rows.into_iter()
+            .map(|row| {
+                let page_content: String = row.try_get(0)?;
+                let metadata_json: Value = row.try_get(1)?;
+                let score: f64 = row.try_get(2)?;
+
+                let metadata = if let Value::Object(obj) = metadata_json {
+                    obj.into_iter().collect()
+                } else {
+                    HashMap::new() // Or handle this case as needed
+                };
+
+                Ok(Document {
+                    page_content,
+                    metadata,
+                    score,
+                    embedding: None,
+                })
+            })
+            .collect()
+    }
+
+    /// Search combining the vector similarity with a Postgres full-text rank over the
+    /// document, which helps with keyword-heavy queries. The score of each document is
+    /// `alpha * (1 - cosine_distance) + (1 - alpha) * ts_rank`. `alpha = 1.0` is a pure vector
+    /// search and `alpha = 0.0` a pure full-text search.
+    ///
+    /// Only `DistanceMetric::Cosine` is supported, the scores of the other metrics are not on
+    /// the same scale as the rank. The name space, metadata filters and score threshold of
+    /// `opt` are applied as in `similarity_search`, the threshold being compared to the
+    /// blended score.
+    ///
+    /// # Example
+    /// ```rust,ignore
+    /// let docs = store
+    ///     .hybrid_search("error code E1042", 5, 0.7, &VecStoreOptions::default())
+    ///     .await?;
+    /// ```
+    pub async fn hybrid_search(
+        &self,
+        query: &str,
+        limit: usize,
+        alpha: f32,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        if !(0.0..=1.0).contains(&alpha) {
+            return Err("Invalid alpha, it must be between 0 and 1".into());
+        }
+        if opt.distance_metric != DistanceMetric::Cosine {
+            return Err("Hybrid search only supports the cosine distance metric".into());
+        }
+        let collection_name = self.get_name_space(opt);
+        let where_querys = self.get_where_querys(opt)?;
+        let score_threshold = self.get_score_threshold(opt)?;
+        let distance_operator = Self::get_distance_operator(opt.distance_metric);
+        // ts_rank normalization 32 scales the rank to [0, 1) so it can be blended with the
+        // cosine similarity, which is in [0, 1] for the embeddings of text.
+        let score_expression = format!(
+            "($4 * {} + (1 - $4) * data.text_rank)",
+            Self::get_score_expression(opt.distance_metric)
+        );
+
+        let sql = format!(
+            r#"WITH filtered_embedding_dims AS MATERIALIZED (
+                SELECT
+                    *
+                FROM
+                    {}
+                WHERE
+                    vector_dims(embedding) = $1
+            )
+            SELECT
+                data.document,
+                data.cmetadata,
+                {}::float8 AS score
+            FROM (
+                SELECT
+                    filtered_embedding_dims.*,
+                    embedding {} $2 AS distance,
+                    ts_rank(
+                        to_tsvector(filtered_embedding_dims.document),
+                        plainto_tsquery($5),
+                        32
+                    ) AS text_rank
+                FROM
+                    filtered_embedding_dims
+                    JOIN {} ON filtered_embedding_dims.collection_id = {}.uuid
+                WHERE {}.name = '{}'
+            ) AS data
+            WHERE {} AND {} >= $6
+            ORDER BY
+                score DESC
+            LIMIT $3"#,
+            self.embedder_table_name,
+            score_expression,
+            distance_operator,
+            self.collection_table_name,
+            self.collection_table_name,
+            self.collection_table_name,
+            collection_name,
+            where_querys,
+            score_expression,
+        );
+
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let query_vector = embedder.embed_query(query).await?;
+        let vector_dims = query_vector.len();
+
+        let rows = sqlx::query(&sql)
+            .bind(vector_dims as i64)
+            .bind(&Vector::from(
+                query_vector
+                    .into_iter()
+                    .map(|x| x as f32)
+                    .collect::<Vec<f32>>(),
+            ))
+            .bind(limit as i32)
+            .bind(alpha as f64)
+            .bind(query)
+            .bind(score_threshold)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(Self::rows_to_documents(rows)?)
+    }
+
     fn get_name_space(&self, opt: &VecStoreOptions) -> String {
         match &opt.name_space {
             Some(name_space) => name_space.clone(),
@@ -64,15 +219,36 @@ impl Store {
         }
     }
 
-    fn get_score_threshold(&self, opt: &VecStoreOptions) -> Result<f32, Box<dyn Error>> {
+    fn get_score_threshold(&self, opt: &VecStoreOptions) -> Result<f64, Box<dyn Error>> {
         match &opt.score_threshold {
             Some(score_threshold) => {
-                if *score_threshold < 0.0 || *score_threshold > 1.0 {
+                // The inner product is not bounded, any threshold is valid
+                if opt.distance_metric != DistanceMetric::InnerProduct
+                    && (*score_threshold < 0.0 || *score_threshold > 1.0)
+                {
                     return Err("Invalid score threshold".into());
                 }
-                Ok(*score_threshold)
+                Ok(*score_threshold as f64)
             }
-            None => Ok(0.0),
+            None => Ok(f64::NEG_INFINITY),
+        }
+    }
+
+    // The pgvector operator returning the distance for the metric, smaller is closer
+    fn get_distance_operator(metric: DistanceMetric) -> &'static str {
+        match metric {
+            DistanceMetric::Cosine => "<=>",
+            DistanceMetric::L2 => "<->",
+            DistanceMetric::InnerProduct => "<#>",
+        }
+    }
+
+    // SQL version of `DistanceMetric::score`, higher is more similar
+    fn get_score_expression(metric: DistanceMetric) -> &'static str {
+        match metric {
+            DistanceMetric::Cosine => "(1 - data.distance)",
+            DistanceMetric::L2 => "(1 / (1 + data.distance))",
+            DistanceMetric::InnerProduct => "(-data.distance)",
         }
     }
 
@@ -94,31 +270,16 @@ impl Store {
         Ok(())
     }
 
-    async fn remove_collection(&self) -> Result<(), Box<dyn Error>> {
-        sqlx::query(r#"DELETE FROM collection WHERE uuid = $1"#)
-            .bind(&self.collection_uuid)
-            .execute(&self.pool)
-            .await?;
-        Ok(())
-    }
-}
-#[async_trait]
-impl VectorStore for Store {
-    async fn add_documents(
+    /// Embeds the documents and inserts them in a single transaction, so either all of them
+    /// are stored or none. A document with the id of a stored one replaces it.
+    async fn add_batch(
         &self,
         docs: &[Document],
-        opt: &VecStoreOptions,
-    ) -> Result<Vec<String>, Box<dyn Error>> {
-        if opt.score_threshold.is_some() || opt.filters.is_some() || opt.name_space.is_some() {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "score_threshold, filters, and name_space are not supported in pgvector",
-            )));
-        }
+        embedder: &dyn Embedder,
+        id_strategy: &IdStrategy,
+    ) -> Result<Vec<(String, Vec<f64>)>, Box<dyn Error>> {
         let texts: Vec<String> = docs.iter().map(|d| d.page_content.clone()).collect();
 
-        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
-
         let vectors = embedder.embed_documents(&texts).await?;
 
         if vectors.len() != docs.len() {
@@ -133,7 +294,7 @@ impl VectorStore for Store {
         let mut ids = Vec::with_capacity(docs.len());
 
         for (doc, vector) in docs.iter().zip(vectors.iter()) {
-            let id = Uuid::new_v4().to_string();
+            let id = id_strategy.document_id(doc, &self.collection_uuid);
             ids.push(id.clone());
 
             let vector_value =
@@ -141,7 +302,12 @@ impl VectorStore for Store {
 
             sqlx::query(&format!(
                 r#"INSERT INTO {} 
-(uuid, document, embedding, cmetadata, collection_id) VALUES ($1, $2, $3, $4, $5)"#,
+(uuid, document, embedding, cmetadata, collection_id) VALUES ($1, $2, $3, $4, $5)
+ON CONFLICT (uuid) DO UPDATE SET
+document = EXCLUDED.document,
+embedding = EXCLUDED.embedding,
+cmetadata = EXCLUDED.cmetadata,
+collection_id = EXCLUDED.collection_id"#,
                 self.embedder_table_name
             ))
             .bind(&id)
@@ -155,7 +321,101 @@ impl VectorStore for Store {
 
         tx.commit().await?;
 
-        Ok(ids)
+        Ok(ids.into_iter().zip(vectors).collect())
+    }
+
+    async fn remove_collection(&self) -> Result<(), Box<dyn Error>> {
+        sqlx::query(r#"DELETE FROM collection WHERE uuid = $1"#)
+            .bind(&self.collection_uuid)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+/// Quotes a string as a SQL literal.
+fn sql_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// The SQL condition of the `MetadataFilter`, comparing the `jsonb` values so numbers,
+/// strings and booleans match their type. `Gt` and `Lt` only compare values of the same
+/// type.
+fn metadata_filter_sql(filter: &MetadataFilter) -> Result<String, UnsupportedFilterError> {
+    let field = |key: &str| format!("(data.cmetadata -> {})", sql_literal(key));
+    let jsonb = |value: &Value| format!("{}::jsonb", sql_literal(&value.to_string()));
+    let group = |filters: &[MetadataFilter],
+                 operator: &str,
+                 empty: &str|
+     -> Result<String, UnsupportedFilterError> {
+        let conditions = filters
+            .iter()
+            .map(metadata_filter_sql)
+            .collect::<Result<Vec<_>, _>>()?;
+        if conditions.is_empty() {
+            return Ok(empty.to_string());
+        }
+        Ok(format!("({})", conditions.join(operator)))
+    };
+    let compare = |key: &str, operator: &str, value: &Value| match value {
+        Value::Number(_) | Value::String(_) => Ok(format!(
+            "(jsonb_typeof({}) = jsonb_typeof({}) AND {} {} {})",
+            field(key),
+            jsonb(value),
+            field(key),
+            operator,
+            jsonb(value)
+        )),
+        _ => Err(UnsupportedFilterError::new("pgvector", filter)),
+    };
+
+    match filter {
+        MetadataFilter::Eq(key, value) => Ok(format!("{} = {}", field(key), jsonb(value))),
+        MetadataFilter::Ne(key, value) => Ok(format!("{} <> {}", field(key), jsonb(value))),
+        MetadataFilter::Gt(key, value) => compare(key, ">", value),
+        MetadataFilter::Lt(key, value) => compare(key, "<", value),
+        MetadataFilter::In(_, values) if values.is_empty() => Ok("FALSE".to_string()),
+        MetadataFilter::In(key, values) => Ok(format!(
+            "{} IN ({})",
+            field(key),
+            values.iter().map(jsonb).collect::<Vec<_>>().join(", ")
+        )),
+        MetadataFilter::And(filters) => group(filters, " AND ", "TRUE"),
+        MetadataFilter::Or(filters) => group(filters, " OR ", "FALSE"),
+    }
+}
+
+#[async_trait]
+impl VectorStore for Store {
+    async fn add_documents(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let added = self.add_documents_with_embeddings(docs, opt).await?;
+        Ok(added.into_iter().map(|(id, _)| id).collect())
+    }
+
+    async fn add_documents_with_embeddings(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<(String, Vec<f64>)>, Box<dyn Error>> {
+        if docs.is_empty() {
+            return Ok(Vec::new());
+        }
+        if opt.score_threshold.is_some() || opt.filters.is_some() || opt.name_space.is_some() {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "score_threshold, filters, and name_space are not supported in pgvector",
+            )));
+        }
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        add_documents_with_embeddings_in_batches(docs, opt, |batch| async move {
+            self.add_batch(&batch, embedder.as_ref(), &opt.id_strategy)
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await
     }
 
     async fn similarity_search(
@@ -165,23 +425,13 @@ impl VectorStore for Store {
         opt: &VecStoreOptions,
     ) -> Result<Vec<Document>, Box<dyn Error>> {
         let collection_name = self.get_name_space(opt);
-        let filter = self.get_filters(opt)?;
-        let mut where_querys = filter
-            .iter()
-            .map(|(k, v)| {
-                format!(
-                    "(data.cmetadata ->> '{}') = '{}'",
-                    k,
-                    v.to_string().trim_matches('"')
-                )
-            })
-            .collect::<Vec<String>>()
-            .join(" AND ");
-
-        if where_querys.is_empty() {
-            where_querys = "TRUE".to_string();
-        }
+        let where_querys = self.get_where_querys(opt)?;
+        let score_threshold = self.get_score_threshold(opt)?;
+        let distance_operator = Self::get_distance_operator(opt.distance_metric);
+        let score_expression = Self::get_score_expression(opt.distance_metric);
 
+        // The operators return a distance, smaller is closer. The threshold and the returned
+        // score are the similarity, higher is closer.
         let sql = format!(
             r#"WITH filtered_embedding_dims AS MATERIALIZED (
                 SELECT
@@ -194,26 +444,29 @@ impl VectorStore for Store {
             SELECT
                 data.document,
                 data.cmetadata,
-                data.distance
+                {} AS score
             FROM (
                 SELECT
                     filtered_embedding_dims.*,
-                    embedding <=> $2 AS distance
+                    embedding {} $2 AS distance
                 FROM
                     filtered_embedding_dims
                     JOIN {} ON filtered_embedding_dims.collection_id = {}.uuid
                 WHERE {}.name = '{}'
             ) AS data
-            WHERE {}
+            WHERE {} AND {} >= $4
             ORDER BY
-                data.distance DESC
+                data.distance ASC
             LIMIT $3"#,
             self.embedder_table_name,
+            score_expression,
+            distance_operator,
             self.collection_table_name,
             self.collection_table_name,
             self.collection_table_name,
             collection_name,
             where_querys,
+            score_expression,
         );
 
         let query_vector = self.embedder.embed_query(query).await?;
@@ -229,30 +482,212 @@ impl VectorStore for Store {
                     .collect::<Vec<f32>>(),
             ))
             .bind(limit as i32)
+            .bind(score_threshold)
             .fetch_all(&self.pool)
             .await?;
 
-        let docs = rows
+        Ok(Self::rows_to_documents(rows)?)
+    }
+
+    async fn get_by_ids(&self, ids: &[String]) -> Result<Vec<Document>, Box<dyn Error>> {
+        let sql = format!(
+            r#"SELECT uuid, document, cmetadata
+            FROM {}
+            WHERE collection_id = $1 AND uuid = ANY($2)"#,
+            self.embedder_table_name
+        );
+
+        let rows = sqlx::query(&sql)
+            .bind(&self.collection_uuid)
+            .bind(ids)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut docs = rows
             .into_iter()
             .map(|row| {
-                let page_content: String = row.try_get(0)?;
-                let metadata_json: Value = row.try_get(1)?;
-                let score: f64 = row.try_get(2)?;
+                let id: String = row.try_get(0)?;
+                let page_content: String = row.try_get(1)?;
+                let metadata_json: Value = row.try_get(2)?;
 
-                let metadata = if let Value::Object(obj) = metadata_json {
+                let mut metadata: HashMap<String, Value> = if let Value::Object(obj) = metadata_json
+                {
                     obj.into_iter().collect()
                 } else {
-                    HashMap::new() // Or handle this case as needed
+                    HashMap::new()
                 };
+                metadata.insert("id".to_string(), Value::String(id));
 
-                Ok(Document {
-                    page_content,
-                    metadata,
-                    score,
-                })
+                Ok(Document::new(page_content).with_metadata(metadata))
             })
             .collect::<Result<Vec<Document>, sqlx::Error>>()?;
 
+        // Keep the order of the requested ids
+        docs.sort_by_key(|doc| ids.iter().position(|id| doc.metadata["id"] == *id));
         Ok(docs)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        embedding::EmbedderError, vectorstore::pgvector::StoreBuilder, vectorstore::VectorStore,
+    };
+
+    /// Embeds each known word on its own axis, so the nearest document is predictable.
+    struct AxisEmbedder {}
+
+    #[async_trait]
+    impl Embedder for AxisEmbedder {
+        async fn embed_documents(
+            &self,
+            documents: &[String],
+        ) -> Result<Vec<Vec<f64>>, EmbedderError> {
+            let mut vectors = Vec::with_capacity(documents.len());
+            for document in documents {
+                vectors.push(self.embed_query(document).await?);
+            }
+            Ok(vectors)
+        }
+
+        async fn embed_query(&self, text: &str) -> Result<Vec<f64>, EmbedderError> {
+            Ok(match text {
+                "cat" => vec![1.0, 0.0, 0.0],
+                "kitten" => vec![0.9, 0.1, 0.0],
+                "car" => vec![0.0, 0.0, 1.0],
+                _ => vec![0.0, 1.0, 0.0],
+            })
+        }
+    }
+
+    // The pool is lazy and the server doesn't exist, any query would fail
+    fn unreachable_store() -> Store {
+        Store {
+            embedder: Arc::new(AxisEmbedder {}),
+            pool: sqlx::postgres::PgPoolOptions::new()
+                .connect_lazy("postgres://postgres@127.0.0.1:9/none")
+                .unwrap(),
+            collection_name: "test".into(),
+            collection_table_name: "langchain_pg_collection".into(),
+            collection_uuid: Uuid::new_v4().to_string(),
+            collection_metadata: HashMap::new(),
+            embedder_table_name: "langchain_pg_embedding".into(),
+            pre_delete_collection: false,
+            vector_dimensions: 3,
+            hns_index: None,
+            vstore_options: VecStoreOptions::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_no_documents() {
+        let store = unreachable_store();
+        let ids = store
+            .add_documents(&[], &VecStoreOptions::default())
+            .await
+            .unwrap();
+        assert!(ids.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_hybrid_search_rejects_unsupported_options() {
+        let store = unreachable_store();
+        let opt = VecStoreOptions::default().with_distance_metric(DistanceMetric::InnerProduct);
+        let err = store.hybrid_search("cat", 3, 0.5, &opt).await.unwrap_err();
+        assert!(err.to_string().contains("cosine"));
+
+        let opt = VecStoreOptions::default().with_score_threshold(1.5);
+        let err = store.hybrid_search("cat", 3, 0.5, &opt).await.unwrap_err();
+        assert_eq!(err.to_string(), "Invalid score threshold");
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_similarity_search_returns_nearest_first() {
+        // Requires PGVECTOR_CONNECTION_STRING pointing to a Postgres with pgvector
+        let store = StoreBuilder::new()
+            .embedder(AxisEmbedder {})
+            .collection_name("test_similarity_order")
+            .pre_delete_collection(true)
+            .vector_dimensions(3)
+            .build()
+            .await
+            .unwrap();
+
+        let docs = ["car", "kitten", "dog"]
+            .iter()
+            .map(|text| Document::new(*text))
+            .collect::<Vec<_>>();
+        store
+            .add_documents(&docs, &VecStoreOptions::default())
+            .await
+            .unwrap();
+
+        let results = store
+            .similarity_search("cat", 3, &VecStoreOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(results[0].page_content, "kitten");
+
+        let results = store
+            .similarity_search(
+                "cat",
+                3,
+                &VecStoreOptions::default().with_score_threshold(0.5),
+            )
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].page_content, "kitten");
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_get_by_ids() {
+        // Requires PGVECTOR_CONNECTION_STRING pointing to a Postgres with pgvector
+        let store = StoreBuilder::new()
+            .embedder(AxisEmbedder {})
+            .collection_name("test_get_by_ids")
+            .pre_delete_collection(true)
+            .vector_dimensions(3)
+            .build()
+            .await
+            .unwrap();
+
+        let docs = [Document::new("cat"), Document::new("car")];
+        let ids = store
+            .add_documents(&docs, &VecStoreOptions::default())
+            .await
+            .unwrap();
+
+        let requested = vec![ids[1].clone(), "missing".to_string(), ids[0].clone()];
+        let results = store.get_by_ids(&requested).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].page_content, "car");
+        assert_eq!(results[0].metadata["id"], json!(ids[1]));
+        assert_eq!(results[1].page_content, "cat");
+    }
+
+    #[test]
+    fn test_metadata_filter_sql() {
+        let filter = MetadataFilter::eq("author", "O'Brien")
+            .and(MetadataFilter::gt("year", 1990).or(MetadataFilter::is_in("tag", [1, 2])));
+
+        assert_eq!(
+            metadata_filter_sql(&filter).unwrap(),
+            "((data.cmetadata -> 'author') = '\"O''Brien\"'::jsonb AND \
+             ((jsonb_typeof((data.cmetadata -> 'year')) = jsonb_typeof('1990'::jsonb) \
+             AND (data.cmetadata -> 'year') > '1990'::jsonb) \
+             OR (data.cmetadata -> 'tag') IN ('1'::jsonb, '2'::jsonb)))"
+        );
+        assert_eq!(
+            metadata_filter_sql(&MetadataFilter::Or(vec![])).unwrap(),
+            "FALSE"
+        );
+        assert!(metadata_filter_sql(&MetadataFilter::lt("draft", true)).is_err());
+    }
+}
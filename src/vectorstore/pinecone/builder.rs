@@ -0,0 +1,105 @@
+use std::{error::Error, sync::Arc};
+
+use reqwest::Client;
+
+use crate::{embedding::embedder_trait::Embedder, vectorstore::DistanceMetric};
+
+use super::Store;
+
+const DEFAULT_CONTENT_FIELD: &str = "text";
+
+pub struct StoreBuilder {
+    client: Option<Client>,
+    host: Option<String>,
+    api_key: Option<String>,
+    embedder: Option<Arc<dyn Embedder>>,
+    namespace: String,
+    content_field: String,
+    distance_metric: DistanceMetric,
+}
+
+impl StoreBuilder {
+    // Returns a new StoreBuilder instance with default values for each option
+    pub fn new() -> Self {
+        StoreBuilder {
+            client: None,
+            host: None,
+            api_key: None,
+            embedder: None,
+            namespace: String::new(),
+            content_field: DEFAULT_CONTENT_FIELD.into(),
+            distance_metric: DistanceMetric::default(),
+        }
+    }
+
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Host of the index, as shown in the Pinecone console, like
+    /// `my-index-abc123.svc.aped-4627-b74a.pinecone.io`. `https://` is added when the host
+    /// has no scheme.
+    pub fn host(mut self, host: &str) -> Self {
+        let host = host.trim_end_matches('/');
+        self.host = Some(if host.contains("://") {
+            host.into()
+        } else {
+            format!("https://{}", host)
+        });
+        self
+    }
+
+    pub fn api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    pub fn embedder<E: Embedder + 'static>(mut self, embedder: E) -> Self {
+        self.embedder = Some(Arc::new(embedder));
+        self
+    }
+
+    /// Namespace of the index used when the options have no `name_space`, the default
+    /// namespace by default.
+    pub fn namespace(mut self, namespace: &str) -> Self {
+        self.namespace = namespace.into();
+        self
+    }
+
+    /// Metadata field holding the content of the documents, `text` by default.
+    pub fn content_field(mut self, content_field: &str) -> Self {
+        self.content_field = content_field.into();
+        self
+    }
+
+    /// Metric of the index, set when the index was created. It's used to normalize the
+    /// scores of the search results.
+    pub fn distance_metric(mut self, distance_metric: DistanceMetric) -> Self {
+        self.distance_metric = distance_metric;
+        self
+    }
+
+    // Finalize the builder and construct the Store object, the index must exist
+    pub fn build(self) -> Result<Store, Box<dyn Error>> {
+        let host = self.host.ok_or("Index host is required")?;
+        let api_key = self.api_key.ok_or("API key is required")?;
+        let embedder = self.embedder.ok_or("Embedder is required")?;
+
+        Ok(Store {
+            client: self.client.unwrap_or_default(),
+            host,
+            api_key,
+            embedder,
+            namespace: self.namespace,
+            content_field: self.content_field,
+            distance_metric: self.distance_metric,
+        })
+    }
+}
+
+impl Default for StoreBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,416 @@
+use std::{error::Error, sync::Arc};
+
+use async_trait::async_trait;
+use reqwest::{Client, Method, RequestBuilder};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    embedding::embedder_trait::Embedder,
+    schemas::Document,
+    vectorstore::{
+        add_documents_with_embeddings_in_batches, DistanceMetric, IdStrategy, VecStoreOptions,
+        VectorStore,
+    },
+};
+
+/// Metadata field holding the whole metadata of a document as a JSON string. Pinecone
+/// metadata values can only be strings, numbers, booleans or lists of strings, the top
+/// level metadata values of these types are also stored as fields of their own, so they
+/// can be used in filters.
+pub(crate) const METADATA_FIELD: &str = "metadata_json";
+
+const API_VERSION: &str = "2024-07";
+
+/// Maximum number of vectors of an upsert or a fetch request, Pinecone recommends
+/// batches of 100 vectors.
+const REQUEST_BATCH_SIZE: usize = 100;
+
+// https://docs.pinecone.io/reference/api/2024-07/data-plane
+
+pub struct Store {
+    pub(crate) client: Client,
+    pub(crate) host: String,
+    pub(crate) api_key: String,
+    pub(crate) embedder: Arc<dyn Embedder>,
+    pub(crate) namespace: String,
+    pub(crate) content_field: String,
+    pub(crate) distance_metric: DistanceMetric,
+}
+
+impl Store {
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.client
+            .request(method, format!("{}{}", self.host, path))
+            .header("Api-Key", &self.api_key)
+            .header("X-Pinecone-API-Version", API_VERSION)
+    }
+
+    /// Sends the request, the error has the message of the response when it fails.
+    async fn send(request: RequestBuilder) -> Result<Value, Box<dyn Error>> {
+        let response = request.send().await?;
+        let status = response.status();
+        let body = response.text().await?;
+        if !status.is_success() {
+            return Err(format!("Pinecone error {}: {}", status, body).into());
+        }
+        if body.is_empty() {
+            return Ok(Value::Null);
+        }
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    fn get_namespace<'a>(&'a self, opt: &'a VecStoreOptions) -> &'a str {
+        opt.name_space.as_deref().unwrap_or(&self.namespace)
+    }
+
+    fn metadata(&self, doc: &Document) -> Result<Value, Box<dyn Error>> {
+        let mut metadata = Map::new();
+        for (key, value) in &doc.metadata {
+            if is_metadata_value(value) && key != &self.content_field && key != METADATA_FIELD {
+                metadata.insert(key.clone(), value.clone());
+            }
+        }
+        metadata.insert(
+            self.content_field.clone(),
+            Value::from(doc.page_content.clone()),
+        );
+        metadata.insert(
+            METADATA_FIELD.to_string(),
+            Value::from(serde_json::to_string(&doc.metadata)?),
+        );
+        Ok(Value::Object(metadata))
+    }
+
+    /// The document of the metadata of a vector. The vectors not added by the store have
+    /// their metadata fields, without the content, as the metadata of the document.
+    fn document_from_metadata(&self, metadata: &Value) -> Document {
+        let page_content = metadata[&self.content_field]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        let metadata = match metadata[METADATA_FIELD].as_str() {
+            Some(metadata) => serde_json::from_str(metadata).unwrap_or_default(),
+            None => metadata
+                .as_object()
+                .into_iter()
+                .flatten()
+                .filter(|(key, _)| *key != &self.content_field)
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+        };
+        Document::new(page_content).with_metadata(metadata)
+    }
+
+    /// Pinecone returns the similarity for the cosine and dot product metrics, and the
+    /// squared distance for the euclidean metric.
+    fn score(&self, score: f64) -> f64 {
+        match self.distance_metric {
+            DistanceMetric::Cosine | DistanceMetric::InnerProduct => score,
+            DistanceMetric::L2 => DistanceMetric::squared_l2_score(score),
+        }
+    }
+
+    async fn add_batch(
+        &self,
+        docs: &[Document],
+        embedder: &dyn Embedder,
+        id_strategy: &IdStrategy,
+        namespace: &str,
+    ) -> Result<Vec<(String, Vec<f64>)>, Box<dyn Error>> {
+        let texts: Vec<String> = docs.iter().map(|d| d.page_content.clone()).collect();
+        let vectors = embedder.embed_documents(&texts).await?;
+
+        if vectors.len() != docs.len() {
+            return Err("Number of vectors and documents do not match".into());
+        }
+
+        let mut added = Vec::with_capacity(docs.len());
+        let mut records = Vec::with_capacity(docs.len());
+        for (doc, vector) in docs.iter().zip(vectors) {
+            let id = id_strategy.document_id(doc, namespace);
+            records.push(json!({
+                "id": id,
+                "values": vector,
+                "metadata": self.metadata(doc)?,
+            }));
+            added.push((id, vector));
+        }
+
+        for records in records.chunks(REQUEST_BATCH_SIZE) {
+            Self::send(
+                self.request(Method::POST, "/vectors/upsert")
+                    .json(&json!({ "vectors": records, "namespace": namespace })),
+            )
+            .await?;
+        }
+
+        Ok(added)
+    }
+}
+
+#[async_trait]
+impl VectorStore for Store {
+    /// Add documents to the store, upserting them in the namespace.
+    /// Returns a list of the ids of the vectors.
+    async fn add_documents(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let added = self.add_documents_with_embeddings(docs, opt).await?;
+        Ok(added.into_iter().map(|(id, _)| id).collect())
+    }
+
+    async fn add_documents_with_embeddings(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<(String, Vec<f64>)>, Box<dyn Error>> {
+        if docs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let namespace = self.get_namespace(opt);
+        add_documents_with_embeddings_in_batches(docs, opt, |batch| async move {
+            self.add_batch(&batch, embedder.as_ref(), &opt.id_strategy, namespace)
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await
+    }
+
+    /// Perform a similarity search on the namespace. `filters` are Pinecone metadata
+    /// filters, for example `{"source": {"$eq": "a.txt"}}`.
+    async fn similarity_search(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let query_vector = embedder.embed_query(query).await?;
+
+        let mut body = json!({
+            "vector": query_vector,
+            "topK": limit,
+            "namespace": self.get_namespace(opt),
+            "includeMetadata": true,
+            "includeValues": opt.include_embeddings,
+        });
+        let metadata_filter = opt
+            .metadata_filter
+            .as_ref()
+            .map(|filter| filter.to_operator_json("Pinecone"))
+            .transpose()?;
+        match (&opt.filters, metadata_filter) {
+            (Some(filters), Some(filter)) => body["filter"] = json!({ "$and": [filters, filter] }),
+            (Some(filters), None) => body["filter"] = filters.clone(),
+            (None, Some(filter)) => body["filter"] = filter,
+            (None, None) => {}
+        }
+        let response = Self::send(self.request(Method::POST, "/query").json(&body)).await?;
+
+        let mut documents = response["matches"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|record| {
+                let score = record["score"].as_f64().unwrap_or_default();
+                let mut doc = self
+                    .document_from_metadata(&record["metadata"])
+                    .with_score(self.score(score));
+                doc.embedding = serde_json::from_value(record["values"].clone())
+                    .ok()
+                    .filter(|values: &Vec<f64>| !values.is_empty());
+                doc
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(score_threshold) = opt.score_threshold {
+            documents.retain(|doc| doc.score >= score_threshold as f64);
+        }
+
+        Ok(documents)
+    }
+
+    async fn get_by_ids(&self, ids: &[String]) -> Result<Vec<Document>, Box<dyn Error>> {
+        let mut docs = Vec::with_capacity(ids.len());
+        for ids in ids.chunks(REQUEST_BATCH_SIZE) {
+            let mut query: Vec<(&str, &str)> = ids.iter().map(|id| ("ids", id.as_str())).collect();
+            query.push(("namespace", self.namespace.as_str()));
+            let response =
+                Self::send(self.request(Method::GET, "/vectors/fetch").query(&query)).await?;
+
+            for id in ids {
+                let record = &response["vectors"][id];
+                if record.is_null() {
+                    continue;
+                }
+                let mut doc = self.document_from_metadata(&record["metadata"]);
+                doc.metadata
+                    .insert("id".to_string(), Value::from(id.clone()));
+                docs.push(doc);
+            }
+        }
+        Ok(docs)
+    }
+
+    async fn delete_documents(&self, ids: &[String]) -> Result<(), Box<dyn Error>> {
+        for ids in ids.chunks(1000) {
+            Self::send(
+                self.request(Method::POST, "/vectors/delete")
+                    .json(&json!({ "ids": ids, "namespace": self.namespace })),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether Pinecone accepts the value in the metadata: a string, a number, a boolean or
+/// a list of strings.
+fn is_metadata_value(value: &Value) -> bool {
+    match value {
+        Value::String(_) | Value::Number(_) | Value::Bool(_) => true,
+        Value::Array(values) => values.iter().all(Value::is_string),
+        Value::Null | Value::Object(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use async_trait::async_trait;
+    use mockito::Matcher;
+
+    use super::*;
+    use crate::{embedding::EmbedderError, vectorstore::pinecone::StoreBuilder};
+
+    struct FakeEmbedder {}
+
+    #[async_trait]
+    impl Embedder for FakeEmbedder {
+        async fn embed_documents(
+            &self,
+            documents: &[String],
+        ) -> Result<Vec<Vec<f64>>, EmbedderError> {
+            Ok(documents.iter().map(|_| vec![1.0, 0.0]).collect())
+        }
+
+        async fn embed_query(&self, _text: &str) -> Result<Vec<f64>, EmbedderError> {
+            Ok(vec![1.0, 0.0])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pinecone_add_documents() {
+        let mut server = mockito::Server::new_async().await;
+        let metadata = HashMap::from([
+            ("source".to_string(), json!("a.txt")),
+            ("page".to_string(), json!(2)),
+            ("tags".to_string(), json!(["a", "b"])),
+            ("author".to_string(), json!({"name": "Ana"})),
+            ("pages".to_string(), json!([1, 2])),
+            ("missing".to_string(), Value::Null),
+        ]);
+        let doc = Document::new("first").with_metadata(metadata.clone());
+        let upsert = server
+            .mock("POST", "/vectors/upsert")
+            .match_header("Api-Key", "key")
+            .match_body(Matcher::PartialJson(json!({
+                "namespace": "docs",
+                "vectors": [{
+                    "values": [1.0, 0.0],
+                    "metadata": {
+                        "text": "first",
+                        "source": "a.txt",
+                        "page": 2,
+                        "tags": ["a", "b"],
+                        "metadata_json": serde_json::to_string(&metadata).unwrap(),
+                    }
+                }]
+            })))
+            .with_body(r#"{"upsertedCount": 1}"#)
+            .create_async()
+            .await;
+
+        let store = StoreBuilder::new()
+            .host(&server.url())
+            .api_key("key")
+            .namespace("docs")
+            .embedder(FakeEmbedder {})
+            .build()
+            .unwrap();
+        let ids = store
+            .add_documents(std::slice::from_ref(&doc), &VecStoreOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(ids.len(), 1);
+        upsert.assert_async().await;
+
+        let stored = store.metadata(&doc).unwrap();
+        assert!(stored.get("author").is_none());
+        assert!(stored.get("pages").is_none());
+        assert!(stored.get("missing").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pinecone_similarity_search() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/query")
+            .match_body(Matcher::PartialJson(json!({
+                "vector": [1.0, 0.0],
+                "topK": 2,
+                "namespace": "other",
+                "filter": {"source": {"$eq": "a.txt"}},
+            })))
+            .with_body(
+                json!({"matches": [
+                    {
+                        "id": "1",
+                        // The squared distance, 0.25 apart
+                        "score": 0.0625,
+                        "metadata": {
+                            "text": "first",
+                            "source": "a.txt",
+                            "metadata_json": "{\"source\":\"a.txt\",\"author\":{\"name\":\"Ana\"}}"
+                        }
+                    },
+                    {"id": "2", "score": 9.0, "metadata": {"text": "second", "lang": "en"}}
+                ]})
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let store = StoreBuilder::new()
+            .host(&server.url())
+            .api_key("key")
+            .distance_metric(DistanceMetric::L2)
+            .embedder(FakeEmbedder {})
+            .build()
+            .unwrap();
+        let opt = VecStoreOptions::default()
+            .with_name_space("other")
+            .with_filters(json!({"source": {"$eq": "a.txt"}}));
+        let docs = store.similarity_search("query", 2, &opt).await.unwrap();
+
+        assert_eq!(docs.len(), 2);
+        assert_eq!(docs[0].page_content, "first");
+        assert_eq!(docs[0].score, 0.8);
+        assert_eq!(docs[0].metadata["author"], json!({"name": "Ana"}));
+        assert_eq!(docs[1].score, 0.25);
+        assert_eq!(
+            docs[1].metadata,
+            HashMap::from([("lang".to_string(), json!("en"))])
+        );
+
+        let docs = store
+            .similarity_search("query", 2, &opt.with_score_threshold(0.5))
+            .await
+            .unwrap();
+        assert_eq!(docs.len(), 1);
+    }
+}
@@ -1,7 +1,10 @@
 use async_trait::async_trait;
 use qdrant_client::client::Payload;
-use qdrant_client::qdrant::{Filter, PointStruct, SearchPointsBuilder, UpsertPointsBuilder};
-use serde_json::json;
+use qdrant_client::qdrant::{
+    vector_output::Vector, vectors_output::VectorsOptions, Condition, Filter, PointStruct, Range,
+    SearchPointsBuilder, UpsertPointsBuilder, VectorsOutput,
+};
+use serde_json::{json, Value};
 use std::error::Error;
 use std::sync::Arc;
 
@@ -14,7 +17,7 @@ pub use qdrant_client::Qdrant as QdrantClient;
 use crate::{
     embedding::embedder_trait::Embedder,
     schemas::Document,
-    vectorstore::{VecStoreOptions, VectorStore},
+    vectorstore::{MetadataFilter, UnsupportedFilterError, VecStoreOptions, VectorStore},
 };
 use uuid::Uuid;
 
@@ -27,6 +30,86 @@ pub struct Store {
     pub search_filter: Option<Filter>,
 }
 
+impl Store {
+    /// The qdrant filter of the `MetadataFilter`, on the keys of the metadata field of the
+    /// payload. `Gt` and `Lt` only compare numbers, and the empty `Or` and `In` filters
+    /// are not supported.
+    fn metadata_filter(&self, filter: &MetadataFilter) -> Result<Filter, UnsupportedFilterError> {
+        Ok(match filter {
+            MetadataFilter::Ne(key, value) => {
+                Filter::must_not([self.metadata_condition(filter, key, value)?])
+            }
+            MetadataFilter::Eq(key, value) => {
+                Filter::must([self.metadata_condition(filter, key, value)?])
+            }
+            MetadataFilter::Gt(key, Value::Number(n)) => Filter::must([Condition::range(
+                self.metadata_key(key),
+                Range {
+                    gt: n.as_f64(),
+                    ..Default::default()
+                },
+            )]),
+            MetadataFilter::Lt(key, Value::Number(n)) => Filter::must([Condition::range(
+                self.metadata_key(key),
+                Range {
+                    lt: n.as_f64(),
+                    ..Default::default()
+                },
+            )]),
+            MetadataFilter::In(key, values) if !values.is_empty() => Filter::should(
+                values
+                    .iter()
+                    .map(|value| self.metadata_condition(filter, key, value))
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+            MetadataFilter::And(filters) => Filter::must(
+                filters
+                    .iter()
+                    .map(|filter| self.metadata_filter(filter).map(Condition::from))
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+            MetadataFilter::Or(filters) if !filters.is_empty() => Filter::should(
+                filters
+                    .iter()
+                    .map(|filter| self.metadata_filter(filter).map(Condition::from))
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+            filter => return Err(UnsupportedFilterError::new("Qdrant", filter)),
+        })
+    }
+
+    /// The condition matching the value of the key.
+    fn metadata_condition(
+        &self,
+        filter: &MetadataFilter,
+        key: &str,
+        value: &Value,
+    ) -> Result<Condition, UnsupportedFilterError> {
+        let key = self.metadata_key(key);
+        match value {
+            Value::String(value) => Ok(Condition::matches(key, value.clone())),
+            Value::Bool(value) => Ok(Condition::matches(key, *value)),
+            Value::Number(n) => match n.as_i64() {
+                Some(n) => Ok(Condition::matches(key, n)),
+                None => Ok(Condition::range(
+                    key,
+                    Range {
+                        gte: n.as_f64(),
+                        lte: n.as_f64(),
+                        ..Default::default()
+                    },
+                )),
+            },
+            Value::Null => Ok(Condition::is_null(key)),
+            _ => Err(UnsupportedFilterError::new("Qdrant", filter)),
+        }
+    }
+
+    fn metadata_key(&self, key: &str) -> String {
+        format!("{}.{}", self.metadata_field, key)
+    }
+}
+
 #[async_trait]
 impl VectorStore for Store {
     /// Add documents to the store.
@@ -36,11 +119,26 @@ impl VectorStore for Store {
         docs: &[Document],
         opt: &VecStoreOptions,
     ) -> Result<Vec<String>, Box<dyn Error>> {
+        let added = self.add_documents_with_embeddings(docs, opt).await?;
+        Ok(added.into_iter().map(|(id, _)| id).collect())
+    }
+
+    async fn add_documents_with_embeddings(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<(String, Vec<f64>)>, Box<dyn Error>> {
+        if docs.is_empty() {
+            return Ok(Vec::new());
+        }
         let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
         let texts: Vec<String> = docs.iter().map(|d| d.page_content.clone()).collect();
 
-        let ids = docs.iter().map(|_| Uuid::new_v4().to_string());
-        let vectors = embedder.embed_documents(&texts).await?.into_iter();
+        let ids: Vec<String> = docs.iter().map(|_| Uuid::new_v4().to_string()).collect();
+        let vectors = embedder.embed_documents(&texts).await?;
+        if vectors.len() != docs.len() {
+            return Err("Number of vectors and documents do not match".into());
+        }
         let payloads = docs.iter().map(|d| {
             json!({
                 &self.content_field: d.page_content,
@@ -50,9 +148,9 @@ impl VectorStore for Store {
 
         let mut points: Vec<PointStruct> = Vec::with_capacity(docs.len());
 
-        for (id, (vector, payload)) in ids.clone().zip(vectors.zip(payloads)) {
-            let vector: Vec<f32> = vector.into_iter().map(|f| f as f32).collect();
-            let point = PointStruct::new(id, vector, Payload::try_from(payload).unwrap());
+        for (id, (vector, payload)) in ids.iter().zip(vectors.iter().zip(payloads)) {
+            let vector: Vec<f32> = vector.iter().map(|f| *f as f32).collect();
+            let point = PointStruct::new(id.clone(), vector, Payload::try_from(payload).unwrap());
             points.push(point);
         }
 
@@ -60,7 +158,7 @@ impl VectorStore for Store {
             .upsert_points(UpsertPointsBuilder::new(&self.collection_name, points).wait(true))
             .await?;
 
-        Ok(ids.collect())
+        Ok(ids.into_iter().zip(vectors).collect())
     }
 
     /// Perform a similarity search on the store.
@@ -78,7 +176,7 @@ impl VectorStore for Store {
         if opt.filters.is_some() {
             return Err(
                 "'qdrant_client' doesn't support 'serde_json::Value' filters. 
-            Use `search_filter` when constructing VectorStore instead"
+            Use `metadata_filter`, or `search_filter` when constructing VectorStore instead"
                     .into(),
             );
         }
@@ -93,12 +191,26 @@ impl VectorStore for Store {
 
         let mut operation =
             SearchPointsBuilder::new(&self.collection_name, query_vector, limit as u64)
-                .with_payload(true);
+                .with_payload(true)
+                .with_vectors(opt.include_embeddings);
         if let Some(score_threshold) = opt.score_threshold {
             operation = operation.score_threshold(score_threshold);
         }
-        if let Some(filter) = &self.search_filter {
-            operation = operation.filter(filter.clone());
+        let metadata_filter = opt
+            .metadata_filter
+            .as_ref()
+            .map(|filter| self.metadata_filter(filter))
+            .transpose()?;
+        match (&self.search_filter, metadata_filter) {
+            (Some(search_filter), Some(filter)) => {
+                operation = operation.filter(Filter::must([
+                    Condition::from(search_filter.clone()),
+                    Condition::from(filter),
+                ]))
+            }
+            (Some(filter), None) => operation = operation.filter(filter.clone()),
+            (None, Some(filter)) => operation = operation.filter(filter),
+            (None, None) => {}
         }
         let results = self.client.search_points(operation).await?;
 
@@ -117,6 +229,7 @@ impl VectorStore for Store {
                     page_content,
                     metadata,
                     score,
+                    embedding: dense_vector(scored_point.vectors),
                 }
             })
             .collect();
@@ -124,3 +237,19 @@ impl VectorStore for Store {
         Ok(documents)
     }
 }
+
+/// The unnamed dense vector of a point, stores with named or sparse vectors have none.
+fn dense_vector(vectors: Option<VectorsOutput>) -> Option<Vec<f64>> {
+    match vectors?.vectors_options? {
+        VectorsOptions::Vector(vector) => {
+            #[allow(deprecated)]
+            let data = match vector.vector {
+                Some(Vector::Dense(dense)) => dense.data,
+                Some(_) => return None,
+                None => vector.data,
+            };
+            Some(data.into_iter().map(f64::from).collect())
+        }
+        VectorsOptions::Vectors(_) => None,
+    }
+}
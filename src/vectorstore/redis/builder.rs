@@ -0,0 +1,182 @@
+use std::{error::Error, sync::Arc};
+
+use ::redis::Client;
+
+use crate::{embedding::embedder_trait::Embedder, vectorstore::DistanceMetric};
+
+use super::{IndexAlgorithm, Store, CONTENT_FIELD, METADATA_FIELD, VECTOR_FIELD};
+
+const DEFAULT_URL: &str = "redis://127.0.0.1:6379";
+const DEFAULT_INDEX_NAME: &str = "langchain";
+
+pub struct StoreBuilder {
+    client: Option<Client>,
+    url: String,
+    embedder: Option<Arc<dyn Embedder>>,
+    index_name: String,
+    key_prefix: Option<String>,
+    vector_dimensions: i32,
+    distance_metric: DistanceMetric,
+    algorithm: IndexAlgorithm,
+    tag_fields: Vec<String>,
+    numeric_fields: Vec<String>,
+}
+
+impl StoreBuilder {
+    // Returns a new StoreBuilder instance with default values for each option
+    pub fn new() -> Self {
+        StoreBuilder {
+            client: None,
+            url: DEFAULT_URL.into(),
+            embedder: None,
+            index_name: DEFAULT_INDEX_NAME.into(),
+            key_prefix: None,
+            vector_dimensions: 0,
+            distance_metric: DistanceMetric::default(),
+            algorithm: IndexAlgorithm::default(),
+            tag_fields: Vec::new(),
+            numeric_fields: Vec::new(),
+        }
+    }
+
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Url of the Redis Stack server, used when no client is set.
+    pub fn url(mut self, url: &str) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    pub fn embedder<E: Embedder + 'static>(mut self, embedder: E) -> Self {
+        self.embedder = Some(Arc::new(embedder));
+        self
+    }
+
+    pub fn index_name(mut self, index_name: &str) -> Self {
+        self.index_name = index_name.into();
+        self
+    }
+
+    /// Prefix of the keys of the documents, `doc:<index_name>:` by default.
+    pub fn key_prefix(mut self, key_prefix: &str) -> Self {
+        self.key_prefix = Some(key_prefix.into());
+        self
+    }
+
+    pub fn vector_dimensions(mut self, vector_dimensions: i32) -> Self {
+        self.vector_dimensions = vector_dimensions;
+        self
+    }
+
+    /// Distance of the vector field when the index is created by the builder, and used to
+    /// normalize the scores of the search results.
+    pub fn distance_metric(mut self, distance_metric: DistanceMetric) -> Self {
+        self.distance_metric = distance_metric;
+        self
+    }
+
+    pub fn algorithm(mut self, algorithm: IndexAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Metadata keys indexed as `TAG` fields, to filter by strings.
+    pub fn tag_fields<S: Into<String>>(mut self, tag_fields: Vec<S>) -> Self {
+        self.tag_fields = tag_fields.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Metadata keys indexed as `NUMERIC` fields, to filter by numbers and ranges.
+    pub fn numeric_fields<S: Into<String>>(mut self, numeric_fields: Vec<S>) -> Self {
+        self.numeric_fields = numeric_fields.into_iter().map(Into::into).collect();
+        self
+    }
+
+    // Finalize the builder and construct the Store object, the index is created if it
+    // doesn't exist
+    pub async fn build(self) -> Result<Store, Box<dyn Error>> {
+        if self.embedder.is_none() {
+            return Err("Embedder is required".into());
+        }
+        if self.vector_dimensions <= 0 {
+            return Err("Vector dimensions are required".into());
+        }
+
+        let client = match self.client {
+            Some(client) => client,
+            None => Client::open(self.url.as_str())?,
+        };
+        let mut connection = client.get_multiplexed_async_connection().await?;
+
+        let indexes: Vec<String> = ::redis::cmd("FT._LIST")
+            .query_async(&mut connection)
+            .await?;
+        let key_prefix = self
+            .key_prefix
+            .unwrap_or_else(|| format!("doc:{}:", self.index_name));
+
+        if !indexes.contains(&self.index_name) {
+            let mut command = ::redis::cmd("FT.CREATE");
+            command
+                .arg(&self.index_name)
+                .arg("ON")
+                .arg("HASH")
+                .arg("PREFIX")
+                .arg(1)
+                .arg(&key_prefix)
+                .arg("SCHEMA")
+                .arg(CONTENT_FIELD)
+                .arg("TEXT")
+                .arg(METADATA_FIELD)
+                .arg("TEXT")
+                .arg("NOINDEX");
+            for field in &self.tag_fields {
+                command.arg(field).arg("TAG");
+            }
+            for field in &self.numeric_fields {
+                command.arg(field).arg("NUMERIC");
+            }
+            let algorithm = match self.algorithm {
+                IndexAlgorithm::Flat => "FLAT",
+                IndexAlgorithm::Hnsw => "HNSW",
+            };
+            let distance = match self.distance_metric {
+                DistanceMetric::Cosine => "COSINE",
+                DistanceMetric::L2 => "L2",
+                DistanceMetric::InnerProduct => "IP",
+            };
+            command
+                .arg(VECTOR_FIELD)
+                .arg("VECTOR")
+                .arg(algorithm)
+                .arg(6)
+                .arg("TYPE")
+                .arg("FLOAT32")
+                .arg("DIM")
+                .arg(self.vector_dimensions)
+                .arg("DISTANCE_METRIC")
+                .arg(distance);
+            let _: () = command.query_async(&mut connection).await?;
+        }
+
+        Ok(Store {
+            connection,
+            embedder: self.embedder.unwrap(),
+            index_name: self.index_name,
+            key_prefix,
+            vector_dimensions: self.vector_dimensions,
+            distance_metric: self.distance_metric,
+            tag_fields: self.tag_fields,
+            numeric_fields: self.numeric_fields,
+        })
+    }
+}
+
+impl Default for StoreBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
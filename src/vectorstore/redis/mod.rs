@@ -0,0 +1,5 @@
+mod builder;
+mod redis;
+
+pub use self::redis::*;
+pub use builder::*;
@@ -0,0 +1,544 @@
+use std::{collections::HashMap, error::Error, sync::Arc};
+
+use ::redis::{aio::MultiplexedConnection, from_redis_value, Value as RedisValue};
+use async_trait::async_trait;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{
+    embedding::embedder_trait::Embedder,
+    schemas::Document,
+    vectorstore::{
+        DistanceMetric, MetadataFilter, UnsupportedFilterError, VecStoreOptions, VectorStore,
+    },
+};
+
+pub(crate) const CONTENT_FIELD: &str = "content";
+pub(crate) const METADATA_FIELD: &str = "metadata";
+pub(crate) const VECTOR_FIELD: &str = "content_vector";
+const SCORE_FIELD: &str = "vector_score";
+
+// https://redis.io/docs/latest/develop/interact/search-and-query/advanced-concepts/vectors/
+
+/// Vector index algorithm of RediSearch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IndexAlgorithm {
+    /// Brute force search, exact but slower on large indexes.
+    Flat,
+    #[default]
+    Hnsw,
+}
+
+pub struct Store {
+    pub(crate) connection: MultiplexedConnection,
+    pub(crate) embedder: Arc<dyn Embedder>,
+    pub(crate) index_name: String,
+    pub(crate) key_prefix: String,
+    pub(crate) vector_dimensions: i32,
+    pub(crate) distance_metric: DistanceMetric,
+    pub(crate) tag_fields: Vec<String>,
+    pub(crate) numeric_fields: Vec<String>,
+}
+
+impl Store {
+    fn key(&self, id: &str) -> String {
+        format!("{}{}", self.key_prefix, id)
+    }
+
+    /// Hash fields of a document. The whole metadata is saved as JSON, and the metadata
+    /// values of the tag and numeric fields of the index are also saved on their own so
+    /// they can be used in filters.
+    fn fields(&self, doc: &Document, vector: &[f64]) -> Vec<(String, Vec<u8>)> {
+        let mut fields = vec![
+            (
+                CONTENT_FIELD.to_string(),
+                doc.page_content.clone().into_bytes(),
+            ),
+            (
+                METADATA_FIELD.to_string(),
+                Value::from_iter(doc.metadata.clone())
+                    .to_string()
+                    .into_bytes(),
+            ),
+            (VECTOR_FIELD.to_string(), vector_to_bytes(vector)),
+        ];
+        for field in &self.tag_fields {
+            let value = match doc.metadata.get(field) {
+                Some(Value::String(value)) => value.clone(),
+                Some(Value::Bool(value)) => value.to_string(),
+                Some(Value::Array(values)) => values
+                    .iter()
+                    .filter_map(|value| value.as_str())
+                    .collect::<Vec<_>>()
+                    .join(","),
+                _ => continue,
+            };
+            fields.push((field.clone(), value.into_bytes()));
+        }
+        for field in &self.numeric_fields {
+            if let Some(Value::Number(value)) = doc.metadata.get(field) {
+                fields.push((field.clone(), value.to_string().into_bytes()));
+            }
+        }
+        fields
+    }
+
+    fn document_from_fields(&self, fields: &HashMap<String, Vec<u8>>) -> Document {
+        let page_content = fields
+            .get(CONTENT_FIELD)
+            .map(|content| String::from_utf8_lossy(content).into_owned())
+            .unwrap_or_default();
+        let metadata = fields
+            .get(METADATA_FIELD)
+            .and_then(|metadata| serde_json::from_slice(metadata).ok())
+            .unwrap_or_default();
+        Document::new(page_content).with_metadata(metadata)
+    }
+}
+
+fn vector_to_bytes(vector: &[f64]) -> Vec<u8> {
+    vector
+        .iter()
+        .flat_map(|x| (*x as f32).to_le_bytes())
+        .collect()
+}
+
+fn vector_from_bytes(bytes: &[u8]) -> Vec<f64> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) as f64)
+        .collect()
+}
+
+/// RediSearch returns distances, converted to scores where higher is more similar. The
+/// `IP` distance of RediSearch is `1 - inner product`, and its `L2` distance is squared.
+fn score(distance_metric: DistanceMetric, distance: f64) -> f64 {
+    match distance_metric {
+        DistanceMetric::InnerProduct => 1.0 - distance,
+        DistanceMetric::L2 => DistanceMetric::squared_l2_score(distance),
+        metric => metric.score(distance),
+    }
+}
+
+/// Escapes the punctuation of a tag value, as required by the query syntax.
+fn escape_tag(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if !c.is_alphanumeric() && c != '_' {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Builds the RediSearch query of the `filters`. Filters are a map of field to value, tag
+/// fields match a string or any of a list of strings, numeric fields match a number or a
+/// range like `{"$gte": 1, "$lt": 10}`.
+fn filter_query(
+    filters: &Value,
+    tag_fields: &[String],
+    numeric_fields: &[String],
+) -> Result<String, Box<dyn Error>> {
+    let filters = filters
+        .as_object()
+        .ok_or("Redis filters must be an object")?;
+
+    let mut clauses = Vec::with_capacity(filters.len());
+    for (field, value) in filters {
+        let clause = if tag_fields.contains(field) {
+            let tags = match value {
+                Value::String(tag) => vec![escape_tag(tag)],
+                Value::Bool(tag) => vec![tag.to_string()],
+                Value::Array(tags) => tags
+                    .iter()
+                    .map(|tag| tag.as_str().map(escape_tag))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| format!("Invalid tags for {}: {}", field, value))?,
+                _ => return Err(format!("Invalid tag for {}: {}", field, value).into()),
+            };
+            format!("@{}:{{{}}}", field, tags.join(" | "))
+        } else if numeric_fields.contains(field) {
+            let (min, max) = match value {
+                Value::Number(n) => (n.to_string(), n.to_string()),
+                Value::Object(range) => {
+                    let mut min = "-inf".to_string();
+                    let mut max = "+inf".to_string();
+                    for (operator, n) in range {
+                        let n = n
+                            .as_f64()
+                            .ok_or_else(|| format!("Invalid number for {}: {}", field, n))?;
+                        match operator.as_str() {
+                            "$gte" => min = n.to_string(),
+                            "$gt" => min = format!("({}", n),
+                            "$lte" => max = n.to_string(),
+                            "$lt" => max = format!("({}", n),
+                            _ => return Err(format!("Unknown operator {}", operator).into()),
+                        }
+                    }
+                    (min, max)
+                }
+                _ => return Err(format!("Invalid number for {}: {}", field, value).into()),
+            };
+            format!("@{}:[{} {}]", field, min, max)
+        } else {
+            return Err(format!("{} is not a tag or numeric field of the index", field).into());
+        };
+        clauses.push(clause);
+    }
+
+    if clauses.is_empty() {
+        return Ok("*".to_string());
+    }
+    Ok(format!("({})", clauses.join(" ")))
+}
+
+/// Builds the RediSearch query of the `MetadataFilter`. Tag fields support `Eq`, `Ne` and
+/// `In` on strings and booleans, numeric fields support `Eq`, `Ne`, `Gt` and `Lt` on
+/// numbers, the empty groups are not supported.
+fn metadata_filter_query(
+    filter: &MetadataFilter,
+    tag_fields: &[String],
+    numeric_fields: &[String],
+) -> Result<String, Box<dyn Error>> {
+    let unsupported = || -> Box<dyn Error> { UnsupportedFilterError::new("Redis", filter).into() };
+    let field = |key: &String| -> Result<(), Box<dyn Error>> {
+        if tag_fields.contains(key) || numeric_fields.contains(key) {
+            return Ok(());
+        }
+        Err(format!("{} is not a tag or numeric field of the index", key).into())
+    };
+    let tag = |key: &String, value: &Value| match value {
+        Value::String(tag) if tag_fields.contains(key) => Ok(escape_tag(tag)),
+        Value::Bool(tag) if tag_fields.contains(key) => Ok(tag.to_string()),
+        _ => Err(unsupported()),
+    };
+    let number = |key: &String, value: &Value| match value.as_f64() {
+        Some(n) if numeric_fields.contains(key) => Ok(n),
+        _ => Err(unsupported()),
+    };
+    let group = |filters: &[MetadataFilter], separator: &str| -> Result<String, Box<dyn Error>> {
+        if filters.is_empty() {
+            return Err(unsupported());
+        }
+        let queries = filters
+            .iter()
+            .map(|filter| metadata_filter_query(filter, tag_fields, numeric_fields))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(format!("({})", queries.join(separator)))
+    };
+
+    match filter {
+        MetadataFilter::Eq(key, value) | MetadataFilter::Ne(key, value) => {
+            field(key)?;
+            let negation = if matches!(filter, MetadataFilter::Ne(..)) {
+                "-"
+            } else {
+                ""
+            };
+            if numeric_fields.contains(key) {
+                let n = number(key, value)?;
+                Ok(format!("{}@{}:[{} {}]", negation, key, n, n))
+            } else {
+                Ok(format!("{}@{}:{{{}}}", negation, key, tag(key, value)?))
+            }
+        }
+        MetadataFilter::Gt(key, value) => {
+            field(key)?;
+            Ok(format!("@{}:[({} +inf]", key, number(key, value)?))
+        }
+        MetadataFilter::Lt(key, value) => {
+            field(key)?;
+            Ok(format!("@{}:[-inf ({}]", key, number(key, value)?))
+        }
+        MetadataFilter::In(key, values) => {
+            field(key)?;
+            if values.is_empty() {
+                return Err(unsupported());
+            }
+            let tags = values
+                .iter()
+                .map(|value| tag(key, value))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(format!("@{}:{{{}}}", key, tags.join(" | ")))
+        }
+        MetadataFilter::And(filters) => group(filters, " "),
+        MetadataFilter::Or(filters) => group(filters, " | "),
+    }
+}
+
+#[async_trait]
+impl VectorStore for Store {
+    /// Add documents to the store, each document is saved as a hash.
+    /// Returns a list of the ids of the documents, the hash keys are the ids with the
+    /// key prefix of the store.
+    async fn add_documents(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let added = self.add_documents_with_embeddings(docs, opt).await?;
+        Ok(added.into_iter().map(|(id, _)| id).collect())
+    }
+
+    async fn add_documents_with_embeddings(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<(String, Vec<f64>)>, Box<dyn Error>> {
+        if docs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let texts: Vec<String> = docs.iter().map(|d| d.page_content.clone()).collect();
+        let vectors = embedder.embed_documents(&texts).await?;
+
+        if vectors.len() != docs.len() {
+            return Err("Number of vectors and documents do not match".into());
+        }
+
+        let mut added = Vec::with_capacity(docs.len());
+        let mut pipe = ::redis::pipe();
+        for (doc, vector) in docs.iter().zip(vectors) {
+            if vector.len() != self.vector_dimensions as usize {
+                return Err(format!(
+                    "Embedding has {} dimensions, the index expects {}",
+                    vector.len(),
+                    self.vector_dimensions
+                )
+                .into());
+            }
+            let id = Uuid::new_v4().to_string();
+            pipe.cmd("HSET").arg(self.key(&id));
+            for (field, value) in self.fields(doc, &vector) {
+                pipe.arg(field).arg(value);
+            }
+            pipe.ignore();
+            added.push((id, vector));
+        }
+
+        let mut connection = self.connection.clone();
+        let _: () = pipe.query_async(&mut connection).await?;
+
+        Ok(added)
+    }
+
+    /// Perform a `KNN` similarity search on the store, see `filter_query` for the
+    /// supported filters.
+    async fn similarity_search(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        if opt.name_space.is_some() {
+            return Err("Redis doesn't support namespaces, use an index instead".into());
+        }
+
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let query_vector = embedder.embed_query(query).await?;
+
+        let filters = match &opt.filters {
+            Some(filters) => filter_query(filters, &self.tag_fields, &self.numeric_fields)?,
+            None => "*".to_string(),
+        };
+        let filter = match &opt.metadata_filter {
+            Some(filter) => {
+                let query = metadata_filter_query(filter, &self.tag_fields, &self.numeric_fields)?;
+                if filters == "*" {
+                    format!("({})", query)
+                } else {
+                    format!("({} {})", filters, query)
+                }
+            }
+            None => filters,
+        };
+        let mut return_fields = vec![CONTENT_FIELD, METADATA_FIELD, SCORE_FIELD];
+        if opt.include_embeddings {
+            return_fields.push(VECTOR_FIELD);
+        }
+
+        let mut command = ::redis::cmd("FT.SEARCH");
+        command
+            .arg(&self.index_name)
+            .arg(format!(
+                "{}=>[KNN {} @{} $vector AS {}]",
+                filter, limit, VECTOR_FIELD, SCORE_FIELD
+            ))
+            .arg("PARAMS")
+            .arg(2)
+            .arg("vector")
+            .arg(vector_to_bytes(&query_vector))
+            .arg("SORTBY")
+            .arg(SCORE_FIELD)
+            .arg("ASC")
+            .arg("RETURN")
+            .arg(return_fields.len())
+            .arg(&return_fields)
+            .arg("LIMIT")
+            .arg(0)
+            .arg(limit)
+            .arg("DIALECT")
+            .arg(2);
+
+        let mut connection = self.connection.clone();
+        let response: Vec<RedisValue> = command.query_async(&mut connection).await?;
+
+        // The response is the total of results, followed by the key and fields of each one
+        let mut documents = Vec::with_capacity(limit);
+        for result in response.iter().skip(1).collect::<Vec<_>>().chunks_exact(2) {
+            let fields: HashMap<String, Vec<u8>> = from_redis_value(result[1])?;
+            let distance = fields
+                .get(SCORE_FIELD)
+                .and_then(|score| String::from_utf8_lossy(score).parse::<f64>().ok())
+                .unwrap_or_default();
+            let mut doc = self
+                .document_from_fields(&fields)
+                .with_score(score(self.distance_metric, distance));
+            doc.embedding = fields
+                .get(VECTOR_FIELD)
+                .map(Vec::as_slice)
+                .map(vector_from_bytes);
+
+            if let Some(score_threshold) = opt.score_threshold {
+                if doc.score < score_threshold as f64 {
+                    continue;
+                }
+            }
+            documents.push(doc);
+        }
+
+        Ok(documents)
+    }
+
+    async fn get_by_ids(&self, ids: &[String]) -> Result<Vec<Document>, Box<dyn Error>> {
+        let mut connection = self.connection.clone();
+        let mut docs = Vec::with_capacity(ids.len());
+        for id in ids {
+            let fields: HashMap<String, Vec<u8>> = ::redis::cmd("HGETALL")
+                .arg(self.key(id))
+                .query_async(&mut connection)
+                .await?;
+            if fields.is_empty() {
+                continue;
+            }
+            let mut doc = self.document_from_fields(&fields);
+            doc.metadata
+                .insert("id".to_string(), Value::from(id.clone()));
+            docs.push(doc);
+        }
+        Ok(docs)
+    }
+
+    async fn delete_documents(&self, ids: &[String]) -> Result<(), Box<dyn Error>> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let keys: Vec<String> = ids.iter().map(|id| self.key(id)).collect();
+        let mut connection = self.connection.clone();
+        let _: i64 = ::redis::cmd("DEL")
+            .arg(keys)
+            .query_async(&mut connection)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{embedding::EmbedderError, vectorstore::redis::StoreBuilder};
+
+    #[test]
+    fn test_filter_query() {
+        let tags = vec!["source".to_string()];
+        let numbers = vec!["page".to_string()];
+
+        assert_eq!(
+            filter_query(&json!({"source": "a.txt"}), &tags, &numbers).unwrap(),
+            r"(@source:{a\.txt})"
+        );
+        assert_eq!(
+            filter_query(&json!({"source": ["a", "b c"]}), &tags, &numbers).unwrap(),
+            r"(@source:{a | b\ c})"
+        );
+        assert_eq!(
+            filter_query(&json!({"page": {"$gt": 1, "$lte": 5}}), &tags, &numbers).unwrap(),
+            "(@page:[(1 5])"
+        );
+        assert_eq!(
+            filter_query(&json!({"page": 3}), &tags, &numbers).unwrap(),
+            "(@page:[3 3])"
+        );
+        assert_eq!(filter_query(&json!({}), &tags, &numbers).unwrap(), "*");
+        assert!(filter_query(&json!({"author": "me"}), &tags, &numbers).is_err());
+    }
+
+    #[test]
+    fn test_metadata_filter_query() {
+        let tags = vec!["source".to_string()];
+        let numbers = vec!["page".to_string()];
+        let filter = MetadataFilter::ne("source", "a.txt")
+            .and(MetadataFilter::gt("page", 2).or(MetadataFilter::is_in("source", ["b", "c"])));
+
+        assert_eq!(
+            metadata_filter_query(&filter, &tags, &numbers).unwrap(),
+            r"(-@source:{a\.txt} (@page:[(2 +inf] | @source:{b | c}))"
+        );
+        assert_eq!(
+            metadata_filter_query(&MetadataFilter::eq("page", 3), &tags, &numbers).unwrap(),
+            "@page:[3 3]"
+        );
+        assert!(metadata_filter_query(&MetadataFilter::gt("source", 2), &tags, &numbers).is_err());
+        assert!(
+            metadata_filter_query(&MetadataFilter::eq("author", "me"), &tags, &numbers).is_err()
+        );
+    }
+
+    #[test]
+    fn test_score_and_vector_bytes() {
+        assert_eq!(score(DistanceMetric::Cosine, 0.25), 0.75);
+        assert_eq!(score(DistanceMetric::InnerProduct, 0.25), 0.75);
+        assert_eq!(score(DistanceMetric::L2, 1.0), 0.5);
+
+        let vector = vec![0.5, -1.0, 2.0];
+        assert_eq!(vector_from_bytes(&vector_to_bytes(&vector)), vector);
+    }
+
+    struct FakeEmbedder {}
+
+    #[async_trait]
+    impl Embedder for FakeEmbedder {
+        async fn embed_documents(
+            &self,
+            documents: &[String],
+        ) -> Result<Vec<Vec<f64>>, EmbedderError> {
+            Ok(documents.iter().map(|_| vec![1.0, 0.0]).collect())
+        }
+
+        async fn embed_query(&self, _text: &str) -> Result<Vec<f64>, EmbedderError> {
+            Ok(vec![1.0, 0.0])
+        }
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_redis_add_no_documents() {
+        // Requires a Redis Stack server on the default url
+        let store = StoreBuilder::new()
+            .index_name("test_add_no_documents")
+            .embedder(FakeEmbedder {})
+            .vector_dimensions(2)
+            .build()
+            .await
+            .unwrap();
+
+        let ids = store
+            .add_documents(&[], &VecStoreOptions::default())
+            .await
+            .unwrap();
+        assert!(ids.is_empty());
+    }
+}
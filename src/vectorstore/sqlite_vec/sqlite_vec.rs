@@ -7,7 +7,9 @@ use sqlx::{Pool, Row, Sqlite};
 use crate::{
     embedding::embedder_trait::Embedder,
     schemas::Document,
-    vectorstore::{VecStoreOptions, VectorStore},
+    vectorstore::{
+        DistanceMetric, MetadataFilter, UnsupportedFilterError, VecStoreOptions, VectorStore,
+    },
 };
 
 pub struct Store {
@@ -83,6 +85,74 @@ impl Store {
     }
 }
 
+/// Quotes a string as a SQL literal.
+fn sql_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+/// The SQL condition of the `MetadataFilter` on the values extracted from the metadata.
+/// Booleans are stored as 1 and 0, `Gt` and `Lt` only compare values of the same type.
+fn metadata_filter_sql(filter: &MetadataFilter) -> Result<String, UnsupportedFilterError> {
+    let path = |key: &str| sql_literal(&format!("$.\"{}\"", key));
+    let field = |key: &str| format!("json_extract(e.metadata, {})", path(key));
+    let literal = |value: &Value| match value {
+        Value::String(value) => Ok(sql_literal(value)),
+        Value::Number(value) => Ok(value.to_string()),
+        Value::Bool(value) => Ok((*value as u8).to_string()),
+        _ => Err(UnsupportedFilterError::new("SQLite", filter)),
+    };
+    let compare =
+        |key: &str, operator: &str, value: &Value| -> Result<String, UnsupportedFilterError> {
+            let types = match value {
+                Value::Number(_) => "('integer', 'real')",
+                Value::String(_) => "('text')",
+                _ => return Err(UnsupportedFilterError::new("SQLite", filter)),
+            };
+            Ok(format!(
+                "(json_type(e.metadata, {}) IN {} AND {} {} {})",
+                path(key),
+                types,
+                field(key),
+                operator,
+                literal(value)?
+            ))
+        };
+    let group = |filters: &[MetadataFilter],
+                 operator: &str,
+                 empty: &str|
+     -> Result<String, UnsupportedFilterError> {
+        let conditions = filters
+            .iter()
+            .map(metadata_filter_sql)
+            .collect::<Result<Vec<_>, _>>()?;
+        if conditions.is_empty() {
+            return Ok(empty.to_string());
+        }
+        Ok(format!("({})", conditions.join(operator)))
+    };
+
+    match filter {
+        MetadataFilter::Eq(key, Value::Null) => Ok(format!("{} IS NULL", field(key))),
+        MetadataFilter::Ne(key, Value::Null) => Ok(format!("{} IS NOT NULL", field(key))),
+        MetadataFilter::Eq(key, value) => Ok(format!("{} = {}", field(key), literal(value)?)),
+        MetadataFilter::Ne(key, value) => Ok(format!("{} <> {}", field(key), literal(value)?)),
+        MetadataFilter::Gt(key, value) => compare(key, ">", value),
+        MetadataFilter::Lt(key, value) => compare(key, "<", value),
+        MetadataFilter::In(_, values) if values.is_empty() => Ok("FALSE".to_string()),
+        MetadataFilter::In(key, values) => Ok(format!(
+            "{} IN ({})",
+            field(key),
+            values
+                .iter()
+                .map(literal)
+                .collect::<Result<Vec<_>, _>>()?
+                .join(", ")
+        )),
+        MetadataFilter::And(filters) => group(filters, " AND ", "TRUE"),
+        MetadataFilter::Or(filters) => group(filters, " OR ", "FALSE"),
+    }
+}
+
 #[async_trait]
 impl VectorStore for Store {
     async fn add_documents(
@@ -90,6 +160,18 @@ impl VectorStore for Store {
         docs: &[Document],
         opt: &VecStoreOptions,
     ) -> Result<Vec<String>, Box<dyn Error>> {
+        let added = self.add_documents_with_embeddings(docs, opt).await?;
+        Ok(added.into_iter().map(|(id, _)| id).collect())
+    }
+
+    async fn add_documents_with_embeddings(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<(String, Vec<f64>)>, Box<dyn Error>> {
+        if docs.is_empty() {
+            return Ok(Vec::new());
+        }
         let texts: Vec<String> = docs.iter().map(|d| d.page_content.clone()).collect();
 
         let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
@@ -129,7 +211,7 @@ impl VectorStore for Store {
 
         tx.commit().await?;
 
-        Ok(ids)
+        Ok(ids.into_iter().zip(vectors).collect())
     }
 
     async fn similarity_search(
@@ -147,6 +229,12 @@ impl VectorStore for Store {
         let mut metadata_query = filter
             .iter()
             .map(|(k, v)| format!("json_extract(e.metadata, '$.{}') = '{}'", k, v))
+            .chain(
+                opt.metadata_filter
+                    .as_ref()
+                    .map(metadata_filter_sql)
+                    .transpose()?,
+            )
             .collect::<Vec<String>>()
             .join(" AND ");
 
@@ -175,7 +263,9 @@ impl VectorStore for Store {
             .map(|row| {
                 let page_content: String = row.try_get("text")?;
                 let metadata_json: Value = row.try_get("metadata")?;
-                let score: f64 = row.try_get("distance")?;
+                // The index uses the euclidean distance, converted to a higher is closer score
+                let distance: f64 = row.try_get("distance")?;
+                let score = DistanceMetric::L2.score(distance);
 
                 let metadata = if let Value::Object(obj) = metadata_json {
                     obj.into_iter().collect()
@@ -187,6 +277,7 @@ impl VectorStore for Store {
                     page_content,
                     metadata,
                     score,
+                    embedding: None,
                 })
             })
             .collect::<Result<Vec<Document>, sqlx::Error>>()?;
@@ -194,3 +285,61 @@ impl VectorStore for Store {
         Ok(docs)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+    use crate::embedding::EmbedderError;
+
+    /// Fails the test when called.
+    struct UnusedEmbedder {}
+
+    #[async_trait]
+    impl Embedder for UnusedEmbedder {
+        async fn embed_documents(
+            &self,
+            _documents: &[String],
+        ) -> Result<Vec<Vec<f64>>, EmbedderError> {
+            panic!("embed_documents should not be called")
+        }
+
+        async fn embed_query(&self, _text: &str) -> Result<Vec<f64>, EmbedderError> {
+            panic!("embed_query should not be called")
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_no_documents() {
+        // The pool is lazy, the database is never opened
+        let store = Store {
+            pool: SqlitePoolOptions::new()
+                .connect_lazy("sqlite::memory:")
+                .unwrap(),
+            table: "documents".into(),
+            vector_dimensions: 3,
+            embedder: Arc::new(UnusedEmbedder {}),
+        };
+        let ids = store
+            .add_documents(&[], &VecStoreOptions::default())
+            .await
+            .unwrap();
+        assert!(ids.is_empty());
+    }
+
+    #[test]
+    fn test_metadata_filter_sql() {
+        let filter = MetadataFilter::eq("author", "O'Brien")
+            .and(MetadataFilter::lt("year", 1990).or(MetadataFilter::is_in("draft", [true])));
+
+        assert_eq!(
+            metadata_filter_sql(&filter).unwrap(),
+            "(json_extract(e.metadata, '$.\"author\"') = 'O''Brien' AND \
+             ((json_type(e.metadata, '$.\"year\"') IN ('integer', 'real') \
+             AND json_extract(e.metadata, '$.\"year\"') < 1990) \
+             OR json_extract(e.metadata, '$.\"draft\"') IN (1)))"
+        );
+        assert!(metadata_filter_sql(&MetadataFilter::eq("tags", json!(["a"]))).is_err());
+    }
+}
@@ -7,7 +7,7 @@ use sqlx::{Pool, Row, Sqlite};
 use crate::{
     embedding::embedder_trait::Embedder,
     schemas::Document,
-    vectorstore::{VecStoreOptions, VectorStore},
+    vectorstore::{DistanceMetric, UnsupportedFilterError, VecStoreOptions, VectorStore},
 };
 
 pub struct Store {
@@ -78,6 +78,18 @@ impl VectorStore for Store {
         docs: &[Document],
         opt: &VecStoreOptions,
     ) -> Result<Vec<String>, Box<dyn Error>> {
+        let added = self.add_documents_with_embeddings(docs, opt).await?;
+        Ok(added.into_iter().map(|(id, _)| id).collect())
+    }
+
+    async fn add_documents_with_embeddings(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<(String, Vec<f64>)>, Box<dyn Error>> {
+        if docs.is_empty() {
+            return Ok(Vec::new());
+        }
         let texts: Vec<String> = docs.iter().map(|d| d.page_content.clone()).collect();
 
         let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
@@ -117,15 +129,18 @@ impl VectorStore for Store {
 
         tx.commit().await?;
 
-        Ok(ids)
+        Ok(ids.into_iter().zip(vectors).collect())
     }
 
     async fn similarity_search(
         &self,
         query: &str,
         limit: usize,
-        _opt: &VecStoreOptions,
+        opt: &VecStoreOptions,
     ) -> Result<Vec<Document>, Box<dyn Error>> {
+        if let Some(filter) = &opt.metadata_filter {
+            return Err(UnsupportedFilterError::new("sqlite-vss", filter).into());
+        }
         let table = &self.table;
 
         let query_vector = json!(self.embedder.embed_query(query).await?);
@@ -153,7 +168,10 @@ impl VectorStore for Store {
             .map(|row| {
                 let page_content: String = row.try_get("text")?;
                 let metadata_json: Value = row.try_get("metadata")?;
-                let score: f64 = row.try_get("distance")?;
+                // The index returns the squared euclidean distance, converted to a higher is
+                // closer score
+                let distance: f64 = row.try_get("distance")?;
+                let score = DistanceMetric::squared_l2_score(distance);
 
                 let metadata = if let Value::Object(obj) = metadata_json {
                     obj.into_iter().collect()
@@ -165,6 +183,7 @@ impl VectorStore for Store {
                     page_content,
                     metadata,
                     score,
+                    embedding: None,
                 })
             })
             .collect::<Result<Vec<Document>, sqlx::Error>>()?;
@@ -2,9 +2,9 @@ use std::{error::Error, sync::Arc};
 
 use surrealdb::{Connection, Surreal};
 
-use crate::embedding::embedder_trait::Embedder;
+use crate::{embedding::embedder_trait::Embedder, vectorstore::DistanceMetric};
 
-use super::Store;
+use super::{Store, VectorIndex};
 
 pub struct StoreBuilder<C: Connection> {
     db: Option<Surreal<C>>,
@@ -14,6 +14,8 @@ pub struct StoreBuilder<C: Connection> {
     vector_dimensions: i32,
     embedder: Option<Arc<dyn Embedder>>,
     schemafull: bool,
+    distance_metric: DistanceMetric,
+    vector_index: Option<VectorIndex>,
 }
 
 impl<C: Connection> StoreBuilder<C> {
@@ -31,6 +33,8 @@ impl<C: Connection> StoreBuilder<C> {
             vector_dimensions: 0,
             embedder: None,
             schemafull: true,
+            distance_metric: DistanceMetric::default(),
+            vector_index: None,
         }
     }
 
@@ -44,6 +48,8 @@ impl<C: Connection> StoreBuilder<C> {
             vector_dimensions: 0,
             embedder: None,
             schemafull: false,
+            distance_metric: DistanceMetric::default(),
+            vector_index: None,
         }
     }
 
@@ -78,6 +84,10 @@ impl<C: Connection> StoreBuilder<C> {
     /// Setting collection_table_name to None, creates table per collection. Set to some value if
     /// you would like to reuse table. Resuing table is not compatible with python version of
     /// langchain.
+    ///
+    /// Either way the documents are stored with the collection name under the collection
+    /// metadata key, and the searches only return the documents of the collection, even when
+    /// a table per collection is shared with a store reusing it.
     pub fn collection_table_name(mut self, collection_table_name: Option<String>) -> Self {
         self.collection_table_name = collection_table_name;
         self
@@ -101,6 +111,21 @@ impl<C: Connection> StoreBuilder<C> {
         self
     }
 
+    /// Metric used to compare the embeddings, cosine by default. The scores are always
+    /// higher for more similar documents, the euclidean distance `d` is scored as
+    /// `1 / (1 + d)`. Use the metric the embeddings were trained with.
+    pub fn distance_metric(mut self, distance_metric: DistanceMetric) -> Self {
+        self.distance_metric = distance_metric;
+        self
+    }
+
+    /// Index of the embeddings defined by `Store::initialize`, none by default. The
+    /// searches use it, so the store must be initialized before searching.
+    pub fn vector_index(mut self, vector_index: VectorIndex) -> Self {
+        self.vector_index = Some(vector_index);
+        self
+    }
+
     pub fn embedder<E: Embedder + 'static>(mut self, embedder: E) -> Self {
         self.embedder = Some(Arc::new(embedder));
         self
@@ -116,6 +141,15 @@ impl<C: Connection> StoreBuilder<C> {
             return Err("Db is required".into());
         }
 
+        if self.vector_index.is_some() {
+            if self.vector_dimensions <= 0 {
+                return Err("Vector dimensions are required by the vector index".into());
+            }
+            if self.distance_metric == DistanceMetric::InnerProduct {
+                return Err("SurrealDB vector indexes don't support the inner product".into());
+            }
+        }
+
         Ok(Store {
             db: self.db.unwrap(),
             collection_name: self.collection_name,
@@ -124,6 +158,8 @@ impl<C: Connection> StoreBuilder<C> {
             vector_dimensions: self.vector_dimensions,
             embedder: self.embedder.unwrap(),
             schemafull: self.schemafull,
+            distance_metric: self.distance_metric,
+            vector_index: self.vector_index,
         })
     }
 }
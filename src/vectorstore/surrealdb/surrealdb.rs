@@ -8,7 +8,10 @@ use surrealdb::{Connection, Surreal};
 use crate::{
     embedding::embedder_trait::Embedder,
     schemas::Document,
-    vectorstore::{VecStoreOptions, VectorStore},
+    vectorstore::{
+        add_documents_with_embeddings_in_batches, DistanceMetric, IdStrategy, MetadataFilter,
+        VecStoreOptions, VectorStore,
+    },
 };
 
 // INSERT INTO documents {
@@ -18,6 +21,31 @@ use crate::{
 //  collection?: 'collection_name'
 // }
 
+/// Index of the embeddings, the searches use the KNN operator on it instead of comparing
+/// the query to every document. The index is defined by `Store::initialize` with the
+/// metric and the `vector_dimensions` of the store, and only supports the cosine and
+/// euclidean metrics.
+/// See https://surrealdb.com/docs/surrealql/statements/define/indexes#vector-search-indexes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VectorIndex {
+    /// M-Tree index, `capacity` is the maximum number of vectors of a node.
+    MTree { capacity: Option<u16> },
+    /// HNSW index, `efc` is the size of the candidate list while building the index and
+    /// `m` the maximum number of connections of a node. `ef` is the size of the candidate
+    /// list while searching, 40 by default.
+    Hnsw {
+        efc: Option<u16>,
+        m: Option<u8>,
+        ef: Option<u16>,
+    },
+}
+
+const DEFAULT_HNSW_EF: u16 = 40;
+
+/// How many more candidates the KNN operator picks when the search is filtered, see
+/// `Store::knn_predicate`.
+const KNN_FILTER_OVERFETCH: usize = 10;
+
 pub struct Store<C: Connection> {
     pub(crate) db: Surreal<C>,
     pub(crate) collection_name: String,
@@ -26,6 +54,8 @@ pub struct Store<C: Connection> {
     pub(crate) vector_dimensions: i32,
     pub(crate) embedder: Arc<dyn Embedder>,
     pub(crate) schemafull: bool,
+    pub(crate) distance_metric: DistanceMetric,
+    pub(crate) vector_index: Option<VectorIndex>,
 }
 
 impl<C: Connection> Store<C> {
@@ -36,18 +66,265 @@ impl<C: Connection> Store<C> {
         }
     }
 
+    /// The similarity of the stored embedding to `$embedding` with the metric of the
+    /// store, following the `DistanceMetric` contract: higher is more similar.
+    fn similarity_expression(&self) -> &'static str {
+        match self.distance_metric {
+            DistanceMetric::Cosine => "vector::similarity::cosine(embedding, $embedding)",
+            DistanceMetric::L2 => "1 / (1 + vector::distance::euclidean(embedding, $embedding))",
+            DistanceMetric::InnerProduct => "vector::dot(embedding, $embedding)",
+        }
+    }
+
+    /// The `DEFINE INDEX` statement of the vector index of the table, if any.
+    fn index_definition(&self) -> Result<Option<String>, Box<dyn Error>> {
+        let Some(vector_index) = self.vector_index else {
+            return Ok(None);
+        };
+        let distance = match self.distance_metric {
+            DistanceMetric::Cosine => "COSINE",
+            DistanceMetric::L2 => "EUCLIDEAN",
+            DistanceMetric::InnerProduct => {
+                return Err("SurrealDB vector indexes don't support the inner product".into())
+            }
+        };
+        let table = self.get_collection_table_name();
+        let kind = match vector_index {
+            VectorIndex::MTree { .. } => "MTREE",
+            VectorIndex::Hnsw { .. } => "HNSW",
+        };
+        let mut definition = format!(
+            "DEFINE INDEX IF NOT EXISTS {table}_embedding ON {table} FIELDS embedding \
+             {kind} DIMENSION {} DIST {distance} TYPE F64",
+            self.vector_dimensions
+        );
+        match vector_index {
+            VectorIndex::MTree { capacity } => {
+                if let Some(capacity) = capacity {
+                    definition.push_str(&format!(" CAPACITY {capacity}"));
+                }
+            }
+            VectorIndex::Hnsw { efc, m, .. } => {
+                if let Some(efc) = efc {
+                    definition.push_str(&format!(" EFC {efc}"));
+                }
+                if let Some(m) = m {
+                    definition.push_str(&format!(" M {m}"));
+                }
+            }
+        }
+        definition.push(';');
+        Ok(Some(definition))
+    }
+
+    /// The condition selecting the nearest documents, with the KNN operator when the store
+    /// has an index. Without one every document is a candidate.
+    ///
+    /// The KNN operator picks its candidates before the other conditions apply, so a
+    /// `filtered` search, by metadata or on a shared table, picks `KNN_FILTER_OVERFETCH`
+    /// times `limit` candidates. A filter matching fewer of them returns fewer documents.
+    fn knn_predicate(&self, limit: usize, filtered: bool) -> String {
+        let candidates = match filtered {
+            true => limit.saturating_mul(KNN_FILTER_OVERFETCH),
+            false => limit,
+        };
+        match self.vector_index {
+            Some(VectorIndex::MTree { .. }) => {
+                format!("embedding <|{candidates}|> $embedding AND ")
+            }
+            Some(VectorIndex::Hnsw { ef, .. }) => format!(
+                "embedding <|{candidates},{}|> $embedding AND ",
+                // The candidate list can't be smaller than the number of candidates
+                (ef.unwrap_or(DEFAULT_HNSW_EF) as usize).max(candidates)
+            ),
+            None => String::new(),
+        }
+    }
+
+    /// Keeps the documents of the collection. Every document is stored with the collection in
+    /// its metadata; a table of its own also keeps the documents stored without it, before the
+    /// key was always set, while a shared table only keeps the documents of the collection.
+    fn collection_predicate(&self) -> &'static str {
+        match &self.collection_table_name {
+            Some(_) => " AND metadata[$collection_metadata_key] = $collection_name ",
+            None => {
+                " AND (metadata[$collection_metadata_key] = NONE OR metadata[$collection_metadata_key] = $collection_name) "
+            }
+        }
+    }
+
+    /// The predicate of the `MetadataFilter`, the keys and values are added to `bindings`
+    /// and referenced as parameters so they don't need escaping.
+    fn metadata_filter_predicate(
+        filter: &MetadataFilter,
+        bindings: &mut Vec<(String, Value)>,
+    ) -> String {
+        let bind = |key: &str, value: Value, bindings: &mut Vec<(String, Value)>| {
+            let index = bindings.len();
+            bindings.push((format!("filter_key_{}", index), Value::from(key)));
+            bindings.push((format!("filter_value_{}", index), value));
+            (
+                format!("metadata[$filter_key_{}]", index),
+                format!("$filter_value_{}", index),
+            )
+        };
+        let mut compare = |key: &str, operator: &str, value: Value| {
+            let (field, value) = bind(key, value, bindings);
+            format!("{} {} {}", field, operator, value)
+        };
+        match filter {
+            MetadataFilter::Eq(key, value) => compare(key, "=", value.clone()),
+            MetadataFilter::Ne(key, value) => compare(key, "!=", value.clone()),
+            MetadataFilter::Gt(key, value) => compare(key, ">", value.clone()),
+            MetadataFilter::Lt(key, value) => compare(key, "<", value.clone()),
+            MetadataFilter::In(key, values) => compare(key, "IN", Value::from(values.clone())),
+            MetadataFilter::And(filters) if filters.is_empty() => "true".to_string(),
+            MetadataFilter::Or(filters) if filters.is_empty() => "false".to_string(),
+            MetadataFilter::And(filters) | MetadataFilter::Or(filters) => {
+                let operator = if matches!(filter, MetadataFilter::And(_)) {
+                    " AND "
+                } else {
+                    " OR "
+                };
+                let predicates = filters
+                    .iter()
+                    .map(|filter| Self::metadata_filter_predicate(filter, bindings))
+                    .collect::<Vec<_>>();
+                format!("({})", predicates.join(operator))
+            }
+        }
+    }
+
     fn get_collection_metdata_key(&self) -> String {
         self.collection_metadata_key_name
             .clone()
             .unwrap_or_else(|| "collection".to_string())
     }
 
+    /// Embeds the documents and creates them. With random ids, when a document can't be
+    /// created the documents of the batch already created are removed. With ids derived
+    /// from the content, the documents replace the stored ones with the same id and are
+    /// kept, adding the batch again completes it.
+    async fn add_batch(
+        &self,
+        docs: &[Document],
+        embedder: &dyn Embedder,
+        id_strategy: &IdStrategy,
+    ) -> Result<Vec<(String, Vec<f64>)>, Box<dyn Error>> {
+        let texts: Vec<String> = docs.iter().map(|d| d.page_content.clone()).collect();
+
+        let vectors = embedder.embed_documents(&texts).await?;
+        if vectors.len() != docs.len() {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Number of vectors and documents do not match",
+            )));
+        }
+
+        let mut ids = Vec::with_capacity(docs.len());
+        for (doc, vector) in docs.iter().zip(vectors.iter()) {
+            let id = match id_strategy {
+                IdStrategy::Random => None,
+                _ => Some(id_strategy.document_id(doc, &self.collection_name)),
+            };
+            let error = match self.create_document(doc, vector, id).await {
+                Ok(id) => {
+                    ids.push(id);
+                    continue;
+                }
+                Err(error) => error.to_string(),
+            };
+
+            if *id_strategy == IdStrategy::Random {
+                // Remove the documents of the batch already created
+                if let Err(delete_error) = self.delete_ids(&ids).await {
+                    log::warn!(
+                        "Failed to remove the documents of the batch: {}",
+                        delete_error
+                    );
+                }
+            }
+            return Err(error.into());
+        }
+
+        Ok(ids.into_iter().zip(vectors).collect())
+    }
+
+    /// Creates the document, or replaces the stored one when an id is given.
+    async fn create_document(
+        &self,
+        doc: &Document,
+        vector: &[f64],
+        id: Option<String>,
+    ) -> Result<String, Box<dyn Error>> {
+        let mut metadata: HashMap<String, Value> = doc.metadata.clone();
+        metadata.insert(
+            self.get_collection_metdata_key(),
+            Value::String(self.collection_name.to_owned()),
+        );
+
+        let collection_table_name = self.get_collection_table_name();
+        let target = match id {
+            Some(_) => format!("UPSERT type::thing('{collection_table_name}', $id)"),
+            None => format!("CREATE {collection_table_name}"),
+        };
+        let mut result = self
+            .db
+            .query(format!(
+                r#"{target} CONTENT {{
+                    text: $text,
+                    embedding: $embedding,
+                    metadata: $metadata,
+                }}
+                RETURN record::id(id) as id"#
+            ))
+            .bind(("id", id))
+            .bind(("text", doc.page_content.to_owned()))
+            .bind(("embedding", vector.to_owned()))
+            .bind(("metadata", metadata))
+            .await?
+            .check()?;
+
+        let id: Option<String> = result.take("id")?;
+        Ok(id.ok_or("The created document has no id")?)
+    }
+
+    async fn delete_ids(&self, ids: &[String]) -> Result<(), Box<dyn Error>> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let collection_predicate = self.collection_predicate();
+        self.db
+            .query(format!(
+                "DELETE type::table($table) WHERE record::id(id) IN $ids {collection_predicate}"
+            ))
+            .bind(("table", self.get_collection_table_name().to_string()))
+            .bind(("collection_name", self.collection_name.to_owned()))
+            .bind((
+                "collection_metadata_key",
+                self.get_collection_metdata_key().to_owned(),
+            ))
+            .bind(("ids", ids.to_vec()))
+            .await?
+            .check()?;
+        Ok(())
+    }
+
     pub async fn initialize(&self) -> Result<(), Box<dyn Error>> {
         self.create_collection_table_if_not_exists().await?;
         Ok(())
     }
 
     async fn create_collection_table_if_not_exists(&self) -> Result<(), Box<dyn Error>> {
+        self.define_fields().await?;
+        if let Some(index_definition) = self.index_definition()? {
+            log::debug!("Defining the index: {}", index_definition);
+            self.db.query(index_definition).await?.check()?;
+        }
+        Ok(())
+    }
+
+    async fn define_fields(&self) -> Result<(), Box<dyn Error>> {
         if !self.schemafull {
             return Ok(());
         }
@@ -70,7 +347,7 @@ impl<C: Connection> Store<C> {
             }
             None => {
                 let collection_table_name = &self.collection_name;
-                dbg!(&collection_table_name);
+                log::debug!("Creating the table {}", collection_table_name);
                 self.db
                     .query(format!(
                         r#"
@@ -96,73 +373,25 @@ impl<C: Connection> VectorStore for Store<C> {
         docs: &[Document],
         opt: &VecStoreOptions,
     ) -> Result<Vec<String>, Box<dyn Error>> {
-        let texts: Vec<String> = docs.iter().map(|d| d.page_content.clone()).collect();
-
-        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
-
-        let vectors = embedder.embed_documents(&texts).await?;
-        if vectors.len() != docs.len() {
-            return Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Number of vectors and documents do not match",
-            )));
-        }
-
-        let mut ids = Vec::with_capacity(docs.len());
-
-        for (doc, vector) in docs.iter().zip(vectors.iter()) {
-            match &self.collection_table_name {
-                Some(collection_table_name) => {
-                    let mut metadata: HashMap<String, Value> = doc.metadata.clone();
-                    metadata.insert(
-                        self.get_collection_metdata_key(),
-                        Value::String(self.collection_name.to_owned()),
-                    );
+        let added = self.add_documents_with_embeddings(docs, opt).await?;
+        Ok(added.into_iter().map(|(id, _)| id).collect())
+    }
 
-                    let mut result = self
-                        .db
-                        .query(format!(
-                            r#"CREATE {collection_table_name} CONTENT {{
-                                text: $text,
-                                embedding: $embedding,
-                                metadata: $metadata,
-                            }}
-                            RETURN record::id(id) as id"#
-                        ))
-                        .bind(("text", doc.page_content.to_owned()))
-                        .bind(("embedding", vector.to_owned()))
-                        .bind(("metadata", metadata.to_owned()))
-                        .await?
-                        .check()?;
-
-                    let id: Option<String> = result.take("id")?;
-                    ids.push(id.unwrap());
-                }
-                None => {
-                    let collection_table_name = &self.collection_name;
-                    let mut result = self
-                        .db
-                        .query(format!(
-                            r#"CREATE {collection_table_name} CONTENT {{
-                                text: $text,
-                                embedding: $embedding,
-                                metadata: $metadata,
-                            }}
-                            RETURN record::id(id) as id"#
-                        ))
-                        .bind(("text", doc.page_content.to_owned()))
-                        .bind(("embedding", vector.to_owned()))
-                        .bind(("metadata", doc.metadata.to_owned()))
-                        .await?
-                        .check()?;
-
-                    let id: Option<String> = result.take("id")?;
-                    ids.push(id.unwrap());
-                }
-            }
+    async fn add_documents_with_embeddings(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<(String, Vec<f64>)>, Box<dyn Error>> {
+        if docs.is_empty() {
+            return Ok(Vec::new());
         }
-
-        Ok(ids)
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        add_documents_with_embeddings_in_batches(docs, opt, |batch| async move {
+            self.add_batch(&batch, embedder.as_ref(), &opt.id_strategy)
+                .await
+                .map_err(|e| e.to_string())
+        })
+        .await
     }
 
     async fn similarity_search(
@@ -176,29 +405,45 @@ impl<C: Connection> VectorStore for Store<C> {
 
         let query_vector = self.embedder.embed_query(query).await?;
 
-        let collection_predicate = match &self.collection_table_name {
-            Some(_) => " AND metadata[$collection_metadata_key] = $collection_name ",
-            None => "",
+        let collection_predicate = self.collection_predicate();
+
+        let similarity = self.similarity_expression();
+        let filtered = opt.metadata_filter.is_some() || self.collection_table_name.is_some();
+        let knn_predicate = self.knn_predicate(limit, filtered);
+
+        let mut bindings = Vec::new();
+        let metadata_predicate = match &opt.metadata_filter {
+            Some(filter) => format!(
+                " AND {} ",
+                Self::metadata_filter_predicate(filter, &mut bindings)
+            ),
+            None => String::new(),
         };
 
-        let mut result = self
+        let mut query = self
             .db
             .query(format!(
                 r#"
         SELECT record::id(id) as id, text, metadata,
-        vector::similarity::cosine(embedding, $embedding) as similarity
+        {similarity} as similarity
         FROM {collection_table_name}
-        WHERE vector::similarity::cosine(embedding, $embedding) >= $score_threshold {collection_predicate}
+        WHERE {knn_predicate}{similarity} >= $score_threshold {collection_predicate}{metadata_predicate}
         ORDER BY similarity DESC LIMIT $k
             "#
             ))
             .bind(("collection_name", collection_name.to_owned()))
-            .bind(("collection_metadata_key", self.get_collection_metdata_key().to_owned()))
-            .bind(("score_threshold", opt.score_threshold.unwrap_or(0.0)))
+            .bind((
+                "collection_metadata_key",
+                self.get_collection_metdata_key().to_owned(),
+            ))
+            // Dot products can be negative, without a threshold no document is filtered
+            .bind(("score_threshold", opt.score_threshold.unwrap_or(f32::MIN)))
             .bind(("k", limit))
-            .bind(("embedding", query_vector.to_owned()))
-            .await?
-            .check()?;
+            .bind(("embedding", query_vector.to_owned()));
+        for binding in bindings {
+            query = query.bind(binding);
+        }
+        let mut result = query.await?.check()?;
 
         let query_result: Vec<Row> = result.take(0)?;
 
@@ -208,11 +453,59 @@ impl<C: Connection> VectorStore for Store<C> {
                 page_content: row.text,
                 metadata: row.metadata,
                 score: row.similarity,
+                embedding: None,
             })
             .collect();
 
         Ok(documents)
     }
+
+    async fn get_by_ids(&self, ids: &[String]) -> Result<Vec<Document>, Box<dyn Error>> {
+        let collection_table_name = self.get_collection_table_name();
+
+        let collection_predicate = self.collection_predicate();
+
+        let mut result = self
+            .db
+            .query(format!(
+                r#"
+        SELECT record::id(id) as id, text, metadata
+        FROM {collection_table_name}
+        WHERE record::id(id) IN $ids {collection_predicate}
+            "#
+            ))
+            .bind(("collection_name", self.collection_name.to_owned()))
+            .bind((
+                "collection_metadata_key",
+                self.get_collection_metdata_key().to_owned(),
+            ))
+            .bind(("ids", ids.to_vec()))
+            .await?
+            .check()?;
+
+        let query_result: Vec<StoredRow> = result.take(0)?;
+
+        let mut documents = query_result
+            .into_iter()
+            .map(|row| {
+                let mut metadata = row.metadata;
+                metadata.insert("id".to_string(), Value::String(row.id));
+                Document::new(row.text).with_metadata(metadata)
+            })
+            .collect::<Vec<Document>>();
+
+        // Keep the order of the requested ids
+        documents.sort_by_key(|doc| ids.iter().position(|id| doc.metadata["id"] == *id));
+        Ok(documents)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct StoredRow {
+    id: String,
+    text: String,
+    #[serde(default)]
+    metadata: HashMap<String, Value>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -222,3 +515,66 @@ struct Row {
     metadata: HashMap<String, Value>,
     similarity: f64,
 }
+
+#[cfg(test)]
+mod tests {
+    use surrealdb::engine::any::Any;
+
+    use super::*;
+    use crate::{embedding::EmbedderError, vectorstore::surrealdb::StoreBuilder};
+
+    struct FakeEmbedder {}
+
+    #[async_trait]
+    impl Embedder for FakeEmbedder {
+        async fn embed_documents(
+            &self,
+            documents: &[String],
+        ) -> Result<Vec<Vec<f64>>, EmbedderError> {
+            Ok(documents.iter().map(|_| vec![1.0, 0.0]).collect())
+        }
+
+        async fn embed_query(&self, _text: &str) -> Result<Vec<f64>, EmbedderError> {
+            Ok(vec![1.0, 0.0])
+        }
+    }
+
+    async fn store(vector_index: VectorIndex) -> Store<Any> {
+        StoreBuilder::new()
+            .db(Surreal::init())
+            .vector_dimensions(2)
+            .vector_index(vector_index)
+            .embedder(FakeEmbedder {})
+            .build()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_knn_predicate_overfetches_filtered_searches() {
+        let mtree = store(VectorIndex::MTree { capacity: None }).await;
+        assert_eq!(
+            mtree.knn_predicate(4, false),
+            "embedding <|4|> $embedding AND "
+        );
+        assert_eq!(
+            mtree.knn_predicate(4, true),
+            "embedding <|40|> $embedding AND "
+        );
+
+        let hnsw = store(VectorIndex::Hnsw {
+            efc: None,
+            m: None,
+            ef: None,
+        })
+        .await;
+        assert_eq!(
+            hnsw.knn_predicate(4, false),
+            "embedding <|4,40|> $embedding AND "
+        );
+        assert_eq!(
+            hnsw.knn_predicate(10, true),
+            "embedding <|100,100|> $embedding AND "
+        );
+    }
+}
@@ -2,7 +2,10 @@ use std::error::Error;
 
 use async_trait::async_trait;
 
-use crate::schemas::{self, Document};
+use crate::{
+    schemas::{self, Document, RetrievalOptions, RetrieverError},
+    semantic_router::utils::cosine_similarity,
+};
 
 use super::VecStoreOptions;
 
@@ -16,12 +19,65 @@ pub trait VectorStore: Send + Sync {
         opt: &VecStoreOptions,
     ) -> Result<Vec<String>, Box<dyn Error>>;
 
+    /// Like `add_documents`, with the embedding each document was stored with next to its
+    /// id, in the order of the documents, so it doesn't have to be embedded again.
+    ///
+    /// Stores that can't return the embeddings fail before adding anything.
+    async fn add_documents_with_embeddings(
+        &self,
+        _docs: &[Document],
+        _opt: &VecStoreOptions,
+    ) -> Result<Vec<(String, Vec<f64>)>, Box<dyn Error>> {
+        Err("add_documents_with_embeddings is not supported by this vector store".into())
+    }
+
     async fn similarity_search(
         &self,
         query: &str,
         limit: usize,
         opt: &VecStoreOptions,
     ) -> Result<Vec<Document>, Box<dyn Error>>;
+
+    /// Like `similarity_search`, with the relevance score of each document next to it. The
+    /// score follows the `DistanceMetric` contract on every store, higher is more similar,
+    /// so a threshold on it means the same whatever the backend for a given metric.
+    async fn similarity_search_with_score(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<(Document, f32)>, Box<dyn Error>> {
+        let documents = self.similarity_search(query, limit, opt).await?;
+        Ok(documents
+            .into_iter()
+            .map(|document| {
+                let score = document.score as f32;
+                (document, score)
+            })
+            .collect())
+    }
+
+    /// Fetch stored documents by the ids returned by `add_documents`. The id is added to
+    /// the metadata of each document under the `id` key. Ids that are not found are not
+    /// in the result.
+    async fn get_by_ids(&self, _ids: &[String]) -> Result<Vec<Document>, Box<dyn Error>> {
+        Err("get_by_ids is not supported by this vector store".into())
+    }
+
+    /// Delete stored documents by the ids returned by `add_documents`. Ids that are not
+    /// found are ignored.
+    async fn delete_documents(&self, _ids: &[String]) -> Result<(), Box<dyn Error>> {
+        Err("delete_documents is not supported by this vector store".into())
+    }
+
+    /// Wraps the store in a `Retriever` returning the top `num_docs` documents of a
+    /// similarity search with `options`, ready for the retrieval chains.
+    fn into_retriever(self, num_docs: usize, options: VecStoreOptions) -> Retriever
+    where
+        Self: Sized + 'static,
+    {
+        Retriever::new(self, num_docs).with_options(options)
+    }
 }
 impl<VS> From<VS> for Box<dyn VectorStore>
 where
@@ -56,11 +112,39 @@ macro_rules! similarity_search {
     };
 }
 
+#[macro_export]
+macro_rules! similarity_search_with_score {
+    ($obj:expr, $query:expr, $limit:expr) => {
+        $obj.similarity_search_with_score(
+            $query,
+            $limit,
+            &$crate::vectorstore::VecStoreOptions::default(),
+        )
+    };
+    ($obj:expr, $query:expr, $limit:expr, $opt:expr) => {
+        $obj.similarity_search_with_score($query, $limit, $opt)
+    };
+}
+
+/// How a `Retriever` picks the documents it returns.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SearchType {
+    /// The most similar documents, as returned by the store.
+    #[default]
+    Similarity,
+    /// Maximal marginal relevance: `fetch_k` documents are fetched with their embeddings,
+    /// then picked one at a time, trading their score against their similarity to the
+    /// documents already picked. A `lambda` of 1 only looks at the score, 0 only at the
+    /// diversity.
+    Mmr { fetch_k: usize, lambda: f64 },
+}
+
 // Retriever is a retriever for vector stores.
 pub struct Retriever {
     vstore: Box<dyn VectorStore>,
     num_docs: usize,
     options: VecStoreOptions,
+    search_type: SearchType,
 }
 impl Retriever {
     pub fn new<V: Into<Box<dyn VectorStore>>>(vstore: V, num_docs: usize) -> Self {
@@ -68,6 +152,7 @@ impl Retriever {
             vstore: vstore.into(),
             num_docs,
             options: VecStoreOptions::default(),
+            search_type: SearchType::default(),
         }
     }
 
@@ -75,13 +160,383 @@ impl Retriever {
         self.options = options;
         self
     }
+
+    pub fn with_search_type(mut self, search_type: SearchType) -> Self {
+        self.search_type = search_type;
+        self
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        num_docs: usize,
+        options: &VecStoreOptions,
+        search_type: SearchType,
+    ) -> Result<Vec<Document>, RetrieverError> {
+        match search_type {
+            SearchType::Similarity => Ok(self
+                .vstore
+                .similarity_search(query, num_docs, options)
+                .await?),
+            SearchType::Mmr { fetch_k, lambda } => {
+                let mmr_options = VecStoreOptions {
+                    include_embeddings: true,
+                    ..options.clone()
+                };
+                let documents = self
+                    .vstore
+                    .similarity_search(query, fetch_k.max(num_docs), &mmr_options)
+                    .await?;
+                let mut documents = maximal_marginal_relevance(documents, num_docs, lambda);
+                if !options.include_embeddings {
+                    documents.iter_mut().for_each(|doc| doc.embedding = None);
+                }
+                Ok(documents)
+            }
+        }
+    }
 }
 
 #[async_trait]
 impl schemas::Retriever for Retriever {
-    async fn get_relevant_documents(&self, query: &str) -> Result<Vec<Document>, Box<dyn Error>> {
-        self.vstore
-            .similarity_search(query, self.num_docs, &self.options)
+    async fn get_relevant_documents(&self, query: &str) -> Result<Vec<Document>, RetrieverError> {
+        self.search(query, self.num_docs, &self.options, self.search_type)
+            .await
+    }
+
+    /// Searches the store with the top k as number of documents, the score threshold of
+    /// the options of the search and the search type.
+    async fn get_relevant_documents_with_options(
+        &self,
+        query: &str,
+        options: &RetrievalOptions,
+    ) -> Result<Vec<Document>, RetrieverError> {
+        let mut store_options = self.options.clone();
+        if let Some(score_threshold) = options.score_threshold {
+            store_options.score_threshold = Some(score_threshold);
+        }
+        self.search(
+            query,
+            options.top_k.unwrap_or(self.num_docs),
+            &store_options,
+            options.search_type.unwrap_or(self.search_type),
+        )
+        .await
+    }
+}
+
+/// Picks `k` documents, each time the one with the best balance between its score and its
+/// highest cosine similarity to the documents already picked. Documents without an embedding
+/// are only ranked by their score.
+fn maximal_marginal_relevance(
+    mut candidates: Vec<Document>,
+    k: usize,
+    lambda: f64,
+) -> Vec<Document> {
+    let mut selected: Vec<Document> = Vec::with_capacity(k.min(candidates.len()));
+    while selected.len() < k && !candidates.is_empty() {
+        let best = candidates
+            .iter()
+            .enumerate()
+            .map(|(index, candidate)| {
+                let redundancy = candidate
+                    .embedding
+                    .as_ref()
+                    .and_then(|embedding| {
+                        selected
+                            .iter()
+                            .filter_map(|doc| doc.embedding.as_ref())
+                            .map(|selected| cosine_similarity(embedding, selected))
+                            .reduce(f64::max)
+                    })
+                    .unwrap_or(0.0);
+                (
+                    index,
+                    lambda * candidate.score - (1.0 - lambda) * redundancy,
+                )
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(index, _)| index)
+            .unwrap_or(0);
+        selected.push(candidates.remove(best));
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{
+        embedding::{Embedder, EmbedderError},
+        vectorstore::add_documents_with_embeddings_in_batches,
+    };
+
+    /// Embeds the texts as their length.
+    struct LengthEmbedder {}
+
+    #[async_trait]
+    impl Embedder for LengthEmbedder {
+        async fn embed_documents(
+            &self,
+            documents: &[String],
+        ) -> Result<Vec<Vec<f64>>, EmbedderError> {
+            Ok(documents.iter().map(|doc| vec![doc.len() as f64]).collect())
+        }
+
+        async fn embed_query(&self, text: &str) -> Result<Vec<f64>, EmbedderError> {
+            Ok(vec![text.len() as f64])
+        }
+    }
+
+    /// Embeds the documents with the embedder of the options or its own, one batch at a
+    /// time, the id of a document is its text.
+    struct BatchStore {
+        embedder: Arc<dyn Embedder>,
+    }
+
+    #[async_trait]
+    impl VectorStore for BatchStore {
+        async fn add_documents(
+            &self,
+            docs: &[Document],
+            opt: &VecStoreOptions,
+        ) -> Result<Vec<String>, Box<dyn Error>> {
+            let added = self.add_documents_with_embeddings(docs, opt).await?;
+            Ok(added.into_iter().map(|(id, _)| id).collect())
+        }
+
+        async fn add_documents_with_embeddings(
+            &self,
+            docs: &[Document],
+            opt: &VecStoreOptions,
+        ) -> Result<Vec<(String, Vec<f64>)>, Box<dyn Error>> {
+            let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+            add_documents_with_embeddings_in_batches(docs, opt, |batch| async move {
+                let texts: Vec<String> = batch.iter().map(|d| d.page_content.clone()).collect();
+                let vectors = embedder
+                    .embed_documents(&texts)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                Ok(texts
+                    .iter()
+                    .map(|text| format!("id-{}", text))
+                    .zip(vectors)
+                    .collect())
+            })
             .await
+        }
+
+        async fn similarity_search(
+            &self,
+            _query: &str,
+            _limit: usize,
+            _opt: &VecStoreOptions,
+        ) -> Result<Vec<Document>, Box<dyn Error>> {
+            Ok(Vec::new())
+        }
+    }
+
+    /// Returns its documents, best score first, with their embeddings only when asked.
+    struct FixedStore {
+        documents: Vec<Document>,
+    }
+
+    #[async_trait]
+    impl VectorStore for FixedStore {
+        async fn add_documents(
+            &self,
+            _docs: &[Document],
+            _opt: &VecStoreOptions,
+        ) -> Result<Vec<String>, Box<dyn Error>> {
+            Ok(Vec::new())
+        }
+
+        async fn similarity_search(
+            &self,
+            _query: &str,
+            limit: usize,
+            opt: &VecStoreOptions,
+        ) -> Result<Vec<Document>, Box<dyn Error>> {
+            Ok(self
+                .documents
+                .iter()
+                .take(limit)
+                .cloned()
+                .map(|mut doc| {
+                    if !opt.include_embeddings {
+                        doc.embedding = None;
+                    }
+                    doc
+                })
+                .collect())
+        }
+    }
+
+    fn document(content: &str, score: f64, embedding: Vec<f64>) -> Document {
+        let mut document = Document::new(content).with_score(score);
+        document.embedding = Some(embedding);
+        document
+    }
+
+    #[test]
+    fn test_maximal_marginal_relevance_prefers_diverse_documents() {
+        let documents = vec![
+            document("a", 0.9, vec![1.0, 0.0]),
+            document("a copy", 0.89, vec![1.0, 0.01]),
+            document("b", 0.7, vec![0.0, 1.0]),
+        ];
+
+        let picked: Vec<String> = maximal_marginal_relevance(documents, 2, 0.5)
+            .into_iter()
+            .map(|doc| doc.page_content)
+            .collect();
+
+        assert_eq!(picked, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_maximal_marginal_relevance_with_lambda_one_keeps_the_order() {
+        let documents = vec![
+            document("a", 0.9, vec![1.0, 0.0]),
+            document("a copy", 0.89, vec![1.0, 0.01]),
+            document("b", 0.7, vec![0.0, 1.0]),
+        ];
+
+        let picked: Vec<String> = maximal_marginal_relevance(documents, 2, 1.0)
+            .into_iter()
+            .map(|doc| doc.page_content)
+            .collect();
+
+        assert_eq!(picked, vec!["a", "a copy"]);
+    }
+
+    /// Embeds the texts as the position of their call, so the same text embedded twice
+    /// gets two embeddings.
+    struct CountingEmbedder {
+        calls: Mutex<f64>,
+    }
+
+    #[async_trait]
+    impl Embedder for CountingEmbedder {
+        async fn embed_documents(
+            &self,
+            documents: &[String],
+        ) -> Result<Vec<Vec<f64>>, EmbedderError> {
+            let mut calls = self.calls.lock().unwrap();
+            Ok(documents
+                .iter()
+                .map(|_| {
+                    *calls += 1.0;
+                    vec![*calls]
+                })
+                .collect())
+        }
+
+        async fn embed_query(&self, _text: &str) -> Result<Vec<f64>, EmbedderError> {
+            Ok(vec![0.0])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_documents_with_embeddings() {
+        let docs = vec![
+            Document::new("a"),
+            Document::new("abc"),
+            Document::new("ab"),
+        ];
+        let opt = VecStoreOptions::default()
+            .with_batch_size(1)
+            .with_max_concurrency(2);
+        let store = BatchStore {
+            embedder: Arc::new(LengthEmbedder {}),
+        };
+
+        let added = store
+            .add_documents_with_embeddings(&docs, &opt)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            added,
+            vec![
+                ("id-a".to_string(), vec![1.0]),
+                ("id-abc".to_string(), vec![3.0]),
+                ("id-ab".to_string(), vec![2.0]),
+            ]
+        );
+
+        let duplicates = vec![Document::new("a"), Document::new("a")];
+        let embedder = CountingEmbedder {
+            calls: Mutex::new(0.0),
+        };
+        let added = store
+            .add_documents_with_embeddings(&duplicates, &opt.with_embedder(embedder))
+            .await
+            .unwrap();
+        let embeddings: Vec<Vec<f64>> = added.into_iter().map(|(_, e)| e).collect();
+        assert_eq!(embeddings, vec![vec![1.0], vec![2.0]]);
+    }
+
+    #[tokio::test]
+    async fn test_add_documents_with_embeddings_is_unsupported_by_default() {
+        let store = FixedStore {
+            documents: Vec::new(),
+        };
+
+        assert!(store
+            .add_documents_with_embeddings(&[Document::new("a")], &VecStoreOptions::default())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_retriever_with_mmr_search() {
+        use crate::schemas::Retriever as _;
+
+        let store = FixedStore {
+            documents: vec![
+                document("a", 0.9, vec![1.0, 0.0]),
+                document("a copy", 0.89, vec![1.0, 0.01]),
+                document("b", 0.7, vec![0.0, 1.0]),
+            ],
+        };
+        let retriever = store
+            .into_retriever(2, VecStoreOptions::default())
+            .with_search_type(SearchType::Mmr {
+                fetch_k: 3,
+                lambda: 0.5,
+            });
+
+        let documents = retriever.get_relevant_documents("a").await.unwrap();
+
+        let contents: Vec<&str> = documents
+            .iter()
+            .map(|doc| doc.page_content.as_str())
+            .collect();
+        assert_eq!(contents, vec!["a", "b"]);
+        assert!(documents.iter().all(|doc| doc.embedding.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_similarity_search_with_score_uses_the_document_scores() {
+        let store = FixedStore {
+            documents: vec![
+                document("a", 0.75, vec![1.0, 0.0]),
+                document("b", 0.5, vec![0.0, 1.0]),
+            ],
+        };
+
+        let scored = store
+            .similarity_search_with_score("a", 2, &VecStoreOptions::default())
+            .await
+            .unwrap();
+
+        let scored: Vec<(&str, f32)> = scored
+            .iter()
+            .map(|(doc, score)| (doc.page_content.as_str(), *score))
+            .collect();
+        assert_eq!(scored, vec![("a", 0.75), ("b", 0.5)]);
     }
 }
@@ -0,0 +1,154 @@
+use std::{error::Error, sync::Arc};
+
+use reqwest::{Client, StatusCode};
+use serde_json::json;
+
+use crate::{embedding::embedder_trait::Embedder, vectorstore::DistanceMetric};
+
+use super::{Store, METADATA_PROPERTY};
+
+const DEFAULT_URL: &str = "http://localhost:8080";
+const DEFAULT_CLASS_NAME: &str = "Langchain";
+const DEFAULT_CONTENT_FIELD: &str = "text";
+
+pub struct StoreBuilder {
+    client: Option<Client>,
+    url: String,
+    api_key: Option<String>,
+    embedder: Option<Arc<dyn Embedder>>,
+    class_name: String,
+    content_field: String,
+    vector_dimensions: i32,
+    distance_metric: DistanceMetric,
+}
+
+impl StoreBuilder {
+    // Returns a new StoreBuilder instance with default values for each option
+    pub fn new() -> Self {
+        StoreBuilder {
+            client: None,
+            url: DEFAULT_URL.into(),
+            api_key: None,
+            embedder: None,
+            class_name: DEFAULT_CLASS_NAME.into(),
+            content_field: DEFAULT_CONTENT_FIELD.into(),
+            vector_dimensions: 0,
+            distance_metric: DistanceMetric::default(),
+        }
+    }
+
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Base url of the Weaviate instance, `http://localhost:8080` by default.
+    pub fn url(mut self, url: &str) -> Self {
+        self.url = url.trim_end_matches('/').into();
+        self
+    }
+
+    pub fn api_key(mut self, api_key: &str) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    pub fn embedder<E: Embedder + 'static>(mut self, embedder: E) -> Self {
+        self.embedder = Some(Arc::new(embedder));
+        self
+    }
+
+    /// Weaviate class the documents are stored in, it must start with an uppercase letter.
+    pub fn class_name(mut self, class_name: &str) -> Self {
+        self.class_name = class_name.into();
+        self
+    }
+
+    pub fn content_field(mut self, content_field: &str) -> Self {
+        self.content_field = content_field.into();
+        self
+    }
+
+    /// When set, `add_documents` fails if the embeddings have a different size.
+    pub fn vector_dimensions(mut self, vector_dimensions: i32) -> Self {
+        self.vector_dimensions = vector_dimensions;
+        self
+    }
+
+    /// Distance used by the class when it's created by the builder, and to normalize the
+    /// scores of the search results.
+    pub fn distance_metric(mut self, distance_metric: DistanceMetric) -> Self {
+        self.distance_metric = distance_metric;
+        self
+    }
+
+    // Finalize the builder and construct the Store object, the class is created if it
+    // doesn't exist
+    pub async fn build(self) -> Result<Store, Box<dyn Error>> {
+        if self.embedder.is_none() {
+            return Err("Embedder is required".into());
+        }
+
+        let store = Store {
+            client: self.client.unwrap_or_default(),
+            url: self.url,
+            api_key: self.api_key,
+            embedder: self.embedder.unwrap(),
+            class_name: self.class_name,
+            content_field: self.content_field,
+            vector_dimensions: self.vector_dimensions,
+            distance_metric: self.distance_metric,
+        };
+        create_class_if_not_exists(&store).await?;
+
+        Ok(store)
+    }
+}
+
+impl Default for StoreBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+async fn create_class_if_not_exists(store: &Store) -> Result<(), Box<dyn Error>> {
+    let response = store
+        .request(
+            reqwest::Method::GET,
+            &format!("/v1/schema/{}", store.class_name),
+        )
+        .send()
+        .await?;
+    if response.status() != StatusCode::NOT_FOUND {
+        response.error_for_status()?;
+        return Ok(());
+    }
+
+    let distance = match store.distance_metric {
+        DistanceMetric::Cosine => "cosine",
+        DistanceMetric::L2 => "l2-squared",
+        DistanceMetric::InnerProduct => "dot",
+    };
+    let class = json!({
+        "class": store.class_name,
+        "vectorizer": "none",
+        "vectorIndexConfig": { "distance": distance },
+        "properties": [
+            { "name": store.content_field, "dataType": ["text"] },
+            {
+                "name": METADATA_PROPERTY,
+                "dataType": ["text"],
+                "indexFilterable": false,
+                "indexSearchable": false,
+            },
+        ],
+    });
+    store
+        .request(reqwest::Method::POST, "/v1/schema")
+        .json(&class)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(())
+}
@@ -0,0 +1,5 @@
+mod builder;
+mod weaviate;
+
+pub use builder::*;
+pub use weaviate::*;
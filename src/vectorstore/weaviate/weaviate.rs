@@ -0,0 +1,532 @@
+use std::{error::Error, sync::Arc};
+
+use async_trait::async_trait;
+use reqwest::{Client, Method, RequestBuilder, StatusCode};
+use serde_json::{json, Map, Value};
+use uuid::Uuid;
+
+use crate::{
+    embedding::embedder_trait::Embedder,
+    schemas::Document,
+    vectorstore::{
+        DistanceMetric, MetadataFilter, UnsupportedFilterError, VecStoreOptions, VectorStore,
+    },
+};
+
+/// Property holding the whole metadata of a document as a JSON string. The top level
+/// metadata values that are strings, numbers or booleans are also stored as properties of
+/// their own, so they can be used in `where` filters.
+pub(crate) const METADATA_PROPERTY: &str = "metadata_json";
+
+// https://weaviate.io/developers/weaviate/api/rest
+// https://weaviate.io/developers/weaviate/api/graphql/search-operators#nearvector
+
+pub struct Store {
+    pub(crate) client: Client,
+    pub(crate) url: String,
+    pub(crate) api_key: Option<String>,
+    pub(crate) embedder: Arc<dyn Embedder>,
+    pub(crate) class_name: String,
+    pub(crate) content_field: String,
+    pub(crate) vector_dimensions: i32,
+    pub(crate) distance_metric: DistanceMetric,
+}
+
+impl Store {
+    pub(crate) fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self.client.request(method, format!("{}{}", self.url, path));
+        match &self.api_key {
+            Some(api_key) => request.bearer_auth(api_key),
+            None => request,
+        }
+    }
+
+    fn properties(&self, doc: &Document) -> Result<Value, Box<dyn Error>> {
+        let mut properties = Map::new();
+        for (key, value) in &doc.metadata {
+            let is_scalar = value.is_string() || value.is_number() || value.is_boolean();
+            if is_scalar && is_property_name(key) && key != &self.content_field {
+                properties.insert(key.clone(), value.clone());
+            }
+        }
+        properties.insert(
+            self.content_field.clone(),
+            Value::from(doc.page_content.clone()),
+        );
+        properties.insert(
+            METADATA_PROPERTY.to_string(),
+            Value::from(serde_json::to_string(&doc.metadata)?),
+        );
+        Ok(Value::Object(properties))
+    }
+
+    fn document_from_properties(&self, properties: &Value) -> Document {
+        let page_content = properties[&self.content_field]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        let metadata = properties[METADATA_PROPERTY]
+            .as_str()
+            .and_then(|metadata| serde_json::from_str(metadata).ok())
+            .unwrap_or_default();
+        Document::new(page_content).with_metadata(metadata)
+    }
+
+    fn similarity_search_query(
+        &self,
+        query_vector: &[f64],
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<String, Box<dyn Error>> {
+        let mut arguments = format!(
+            "nearVector: {{vector: {}}}, limit: {}",
+            serde_json::to_string(query_vector)?,
+            limit
+        );
+        let metadata_filter = opt.metadata_filter.as_ref().map(where_filter).transpose()?;
+        let filter = match (&opt.filters, metadata_filter) {
+            (Some(filters), Some(filter)) => Some(json!({
+                "operator": "And",
+                "operands": [filters, filter],
+            })),
+            (filters, filter) => filters.clone().or(filter),
+        };
+        if let Some(filter) = &filter {
+            arguments.push_str(&format!(", where: {}", graphql_value(filter)?));
+        }
+        let additional = if opt.include_embeddings {
+            "id distance vector"
+        } else {
+            "id distance"
+        };
+        Ok(format!(
+            "{{ Get {{ {}({}) {{ {} {} _additional {{ {} }} }} }} }}",
+            self.class_name, arguments, self.content_field, METADATA_PROPERTY, additional
+        ))
+    }
+}
+
+#[async_trait]
+impl VectorStore for Store {
+    /// Add documents to the store.
+    /// Returns a list of the ids of the Weaviate objects created.
+    async fn add_documents(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let added = self.add_documents_with_embeddings(docs, opt).await?;
+        Ok(added.into_iter().map(|(id, _)| id).collect())
+    }
+
+    async fn add_documents_with_embeddings(
+        &self,
+        docs: &[Document],
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<(String, Vec<f64>)>, Box<dyn Error>> {
+        if docs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let texts: Vec<String> = docs.iter().map(|d| d.page_content.clone()).collect();
+        let vectors = embedder.embed_documents(&texts).await?;
+
+        if vectors.len() != docs.len() {
+            return Err("Number of vectors and documents do not match".into());
+        }
+
+        let mut added = Vec::with_capacity(docs.len());
+        let mut objects = Vec::with_capacity(docs.len());
+        for (doc, vector) in docs.iter().zip(vectors) {
+            if self.vector_dimensions > 0 && vector.len() != self.vector_dimensions as usize {
+                return Err(format!(
+                    "Embedding has {} dimensions, the store expects {}",
+                    vector.len(),
+                    self.vector_dimensions
+                )
+                .into());
+            }
+            let id = Uuid::new_v4().to_string();
+            objects.push(json!({
+                "class": self.class_name,
+                "id": id,
+                "properties": self.properties(doc)?,
+                "vector": vector,
+            }));
+            added.push((id, vector));
+        }
+
+        let response = self
+            .request(Method::POST, "/v1/batch/objects")
+            .json(&json!({ "objects": objects }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?;
+
+        // The batch endpoint succeeds even if some objects fail, the errors are per object
+        let errors = response
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|object| {
+                object["result"]["errors"]["error"]
+                    .as_array()
+                    .cloned()
+                    .unwrap_or_default()
+            })
+            .filter_map(|error| error["message"].as_str().map(String::from))
+            .collect::<Vec<_>>();
+        if !errors.is_empty() {
+            return Err(format!("Weaviate batch errors: {}", errors.join("; ")).into());
+        }
+
+        Ok(added)
+    }
+
+    /// Perform a similarity search on the store using `nearVector`.
+    /// `filters` are Weaviate `where` filters written as JSON, for example
+    /// `{"path": ["source"], "operator": "Equal", "valueText": "a.txt"}`.
+    async fn similarity_search(
+        &self,
+        query: &str,
+        limit: usize,
+        opt: &VecStoreOptions,
+    ) -> Result<Vec<Document>, Box<dyn Error>> {
+        if opt.name_space.is_some() {
+            return Err("Weaviate doesn't support namespaces".into());
+        }
+
+        let embedder = opt.embedder.as_ref().unwrap_or(&self.embedder);
+        let query_vector = embedder.embed_query(query).await?;
+        let query = self.similarity_search_query(&query_vector, limit, opt)?;
+
+        let response = self
+            .request(Method::POST, "/v1/graphql")
+            .json(&json!({ "query": query }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?;
+
+        if let Some(errors) = response["errors"].as_array() {
+            let messages = errors
+                .iter()
+                .filter_map(|error| error["message"].as_str())
+                .collect::<Vec<_>>();
+            return Err(format!("Weaviate query errors: {}", messages.join("; ")).into());
+        }
+
+        let mut documents = response["data"]["Get"][&self.class_name]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|object| {
+                let additional = &object["_additional"];
+                let distance = additional["distance"].as_f64().unwrap_or_default();
+                // The class is created with the `l2-squared` distance for `L2`
+                let score = match self.distance_metric {
+                    DistanceMetric::L2 => DistanceMetric::squared_l2_score(distance),
+                    metric => metric.score(distance),
+                };
+                let mut doc = self.document_from_properties(object).with_score(score);
+                doc.embedding = serde_json::from_value(additional["vector"].clone()).ok();
+                doc
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(score_threshold) = opt.score_threshold {
+            documents.retain(|doc| doc.score >= score_threshold as f64);
+        }
+
+        Ok(documents)
+    }
+
+    async fn get_by_ids(&self, ids: &[String]) -> Result<Vec<Document>, Box<dyn Error>> {
+        let mut docs = Vec::with_capacity(ids.len());
+        for id in ids {
+            let response = self
+                .request(
+                    Method::GET,
+                    &format!("/v1/objects/{}/{}", self.class_name, id),
+                )
+                .send()
+                .await?;
+            if response.status() == StatusCode::NOT_FOUND {
+                continue;
+            }
+            let object = response.error_for_status()?.json::<Value>().await?;
+
+            let mut doc = self.document_from_properties(&object["properties"]);
+            doc.metadata
+                .insert("id".to_string(), Value::from(id.clone()));
+            docs.push(doc);
+        }
+        Ok(docs)
+    }
+
+    async fn delete_documents(&self, ids: &[String]) -> Result<(), Box<dyn Error>> {
+        for id in ids {
+            let response = self
+                .request(
+                    Method::DELETE,
+                    &format!("/v1/objects/{}/{}", self.class_name, id),
+                )
+                .send()
+                .await?;
+            if response.status() != StatusCode::NOT_FOUND {
+                response.error_for_status()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether the name is valid for a Weaviate property, as well as a GraphQL name.
+fn is_property_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The `where` filter of the `MetadataFilter` on the properties of the metadata values.
+/// `In` is written as `Or` of `Equal` filters, the arrays, objects and empty groups are
+/// not supported.
+fn where_filter(filter: &MetadataFilter) -> Result<Value, UnsupportedFilterError> {
+    let operand = |operator: &str, key: &str, value: &Value| {
+        let value_key = match value {
+            Value::String(_) => "valueText",
+            Value::Number(n) if n.is_i64() || n.is_u64() => "valueInt",
+            Value::Number(_) => "valueNumber",
+            Value::Bool(_) => "valueBoolean",
+            _ => return Err(UnsupportedFilterError::new("Weaviate", filter)),
+        };
+        Ok(json!({ "operator": operator, "path": [key], value_key: value }))
+    };
+    let group = |operator: &str, operands: Vec<Value>| {
+        if operands.is_empty() {
+            return Err(UnsupportedFilterError::new("Weaviate", filter));
+        }
+        Ok(json!({ "operator": operator, "operands": operands }))
+    };
+    let where_filters = |filters: &[MetadataFilter]| {
+        filters
+            .iter()
+            .map(where_filter)
+            .collect::<Result<Vec<_>, _>>()
+    };
+    match filter {
+        MetadataFilter::Eq(key, Value::Null) => {
+            Ok(json!({ "operator": "IsNull", "path": [key], "valueBoolean": true }))
+        }
+        MetadataFilter::Ne(key, Value::Null) => {
+            Ok(json!({ "operator": "IsNull", "path": [key], "valueBoolean": false }))
+        }
+        MetadataFilter::Eq(key, value) => operand("Equal", key, value),
+        MetadataFilter::Ne(key, value) => operand("NotEqual", key, value),
+        MetadataFilter::Gt(key, value) => operand("GreaterThan", key, value),
+        MetadataFilter::Lt(key, value) => operand("LessThan", key, value),
+        MetadataFilter::In(key, values) => group(
+            "Or",
+            values
+                .iter()
+                .map(|value| operand("Equal", key, value))
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        MetadataFilter::And(filters) => group("And", where_filters(filters)?),
+        MetadataFilter::Or(filters) => group("Or", where_filters(filters)?),
+    }
+}
+
+/// Writes a JSON `where` filter as a GraphQL input value, the `operator` values are enums
+/// so they are written without quotes.
+fn graphql_value(value: &Value) -> Result<String, Box<dyn Error>> {
+    match value {
+        Value::Object(map) => {
+            let fields = map
+                .iter()
+                .map(|(key, value)| -> Result<String, Box<dyn Error>> {
+                    if !is_property_name(key) {
+                        return Err(format!("Invalid filter key: {}", key).into());
+                    }
+                    match (key.as_str(), value) {
+                        ("operator", Value::String(operator)) => {
+                            if !is_property_name(operator) {
+                                return Err(format!("Invalid filter operator: {}", operator).into());
+                            }
+                            Ok(format!("{}: {}", key, operator))
+                        }
+                        _ => Ok(format!("{}: {}", key, graphql_value(value)?)),
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(format!("{{{}}}", fields.join(", ")))
+        }
+        Value::Array(values) => {
+            let values = values
+                .iter()
+                .map(graphql_value)
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(format!("[{}]", values.join(", ")))
+        }
+        // JSON strings, numbers and booleans are valid GraphQL values
+        value => Ok(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use async_trait::async_trait;
+    use mockito::Matcher;
+
+    use super::*;
+    use crate::{embedding::EmbedderError, vectorstore::weaviate::StoreBuilder};
+
+    struct FakeEmbedder {}
+
+    #[async_trait]
+    impl Embedder for FakeEmbedder {
+        async fn embed_documents(
+            &self,
+            documents: &[String],
+        ) -> Result<Vec<Vec<f64>>, EmbedderError> {
+            Ok(documents.iter().map(|_| vec![1.0, 0.0]).collect())
+        }
+
+        async fn embed_query(&self, _text: &str) -> Result<Vec<f64>, EmbedderError> {
+            Ok(vec![1.0, 0.0])
+        }
+    }
+
+    #[test]
+    fn test_graphql_value() {
+        let filter = json!({
+            "operands": [
+                {"operator": "Equal", "path": ["source"], "valueText": "a \"b\".txt"},
+                {"operator": "GreaterThan", "path": ["page"], "valueInt": 2},
+            ],
+            "operator": "And",
+        });
+        assert_eq!(
+            graphql_value(&filter).unwrap(),
+            r#"{operands: [{operator: Equal, path: ["source"], valueText: "a \"b\".txt"}, {operator: GreaterThan, path: ["page"], valueInt: 2}], operator: And}"#
+        );
+        assert!(graphql_value(&json!({"operator": "Equal) { x"})).is_err());
+    }
+
+    #[test]
+    fn test_where_filter() {
+        let filter = MetadataFilter::eq("source", "a.txt").and(MetadataFilter::gt("page", 2));
+
+        assert_eq!(
+            where_filter(&filter).unwrap(),
+            json!({
+                "operands": [
+                    {"operator": "Equal", "path": ["source"], "valueText": "a.txt"},
+                    {"operator": "GreaterThan", "path": ["page"], "valueInt": 2},
+                ],
+                "operator": "And",
+            })
+        );
+        assert_eq!(
+            where_filter(&MetadataFilter::is_in("lang", ["en", "es"])).unwrap()["operands"][1],
+            json!({"operator": "Equal", "path": ["lang"], "valueText": "es"})
+        );
+        assert!(where_filter(&MetadataFilter::eq("tags", json!(["a"]))).is_err());
+        assert!(where_filter(&MetadataFilter::Or(vec![])).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_weaviate_similarity_search() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/v1/schema/Docs")
+            .with_body("{}")
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/v1/graphql")
+            .match_body(Matcher::Regex(
+                r"Docs\(nearVector: \{vector: \[1.0,0.0\]\}, limit: 2".to_string(),
+            ))
+            .with_body(
+                json!({"data": {"Get": {"Docs": [
+                    {
+                        "text": "first",
+                        "metadata_json": "{\"source\":\"a.txt\"}",
+                        "_additional": {"id": "1", "distance": 0.25}
+                    },
+                    {
+                        "text": "second",
+                        "metadata_json": "{}",
+                        "_additional": {"id": "2", "distance": 0.75}
+                    }
+                ]}}})
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let store = StoreBuilder::new()
+            .url(&server.url())
+            .class_name("Docs")
+            .embedder(FakeEmbedder {})
+            .build()
+            .await
+            .unwrap();
+
+        let docs = store
+            .similarity_search(
+                "query",
+                2,
+                &VecStoreOptions::default().with_score_threshold(0.5),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].page_content, "first");
+        assert_eq!(docs[0].score, 0.75);
+        assert_eq!(
+            docs[0].metadata,
+            HashMap::from([("source".to_string(), json!("a.txt"))])
+        );
+        assert!(docs[0].embedding.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_weaviate_add_no_documents() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/v1/schema/Docs")
+            .with_body("{}")
+            .create_async()
+            .await;
+        let batch = server
+            .mock("POST", "/v1/batch/objects")
+            .expect(0)
+            .create_async()
+            .await;
+
+        let store = StoreBuilder::new()
+            .url(&server.url())
+            .class_name("Docs")
+            .embedder(FakeEmbedder {})
+            .build()
+            .await
+            .unwrap();
+        let ids = store
+            .add_documents(&[], &VecStoreOptions::default())
+            .await
+            .unwrap();
+
+        assert!(ids.is_empty());
+        batch.assert_async().await;
+    }
+}